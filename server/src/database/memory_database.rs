@@ -0,0 +1,1566 @@
+use std::collections::{HashMap, HashSet};
+
+use axum::async_trait;
+use futures::stream::{self, BoxStream, StreamExt};
+use mongodb::{
+    bson::{oid::ObjectId, DateTime},
+    error::Error,
+};
+use tokio::sync::RwLock;
+
+use super::storage::Storage;
+use crate::{
+    models::{
+        activity_model::{
+            Activity, ActivityCursor, ActivityData, ActivityDelete, ActivityVariant,
+            CardioExercise, DeleteActivityPayload, Exercise, ExerciseVariant, GetActivitiesPayload,
+            GetActivityPayload, PatchActivityPayload, PostActivityPayload, Set, VersionedWrite,
+        },
+        auth_model::{ResetTokenDB, TokenDB},
+        idempotency_model::{IdempotencyRecord, StoredResponse},
+        progress_model::{
+            CardioSession, ExerciseProgress, ExerciseProgressPayload, ExerciseSummary,
+            StrengthSession,
+        },
+        record_model::PersonalRecord,
+        series_model::{Series, MAX_SERIES},
+        stats_model::{ActivityStats, StatsPayload},
+        template_model::{PatchTemplatePayload, PostTemplatePayload, Template},
+        user_model::{AccountErasure, PatchUserPayload, RegisterUserPayload, User},
+    },
+    utils::utils::{parse_date, parse_object_id},
+};
+
+/// Process-local storage that mirrors the behaviour of `MongoDatabase`.
+/// Nothing is persisted; everything is dropped with the process.
+#[derive(Debug, Default)]
+pub struct MemoryDatabase {
+    activities: RwLock<HashMap<ObjectId, Activity>>,
+    users: RwLock<HashMap<ObjectId, User>>,
+    tokens: RwLock<Vec<TokenDB>>,
+    reset_tokens: RwLock<Vec<ResetTokenDB>>,
+    idempotency: RwLock<HashMap<(ObjectId, String), IdempotencyRecord>>,
+    records: RwLock<Vec<PersonalRecord>>,
+    templates: RwLock<HashMap<ObjectId, Template>>,
+    series: RwLock<HashMap<ObjectId, Series>>,
+}
+
+impl MemoryDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryDatabase {
+    async fn create_user(&self, payload: RegisterUserPayload) -> Result<Option<User>, Error> {
+        let new_user = User::from(payload);
+
+        let mut users = self.users.write().await;
+        users.insert(new_user.id, new_user.clone());
+
+        Ok(Some(new_user))
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Result<Option<User>, Error> {
+        let users = self.users.read().await;
+
+        Ok(users.values().find(|u| u.email == email).cloned())
+    }
+
+    async fn get_user_by_id(&self, uid: ObjectId) -> Result<Option<User>, Error> {
+        Ok(self.users.read().await.get(&uid).cloned())
+    }
+
+    async fn update_user_password(&self, uid: ObjectId, pass: String) -> Result<(), Error> {
+        if let Some(user) = self.users.write().await.get_mut(&uid) {
+            user.pass = pass;
+            user.password_set = true;
+        }
+
+        Ok(())
+    }
+
+    async fn update_user_profile(
+        &self,
+        uid: ObjectId,
+        payload: PatchUserPayload,
+    ) -> Result<Option<User>, Error> {
+        let mut users = self.users.write().await;
+
+        let user = match users.get_mut(&uid) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        if let Some(given_name) = payload.given_name {
+            user.given_name = given_name;
+        }
+        if let Some(family_name) = payload.family_name {
+            user.family_name = family_name;
+        }
+        if let Some(img) = payload.img {
+            user.img = img;
+        }
+        user.v += 1;
+
+        Ok(Some(user.clone()))
+    }
+
+    async fn deactivate_user(&self, uid: ObjectId, purge_at: DateTime) -> Result<(), Error> {
+        if let Some(user) = self.users.write().await.get_mut(&uid) {
+            user.active = false;
+            user.purge_at = Some(purge_at);
+        }
+
+        Ok(())
+    }
+
+    async fn get_users_to_purge(&self, now: DateTime) -> Result<Vec<ObjectId>, Error> {
+        let users = self.users.read().await;
+
+        Ok(users
+            .values()
+            .filter(|u| !u.active && u.purge_at.is_some_and(|at| at <= now))
+            .map(|u| u.id)
+            .collect())
+    }
+
+    async fn erase_user(&self, uid: ObjectId) -> Result<AccountErasure, Error> {
+        let mut activities = self.activities.write().await;
+        let count = activities.len();
+        activities.retain(|_, a| a.user != uid);
+        let erased_activities = (count - activities.len()) as u64;
+        drop(activities);
+
+        let mut tokens = self.tokens.write().await;
+        let count = tokens.len();
+        tokens.retain(|t| t.uid != uid);
+        let erased_tokens = (count - tokens.len()) as u64;
+        drop(tokens);
+
+        self.reset_tokens.write().await.retain(|t| t.uid != uid);
+        self.idempotency
+            .write()
+            .await
+            .retain(|(user, _), _| *user != uid);
+        self.records.write().await.retain(|r| r.user != uid);
+        self.templates.write().await.retain(|_, t| t.user != uid);
+        self.series.write().await.retain(|_, s| s.user != uid);
+        self.users.write().await.remove(&uid);
+
+        Ok(AccountErasure {
+            activities: erased_activities,
+            tokens: erased_tokens,
+        })
+    }
+
+    async fn set_activity_colors(
+        &self,
+        uid: ObjectId,
+        colors: HashMap<String, String>,
+    ) -> Result<(), Error> {
+        if let Some(user) = self.users.write().await.get_mut(&uid) {
+            user.activities.extend(colors);
+        }
+
+        Ok(())
+    }
+
+    async fn set_user_verified(&self, uid: ObjectId, email: &str) -> Result<bool, Error> {
+        match self.users.write().await.get_mut(&uid) {
+            Some(user) if user.email == email => {
+                user.verified = true;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn set_calendar_token(&self, uid: ObjectId, hash: Option<String>) -> Result<bool, Error> {
+        match self.users.write().await.get_mut(&uid) {
+            Some(user) => {
+                user.calendar_token = hash;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn get_user_by_calendar_token(&self, hash: &str) -> Result<Option<User>, Error> {
+        let users = self.users.read().await;
+
+        Ok(users
+            .values()
+            .find(|u| u.calendar_token.as_deref() == Some(hash))
+            .cloned())
+    }
+
+    async fn create_token(&self, token: TokenDB) -> Result<Option<ObjectId>, Error> {
+        let id = token.id;
+        self.tokens.write().await.push(token);
+
+        Ok(Some(id))
+    }
+
+    async fn get_token(&self, jti: &str) -> Result<Option<TokenDB>, Error> {
+        let tokens = self.tokens.read().await;
+
+        Ok(tokens.iter().find(|t| t.jti == jti).cloned())
+    }
+
+    async fn blacklist_user_token(&self, jti: &str) -> Result<(), Error> {
+        let mut tokens = self.tokens.write().await;
+
+        if let Some(token) = tokens.iter_mut().find(|t| t.jti == jti) {
+            token.black = true;
+        }
+
+        Ok(())
+    }
+
+    async fn blacklist_user_tokens(&self, uid: ObjectId) -> Result<(), Error> {
+        let mut tokens = self.tokens.write().await;
+
+        tokens
+            .iter_mut()
+            .filter(|t| t.uid == uid)
+            .for_each(|t| t.black = true);
+
+        Ok(())
+    }
+
+    async fn create_reset_token(&self, token: ResetTokenDB) -> Result<(), Error> {
+        self.reset_tokens.write().await.push(token);
+
+        Ok(())
+    }
+
+    async fn consume_reset_token(&self, hash: &str) -> Result<Option<ResetTokenDB>, Error> {
+        let now = DateTime::now();
+        let mut tokens = self.reset_tokens.write().await;
+
+        match tokens
+            .iter_mut()
+            .find(|t| t.hash == hash && !t.used && t.exp > now)
+        {
+            Some(token) => {
+                token.used = true;
+                Ok(Some(token.clone()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn reserve_idempotency_key(
+        &self,
+        record: IdempotencyRecord,
+    ) -> Result<Option<IdempotencyRecord>, Error> {
+        let mut records = self.idempotency.write().await;
+        let id = (record.user, record.key.clone());
+
+        match records.get(&id) {
+            Some(existing) if !existing.expired() => Ok(Some(existing.clone())),
+            _ => {
+                records.insert(id, record);
+                Ok(None)
+            }
+        }
+    }
+
+    async fn complete_idempotency_key(
+        &self,
+        uid: ObjectId,
+        key: &str,
+        response: StoredResponse,
+    ) -> Result<(), Error> {
+        if let Some(record) = self
+            .idempotency
+            .write()
+            .await
+            .get_mut(&(uid, key.to_string()))
+        {
+            record.response = Some(response);
+            record.exp = IdempotencyRecord::retained_until();
+        }
+
+        Ok(())
+    }
+
+    async fn renew_idempotency_key(&self, uid: ObjectId, key: &str) -> Result<(), Error> {
+        if let Some(record) = self
+            .idempotency
+            .write()
+            .await
+            .get_mut(&(uid, key.to_string()))
+        {
+            if record.response.is_none() {
+                record.exp = IdempotencyRecord::leased_until();
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn release_idempotency_key(&self, uid: ObjectId, key: &str) -> Result<(), Error> {
+        self.idempotency
+            .write()
+            .await
+            .remove(&(uid, key.to_string()));
+
+        Ok(())
+    }
+
+    async fn create_activity(
+        &self,
+        payload: PostActivityPayload,
+        user_id: String,
+    ) -> Result<Option<Activity>, Error> {
+        let uid = parse_object_id(&user_id)?;
+
+        let mut activity = Activity::new(
+            payload.variant,
+            payload.title.to_string(),
+            payload.group.to_string(),
+            payload.notes.unwrap_or(String::from("")),
+            parse_date(&payload.start)?,
+            Some(parse_date(&payload.end)?),
+            payload.timezone,
+            payload.data,
+            uid,
+        );
+        activity.ical_uid = payload.ical_uid;
+        activity.occurrence = payload.occurrence;
+
+        if let Some(color) = payload.color {
+            if let Some(user) = self.users.write().await.get_mut(&uid) {
+                user.activities.insert(activity.title.clone(), color);
+            }
+        }
+
+        self.activities
+            .write()
+            .await
+            .insert(activity.id, activity.clone());
+
+        Ok(Some(activity))
+    }
+
+    async fn update_activity_by_id(
+        &self,
+        payload: PatchActivityPayload,
+        user_id: String,
+    ) -> Result<VersionedWrite<Activity>, Error> {
+        let id = parse_object_id(&payload.id)?;
+        let uid = parse_object_id(&user_id)?;
+
+        let start = payload.start.as_deref().map(parse_date).transpose()?;
+        let end = payload.end.as_deref().map(parse_date).transpose()?;
+
+        let mut activities = self.activities.write().await;
+        let activity = match activities.get_mut(&id) {
+            Some(a) if a.user == uid && !a.is_deleted() => a,
+            _ => return Ok(VersionedWrite::NotFound),
+        };
+
+        if payload.v.is_some_and(|v| v != activity.v) {
+            return Ok(VersionedWrite::Conflict(Box::new(activity.clone())));
+        }
+
+        if let Some(field) = activity.out_of_range(start, end) {
+            return Ok(VersionedWrite::OutOfRange(field));
+        }
+
+        if let (Some(title), Some(color)) = (&payload.title, payload.color) {
+            if let Some(user) = self.users.write().await.get_mut(&uid) {
+                user.activities.insert(title.clone(), color);
+            }
+        }
+
+        if let Some(start) = start {
+            activity.start = start;
+        }
+        if let Some(end) = end {
+            activity.end = Some(end);
+        }
+        if let Some(variant) = payload.variant {
+            activity.variant = variant;
+        }
+        if let Some(title) = payload.title {
+            activity.title = title;
+        }
+        if let Some(group) = payload.group {
+            activity.group = group;
+        }
+        if let Some(notes) = payload.notes {
+            activity.notes = notes;
+        }
+        if let Some(timezone) = payload.timezone {
+            activity.timezone = timezone;
+        }
+        if let Some(exercise) = payload.data.and_then(|d| d.exercise) {
+            let mut data = activity
+                .data
+                .clone()
+                .unwrap_or(ActivityData { exercise: None });
+            data.exercise = Some(exercise);
+            activity.data = Some(data);
+        }
+        activity.updated_at = DateTime::now();
+        activity.v += 1;
+
+        Ok(VersionedWrite::Done(activity.clone()))
+    }
+
+    async fn delete_activity_by_id(
+        &self,
+        payload: DeleteActivityPayload,
+        user_id: String,
+    ) -> Result<VersionedWrite<ActivityDelete>, Error> {
+        let id = parse_object_id(&payload.id)?;
+        let uid = parse_object_id(&user_id)?;
+
+        let mut activities = self.activities.write().await;
+        let activity = match activities.get_mut(&id) {
+            Some(a) if a.user == uid && !a.is_deleted() => a,
+            _ => return Ok(VersionedWrite::NotFound),
+        };
+
+        if payload.v.is_some_and(|v| v != activity.v) {
+            return Ok(VersionedWrite::Conflict(Box::new(activity.clone())));
+        }
+
+        // keep a tombstone so sync can report the delete
+        let now = DateTime::now();
+        activity.end.get_or_insert(now);
+        activity.deleted_at = Some(now);
+        activity.updated_at = now;
+        activity.v += 1;
+
+        Ok(VersionedWrite::Done(ActivityDelete { id }))
+    }
+
+    async fn get_activity_by_id(
+        &self,
+        payload: GetActivityPayload,
+        user_id: String,
+    ) -> Result<Option<Activity>, Error> {
+        let id = parse_object_id(&payload.id)?;
+        let uid = parse_object_id(&user_id)?;
+
+        let activities = self.activities.read().await;
+
+        Ok(activities
+            .get(&id)
+            .filter(|a| a.user == uid && !a.is_deleted())
+            .cloned())
+    }
+
+    async fn get_activities(
+        &self,
+        payload: GetActivitiesPayload,
+        user_id: String,
+    ) -> Result<Vec<Activity>, Error> {
+        let uid = parse_object_id(&user_id)?;
+        let start = payload.start.as_deref().map(parse_date).transpose()?;
+        let end = payload.end.as_deref().map(parse_date).transpose()?;
+        let after = match payload.after {
+            Some(after) => Some(
+                ActivityCursor::decode(&after)
+                    .ok_or_else(|| Error::custom(format!("invalid cursor: {}", after)))?,
+            ),
+            None => None,
+        };
+
+        let activities = self.activities.read().await;
+
+        let mut res: Vec<Activity> = activities
+            .values()
+            .filter(|a| a.user == uid && !a.is_deleted())
+            .filter(|a| payload.title.as_ref().is_none_or(|t| &a.title == t))
+            .filter(|a| payload.group.as_ref().is_none_or(|g| &a.group == g))
+            .filter(|a| payload.variant.is_none_or(|v| a.variant == v))
+            .filter(|a| start.is_none_or(|s| a.end_or_now() >= s))
+            .filter(|a| end.is_none_or(|e| a.start <= e))
+            .filter(|a| after.is_none_or(|c| (a.start, a.id) > (c.start, c.id)))
+            .cloned()
+            .collect();
+
+        res.sort_by(|a, b| a.start.cmp(&b.start).then(a.id.cmp(&b.id)));
+
+        if let Some(limit) = payload.limit {
+            res.truncate(limit as usize);
+        }
+
+        Ok(res)
+    }
+
+    async fn get_stats(
+        &self,
+        payload: StatsPayload,
+        user_id: String,
+    ) -> Result<ActivityStats, Error> {
+        let uid = parse_object_id(&user_id)?;
+        let start = parse_date(&payload.start)?;
+        let end = parse_date(&payload.end)?;
+
+        let stored = self.activities.read().await;
+
+        let activities = stored
+            .values()
+            .filter(|a| a.user == uid && !a.is_deleted())
+            .filter(|a| payload.title.as_ref().is_none_or(|t| &a.title == t))
+            .filter(|a| payload.group.as_ref().is_none_or(|g| &a.group == g))
+            .filter(|a| payload.variant.is_none_or(|v| a.variant == v))
+            .filter(|a| a.end_or_now() >= start && a.start <= end);
+
+        Ok(ActivityStats::of(
+            activities,
+            start,
+            end,
+            payload.group_by,
+            payload.timezone,
+        ))
+    }
+
+    async fn get_exercises(&self, user_id: String) -> Result<Vec<ExerciseSummary>, Error> {
+        let uid = parse_object_id(&user_id)?;
+
+        let activities = self.activities.read().await;
+
+        let mut summaries: HashMap<(String, String), ExerciseSummary> = HashMap::new();
+
+        for a in activities
+            .values()
+            .filter(|a| a.user == uid && !a.is_deleted())
+        {
+            let exercises = a.data.iter().flat_map(|d| d.exercise.iter().flatten());
+
+            // an exercise logged twice in an activity is one session
+            let mut seen = HashSet::new();
+
+            for (title, variant) in exercises.map(|e| match e {
+                Exercise::Strength(e) => (&e.title, ExerciseVariant::Strength),
+                Exercise::Mobility(e) => (&e.title, ExerciseVariant::Mobility),
+                Exercise::Cardio(e) => (&e.title, ExerciseVariant::Cardio),
+            }) {
+                let key = (title.clone(), String::from(variant));
+                if !seen.insert(key.clone()) {
+                    continue;
+                }
+
+                let summary = summaries.entry(key).or_insert(ExerciseSummary {
+                    title: title.clone(),
+                    variant,
+                    sessions: 0,
+                    last: a.start,
+                });
+                summary.sessions += 1;
+                summary.last = summary.last.max(a.start);
+            }
+        }
+
+        let mut res: Vec<_> = summaries.into_iter().collect();
+        res.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(res.into_iter().map(|(_, s)| s).collect())
+    }
+
+    async fn get_exercise_progress(
+        &self,
+        payload: ExerciseProgressPayload,
+        user_id: String,
+    ) -> Result<ExerciseProgress, Error> {
+        let uid = parse_object_id(&user_id)?;
+        let start = payload.start.as_deref().map(parse_date).transpose()?;
+        let end = payload.end.as_deref().map(parse_date).transpose()?;
+
+        let activities = self.activities.read().await;
+
+        let mut matching: Vec<&Activity> = activities
+            .values()
+            .filter(|a| a.user == uid && !a.is_deleted())
+            .filter(|a| start.is_none_or(|s| a.end_or_now() >= s))
+            .filter(|a| end.is_none_or(|e| a.start <= e))
+            .collect();
+
+        matching.sort_by(|a, b| a.start.cmp(&b.start).then(a.id.cmp(&b.id)));
+
+        let mut res = ExerciseProgress::default();
+
+        for a in matching {
+            let exercises: Vec<&Exercise> = a
+                .data
+                .iter()
+                .flat_map(|d| d.exercise.iter().flatten())
+                .collect();
+
+            let sets: Vec<&Set> = exercises
+                .iter()
+                .filter_map(|e| match e {
+                    Exercise::Strength(e) if e.title == payload.title => Some(&e.sets),
+                    _ => None,
+                })
+                .flatten()
+                .collect();
+            res.strength
+                .extend(StrengthSession::new(a.id, a.start, &sets));
+
+            let cardio: Vec<&CardioExercise> = exercises
+                .iter()
+                .filter_map(|e| match e {
+                    Exercise::Cardio(e) if e.title == payload.title => Some(e),
+                    _ => None,
+                })
+                .collect();
+            if !cardio.is_empty() {
+                res.cardio.push(CardioSession::new(a.id, a.start, &cardio));
+            }
+        }
+
+        Ok(res)
+    }
+
+    async fn find_exercise_activities(
+        &self,
+        user_id: String,
+        titles: Vec<String>,
+    ) -> Result<Vec<Activity>, Error> {
+        let uid = parse_object_id(&user_id)?;
+
+        let activities = self.activities.read().await;
+
+        Ok(activities
+            .values()
+            .filter(|a| a.user == uid && !a.is_deleted())
+            .filter(|a| a.variant == ActivityVariant::Exercise)
+            .filter(|a| {
+                a.data
+                    .iter()
+                    .flat_map(|d| d.exercise.iter().flatten())
+                    .any(|e| match e {
+                        Exercise::Strength(e) => titles.contains(&e.title),
+                        Exercise::Mobility(e) => titles.contains(&e.title),
+                        Exercise::Cardio(e) => titles.contains(&e.title),
+                    })
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn get_last_sets(
+        &self,
+        user_id: String,
+        titles: Vec<String>,
+    ) -> Result<HashMap<String, Vec<Set>>, Error> {
+        let uid = parse_object_id(&user_id)?;
+
+        let activities = self.activities.read().await;
+
+        let mut latest: Vec<&Activity> = activities
+            .values()
+            .filter(|a| a.user == uid && !a.is_deleted())
+            .filter(|a| a.variant == ActivityVariant::Exercise)
+            .collect();
+        latest.sort_by(|a, b| b.start.cmp(&a.start).then(b.id.cmp(&a.id)));
+
+        let mut res = HashMap::new();
+
+        for exercise in latest
+            .iter()
+            .flat_map(|a| a.data.iter().flat_map(|d| d.exercise.iter().flatten()))
+        {
+            let Exercise::Strength(e) = exercise else {
+                continue;
+            };
+            if titles.contains(&e.title)
+                && !res.contains_key(&e.title)
+                && e.sets.iter().any(|s| s.weight.unwrap_or(0) > 0)
+            {
+                res.insert(e.title.clone(), e.sets.clone());
+            }
+        }
+
+        Ok(res)
+    }
+
+    async fn get_records(&self, user_id: String) -> Result<Vec<PersonalRecord>, Error> {
+        let uid = parse_object_id(&user_id)?;
+
+        let records = self.records.read().await;
+
+        Ok(records.iter().filter(|r| r.user == uid).cloned().collect())
+    }
+
+    async fn replace_records(
+        &self,
+        user_id: String,
+        titles: Vec<String>,
+        records: Vec<PersonalRecord>,
+    ) -> Result<(), Error> {
+        let uid = parse_object_id(&user_id)?;
+
+        let mut stored = self.records.write().await;
+        stored.retain(|r| r.user != uid || !titles.contains(&r.title));
+        stored.extend(records);
+
+        Ok(())
+    }
+
+    async fn get_activity_changes(
+        &self,
+        since: Option<DateTime>,
+        user_id: String,
+    ) -> Result<Vec<Activity>, Error> {
+        let uid = parse_object_id(&user_id)?;
+
+        let activities = self.activities.read().await;
+
+        let mut res: Vec<Activity> = activities
+            .values()
+            .filter(|a| a.user == uid)
+            .filter(|a| match since {
+                Some(since) => a.updated_at > since,
+                None => !a.is_deleted(),
+            })
+            .cloned()
+            .collect();
+
+        res.sort_by(|a, b| a.updated_at.cmp(&b.updated_at).then(a.id.cmp(&b.id)));
+
+        Ok(res)
+    }
+
+    async fn find_ical_uids(
+        &self,
+        user_id: String,
+        keys: Vec<String>,
+    ) -> Result<HashSet<String>, Error> {
+        let uid = parse_object_id(&user_id)?;
+        let keys: HashSet<String> = keys.into_iter().collect();
+
+        let activities = self.activities.read().await;
+
+        Ok(activities
+            .values()
+            .filter(|a| a.user == uid)
+            .filter_map(|a| a.ical_uid.as_ref())
+            .filter(|k| keys.contains(*k))
+            .cloned()
+            .collect())
+    }
+
+    // everything is in memory already, so this streams a snapshot
+    async fn stream_activities(
+        &self,
+        user_id: String,
+    ) -> Result<BoxStream<'static, Result<Activity, Error>>, Error> {
+        let uid = parse_object_id(&user_id)?;
+
+        let mut res: Vec<Activity> = self
+            .activities
+            .read()
+            .await
+            .values()
+            .filter(|a| a.user == uid && !a.is_deleted())
+            .cloned()
+            .collect();
+
+        res.sort_by(|a, b| a.start.cmp(&b.start).then(a.id.cmp(&b.id)));
+
+        Ok(stream::iter(res.into_iter().map(Ok)).boxed())
+    }
+
+    async fn create_template(
+        &self,
+        payload: PostTemplatePayload,
+        user_id: String,
+    ) -> Result<Template, Error> {
+        let template = Template::new(payload, parse_object_id(&user_id)?);
+
+        let mut templates = self.templates.write().await;
+        templates.insert(template.id, template.clone());
+
+        Ok(template)
+    }
+
+    async fn get_templates(&self, user_id: String) -> Result<Vec<Template>, Error> {
+        let uid = parse_object_id(&user_id)?;
+
+        let templates = self.templates.read().await;
+
+        let mut res: Vec<Template> = templates
+            .values()
+            .filter(|t| t.user == uid)
+            .cloned()
+            .collect();
+
+        res.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+
+        Ok(res)
+    }
+
+    async fn get_template_by_id(
+        &self,
+        id: ObjectId,
+        user_id: String,
+    ) -> Result<Option<Template>, Error> {
+        let uid = parse_object_id(&user_id)?;
+
+        let templates = self.templates.read().await;
+
+        Ok(templates.get(&id).filter(|t| t.user == uid).cloned())
+    }
+
+    async fn update_template_by_id(
+        &self,
+        id: ObjectId,
+        payload: PatchTemplatePayload,
+        user_id: String,
+    ) -> Result<Option<Template>, Error> {
+        let uid = parse_object_id(&user_id)?;
+
+        let mut templates = self.templates.write().await;
+
+        let template = match templates.get_mut(&id) {
+            Some(t) if t.user == uid => t,
+            _ => return Ok(None),
+        };
+        template.apply(payload);
+
+        Ok(Some(template.clone()))
+    }
+
+    async fn delete_template_by_id(&self, id: ObjectId, user_id: String) -> Result<bool, Error> {
+        let uid = parse_object_id(&user_id)?;
+
+        let mut templates = self.templates.write().await;
+
+        if templates.get(&id).is_none_or(|t| t.user != uid) {
+            return Ok(false);
+        }
+        templates.remove(&id);
+
+        Ok(true)
+    }
+
+    async fn create_series(&self, series: Series) -> Result<bool, Error> {
+        let mut stored = self.series.write().await;
+
+        let live = stored
+            .values()
+            .filter(|s| s.user == series.user && !s.is_deleted())
+            .count();
+        if live as u64 >= MAX_SERIES {
+            return Ok(false);
+        }
+
+        stored.insert(series.id, series);
+
+        Ok(true)
+    }
+
+    async fn get_series(&self, user_id: String) -> Result<Vec<Series>, Error> {
+        let uid = parse_object_id(&user_id)?;
+
+        let series = self.series.read().await;
+
+        let mut res: Vec<Series> = series
+            .values()
+            .filter(|s| s.user == uid && !s.is_deleted())
+            .cloned()
+            .collect();
+
+        res.sort_by(|a, b| a.start.cmp(&b.start).then(a.id.cmp(&b.id)));
+
+        Ok(res)
+    }
+
+    async fn get_series_by_id(
+        &self,
+        id: ObjectId,
+        user_id: String,
+    ) -> Result<Option<Series>, Error> {
+        let uid = parse_object_id(&user_id)?;
+
+        let series = self.series.read().await;
+
+        Ok(series
+            .get(&id)
+            .filter(|s| s.user == uid && !s.is_deleted())
+            .cloned())
+    }
+
+    async fn replace_series(&self, series: Series) -> Result<bool, Error> {
+        let mut stored = self.series.write().await;
+
+        match stored.get_mut(&series.id) {
+            Some(s) if s.user == series.user && !s.is_deleted() && s.v == series.v => {
+                *s = Series {
+                    v: series.v + 1,
+                    ..series
+                };
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn add_series_exception(
+        &self,
+        id: ObjectId,
+        user_id: String,
+        at: DateTime,
+    ) -> Result<bool, Error> {
+        let uid = parse_object_id(&user_id)?;
+
+        let mut stored = self.series.write().await;
+
+        match stored.get_mut(&id) {
+            Some(s) if s.user == uid && !s.is_deleted() && !s.exceptions.contains(&at) => {
+                s.exceptions.push(at);
+                s.updated_at = DateTime::now();
+                s.v += 1;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn delete_series_by_id(&self, id: ObjectId, user_id: String) -> Result<bool, Error> {
+        let uid = parse_object_id(&user_id)?;
+
+        let mut series = self.series.write().await;
+        let series = match series.get_mut(&id) {
+            Some(s) if s.user == uid && !s.is_deleted() => s,
+            _ => return Ok(false),
+        };
+
+        // keep a tombstone so sync can report the delete
+        let now = DateTime::now();
+        series.deleted_at = Some(now);
+        series.updated_at = now;
+        series.v += 1;
+
+        Ok(true)
+    }
+
+    async fn get_series_changes(
+        &self,
+        since: Option<DateTime>,
+        user_id: String,
+    ) -> Result<Vec<Series>, Error> {
+        let uid = parse_object_id(&user_id)?;
+
+        let series = self.series.read().await;
+
+        let mut res: Vec<Series> = series
+            .values()
+            .filter(|s| s.user == uid)
+            .filter(|s| match since {
+                Some(since) => s.updated_at > since,
+                None => !s.is_deleted(),
+            })
+            .cloned()
+            .collect();
+
+        res.sort_by(|a, b| a.updated_at.cmp(&b.updated_at).then(a.id.cmp(&b.id)));
+
+        Ok(res)
+    }
+
+    async fn start_timer(&self, activity: Activity) -> Result<bool, Error> {
+        let mut activities = self.activities.write().await;
+
+        if activities
+            .values()
+            .any(|a| a.user == activity.user && !a.is_deleted() && a.is_running())
+        {
+            return Ok(false);
+        }
+        activities.insert(activity.id, activity);
+
+        Ok(true)
+    }
+
+    async fn get_timer(&self, user_id: String) -> Result<Option<Activity>, Error> {
+        let uid = parse_object_id(&user_id)?;
+
+        let activities = self.activities.read().await;
+
+        Ok(activities
+            .values()
+            .find(|a| a.user == uid && !a.is_deleted() && a.is_running())
+            .cloned())
+    }
+
+    async fn stop_timer(
+        &self,
+        user_id: String,
+        end: DateTime,
+    ) -> Result<VersionedWrite<Activity>, Error> {
+        let uid = parse_object_id(&user_id)?;
+
+        let mut activities = self.activities.write().await;
+        let Some(activity) = activities
+            .values_mut()
+            .find(|a| a.user == uid && !a.is_deleted() && a.is_running())
+        else {
+            return Ok(VersionedWrite::NotFound);
+        };
+
+        if end < activity.start {
+            return Ok(VersionedWrite::OutOfRange("end"));
+        }
+
+        activity.end = Some(end);
+        activity.updated_at = DateTime::now();
+        activity.v += 1;
+
+        Ok(VersionedWrite::Done(activity.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::activity_model::{
+        ActivityVariant, CardioExercise, Exercise, Set, StrengthExercise,
+    };
+    use crate::models::series_model::PostSeriesPayload;
+    use mongodb::bson::DateTime;
+
+    const USER_ID: &str = "5f00b442bab42e04c05f5a9e";
+
+    fn activity_payload(title: &str, start: &str, end: &str) -> PostActivityPayload {
+        PostActivityPayload {
+            title: title.to_string(),
+            variant: ActivityVariant::Default,
+            group: "group".to_string(),
+            notes: None,
+            start: start.to_string(),
+            end: end.to_string(),
+            timezone: 0,
+            data: None,
+            color: None,
+            ical_uid: None,
+            occurrence: None,
+        }
+    }
+
+    fn filters(
+        title: Option<&str>,
+        start: Option<&str>,
+        end: Option<&str>,
+    ) -> GetActivitiesPayload {
+        GetActivitiesPayload {
+            timezone: None,
+            variant: None,
+            title: title.map(String::from),
+            group: None,
+            start: start.map(String::from),
+            end: end.map(String::from),
+            limit: None,
+            after: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn idempotency_lease() {
+        let db = MemoryDatabase::new();
+        let uid = ObjectId::new();
+        let lease_end = DateTime::now().timestamp_millis()
+            + crate::models::idempotency_model::IDEMPOTENCY_LEASE_SECS * 1000;
+
+        let record = IdempotencyRecord::new(uid, "key".to_string(), "request".to_string());
+        assert!(record.exp.timestamp_millis() <= lease_end + 1000);
+
+        // a reservation whose request never finished frees the key with its lease
+        let mut abandoned = record.clone();
+        abandoned.exp = DateTime::from_millis(DateTime::now().timestamp_millis() - 1);
+        assert!(db
+            .reserve_idempotency_key(abandoned)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(db
+            .reserve_idempotency_key(record.clone())
+            .await
+            .unwrap()
+            .is_none());
+
+        // a request still being handled keeps its key by renewing the lease
+        let mut slow = IdempotencyRecord::new(uid, "slow".to_string(), "request".to_string());
+        slow.exp = DateTime::from_millis(DateTime::now().timestamp_millis() + 1000);
+        assert!(db
+            .reserve_idempotency_key(slow.clone())
+            .await
+            .unwrap()
+            .is_none());
+        db.renew_idempotency_key(uid, "slow").await.unwrap();
+        let renewed = db.reserve_idempotency_key(slow).await.unwrap().unwrap();
+        assert!(renewed.exp.timestamp_millis() >= lease_end - 1000);
+
+        let response = StoredResponse {
+            status: 201,
+            etag: None,
+            body: "{}".to_string(),
+        };
+        db.complete_idempotency_key(uid, "key", response)
+            .await
+            .unwrap();
+        // and a completed one isn't cut back to a lease
+        db.renew_idempotency_key(uid, "key").await.unwrap();
+
+        let stored = db.reserve_idempotency_key(record).await.unwrap().unwrap();
+        assert!(stored.response.is_some());
+        assert!(stored.exp.timestamp_millis() > lease_end + 1000);
+    }
+
+    #[tokio::test]
+    async fn activity_create_and_get_one() {
+        let db = MemoryDatabase::new();
+
+        let created = db
+            .create_activity(
+                activity_payload(
+                    "create",
+                    "2000-01-01T09:00:00.000Z",
+                    "2000-01-01T09:30:00.000Z",
+                ),
+                USER_ID.to_string(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        let payload = GetActivityPayload {
+            id: created.id.to_hex(),
+        };
+        let activity = db.get_activity_by_id(payload, USER_ID.to_string()).await;
+        assert!(activity.unwrap().is_some());
+
+        // activities are scoped to their owner
+        let payload = GetActivityPayload {
+            id: created.id.to_hex(),
+        };
+        let activity = db
+            .get_activity_by_id(payload, "5f25a16b81fad94530820f39".to_string())
+            .await;
+        assert!(activity.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn activity_get_many() {
+        let db = MemoryDatabase::new();
+
+        // inserted out of order to check sorting on start
+        for day in ["03", "01", "05", "02", "04"] {
+            let start = format!("2000-01-{}T09:00:00.000Z", day);
+            let end = format!("2000-01-{}T09:30:00.000Z", day);
+            db.create_activity(
+                activity_payload("get many", &start, &end),
+                USER_ID.to_string(),
+            )
+            .await
+            .unwrap();
+        }
+        db.create_activity(
+            activity_payload(
+                "other",
+                "2000-01-01T10:00:00.000Z",
+                "2000-01-01T11:00:00.000Z",
+            ),
+            USER_ID.to_string(),
+        )
+        .await
+        .unwrap();
+
+        let activities = db
+            .get_activities(
+                filters(
+                    Some("get many"),
+                    Some("2000-01-01T00:00:00.000Z"),
+                    Some("2000-01-01T23:59:59.999Z"),
+                ),
+                USER_ID.to_string(),
+            )
+            .await
+            .unwrap();
+        assert!(activities.len() == 1);
+
+        let activities = db
+            .get_activities(
+                filters(
+                    Some("get many"),
+                    Some("2000-01-01T00:00:00.000Z"),
+                    Some("2000-01-03T23:59:59.999Z"),
+                ),
+                USER_ID.to_string(),
+            )
+            .await
+            .unwrap();
+        assert!(activities.len() == 3);
+
+        let activities = db
+            .get_activities(filters(None, None, None), USER_ID.to_string())
+            .await
+            .unwrap();
+        assert!(activities.len() == 6);
+        assert!(activities.windows(2).all(|w| w[0].start <= w[1].start));
+    }
+
+    #[tokio::test]
+    async fn activity_get_page() {
+        let db = MemoryDatabase::new();
+
+        // two activities share a start to check the `_id` tie break
+        for (day, hour) in [("02", "09"), ("01", "09"), ("01", "09"), ("03", "09")] {
+            let start = format!("2000-01-{}T{}:00:00.000Z", day, hour);
+            let end = format!("2000-01-{}T{}:30:00.000Z", day, hour);
+            db.create_activity(activity_payload("page", &start, &end), USER_ID.to_string())
+                .await
+                .unwrap();
+        }
+
+        let mut seen = vec![];
+        let mut after = None;
+        loop {
+            let mut payload = filters(None, None, None);
+            payload.limit = Some(3);
+            payload.after = after;
+
+            let page = db
+                .get_activities(payload, USER_ID.to_string())
+                .await
+                .unwrap();
+            if page.is_empty() {
+                break;
+            }
+            assert!(page.len() <= 3);
+
+            after = page.last().map(|a| ActivityCursor::from(a).encode());
+            seen.extend(page);
+        }
+
+        assert!(seen.len() == 4);
+        assert!(seen
+            .windows(2)
+            .all(|w| (w[0].start, w[0].id) < (w[1].start, w[1].id)));
+
+        let mut payload = filters(None, None, None);
+        payload.after = Some("not a cursor".to_string());
+        assert!(db
+            .get_activities(payload, USER_ID.to_string())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn user_update_generated_password() {
+        let db = MemoryDatabase::new();
+        let user = db
+            .create_user(RegisterUserPayload {
+                email: "oauth@test.com".to_string(),
+                pass: "generated".to_string(),
+                given_name: "test".to_string(),
+                family_name: "testerton".to_string(),
+                generated_pass: true,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!user.password_set);
+
+        db.update_user_password(user.id, "hash".to_string())
+            .await
+            .unwrap();
+
+        let user = db.get_user_by_id(user.id).await.unwrap().unwrap();
+        assert!(user.password_set);
+        assert_eq!(user.pass, "hash");
+    }
+
+    #[tokio::test]
+    async fn activity_update_one() {
+        let db = MemoryDatabase::new();
+        let user = db
+            .create_user(RegisterUserPayload {
+                email: "test@test.com".to_string(),
+                pass: "TEST".to_string(),
+                given_name: "test".to_string(),
+                family_name: "testerton".to_string(),
+                generated_pass: false,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        let created = db
+            .create_activity(
+                activity_payload(
+                    "test",
+                    "2000-01-01T09:00:00.000Z",
+                    "2000-01-01T09:30:00.000Z",
+                ),
+                user.id.to_hex(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        let update_payload = PatchActivityPayload {
+            id: created.id.to_hex(),
+            v: None,
+            title: Some("test update".to_string()),
+            variant: Some(ActivityVariant::Exercise),
+            group: Some("update group".to_string()),
+            notes: Some("update 2".to_string()),
+            start: None,
+            end: Some("2000-01-01T10:30:00.000Z".to_string()),
+            timezone: Some(0),
+            color: Some("#ffffff".to_string()),
+            data: Some(ActivityData {
+                exercise: Some(vec![
+                    Exercise::Strength(StrengthExercise {
+                        title: "pressups".to_string(),
+                        sets: vec![Set {
+                            idx: 0,
+                            reps: Some(30),
+                            rest: Some(60),
+                            weight: None,
+                            duration: None,
+                        }],
+                    }),
+                    Exercise::Cardio(CardioExercise {
+                        title: "running".to_string(),
+                        duration: 30,
+                        distance: 5000,
+                        splits: None,
+                    }),
+                ]),
+            }),
+        };
+
+        let activity = match db
+            .update_activity_by_id(update_payload, user.id.to_hex())
+            .await
+            .unwrap()
+        {
+            VersionedWrite::Done(v) => v,
+            _ => panic!("failed to update activity"),
+        };
+
+        assert!(activity.title == "test update");
+        assert!(activity.variant == ActivityVariant::Exercise);
+        assert!(activity.group == "update group");
+        assert!(activity.notes == "update 2");
+        assert!(activity.start == DateTime::parse_rfc3339_str("2000-01-01T09:00:00.000Z").unwrap());
+        assert!(activity.end == DateTime::parse_rfc3339_str("2000-01-01T10:30:00.000Z").ok());
+        assert!(activity.data.unwrap().exercise.unwrap().len() == 2);
+        assert!(activity.v == 2);
+
+        let user = db
+            .get_user_by_email("test@test.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(user.activities.get("test update") == Some(&"#ffffff".to_string()));
+    }
+
+    #[tokio::test]
+    async fn activity_delete_one() {
+        let db = MemoryDatabase::new();
+        let created = db
+            .create_activity(
+                activity_payload(
+                    "delete",
+                    "2000-01-01T09:00:00.000Z",
+                    "2000-01-01T09:30:00.000Z",
+                ),
+                USER_ID.to_string(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        let payload = DeleteActivityPayload {
+            id: created.id.to_hex(),
+            v: None,
+        };
+        let deleted = db.delete_activity_by_id(payload, USER_ID.to_string()).await;
+        assert!(matches!(deleted.unwrap(), VersionedWrite::Done(d) if d.id == created.id));
+
+        let payload = GetActivityPayload {
+            id: created.id.to_hex(),
+        };
+        let activity = db.get_activity_by_id(payload, USER_ID.to_string()).await;
+        assert!(activity.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn activity_write_version() {
+        let db = MemoryDatabase::new();
+        let created = db
+            .create_activity(
+                activity_payload(
+                    "version",
+                    "2000-01-01T09:00:00.000Z",
+                    "2000-01-01T09:30:00.000Z",
+                ),
+                USER_ID.to_string(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        let patch = |v| PatchActivityPayload {
+            id: created.id.to_hex(),
+            v,
+            title: Some("renamed".to_string()),
+            variant: None,
+            group: None,
+            notes: None,
+            start: None,
+            end: None,
+            timezone: None,
+            data: None,
+            color: None,
+        };
+
+        let res = db.update_activity_by_id(patch(Some(1)), USER_ID.to_string());
+        assert!(matches!(res.await.unwrap(), VersionedWrite::Done(a) if a.v == 2));
+
+        // a stale version leaves the document untouched
+        let res = db.update_activity_by_id(patch(Some(1)), USER_ID.to_string());
+        assert!(matches!(res.await.unwrap(), VersionedWrite::Conflict(a) if a.v == 2));
+
+        let payload = DeleteActivityPayload {
+            id: created.id.to_hex(),
+            v: Some(1),
+        };
+        let res = db.delete_activity_by_id(payload, USER_ID.to_string());
+        assert!(matches!(res.await.unwrap(), VersionedWrite::Conflict(_)));
+
+        let payload = DeleteActivityPayload {
+            id: created.id.to_hex(),
+            v: Some(2),
+        };
+        let res = db.delete_activity_by_id(payload, USER_ID.to_string());
+        assert!(matches!(res.await.unwrap(), VersionedWrite::Done(_)));
+
+        let res = db.update_activity_by_id(patch(None), USER_ID.to_string());
+        assert!(matches!(res.await.unwrap(), VersionedWrite::NotFound));
+    }
+
+    #[tokio::test]
+    async fn stop_timer_bounds() {
+        let db = MemoryDatabase::new();
+        let start = DateTime::parse_rfc3339_str("2999-01-01T09:00:00Z").unwrap();
+        let timer = Activity::new(
+            ActivityVariant::Default,
+            "reading".to_string(),
+            "leisure".to_string(),
+            String::new(),
+            start,
+            None,
+            0,
+            None,
+            parse_object_id(USER_ID).unwrap(),
+        );
+        assert!(db.start_timer(timer).await.unwrap());
+
+        // neither an explicit end nor now may come before the start
+        let early = DateTime::parse_rfc3339_str("2999-01-01T08:00:00Z").unwrap();
+        let res = db.stop_timer(USER_ID.to_string(), early).await.unwrap();
+        assert!(matches!(res, VersionedWrite::OutOfRange("end")));
+        let res = db.stop_timer(USER_ID.to_string(), DateTime::now());
+        assert!(matches!(
+            res.await.unwrap(),
+            VersionedWrite::OutOfRange("end")
+        ));
+
+        let timer = db.get_timer(USER_ID.to_string()).await.unwrap().unwrap();
+        assert_eq!(timer.end, None);
+        assert_eq!(timer.v, 1);
+
+        let res = db.stop_timer(USER_ID.to_string(), start).await.unwrap();
+        let VersionedWrite::Done(stopped) = res else {
+            panic!("timer not stopped");
+        };
+        assert_eq!(stopped.end, Some(start));
+
+        let res = db.stop_timer(USER_ID.to_string(), start).await.unwrap();
+        assert!(matches!(res, VersionedWrite::NotFound));
+    }
+
+    #[tokio::test]
+    async fn series_write_version() {
+        let db = MemoryDatabase::new();
+        let payload = PostSeriesPayload {
+            title: "standup".to_string(),
+            variant: ActivityVariant::Default,
+            group: "work".to_string(),
+            notes: None,
+            start: "2000-01-03T09:00:00.000Z".to_string(),
+            duration: 900,
+            timezone: 0,
+            rrule: "FREQ=DAILY".to_string(),
+            data: None,
+        };
+        let start = parse_date(&payload.start).unwrap();
+        let series = Series::new(payload, start, parse_object_id(USER_ID).unwrap());
+        assert!(db.create_series(series.clone()).await.unwrap());
+
+        // an exception is added once, and bumps the version
+        let at = DateTime::parse_rfc3339_str("2000-01-04T09:00:00Z").unwrap();
+        let res = db.add_series_exception(series.id, USER_ID.to_string(), at);
+        assert!(res.await.unwrap());
+        let res = db.add_series_exception(series.id, USER_ID.to_string(), at);
+        assert!(!res.await.unwrap());
+
+        // so a replace from before it doesn't drop it
+        let mut stale = series.clone();
+        stale.title = "stale".to_string();
+        assert!(!db.replace_series(stale).await.unwrap());
+
+        let mut current = db
+            .get_series_by_id(series.id, USER_ID.to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(current.exceptions, vec![at]);
+        current.title = "current".to_string();
+        assert!(db.replace_series(current).await.unwrap());
+
+        let stored = db.get_series_by_id(series.id, USER_ID.to_string());
+        let stored = stored.await.unwrap().unwrap();
+        assert_eq!(stored.title, "current");
+        assert_eq!(stored.v, 3);
+    }
+
+    #[tokio::test]
+    async fn token_blacklist() {
+        let db = MemoryDatabase::new();
+        let uid = "5f25a16b81fad94530820f39".to_string();
+
+        for jti in ["jti0", "jti1", "jti2"] {
+            let res = db
+                .create_token(TokenDB::new(uid.clone(), jti.to_string(), 1234))
+                .await;
+            assert!(res.unwrap().is_some());
+        }
+
+        db.blacklist_user_token("jti0").await.unwrap();
+        assert!(db.get_token("jti0").await.unwrap().unwrap().black);
+        assert!(!db.get_token("jti1").await.unwrap().unwrap().black);
+
+        db.blacklist_user_tokens(ObjectId::parse_str(&uid).unwrap())
+            .await
+            .unwrap();
+        for jti in ["jti1", "jti2"] {
+            let token = db.get_token(jti).await.unwrap();
+            assert!(token.unwrap().black);
+        }
+    }
+}
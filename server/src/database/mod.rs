@@ -1 +1,3 @@
-pub mod mongodb_database;
\ No newline at end of file
+pub mod memory_database;
+pub mod mongodb_database;
+pub mod storage;
@@ -1,27 +1,117 @@
-use futures::TryStreamExt;
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use axum::async_trait;
+use futures::{stream::BoxStream, StreamExt, TryStreamExt};
 use mongodb::{
-    bson::{doc, oid::ObjectId, Bson, Document},
-    error::Error,
-    Client, Collection,
+    bson::{doc, from_document, oid::ObjectId, Bson, DateTime, Document},
+    error::{Error, ErrorKind, IndexedWriteError, InsertManyError, WriteError, WriteFailure},
+    options::{IndexOptions, ReturnDocument},
+    Client, ClientSession, Collection, IndexModel,
 };
+use serde::Deserialize;
 
+use super::storage::Storage;
 use crate::{
+    error::error::DUPLICATE_KEY,
     models::{
         activity_model::{
-            Activity, ActivityDelete, DeleteActivityPayload, GetActivitiesPayload,
-            GetActivityPayload, PatchActivityPayload, PostActivityPayload,
+            Activity, ActivityCursor, ActivityDelete, ActivityWrite, ActivityWriteResult,
+            DeleteActivityPayload, GetActivitiesPayload, GetActivityPayload, PatchActivityPayload,
+            PostActivityPayload, Set, VersionedWrite,
         },
-        auth_model::TokenDB,
-        user_model::{RegisterUserPayload, User},
+        auth_model::{ResetTokenDB, TokenDB},
+        idempotency_model::{IdempotencyRecord, StoredResponse},
+        progress_model::{ExerciseProgress, ExerciseProgressPayload, ExerciseSummary},
+        record_model::PersonalRecord,
+        series_model::{Series, MAX_SERIES},
+        stats_model::{ActivityStats, StatsBucket, StatsGroupBy, StatsPayload},
+        template_model::{PatchTemplatePayload, PostTemplatePayload, Template},
+        user_model::{AccountErasure, PatchUserPayload, RegisterUserPayload, User},
     },
-    utils::utils::insert_optional,
+    utils::utils::{insert_optional, parse_date, parse_object_id},
 };
 
+/// A `$group` result of the stats pipeline.
+#[derive(Debug, Deserialize)]
+struct StatsGroup {
+    #[serde(rename = "_id")]
+    key: Option<String>,
+    total: i64,
+    count: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct StatsFacets {
+    summary: Vec<StatsGroup>,
+    buckets: Vec<StatsGroup>,
+}
+
+/// Stages splitting each clipped activity into the time buckets it covers, in
+/// local time, and summing the overlap per bucket.
+fn time_bucket_stages(group_by: StatsGroupBy) -> Vec<Document> {
+    let (unit, format) = match group_by {
+        StatsGroupBy::Week => ("week", "%G-W%V"),
+        StatsGroupBy::Month => ("month", "%Y-%m"),
+        _ => ("day", "%Y-%m-%d"),
+    };
+
+    vec![
+        doc! { "$set": {
+            "from": { "$add": ["$from", "$offset"] },
+            "to": { "$add": ["$to", "$offset"] },
+        } },
+        doc! { "$set": {
+            "first": { "$dateTrunc": { "date": "$from", "unit": unit, "startOfWeek": "monday" } },
+        } },
+        doc! { "$set": {
+            "idx": { "$range": [0, { "$add": [
+                { "$dateDiff": {
+                    "startDate": "$first",
+                    "endDate": "$to",
+                    "unit": unit,
+                    "startOfWeek": "monday",
+                } },
+                1,
+            ] }] },
+        } },
+        doc! { "$unwind": "$idx" },
+        doc! { "$set": {
+            "bucket": { "$dateAdd": { "startDate": "$first", "unit": unit, "amount": "$idx" } },
+        } },
+        doc! { "$set": {
+            "overlap": { "$subtract": [
+                { "$min": ["$to", { "$dateAdd": { "startDate": "$bucket", "unit": unit, "amount": 1 } }] },
+                { "$max": ["$from", "$bucket"] },
+            ] },
+        } },
+        // an activity ending on a boundary doesn't touch the next bucket, but
+        // one without length still counts in its own
+        doc! { "$match": { "$or": [{ "overlap": { "$gt": 0 } }, { "idx": 0 }] } },
+        doc! { "$group": {
+            "_id": { "$dateToString": { "date": "$bucket", "format": format } },
+            "total": { "$sum": { "$max": ["$overlap", 0] } },
+            "count": { "$sum": 1 },
+        } },
+    ]
+}
+
 #[derive(Clone, Debug)]
 pub struct MongoDatabase {
+    client: Client,
+    /// Whether the deployment supports transactions, which needs a replica set
+    /// or a sharded cluster.
+    transactions: bool,
     activities: Collection<Activity>,
     users: Collection<User>,
     tokens: Collection<TokenDB>,
+    reset_tokens: Collection<ResetTokenDB>,
+    idempotency: Collection<IdempotencyRecord>,
+    records: Collection<PersonalRecord>,
+    templates: Collection<Template>,
+    series: Collection<Series>,
 }
 
 impl MongoDatabase {
@@ -32,27 +122,455 @@ impl MongoDatabase {
         let activities: Collection<Activity> = db.collection("activities");
         let users: Collection<User> = db.collection("users");
         let tokens: Collection<TokenDB> = db.collection("tokens");
+        let reset_tokens: Collection<ResetTokenDB> = db.collection("reset_tokens");
+        let idempotency: Collection<IdempotencyRecord> = db.collection("idempotency_keys");
+        let records: Collection<PersonalRecord> = db.collection("records");
+        let templates: Collection<Template> = db.collection("templates");
+        let series: Collection<Series> = db.collection("series");
+
+        let hello = db.run_command(doc! { "hello": 1 }).await?;
+        let transactions = hello.contains_key("setName") || hello.get_str("msg") == Ok("isdbgrid");
+
+        // a key is unique per user, and expires after the retention window
+        idempotency
+            .create_indexes([
+                IndexModel::builder()
+                    .keys(doc! { "user": 1, "key": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+                IndexModel::builder()
+                    .keys(doc! { "exp": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .expire_after(Duration::from_secs(0))
+                            .build(),
+                    )
+                    .build(),
+            ])
+            .await?;
+
+        // used reset tokens are kept until they would have expired anyway
+        reset_tokens
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "exp": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .expire_after(Duration::from_secs(0))
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+
+        // an event is imported at most once per user
+        activities
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "user": 1, "icalUid": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .unique(true)
+                            .partial_filter_expression(doc! { "icalUid": { "$exists": true } })
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+
+        // a user has at most one live running timer, named so it can't clash
+        // with another index on `user`
+        activities
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "user": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .name("one_running_timer".to_string())
+                            .unique(true)
+                            .partial_filter_expression(doc! {
+                                "end": { "$type": "null" },
+                                "deletedAt": { "$type": "null" },
+                            })
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+
+        // feeds are looked up by token, most users won't have one
+        users
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "calendarToken": 1 })
+                    .options(IndexOptions::builder().unique(true).sparse(true).build())
+                    .build(),
+            )
+            .await?;
+
+        records
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "user": 1, "title": 1 })
+                    .build(),
+            )
+            .await?;
+
+        templates
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "user": 1, "name": 1 })
+                    .build(),
+            )
+            .await?;
+
+        series
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "user": 1, "start": 1 })
+                    .build(),
+            )
+            .await?;
 
         Ok::<Self, Error>(Self {
+            client,
+            transactions,
             activities,
             users,
             tokens,
+            reset_tokens,
+            idempotency,
+            records,
+            templates,
+            series,
         })
     }
 
+    // applies the writes in order, a run of creates goes out as one
+    // insert_many, and the colors of what was written are collected in `colors`
+    async fn apply_writes(
+        &self,
+        writes: Vec<ActivityWrite>,
+        uid: ObjectId,
+        colors: &mut Document,
+        mut session: Option<&mut ClientSession>,
+    ) -> Vec<Result<ActivityWriteResult, Error>> {
+        let mut results = Vec::with_capacity(writes.len());
+        let mut creates = vec![];
+
+        for write in writes {
+            // flush pending creates first so the batch stays ordered
+            if !matches!(write, ActivityWrite::Create(_)) && !creates.is_empty() {
+                let pending = std::mem::take(&mut creates);
+                let inserted = self
+                    .insert_activities(pending, uid, colors, session.as_deref_mut())
+                    .await;
+                results.extend(inserted);
+            }
+
+            let res = match write {
+                ActivityWrite::Create(payload) => {
+                    creates.push(payload);
+                    continue;
+                }
+                ActivityWrite::Update(payload) => {
+                    let color = update_color(&payload);
+                    let res = self
+                        .update_activity_doc(payload, uid, session.as_deref_mut())
+                        .await;
+                    if let Ok(VersionedWrite::Done(_)) = res {
+                        colors.extend(color);
+                    }
+                    res.map(ActivityWriteResult::Updated)
+                }
+                ActivityWrite::Delete(payload) => self
+                    .delete_activity_doc(payload, uid, session.as_deref_mut())
+                    .await
+                    .map(ActivityWriteResult::Deleted),
+            };
+            results.push(res);
+        }
+
+        if !creates.is_empty() {
+            results.extend(self.insert_activities(creates, uid, colors, session).await);
+        }
+
+        results
+    }
+
+    // applies the writes in one transaction. A failed write aborts all of them,
+    // so they are then applied again one by one to report each failure in place
+    async fn apply_writes_in_transaction(
+        &self,
+        writes: Vec<ActivityWrite>,
+        uid: ObjectId,
+        colors: &mut Document,
+    ) -> Result<Vec<Result<ActivityWriteResult, Error>>, Error> {
+        let mut session = self.client.start_session().await?;
+        session.start_transaction().await?;
+
+        let mut written_colors = Document::new();
+        let results = self
+            .apply_writes(writes.clone(), uid, &mut written_colors, Some(&mut session))
+            .await;
+
+        if results.iter().all(Result::is_ok) {
+            // a failed commit may still have been applied, so it isn't retried
+            session.commit_transaction().await?;
+            colors.extend(written_colors);
+            return Ok(results);
+        }
+
+        session.abort_transaction().await?;
+        Ok(self.apply_writes(writes, uid, colors, None).await)
+    }
+
+    // inserts a run of creates with one unordered insert_many, so a failed
+    // insert doesn't hold back the ones after it
+    async fn insert_activities(
+        &self,
+        payloads: Vec<PostActivityPayload>,
+        uid: ObjectId,
+        colors: &mut Document,
+        session: Option<&mut ClientSession>,
+    ) -> Vec<Result<ActivityWriteResult, Error>> {
+        let mut activities = vec![];
+        let mut activity_colors = vec![];
+        let mut results: Vec<Option<Result<ActivityWriteResult, Error>>> = vec![];
+
+        for payload in payloads {
+            let dates = parse_date(&payload.start).and_then(|s| Ok((s, parse_date(&payload.end)?)));
+            let (start, end) = match dates {
+                Ok(v) => v,
+                Err(e) => {
+                    results.push(Some(Err(e)));
+                    continue;
+                }
+            };
+
+            activity_colors.push(
+                payload
+                    .color
+                    .map(|color| (format!("activities.{}", payload.title), Bson::String(color))),
+            );
+
+            let mut activity = Activity::new(
+                payload.variant,
+                payload.title,
+                payload.group,
+                payload.notes.unwrap_or_default(),
+                start,
+                Some(end),
+                payload.timezone,
+                payload.data,
+                uid,
+            );
+            activity.ical_uid = payload.ical_uid;
+            activity.occurrence = payload.occurrence;
+            activities.push(activity);
+            results.push(None);
+        }
+
+        let inserted = if activities.is_empty() {
+            Ok(())
+        } else {
+            let insert = self.activities.insert_many(&activities).ordered(false);
+            match session {
+                Some(session) => insert.session(session).await,
+                None => insert.await,
+            }
+            .map(|_| ())
+        };
+
+        // write errors point at the failed inserts, any other error leaves
+        // every insert in doubt
+        let failure = |i: usize| match &inserted {
+            Ok(_) => None,
+            Err(e) => match e.kind.as_ref() {
+                ErrorKind::InsertMany(InsertManyError {
+                    write_errors: Some(errors),
+                    write_concern_error: None,
+                    ..
+                }) => errors.iter().find(|w| w.index == i).map(write_error),
+                _ => Some(e.clone()),
+            },
+        };
+
+        let mut activities = activities.into_iter().zip(activity_colors).enumerate();
+        let mut out = Vec::with_capacity(results.len());
+        for res in results {
+            let res = match res {
+                Some(res) => res,
+                None => {
+                    let (i, (activity, color)) =
+                        activities.next().expect("one activity per pending result");
+                    match failure(i) {
+                        Some(e) => Err(e),
+                        None => {
+                            colors.extend(color);
+                            Ok(ActivityWriteResult::Created(activity))
+                        }
+                    }
+                }
+            };
+            out.push(res);
+        }
+
+        out
+    }
+
+    async fn update_activity_doc(
+        &self,
+        payload: PatchActivityPayload,
+        uid: ObjectId,
+        mut session: Option<&mut ClientSession>,
+    ) -> Result<VersionedWrite<Activity>, Error> {
+        let id = parse_object_id(&payload.id)?;
+        let start = payload.start.as_deref().map(parse_date).transpose()?;
+        let end = payload.end.as_deref().map(parse_date).transpose()?;
+
+        let mut filter = doc! {
+            "_id": id,
+            "user": uid,
+            "deletedAt": null,
+        };
+        if let Some(v) = payload.v {
+            filter.insert("__v", v);
+        }
+
+        // one bound alone has to fit the stored other one, a running timer
+        // has no end to check against
+        match (start, end) {
+            (Some(start), None) => filter.insert("end", doc! { "$not": { "$lt": start } }),
+            (None, Some(end)) => filter.insert("start", doc! { "$lte": end }),
+            _ => None,
+        };
+
+        let mut update_doc = Document::new();
+
+        insert_optional(&mut update_doc, "start", start);
+        insert_optional(&mut update_doc, "end", end);
+        insert_optional(&mut update_doc, "variant", payload.variant);
+        insert_optional(&mut update_doc, "title", payload.title);
+        insert_optional(&mut update_doc, "group", payload.group);
+        insert_optional(&mut update_doc, "notes", payload.notes);
+        insert_optional(&mut update_doc, "timezone", payload.timezone);
+
+        if let Some(data) = payload.data {
+            if let Some(exercise) = data.exercise {
+                insert_optional(&mut update_doc, "data.exercise", Some(exercise));
+            }
+        }
+
+        // every write bumps the version, even when no fields change
+        update_doc.insert("updatedAt", DateTime::now());
+        let update = doc! {
+            "$set": update_doc,
+            "$inc": { "__v": 1 },
+        };
+
+        let update = self
+            .activities
+            .find_one_and_update(filter, update)
+            .return_document(ReturnDocument::After);
+        let updated = match session.as_deref_mut() {
+            Some(session) => update.session(session).await?,
+            None => update.await?,
+        };
+
+        if let Some(activity) = updated {
+            return Ok(VersionedWrite::Done(activity));
+        }
+
+        let current = match self.get_activity_doc(id, uid, session).await? {
+            Some(v) => v,
+            None => return Ok(VersionedWrite::NotFound),
+        };
+
+        // a stale edit is a conflict, whether or not its bounds still fit
+        if payload.v.is_some_and(|v| v != current.v) {
+            return Ok(VersionedWrite::Conflict(Box::new(current)));
+        }
+
+        match current.out_of_range(start, end) {
+            Some(field) => Ok(VersionedWrite::OutOfRange(field)),
+            None => Ok(VersionedWrite::Conflict(Box::new(current))),
+        }
+    }
+
+    async fn delete_activity_doc(
+        &self,
+        payload: DeleteActivityPayload,
+        uid: ObjectId,
+        mut session: Option<&mut ClientSession>,
+    ) -> Result<VersionedWrite<ActivityDelete>, Error> {
+        let id = parse_object_id(&payload.id)?;
+
+        let mut filter = doc! {
+            "_id": id,
+            "user": uid,
+            "deletedAt": null,
+        };
+        if let Some(v) = payload.v {
+            filter.insert("__v", v);
+        }
+
+        // keep a tombstone so sync can report the delete, a running timer is
+        // stopped so it doesn't count towards the one a user can have
+        let now = DateTime::now();
+        let update = vec![doc! { "$set": {
+            "deletedAt": now,
+            "updatedAt": now,
+            "end": { "$ifNull": ["$end", now] },
+            "__v": { "$add": ["$__v", 1] },
+        } }];
+
+        let delete = self.activities.find_one_and_update(filter, update);
+        let deleted = match session.as_deref_mut() {
+            Some(session) => delete.session(session).await?,
+            None => delete.await?,
+        };
+
+        if deleted.is_some() {
+            return Ok(VersionedWrite::Done(ActivityDelete { id }));
+        }
+
+        // either the document is gone or its version moved on
+        match self.get_activity_doc(id, uid, session).await? {
+            Some(current) => Ok(VersionedWrite::Conflict(Box::new(current))),
+            None => Ok(VersionedWrite::NotFound),
+        }
+    }
+
     async fn get_activity_doc(
         &self,
         id: ObjectId,
-        user_id: String,
+        uid: ObjectId,
+        session: Option<&mut ClientSession>,
     ) -> Result<Option<Activity>, Error> {
         let filter = doc! {
             "_id": id,
-            "user": ObjectId::parse_str(user_id).expect("failed to parse string to ObjectId")
+            "user": uid,
+            "deletedAt": null,
         };
 
-        let res = self.activities.find_one(filter).await?;
+        let find = self.activities.find_one(filter);
+        match session {
+            Some(session) => find.session(session).await,
+            None => find.await,
+        }
+    }
 
-        Ok(res)
+    // sets the colors of activity titles, losing them doesn't fail the write
+    async fn set_colors(&self, uid: ObjectId, colors: Document) {
+        if colors.is_empty() {
+            return;
+        }
+
+        let update = doc! { "$set": colors };
+        if let Err(e) = self.users.update_one(doc! { "_id": uid }, update).await {
+            tracing::error!("failed to update activity colors: {}", e);
+        }
     }
 
     async fn get_user_doc(&self, id: ObjectId) -> Result<Option<User>, Error> {
@@ -64,8 +582,11 @@ impl MongoDatabase {
 
         Ok(res)
     }
+}
 
-    pub async fn create_user(&self, payload: RegisterUserPayload) -> Result<Option<User>, Error> {
+#[async_trait]
+impl Storage for MongoDatabase {
+    async fn create_user(&self, payload: RegisterUserPayload) -> Result<Option<User>, Error> {
         let new_user = User::from(payload);
 
         let new_id = match self.users.insert_one(new_user).await {
@@ -79,7 +600,7 @@ impl MongoDatabase {
         self.get_user_doc(new_id).await
     }
 
-    pub async fn get_user_by_email(&self, email: &str) -> Result<Option<User>, Error> {
+    async fn get_user_by_email(&self, email: &str) -> Result<Option<User>, Error> {
         let filter = doc! {
             "email": email
         };
@@ -88,17 +609,155 @@ impl MongoDatabase {
         Ok(res)
     }
 
-    pub async fn create_token(&self, token: TokenDB) -> Result<Option<ObjectId>, Error> {
+    async fn get_user_by_id(&self, uid: ObjectId) -> Result<Option<User>, Error> {
+        self.get_user_doc(uid).await
+    }
+
+    async fn update_user_password(&self, uid: ObjectId, pass: String) -> Result<(), Error> {
+        let filter = doc! {
+            "_id": uid,
+        };
+
+        let update = doc! { "$set": { "pass": pass, "passwordSet": true } };
+        self.users.update_one(filter, update).await?;
+
+        Ok(())
+    }
+
+    async fn update_user_profile(
+        &self,
+        uid: ObjectId,
+        payload: PatchUserPayload,
+    ) -> Result<Option<User>, Error> {
+        let filter = doc! {
+            "_id": uid,
+        };
+
+        let mut set = Document::new();
+        insert_optional(&mut set, "givenName", payload.given_name);
+        insert_optional(&mut set, "familyName", payload.family_name);
+        insert_optional(&mut set, "img", payload.img);
+
+        let mut update = doc! { "$inc": { "__v": 1 } };
+        if !set.is_empty() {
+            update.insert("$set", set);
+        }
+
+        self.users
+            .find_one_and_update(filter, update)
+            .return_document(ReturnDocument::After)
+            .await
+    }
+
+    async fn deactivate_user(&self, uid: ObjectId, purge_at: DateTime) -> Result<(), Error> {
+        let filter = doc! {
+            "_id": uid,
+        };
+
+        let update = doc! { "$set": { "active": false, "purgeAt": purge_at } };
+        self.users.update_one(filter, update).await?;
+
+        Ok(())
+    }
+
+    async fn get_users_to_purge(&self, now: DateTime) -> Result<Vec<ObjectId>, Error> {
+        let filter = doc! {
+            "active": false,
+            "purgeAt": { "$lte": now },
+        };
+
+        let users: Vec<User> = self.users.find(filter).await?.try_collect().await?;
+
+        Ok(users.into_iter().map(|u| u.id).collect())
+    }
+
+    async fn erase_user(&self, uid: ObjectId) -> Result<AccountErasure, Error> {
+        // no transaction, standalone servers don't support them. the user goes
+        // last so whatever is left after a failure can still be found and erased
+        let activities = self.activities.delete_many(doc! { "user": uid }).await?;
+        let tokens = self.tokens.delete_many(doc! { "uid": uid }).await?;
+        self.reset_tokens.delete_many(doc! { "uid": uid }).await?;
+        self.idempotency.delete_many(doc! { "user": uid }).await?;
+        self.records.delete_many(doc! { "user": uid }).await?;
+        self.templates.delete_many(doc! { "user": uid }).await?;
+        self.series.delete_many(doc! { "user": uid }).await?;
+        self.users.delete_one(doc! { "_id": uid }).await?;
+
+        Ok(AccountErasure {
+            activities: activities.deleted_count,
+            tokens: tokens.deleted_count,
+        })
+    }
+
+    async fn set_activity_colors(
+        &self,
+        uid: ObjectId,
+        colors: HashMap<String, String>,
+    ) -> Result<(), Error> {
+        if colors.is_empty() {
+            return Ok(());
+        }
+
+        let filter = doc! {
+            "_id": uid,
+        };
+
+        let mut set = Document::new();
+        for (title, color) in colors {
+            set.insert(format!("activities.{}", title), color);
+        }
+
+        self.users.update_one(filter, doc! { "$set": set }).await?;
+
+        Ok(())
+    }
+
+    async fn set_user_verified(&self, uid: ObjectId, email: &str) -> Result<bool, Error> {
+        let filter = doc! {
+            "_id": uid,
+            "email": email,
+        };
+
+        let update = doc! { "$set": { "verified": true } };
+        let res = self.users.update_one(filter, update).await?;
+
+        Ok(res.matched_count == 1)
+    }
+
+    async fn set_calendar_token(&self, uid: ObjectId, hash: Option<String>) -> Result<bool, Error> {
+        let filter = doc! {
+            "_id": uid,
+        };
+
+        let update = match hash {
+            Some(hash) => doc! { "$set": { "calendarToken": hash } },
+            None => doc! { "$unset": { "calendarToken": "" } },
+        };
+        let res = self.users.update_one(filter, update).await?;
+
+        Ok(res.matched_count == 1)
+    }
+
+    async fn get_user_by_calendar_token(&self, hash: &str) -> Result<Option<User>, Error> {
+        let filter = doc! {
+            "calendarToken": hash
+        };
+
+        let res = self.users.find_one(filter).await?;
+        Ok(res)
+    }
+
+    async fn create_token(&self, token: TokenDB) -> Result<Option<ObjectId>, Error> {
         match self.tokens.insert_one(token).await {
             Ok(res) => match res.inserted_id {
                 Bson::ObjectId(oid) => Ok(Some(oid)),
                 _ => panic!("failed to retrieve objectid"),
             },
-            Err(e) => return Err(e),
+            Err(e) => Err(e),
         }
     }
 
-    pub async fn get_token(&self, jti: &String) -> Result<Option<TokenDB>, Error> {
+    async fn get_token(&self, jti: &str) -> Result<Option<TokenDB>, Error> {
         let filter = doc! {
             "jti": jti
         };
@@ -108,7 +767,7 @@ impl MongoDatabase {
         Ok(res)
     }
 
-    pub async fn blacklist_user_token(&self, jti: &String) -> Result<(), Error> {
+    async fn blacklist_user_token(&self, jti: &str) -> Result<(), Error> {
         let filter = doc! {
             "jti": jti,
         };
@@ -116,200 +775,977 @@ impl MongoDatabase {
         let mut update_doc = Document::new();
         update_doc.insert("black", true);
 
-        let update = doc! { "$set": update_doc };
-        self.tokens.update_one(filter, update).await?;
+        let update = doc! { "$set": update_doc };
+        self.tokens.update_one(filter, update).await?;
+
+        Ok(())
+    }
+
+    async fn blacklist_user_tokens(&self, uid: ObjectId) -> Result<(), Error> {
+        let filter = doc! {
+            "uid": uid,
+        };
+
+        let mut update_doc = Document::new();
+        update_doc.insert("black", true);
+
+        let update = doc! { "$set": update_doc };
+        self.tokens.update_many(filter, update).await?;
+
+        Ok(())
+    }
+
+    async fn create_reset_token(&self, token: ResetTokenDB) -> Result<(), Error> {
+        self.reset_tokens.insert_one(token).await?;
+
+        Ok(())
+    }
+
+    async fn consume_reset_token(&self, hash: &str) -> Result<Option<ResetTokenDB>, Error> {
+        let filter = doc! {
+            "hash": hash,
+            "used": false,
+            "exp": { "$gt": DateTime::now() },
+        };
+
+        let update = doc! { "$set": { "used": true } };
+
+        self.reset_tokens.find_one_and_update(filter, update).await
+    }
+
+    async fn reserve_idempotency_key(
+        &self,
+        record: IdempotencyRecord,
+    ) -> Result<Option<IdempotencyRecord>, Error> {
+        let filter = doc! {
+            "user": record.user,
+            "key": &record.key,
+        };
+
+        // the ttl monitor only runs once a minute, so clear an expired record here
+        let mut expired = filter.clone();
+        expired.insert("exp", doc! { "$lte": mongodb::bson::DateTime::now() });
+        self.idempotency.delete_one(expired).await?;
+
+        match self.idempotency.insert_one(record).await {
+            Ok(_) => Ok(None),
+            Err(e) if is_duplicate_key(&e) => self.idempotency.find_one(filter).await,
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn complete_idempotency_key(
+        &self,
+        uid: ObjectId,
+        key: &str,
+        response: StoredResponse,
+    ) -> Result<(), Error> {
+        let filter = doc! {
+            "user": uid,
+            "key": key,
+        };
+
+        let update = doc! { "$set": {
+            "response": mongodb::bson::to_bson(&response)?,
+            "exp": IdempotencyRecord::retained_until(),
+        } };
+        self.idempotency.update_one(filter, update).await?;
+
+        Ok(())
+    }
+
+    async fn renew_idempotency_key(&self, uid: ObjectId, key: &str) -> Result<(), Error> {
+        let filter = doc! {
+            "user": uid,
+            "key": key,
+            "response": null,
+        };
+
+        let update = doc! { "$set": { "exp": IdempotencyRecord::leased_until() } };
+        self.idempotency.update_one(filter, update).await?;
+
+        Ok(())
+    }
+
+    async fn release_idempotency_key(&self, uid: ObjectId, key: &str) -> Result<(), Error> {
+        let filter = doc! {
+            "user": uid,
+            "key": key,
+        };
+
+        self.idempotency.delete_one(filter).await?;
+
+        Ok(())
+    }
+
+    async fn create_activity(
+        &self,
+        payload: PostActivityPayload,
+        user_id: String,
+    ) -> Result<Option<Activity>, Error> {
+        let mut activity = Activity::new(
+            payload.variant,
+            payload.title.to_string(),
+            payload.group.to_string(),
+            payload.notes.unwrap_or(String::from("")),
+            parse_date(&payload.start)?,
+            Some(parse_date(&payload.end)?),
+            payload.timezone,
+            payload.data,
+            parse_object_id(&user_id)?,
+        );
+        activity.ical_uid = payload.ical_uid;
+        activity.occurrence = payload.occurrence;
+
+        let field_key = format!("activities.{}", activity.title);
+        let update = doc! {
+            "$set": { field_key: payload.color }
+        };
+
+        let filter = doc! {
+            "_id": parse_object_id(&user_id)?,
+        };
+
+        let (new_activity, _update_user) = tokio::join!(
+            self.activities.insert_one(activity),
+            self.users.update_one(filter, update)
+        );
+
+        let new_id = match new_activity {
+            Ok(res) => match res.inserted_id {
+                Bson::ObjectId(oid) => oid,
+                _ => panic!("failed to retrieve objectid"),
+            },
+            Err(e) => return Err(e),
+        };
+
+        self.get_activity_doc(new_id, parse_object_id(&user_id)?, None)
+            .await
+    }
+
+    async fn update_activity_by_id(
+        &self,
+        payload: PatchActivityPayload,
+        user_id: String,
+    ) -> Result<VersionedWrite<Activity>, Error> {
+        let uid = parse_object_id(&user_id)?;
+        let color = update_color(&payload);
+
+        let res = self.update_activity_doc(payload, uid, None).await?;
+        if let VersionedWrite::Done(_) = res {
+            self.set_colors(uid, color).await;
+        }
+
+        Ok(res)
+    }
+
+    async fn delete_activity_by_id(
+        &self,
+        payload: DeleteActivityPayload,
+        user_id: String,
+    ) -> Result<VersionedWrite<ActivityDelete>, Error> {
+        self.delete_activity_doc(payload, parse_object_id(&user_id)?, None)
+            .await
+    }
+
+    async fn get_activity_by_id(
+        &self,
+        payload: GetActivityPayload,
+        user_id: String,
+    ) -> Result<Option<Activity>, Error> {
+        let filter = doc! {
+            "_id": parse_object_id(&payload.id)?,
+            "user": parse_object_id(&user_id)?,
+            "deletedAt": null,
+        };
+
+        let res = self.activities.find_one(filter).await?;
+        Ok(res)
+    }
+
+    async fn get_activities(
+        &self,
+        payload: GetActivitiesPayload,
+        user_id: String,
+    ) -> Result<Vec<Activity>, Error> {
+        let mut filter = doc! {
+            "user": parse_object_id(&user_id)?,
+            "deletedAt": null,
+        };
+        insert_optional(&mut filter, "title", payload.title);
+        insert_optional(&mut filter, "group", payload.group);
+        insert_optional(&mut filter, "variant", payload.variant);
+
+        if let Some(start) = payload.start {
+            filter.insert("end", ends_from(parse_date(&start)?));
+        };
+
+        if let Some(end) = payload.end {
+            let end = parse_date(&end)?;
+            filter.insert("start", doc! { "$lte": end });
+        };
+
+        if let Some(after) = payload.after {
+            let cursor = ActivityCursor::decode(&after)
+                .ok_or_else(|| Error::custom(format!("invalid cursor: {}", after)))?;
+            filter.insert(
+                "$or",
+                vec![
+                    doc! { "start": { "$gt": cursor.start } },
+                    doc! { "start": cursor.start, "_id": { "$gt": cursor.id } },
+                ],
+            );
+        };
+
+        let mut find = self
+            .activities
+            .find(filter)
+            .sort(doc! { "start": 1, "_id": 1 });
+
+        if let Some(limit) = payload.limit {
+            find = find.limit(limit.into());
+        }
+
+        let cursor = find.await?;
+        let res = cursor.try_collect().await?;
+
+        Ok(res)
+    }
+
+    async fn get_stats(
+        &self,
+        payload: StatsPayload,
+        user_id: String,
+    ) -> Result<ActivityStats, Error> {
+        let start = parse_date(&payload.start)?;
+        let end = parse_date(&payload.end)?;
+
+        let mut filter = doc! {
+            "user": parse_object_id(&user_id)?,
+            "deletedAt": null,
+            "end": ends_from(start),
+            "start": { "$lte": end },
+        };
+        insert_optional(&mut filter, "title", payload.title);
+        insert_optional(&mut filter, "group", payload.group);
+        insert_optional(&mut filter, "variant", payload.variant);
+
+        // offsets are minutes behind utc, local time is utc minus the offset
+        let timezone = match payload.timezone {
+            Some(timezone) => Bson::Int32(timezone.into()),
+            None => Bson::String("$timezone".to_string()),
+        };
+
+        let buckets = match payload.group_by.field() {
+            Some(field) => vec![doc! { "$group": {
+                "_id": format!("${}", field),
+                "total": { "$sum": { "$subtract": ["$to", "$from"] } },
+                "count": { "$sum": 1 },
+            } }],
+            None => time_bucket_stages(payload.group_by),
+        };
+
+        let pipeline = vec![
+            doc! { "$match": filter },
+            // only the part within the range counts
+            doc! { "$project": {
+                "title": 1,
+                "group": 1,
+                "variant": 1,
+                "from": { "$max": ["$start", start] },
+                "to": { "$min": [{ "$ifNull": ["$end", "$$NOW"] }, end] },
+                "offset": { "$multiply": [timezone, -60_000] },
+            } },
+            doc! { "$facet": {
+                "summary": [{ "$group": {
+                    "_id": null,
+                    "total": { "$sum": { "$subtract": ["$to", "$from"] } },
+                    "count": { "$sum": 1 },
+                } }],
+                "buckets": buckets,
+            } },
+        ];
+
+        let facets = match self
+            .activities
+            .aggregate(pipeline)
+            .await?
+            .try_next()
+            .await?
+        {
+            Some(doc) => from_document::<StatsFacets>(doc)?,
+            None => return Ok(ActivityStats::default()),
+        };
+
+        let (total, count) = facets
+            .summary
+            .first()
+            .map_or((0, 0), |s| (s.total, s.count));
+
+        Ok(ActivityStats {
+            total,
+            count,
+            buckets: facets
+                .buckets
+                .into_iter()
+                .map(|b| StatsBucket {
+                    key: b.key.unwrap_or_default(),
+                    total: b.total,
+                    count: b.count,
+                })
+                .collect(),
+        })
+    }
+
+    async fn get_exercises(&self, user_id: String) -> Result<Vec<ExerciseSummary>, Error> {
+        let pipeline = vec![
+            doc! { "$match": {
+                "user": parse_object_id(&user_id)?,
+                "deletedAt": null,
+                "data.exercise.0": { "$exists": true },
+            } },
+            doc! { "$unwind": "$data.exercise" },
+            // an exercise logged twice in an activity is one session
+            doc! { "$group": {
+                "_id": {
+                    "title": "$data.exercise.title",
+                    "variant": "$data.exercise.variant",
+                    "activity": "$_id",
+                },
+                "start": { "$first": "$start" },
+            } },
+            doc! { "$group": {
+                "_id": { "title": "$_id.title", "variant": "$_id.variant" },
+                "sessions": { "$sum": 1 },
+                "last": { "$max": "$start" },
+            } },
+            doc! { "$project": {
+                "_id": 0,
+                "title": "$_id.title",
+                "variant": "$_id.variant",
+                "sessions": 1,
+                "last": 1,
+            } },
+            doc! { "$sort": { "title": 1, "variant": 1 } },
+        ];
+
+        let cursor = self.activities.aggregate(pipeline).await?;
+        let docs: Vec<Document> = cursor.try_collect().await?;
+
+        docs.into_iter()
+            .map(|doc| from_document(doc).map_err(Error::from))
+            .collect()
+    }
+
+    async fn get_exercise_progress(
+        &self,
+        payload: ExerciseProgressPayload,
+        user_id: String,
+    ) -> Result<ExerciseProgress, Error> {
+        let mut filter = doc! {
+            "user": parse_object_id(&user_id)?,
+            "deletedAt": null,
+            "data.exercise.title": &payload.title,
+        };
+
+        if let Some(start) = payload.start {
+            filter.insert("end", ends_from(parse_date(&start)?));
+        };
+
+        if let Some(end) = payload.end {
+            filter.insert("start", doc! { "$lte": parse_date(&end)? });
+        };
+
+        let strength = vec![
+            doc! { "$match": { "data.exercise.variant": "Strength" } },
+            doc! { "$unwind": "$data.exercise.sets" },
+            doc! { "$set": {
+                "reps": { "$ifNull": ["$data.exercise.sets.reps", 0] },
+                "weight": { "$ifNull": ["$data.exercise.sets.weight", 0] },
+            } },
+            // epley's estimate, see `estimated_one_rep_max`
+            doc! { "$set": {
+                "e1rm": { "$switch": {
+                    "branches": [
+                        {
+                            "case": { "$or": [{ "$eq": ["$reps", 0] }, { "$eq": ["$weight", 0] }] },
+                            "then": null,
+                        },
+                        { "case": { "$eq": ["$reps", 1] }, "then": "$weight" },
+                    ],
+                    "default": { "$multiply": [
+                        "$weight",
+                        { "$add": [1, { "$divide": ["$reps", 30] }] },
+                    ] },
+                } },
+            } },
+            doc! { "$sort": { "e1rm": -1, "weight": -1, "reps": -1 } },
+            doc! { "$group": {
+                "_id": "$_id",
+                "start": { "$first": "$start" },
+                "sets": { "$sum": 1 },
+                "reps": { "$sum": "$reps" },
+                "volume": { "$sum": { "$multiply": ["$reps", "$weight"] } },
+                "estimated1rm": { "$first": "$e1rm" },
+                "bestSet": { "$first": { "reps": "$reps", "weight": "$weight" } },
+            } },
+            doc! { "$sort": { "start": 1, "_id": 1 } },
+            doc! { "$project": {
+                "_id": 0,
+                "activityId": "$_id",
+                "start": 1,
+                "sets": 1,
+                "reps": 1,
+                "volume": 1,
+                "estimated1rm": 1,
+                "bestSet": 1,
+            } },
+        ];
+
+        let cardio = vec![
+            doc! { "$match": { "data.exercise.variant": "Cardio" } },
+            doc! { "$group": {
+                "_id": "$_id",
+                "start": { "$first": "$start" },
+                "duration": { "$sum": "$data.exercise.duration" },
+                "distance": { "$sum": "$data.exercise.distance" },
+                "splits": { "$push": { "$ifNull": ["$data.exercise.splits", []] } },
+            } },
+            // seconds per kilometre of every split with a distance and duration
+            doc! { "$set": {
+                "paces": { "$map": {
+                    "input": { "$filter": {
+                        "input": { "$reduce": {
+                            "input": "$splits",
+                            "initialValue": [],
+                            "in": { "$concatArrays": ["$$value", "$$this"] },
+                        } },
+                        "as": "split",
+                        "cond": { "$and": [
+                            { "$gt": ["$$split.duration", 0] },
+                            { "$gt": ["$$split.distance", 0] },
+                        ] },
+                    } },
+                    "as": "split",
+                    "in": { "$divide": [
+                        { "$multiply": ["$$split.duration", 1000] },
+                        "$$split.distance",
+                    ] },
+                } },
+            } },
+            doc! { "$sort": { "start": 1, "_id": 1 } },
+            doc! { "$project": {
+                "_id": 0,
+                "activityId": "$_id",
+                "start": 1,
+                "duration": 1,
+                "distance": 1,
+                "pace": { "$cond": [
+                    { "$gt": ["$distance", 0] },
+                    { "$divide": [{ "$multiply": ["$duration", 1000] }, "$distance"] },
+                    null,
+                ] },
+                "splits": { "$size": "$paces" },
+                "splitVariation": { "$cond": [
+                    { "$gte": [{ "$size": "$paces" }, 2] },
+                    { "$divide": [{ "$stdDevPop": "$paces" }, { "$avg": "$paces" }] },
+                    null,
+                ] },
+            } },
+        ];
+
+        let pipeline = vec![
+            doc! { "$match": filter },
+            doc! { "$unwind": "$data.exercise" },
+            doc! { "$match": { "data.exercise.title": &payload.title } },
+            doc! { "$facet": { "strength": strength, "cardio": cardio } },
+        ];
+
+        match self
+            .activities
+            .aggregate(pipeline)
+            .await?
+            .try_next()
+            .await?
+        {
+            Some(doc) => Ok(from_document(doc)?),
+            None => Ok(ExerciseProgress::default()),
+        }
+    }
+
+    async fn find_exercise_activities(
+        &self,
+        user_id: String,
+        titles: Vec<String>,
+    ) -> Result<Vec<Activity>, Error> {
+        let filter = doc! {
+            "user": parse_object_id(&user_id)?,
+            "deletedAt": null,
+            "variant": "Exercise",
+            "data.exercise.title": { "$in": titles },
+        };
+
+        self.activities.find(filter).await?.try_collect().await
+    }
+
+    async fn get_last_sets(
+        &self,
+        user_id: String,
+        titles: Vec<String>,
+    ) -> Result<HashMap<String, Vec<Set>>, Error> {
+        /// A `$group` result of the last sets pipeline.
+        #[derive(Debug, Deserialize)]
+        struct LastSets {
+            #[serde(rename = "_id")]
+            title: String,
+            sets: Vec<Set>,
+        }
+
+        let pipeline = vec![
+            doc! { "$match": {
+                "user": parse_object_id(&user_id)?,
+                "deletedAt": null,
+                "variant": "Exercise",
+                "data.exercise.title": { "$in": &titles },
+            } },
+            doc! { "$unwind": "$data.exercise" },
+            doc! { "$match": {
+                "data.exercise.variant": "Strength",
+                "data.exercise.title": { "$in": &titles },
+                "data.exercise.sets.weight": { "$gt": 0 },
+            } },
+            doc! { "$sort": { "start": -1, "_id": -1 } },
+            doc! { "$group": {
+                "_id": "$data.exercise.title",
+                "sets": { "$first": "$data.exercise.sets" },
+            } },
+        ];
+
+        let mut cursor = self.activities.aggregate(pipeline).await?;
+        let mut res = HashMap::new();
+
+        while let Some(doc) = cursor.try_next().await? {
+            let last: LastSets = from_document(doc)?;
+            res.insert(last.title, last.sets);
+        }
+
+        Ok(res)
+    }
+
+    async fn get_records(&self, user_id: String) -> Result<Vec<PersonalRecord>, Error> {
+        let filter = doc! {
+            "user": parse_object_id(&user_id)?,
+        };
+
+        self.records.find(filter).await?.try_collect().await
+    }
+
+    async fn replace_records(
+        &self,
+        user_id: String,
+        titles: Vec<String>,
+        records: Vec<PersonalRecord>,
+    ) -> Result<(), Error> {
+        // records are derived, so the next write to the titles repairs them
+        // if this fails half way
+        let filter = doc! {
+            "user": parse_object_id(&user_id)?,
+            "title": { "$in": titles },
+        };
+        self.records.delete_many(filter).await?;
+
+        if !records.is_empty() {
+            self.records.insert_many(records).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn get_activity_changes(
+        &self,
+        since: Option<DateTime>,
+        user_id: String,
+    ) -> Result<Vec<Activity>, Error> {
+        let mut filter = doc! {
+            "user": parse_object_id(&user_id)?,
+        };
+
+        match since {
+            Some(since) => filter.insert("updatedAt", doc! { "$gt": since }),
+            None => filter.insert("deletedAt", Bson::Null),
+        };
+
+        let cursor = self
+            .activities
+            .find(filter)
+            .sort(doc! { "updatedAt": 1, "_id": 1 })
+            .await?;
+
+        cursor.try_collect().await
+    }
+
+    async fn stream_activities(
+        &self,
+        user_id: String,
+    ) -> Result<BoxStream<'static, Result<Activity, Error>>, Error> {
+        let filter = doc! {
+            "user": parse_object_id(&user_id)?,
+            "deletedAt": null,
+        };
+
+        let cursor = self
+            .activities
+            .find(filter)
+            .sort(doc! { "start": 1, "_id": 1 })
+            .await?;
+
+        Ok(cursor.boxed())
+    }
+
+    async fn find_ical_uids(
+        &self,
+        user_id: String,
+        keys: Vec<String>,
+    ) -> Result<HashSet<String>, Error> {
+        if keys.is_empty() {
+            return Ok(HashSet::new());
+        }
+
+        let filter = doc! {
+            "user": parse_object_id(&user_id)?,
+            "icalUid": { "$in": keys },
+        };
+
+        let res = self.activities.distinct("icalUid", filter).await?;
+
+        Ok(res
+            .into_iter()
+            .filter_map(|v| v.as_str().map(String::from))
+            .collect())
+    }
+
+    async fn write_activities(
+        &self,
+        writes: Vec<ActivityWrite>,
+        user_id: String,
+    ) -> Result<Vec<Result<ActivityWriteResult, Error>>, Error> {
+        let uid = parse_object_id(&user_id)?;
+        let mut colors = Document::new();
+
+        let results = if self.transactions {
+            self.apply_writes_in_transaction(writes, uid, &mut colors)
+                .await?
+        } else {
+            self.apply_writes(writes, uid, &mut colors, None).await
+        };
+
+        self.set_colors(uid, colors).await;
+
+        Ok(results)
+    }
+
+    async fn create_template(
+        &self,
+        payload: PostTemplatePayload,
+        user_id: String,
+    ) -> Result<Template, Error> {
+        let template = Template::new(payload, parse_object_id(&user_id)?);
+        self.templates.insert_one(&template).await?;
+
+        Ok(template)
+    }
+
+    async fn get_templates(&self, user_id: String) -> Result<Vec<Template>, Error> {
+        let filter = doc! {
+            "user": parse_object_id(&user_id)?,
+        };
+
+        let cursor = self
+            .templates
+            .find(filter)
+            .sort(doc! { "name": 1, "_id": 1 })
+            .await?;
+
+        cursor.try_collect().await
+    }
+
+    async fn get_template_by_id(
+        &self,
+        id: ObjectId,
+        user_id: String,
+    ) -> Result<Option<Template>, Error> {
+        let filter = doc! {
+            "_id": id,
+            "user": parse_object_id(&user_id)?,
+        };
+
+        self.templates.find_one(filter).await
+    }
+
+    async fn update_template_by_id(
+        &self,
+        id: ObjectId,
+        payload: PatchTemplatePayload,
+        user_id: String,
+    ) -> Result<Option<Template>, Error> {
+        let filter = doc! {
+            "_id": id,
+            "user": parse_object_id(&user_id)?,
+        };
+
+        let mut set = doc! { "updatedAt": DateTime::now() };
+        insert_optional(&mut set, "name", payload.name);
+        insert_optional(&mut set, "group", payload.group);
+        insert_optional(&mut set, "notes", payload.notes);
+        insert_optional(&mut set, "duration", payload.duration);
+        insert_optional(&mut set, "exercises", payload.exercises);
+
+        self.templates
+            .find_one_and_update(filter, doc! { "$set": set })
+            .return_document(ReturnDocument::After)
+            .await
+    }
+
+    async fn delete_template_by_id(&self, id: ObjectId, user_id: String) -> Result<bool, Error> {
+        let filter = doc! {
+            "_id": id,
+            "user": parse_object_id(&user_id)?,
+        };
+
+        let res = self.templates.delete_one(filter).await?;
+
+        Ok(res.deleted_count > 0)
+    }
+
+    async fn create_series(&self, series: Series) -> Result<bool, Error> {
+        // concurrent creates can each pass the count, which overshoots the cap
+        // by at most one series per request in flight
+        let live = self
+            .series
+            .count_documents(doc! { "user": series.user, "deletedAt": null })
+            .await?;
+        if live >= MAX_SERIES {
+            return Ok(false);
+        }
+
+        self.series.insert_one(series).await?;
 
-        Ok(())
+        Ok(true)
     }
 
-    pub async fn blacklist_user_tokens(&self, uid: ObjectId) -> Result<(), Error> {
+    async fn get_series(&self, user_id: String) -> Result<Vec<Series>, Error> {
         let filter = doc! {
-            "uid": uid,
+            "user": parse_object_id(&user_id)?,
+            "deletedAt": null,
         };
 
-        let mut update_doc = Document::new();
-        update_doc.insert("black", true);
-
-        let update = doc! { "$set": update_doc };
-        self.tokens.update_many(filter, update).await?;
+        let cursor = self
+            .series
+            .find(filter)
+            .sort(doc! { "start": 1, "_id": 1 })
+            .await?;
 
-        Ok(())
+        cursor.try_collect().await
     }
 
-    pub async fn create_activity(
+    async fn get_series_by_id(
         &self,
-        payload: PostActivityPayload,
+        id: ObjectId,
         user_id: String,
-    ) -> Result<Option<Activity>, Error> {
-        let activity = Activity::new(
-            payload.variant,
-            payload.title.to_string(),
-            payload.group.to_string(),
-            payload.notes.unwrap_or(String::from("")).into(),
-            payload.start,
-            payload.end,
-            payload.timezone,
-            payload.data,
-            user_id.clone(),
-        );
-
-        let field_key = format!("activities.{}", activity.title);
-        let update = doc! {
-            "$set": { field_key: payload.color }
-        };
-
+    ) -> Result<Option<Series>, Error> {
         let filter = doc! {
-            "_id": ObjectId::parse_str(user_id.clone()).expect("failed to parse string to ObjectId"),
+            "_id": id,
+            "user": parse_object_id(&user_id)?,
+            "deletedAt": null,
         };
 
-        let (new_activity, _update_user) = tokio::join!(
-            self.activities.insert_one(activity),
-            self.users.update_one(filter, update)
-        );
+        self.series.find_one(filter).await
+    }
 
-        let new_id = match new_activity {
-            Ok(res) => match res.inserted_id {
-                Bson::ObjectId(oid) => oid,
-                _ => panic!("failed to retrieve objectid"),
-            },
-            Err(e) => return Err(e),
+    async fn replace_series(&self, series: Series) -> Result<bool, Error> {
+        let filter = doc! {
+            "_id": series.id,
+            "user": series.user,
+            "deletedAt": null,
+            "__v": series.v,
         };
+        let series = Series {
+            v: series.v + 1,
+            ..series
+        };
+
+        let res = self.series.replace_one(filter, series).await?;
 
-        self.get_activity_doc(new_id, user_id).await
+        Ok(res.matched_count > 0)
     }
 
-    pub async fn update_activity_by_id(
+    async fn add_series_exception(
         &self,
-        payload: PatchActivityPayload,
+        id: ObjectId,
         user_id: String,
-    ) -> Result<Option<Activity>, Error> {
+        at: DateTime,
+    ) -> Result<bool, Error> {
         let filter = doc! {
-            "_id": ObjectId::parse_str(payload.id.clone()).expect("failed to parse string to ObjectId"),
-            "user": ObjectId::parse_str(user_id.clone()).expect("failed to parse string to ObjectId")
-        };
-
-        let mut update_doc = Document::new();
-
-        if let Some(start) = payload.start {
-            let start = mongodb::bson::DateTime::parse_rfc3339_str(start).unwrap();
-            update_doc.insert("start", start);
+            "_id": id,
+            "user": parse_object_id(&user_id)?,
+            "deletedAt": null,
+            "exceptions": { "$ne": at },
         };
-
-        if let Some(end) = payload.end {
-            let end = mongodb::bson::DateTime::parse_rfc3339_str(end).unwrap();
-            update_doc.insert("end", end);
+        // bumping the version makes a concurrent replace with the old
+        // exceptions fail instead of dropping this one
+        let update = doc! {
+            "$addToSet": { "exceptions": at },
+            "$set": { "updatedAt": DateTime::now() },
+            "$inc": { "__v": 1 },
         };
 
-        insert_optional(&mut update_doc, "variant", payload.variant);
-        insert_optional(&mut update_doc, "title", payload.title.clone());
-        insert_optional(&mut update_doc, "group", payload.group);
-        insert_optional(&mut update_doc, "notes", payload.notes);
-        insert_optional(&mut update_doc, "timezone", payload.timezone);
-
-        if let Some(data) = payload.data {
-            if let Some(exercise) = data.exercise {
-                insert_optional(&mut update_doc, "data.exercise", Some(exercise));
-            }
-        }
-
-        match payload.title {
-            Some(title) => {
-                let filter = doc! {
-                    "_id": ObjectId::parse_str(user_id.clone()).expect("failed to parse string to ObjectId"),
-                };
+        let res = self.series.update_one(filter, update).await?;
 
-                let mut update_doc = Document::new();
+        Ok(res.matched_count > 0)
+    }
 
-                let field_key = format!("activities.{}", title);
-                insert_optional(&mut update_doc, &field_key, payload.color);
+    async fn delete_series_by_id(&self, id: ObjectId, user_id: String) -> Result<bool, Error> {
+        let filter = doc! {
+            "_id": id,
+            "user": parse_object_id(&user_id)?,
+            "deletedAt": null,
+        };
 
-                let update = doc! { "$set": update_doc };
-                let _ = self.users.update_one(filter, update).await;
-            }
-            None => (),
-        }
+        // keep a tombstone so sync can report the delete
+        let now = DateTime::now();
+        let update = doc! {
+            "$set": { "deletedAt": now, "updatedAt": now },
+            "$inc": { "__v": 1 },
+        };
 
-        if !update_doc.is_empty() {
-            let update = doc! { "$set": update_doc };
-            let _ = self.activities.update_one(filter, update).await;
-        }
+        let res = self.series.update_one(filter, update).await?;
 
-        self.get_activity_doc(ObjectId::parse_str(payload.id).unwrap(), user_id)
-            .await
+        Ok(res.matched_count > 0)
     }
 
-    pub async fn delete_activity_by_id(
+    async fn get_series_changes(
         &self,
-        payload: DeleteActivityPayload,
+        since: Option<DateTime>,
         user_id: String,
-    ) -> Result<ActivityDelete, Error> {
-        let filter = doc! {
-            "_id": ObjectId::parse_str(payload.id.clone()).expect("failed to parse string to ObjectId"),
-            "user": ObjectId::parse_str(user_id).expect("failed to parse string to ObjectId")
+    ) -> Result<Vec<Series>, Error> {
+        let mut filter = doc! {
+            "user": parse_object_id(&user_id)?,
         };
 
-        self.activities.delete_one(filter).await?;
-        let deleted_activity = ActivityDelete {
-            id: ObjectId::parse_str(payload.id).unwrap(),
+        match since {
+            Some(since) => filter.insert("updatedAt", doc! { "$gt": since }),
+            None => filter.insert("deletedAt", Bson::Null),
         };
 
-        Ok(deleted_activity)
+        let cursor = self
+            .series
+            .find(filter)
+            .sort(doc! { "updatedAt": 1, "_id": 1 })
+            .await?;
+
+        cursor.try_collect().await
     }
 
-    pub async fn get_activity_by_id(
-        &self,
-        payload: GetActivityPayload,
-        user_id: String,
-    ) -> Result<Option<Activity>, Error> {
+    async fn start_timer(&self, activity: Activity) -> Result<bool, Error> {
+        match self.activities.insert_one(activity).await {
+            Ok(_) => Ok(true),
+            Err(e) if is_duplicate_key(&e) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_timer(&self, user_id: String) -> Result<Option<Activity>, Error> {
         let filter = doc! {
-            "_id": ObjectId::parse_str(payload.id).expect("failed to parse string to ObjectId"),
-            "user": ObjectId::parse_str(user_id).expect("failed to parse string to ObjectId")
+            "user": parse_object_id(&user_id)?,
+            "deletedAt": null,
+            "end": null,
         };
 
-        let res = self.activities.find_one(filter).await?;
-        Ok(res)
+        self.activities.find_one(filter).await
     }
 
-    pub async fn get_activities(
+    async fn stop_timer(
         &self,
-        payload: GetActivitiesPayload,
         user_id: String,
-    ) -> Result<Vec<Activity>, Error> {
-        let mut filter = doc! {
-            "user": ObjectId::parse_str(user_id).expect("failed to parse string to ObjectId"),
-        };
-        insert_optional(&mut filter, "title", payload.title);
-        insert_optional(&mut filter, "group", payload.group);
-        insert_optional(&mut filter, "variant", payload.variant);
-
-        if let Some(start) = payload.start {
-            let start = mongodb::bson::DateTime::parse_rfc3339_str(start).unwrap();
-            filter.insert("end", doc! { "$gte": start });
+        end: DateTime,
+    ) -> Result<VersionedWrite<Activity>, Error> {
+        let filter = doc! {
+            "user": parse_object_id(&user_id)?,
+            "deletedAt": null,
+            "end": null,
+            "start": { "$lte": end },
         };
-
-        if let Some(end) = payload.end {
-            let end = mongodb::bson::DateTime::parse_rfc3339_str(end).unwrap();
-            filter.insert("start", doc! { "$lte": end });
+        let update = doc! {
+            "$set": { "end": end, "updatedAt": DateTime::now() },
+            "$inc": { "__v": 1 },
         };
 
-        let cursor = self
+        let stopped = self
             .activities
-            .find(filter)
-            .sort(doc! { "start": 1 })
+            .find_one_and_update(filter, update)
+            .return_document(ReturnDocument::After)
             .await?;
-        let res = cursor.try_collect().await.unwrap_or_else(|_| vec![]);
 
-        Ok(res)
+        if let Some(activity) = stopped {
+            return Ok(VersionedWrite::Done(activity));
+        }
+
+        // either no timer is running or it started after `end`
+        match self.get_timer(user_id).await? {
+            Some(timer) if end < timer.start => Ok(VersionedWrite::OutOfRange("end")),
+            _ => Ok(VersionedWrite::NotFound),
+        }
+    }
+}
+
+/// Matches an `end` at or after `start`. A running timer has a null `end` and
+/// lasts until now.
+fn ends_from(start: DateTime) -> Document {
+    if start <= DateTime::now() {
+        // null is never less than a date
+        doc! { "$not": { "$lt": start } }
+    } else {
+        doc! { "$gte": start }
+    }
+}
+
+// the color an update sets for its title, empty when it sets none
+fn update_color(payload: &PatchActivityPayload) -> Document {
+    match (&payload.title, &payload.color) {
+        (Some(title), Some(color)) => doc! { format!("activities.{}", title): color },
+        _ => Document::new(),
     }
 }
 
+// one failed insert of an insert_many, as the error a single insert would give
+fn write_error(err: &IndexedWriteError) -> Error {
+    let write_error = mongodb::bson::to_document(err)
+        .map_err(Error::from)
+        .and_then(|v| from_document::<WriteError>(v).map_err(Error::from));
+
+    match write_error {
+        Ok(e) => Error::from(ErrorKind::Write(WriteFailure::WriteError(e))),
+        Err(e) => e,
+    }
+}
+
+fn is_duplicate_key(err: &Error) -> bool {
+    matches!(
+        err.kind.as_ref(),
+        ErrorKind::Write(WriteFailure::WriteError(e)) if e.code == DUPLICATE_KEY
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use crate::models::activity_model::{
-        ActivityData, ActivityVariant, CardioExercise, Exercise, MobilityExercise, Set,
+        ActivityData, ActivityVariant, CardioExercise, Exercise, MobilityExercise, Set, Split,
         StrengthExercise,
     };
+    use crate::models::progress_model::StrengthSession;
+    use crate::models::series_model::PostSeriesPayload;
     use crate::models::state_model::EnvironmentVariables;
+    use crate::models::template_model::{PatchTemplatePayload, PostTemplatePayload};
 
     use super::*;
     use core::panic;
@@ -363,7 +1799,7 @@ mod tests {
         let user_id = String::from("5f00b442bab42e04c05f5a9e");
         let payload = PostActivityPayload {
             title: "test get".to_string(),
-            variant: "default".into(),
+            variant: ActivityVariant::Default,
             group: "test group".to_string(),
             notes: Some("insert 2".to_string()),
             start: "2000-01-01T09:00:00.000Z".to_string(),
@@ -371,18 +1807,20 @@ mod tests {
             timezone: 0,
             data: None,
             color: None,
+            ical_uid: None,
+            occurrence: None,
         };
 
         let activity = Activity::new(
             payload.variant,
             payload.title.to_string(),
             payload.group.to_string(),
-            payload.notes.unwrap_or(String::from("")).into(),
-            payload.start,
-            payload.end,
+            payload.notes.unwrap_or(String::from("")),
+            DateTime::parse_rfc3339_str(payload.start).unwrap(),
+            DateTime::parse_rfc3339_str(payload.end).ok(),
             payload.timezone,
             payload.data,
-            user_id.clone(),
+            ObjectId::parse_str(&user_id).unwrap(),
         );
 
         let new_id = match db.activities.insert_one(activity).await {
@@ -397,7 +1835,7 @@ mod tests {
     }
 
     async fn delete_test_activity(db: &MongoDatabase, id: String, user_id: String) {
-        let payload = DeleteActivityPayload { id };
+        let payload = DeleteActivityPayload { id, v: None };
         let _ = db.delete_activity_by_id(payload, user_id).await;
     }
 
@@ -407,6 +1845,7 @@ mod tests {
             pass: "TEST".to_string(),
             given_name: "testy".to_string(),
             family_name: "mctestface".to_string(),
+            generated_pass: false,
         };
 
         let new_id = match db.users.insert_one(User::from(payload)).await {
@@ -433,13 +1872,14 @@ mod tests {
     }
 
     #[tokio::test]
+    #[ignore = "requires a live MongoDB instance"]
     async fn activity_create_one() {
         let db = init_db().await;
 
         let user_id = String::from("5f00b442bab42e04c05f5a9e");
         let data = PostActivityPayload {
             title: "test create".to_string(),
-            variant: "exercise".into(),
+            variant: ActivityVariant::Exercise,
             group: "test group".to_string(),
             notes: Some("insert 1".to_string()),
             start: "2000-01-01T09:00:00.000Z".to_string(),
@@ -476,6 +1916,8 @@ mod tests {
                     }),
                 ]),
             }),
+            ical_uid: None,
+            occurrence: None,
         };
 
         let inserted_activity = db.create_activity(data, user_id.clone()).await;
@@ -486,6 +1928,7 @@ mod tests {
     }
 
     #[tokio::test]
+    #[ignore = "requires a live MongoDB instance"]
     async fn activity_get_one() {
         let db = init_db().await;
         let (new_id, user_id) = create_test_activity(&db).await;
@@ -502,6 +1945,7 @@ mod tests {
     }
 
     #[tokio::test]
+    #[ignore = "requires a live MongoDB instance"]
     async fn activity_get_many() {
         let db = init_db().await;
 
@@ -532,7 +1976,7 @@ mod tests {
         let results_futures = dates.iter().map(|x| {
             let data = PostActivityPayload {
                 title: "get many".to_string(),
-                variant: "default".into(),
+                variant: ActivityVariant::Default,
                 group: "group".to_string(),
                 notes: None,
                 start: x.0.clone(),
@@ -540,6 +1984,8 @@ mod tests {
                 timezone: 0,
                 data: None,
                 color: None,
+                ical_uid: None,
+                occurrence: None,
             };
             db.create_activity(data, user_id.clone())
         });
@@ -561,12 +2007,14 @@ mod tests {
             group: None,
             start: Some("2000-01-01T00:00:00.000Z".to_string()),
             end: Some("2000-01-01T23:59:59.999Z".to_string()),
+            limit: None,
+            after: None,
         };
 
-        let activities = match db.get_activities(filters, user_id.clone()).await {
-            Ok(v) => v,
-            Err(_) => vec![],
-        };
+        let activities = db
+            .get_activities(filters, user_id.clone())
+            .await
+            .unwrap_or_default();
 
         assert!(activities.len() == 1);
 
@@ -577,12 +2025,14 @@ mod tests {
             group: None,
             start: Some("2000-01-01T00:00:00.000Z".to_string()),
             end: Some("2000-01-03T23:59:59.999Z".to_string()),
+            limit: None,
+            after: None,
         };
 
-        let activities = match db.get_activities(filters, user_id.clone()).await {
-            Ok(v) => v,
-            Err(_) => vec![],
-        };
+        let activities = db
+            .get_activities(filters, user_id.clone())
+            .await
+            .unwrap_or_default();
 
         assert!(activities.len() == 3);
 
@@ -593,12 +2043,14 @@ mod tests {
             group: None,
             start: None,
             end: None,
+            limit: None,
+            after: None,
         };
 
-        let activities = match db.get_activities(filters, user_id.clone()).await {
-            Ok(v) => v,
-            Err(_) => vec![],
-        };
+        let activities = db
+            .get_activities(filters, user_id.clone())
+            .await
+            .unwrap_or_default();
 
         assert!(activities.len() == 5);
 
@@ -608,12 +2060,427 @@ mod tests {
     }
 
     #[tokio::test]
+    #[ignore = "requires a live MongoDB instance"]
+    async fn activity_stats() {
+        let db = init_db().await;
+
+        let user_id = ObjectId::new().to_hex();
+        let activities = [
+            ("2000-01-01T22:00:00.000Z", "2000-01-02T02:00:00.000Z", 0),
+            ("2000-01-02T23:30:00.000Z", "2000-01-03T00:30:00.000Z", -60),
+        ];
+
+        for (start, end, timezone) in activities {
+            let data = PostActivityPayload {
+                title: "stats".to_string(),
+                variant: ActivityVariant::Default,
+                group: "group".to_string(),
+                notes: None,
+                start: start.to_string(),
+                end: end.to_string(),
+                timezone,
+                data: None,
+                color: None,
+                ical_uid: None,
+                occurrence: None,
+            };
+            db.create_activity(data, user_id.clone()).await.unwrap();
+        }
+
+        let payload = |group_by, timezone| StatsPayload {
+            start: "2000-01-01T00:00:00.000Z".to_string(),
+            end: "2000-02-01T00:00:00.000Z".to_string(),
+            group_by,
+            timezone,
+            title: None,
+            group: None,
+            variant: None,
+        };
+        let hour = 60 * 60 * 1000;
+
+        let mut stats = db
+            .get_stats(payload(StatsGroupBy::Day, None), user_id.clone())
+            .await
+            .unwrap();
+        stats.buckets.sort_by(|a, b| a.key.cmp(&b.key));
+
+        assert_eq!(stats.total, 5 * hour);
+        assert_eq!(stats.count, 2);
+        assert_eq!(
+            stats.buckets,
+            vec![
+                StatsBucket {
+                    key: "2000-01-01".to_string(),
+                    total: 2 * hour,
+                    count: 1
+                },
+                StatsBucket {
+                    key: "2000-01-02".to_string(),
+                    total: 2 * hour,
+                    count: 1
+                },
+                StatsBucket {
+                    key: "2000-01-03".to_string(),
+                    total: hour,
+                    count: 1
+                },
+            ]
+        );
+
+        // 2000-01-03 starts the first ISO week of 2000
+        let mut stats = db
+            .get_stats(payload(StatsGroupBy::Week, Some(0)), user_id.clone())
+            .await
+            .unwrap();
+        stats.buckets.sort_by(|a, b| a.key.cmp(&b.key));
+
+        assert_eq!(
+            stats.buckets,
+            vec![
+                StatsBucket {
+                    key: "1999-W52".to_string(),
+                    total: 9 * hour / 2,
+                    count: 2
+                },
+                StatsBucket {
+                    key: "2000-W01".to_string(),
+                    total: hour / 2,
+                    count: 1
+                },
+            ]
+        );
+
+        let stats = db
+            .get_stats(payload(StatsGroupBy::Title, None), user_id.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            stats.buckets,
+            vec![StatsBucket {
+                key: "stats".to_string(),
+                total: 5 * hour,
+                count: 2
+            }]
+        );
+
+        let _ = db
+            .activities
+            .delete_many(doc! { "user": parse_object_id(&user_id).unwrap() })
+            .await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a live MongoDB instance"]
+    async fn exercise_progress() {
+        let db = init_db().await;
+
+        let user_id = ObjectId::new().to_hex();
+        let set = |reps, weight| Set {
+            idx: 0,
+            reps: Some(reps),
+            rest: None,
+            weight: Some(weight),
+            duration: None,
+        };
+
+        let data = PostActivityPayload {
+            title: "gym".to_string(),
+            variant: ActivityVariant::Exercise,
+            group: "sport".to_string(),
+            notes: None,
+            start: "2000-01-01T09:00:00.000Z".to_string(),
+            end: "2000-01-01T10:00:00.000Z".to_string(),
+            timezone: 0,
+            color: None,
+            data: Some(ActivityData {
+                exercise: Some(vec![
+                    Exercise::Strength(StrengthExercise {
+                        title: "squat".to_string(),
+                        sets: vec![set(5, 100), set(3, 110)],
+                    }),
+                    Exercise::Cardio(CardioExercise {
+                        title: "row".to_string(),
+                        duration: 600,
+                        distance: 2000,
+                        splits: Some(vec![
+                            Split {
+                                idx: 0,
+                                distance: Some(1000),
+                                duration: Some(280),
+                            },
+                            Split {
+                                idx: 1,
+                                distance: Some(1000),
+                                duration: Some(320),
+                            },
+                        ]),
+                    }),
+                ]),
+            }),
+            ical_uid: None,
+            occurrence: None,
+        };
+        let activity = db
+            .create_activity(data, user_id.clone())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let exercises = db.get_exercises(user_id.clone()).await.unwrap();
+        assert_eq!(exercises.len(), 2);
+        assert_eq!(exercises[0].title, "row");
+        assert_eq!(exercises[1].sessions, 1);
+
+        let payload = |title: &str| ExerciseProgressPayload {
+            title: title.to_string(),
+            start: None,
+            end: None,
+        };
+
+        let progress = db
+            .get_exercise_progress(payload("squat"), user_id.clone())
+            .await
+            .unwrap();
+        let set_refs = [set(5, 100), set(3, 110)];
+        let expected = StrengthSession::new(
+            activity.id,
+            activity.start,
+            &set_refs.iter().collect::<Vec<_>>(),
+        );
+        assert_eq!(progress.strength, expected.into_iter().collect::<Vec<_>>());
+        assert!(progress.cardio.is_empty());
+
+        let progress = db
+            .get_exercise_progress(payload("row"), user_id.clone())
+            .await
+            .unwrap();
+        assert_eq!(progress.cardio.len(), 1);
+        assert_eq!(progress.cardio[0].pace, Some(300.0));
+        assert_eq!(progress.cardio[0].splits, 2);
+
+        let _ = db
+            .activities
+            .delete_many(doc! { "user": parse_object_id(&user_id).unwrap() })
+            .await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a live MongoDB instance"]
+    async fn templates() {
+        let db = init_db().await;
+
+        let user_id = ObjectId::new().to_hex();
+        let squat = |weight| {
+            Exercise::Strength(StrengthExercise {
+                title: "squat".to_string(),
+                sets: vec![Set {
+                    idx: 0,
+                    reps: Some(5),
+                    rest: None,
+                    weight,
+                    duration: None,
+                }],
+            })
+        };
+
+        let template = db
+            .create_template(
+                PostTemplatePayload {
+                    name: "legs".to_string(),
+                    group: "sport".to_string(),
+                    notes: None,
+                    duration: Some(3600),
+                    exercises: vec![squat(None)],
+                },
+                user_id.clone(),
+            )
+            .await
+            .unwrap();
+
+        let updated = db
+            .update_template_by_id(
+                template.id,
+                PatchTemplatePayload {
+                    name: Some("heavy legs".to_string()),
+                    group: None,
+                    notes: None,
+                    duration: None,
+                    exercises: None,
+                },
+                user_id.clone(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.name, "heavy legs");
+        assert_eq!(updated.duration, Some(3600));
+        assert_eq!(db.get_templates(user_id.clone()).await.unwrap().len(), 1);
+
+        let other = ObjectId::new().to_hex();
+        assert!(db
+            .get_template_by_id(template.id, other.clone())
+            .await
+            .unwrap()
+            .is_none());
+        assert!(!db.delete_template_by_id(template.id, other).await.unwrap());
+
+        // the latest session with a weight
+        for (start, weight) in [
+            ("2000-01-01T09:00:00.000Z", Some(100)),
+            ("2000-01-02T09:00:00.000Z", Some(105)),
+            ("2000-01-03T09:00:00.000Z", None),
+        ] {
+            let data = PostActivityPayload {
+                title: "gym".to_string(),
+                variant: ActivityVariant::Exercise,
+                group: "sport".to_string(),
+                notes: None,
+                start: start.to_string(),
+                end: start.to_string(),
+                timezone: 0,
+                color: None,
+                data: Some(ActivityData {
+                    exercise: Some(vec![squat(weight)]),
+                }),
+                ical_uid: None,
+                occurrence: None,
+            };
+            db.create_activity(data, user_id.clone()).await.unwrap();
+        }
+
+        let last_sets = db
+            .get_last_sets(user_id.clone(), vec!["squat".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(last_sets["squat"][0].weight, Some(105));
+
+        assert!(db
+            .delete_template_by_id(template.id, user_id.clone())
+            .await
+            .unwrap());
+
+        let _ = db
+            .activities
+            .delete_many(doc! { "user": parse_object_id(&user_id).unwrap() })
+            .await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a live MongoDB instance"]
+    async fn series() {
+        let db = init_db().await;
+
+        let user_id = ObjectId::new().to_hex();
+        let payload = PostSeriesPayload {
+            title: "standup".to_string(),
+            variant: ActivityVariant::Default,
+            group: "work".to_string(),
+            notes: None,
+            start: "2000-01-03T09:00:00.000Z".to_string(),
+            duration: 900,
+            timezone: 0,
+            rrule: "FREQ=DAILY".to_string(),
+            data: None,
+        };
+        let start = parse_date(&payload.start).unwrap();
+        let mut series = Series::new(payload, start, parse_object_id(&user_id).unwrap());
+        assert!(db.create_series(series.clone()).await.unwrap());
+
+        let at = DateTime::parse_rfc3339_str("2000-01-04T09:00:00Z").unwrap();
+        let res = db.add_series_exception(series.id, user_id.clone(), at);
+        assert!(res.await.unwrap());
+        let res = db.add_series_exception(series.id, user_id.clone(), at);
+        assert!(!res.await.unwrap());
+
+        // the exception moved the version on
+        let rest = series.split(DateTime::parse_rfc3339_str("2000-01-05T09:00:00Z").unwrap());
+        assert!(!db.replace_series(series.clone()).await.unwrap());
+        series.v += 1;
+        series.exceptions.push(at);
+        assert!(db.replace_series(series.clone()).await.unwrap());
+        assert!(db.create_series(rest.clone()).await.unwrap());
+
+        let stored = db.get_series(user_id.clone()).await.unwrap();
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[0].id, series.id);
+        assert_eq!(stored[0].until, series.until);
+        assert_eq!(stored[1].id, rest.id);
+
+        let other = ObjectId::new().to_hex();
+        assert!(db
+            .get_series_by_id(series.id, other.clone())
+            .await
+            .unwrap()
+            .is_none());
+        assert!(!db.delete_series_by_id(series.id, other).await.unwrap());
+
+        for s in [series, rest] {
+            assert!(db.delete_series_by_id(s.id, user_id.clone()).await.unwrap());
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a live MongoDB instance"]
+    async fn timer() {
+        let db = init_db().await;
+
+        let user_id = ObjectId::new().to_hex();
+        let timer = || {
+            Activity::new(
+                ActivityVariant::Default,
+                "reading".to_string(),
+                "leisure".to_string(),
+                String::new(),
+                DateTime::parse_rfc3339_str("2000-01-01T09:00:00Z").unwrap(),
+                None,
+                0,
+                None,
+                parse_object_id(&user_id).unwrap(),
+            )
+        };
+
+        let running = timer();
+        assert!(db.start_timer(running.clone()).await.unwrap());
+        assert!(!db.start_timer(timer()).await.unwrap());
+
+        let stored = db.get_timer(user_id.clone()).await.unwrap().unwrap();
+        assert_eq!(stored.id, running.id);
+
+        let end = DateTime::parse_rfc3339_str("2000-01-01T10:00:00Z").unwrap();
+        let early = DateTime::parse_rfc3339_str("2000-01-01T08:00:00Z").unwrap();
+        let res = db.stop_timer(user_id.clone(), early).await.unwrap();
+        assert!(matches!(res, VersionedWrite::OutOfRange("end")));
+
+        let res = db.stop_timer(user_id.clone(), end).await.unwrap();
+        let VersionedWrite::Done(stopped) = res else {
+            panic!("timer not stopped");
+        };
+        assert_eq!(stopped.end, Some(end));
+        assert_eq!(stopped.v, 2);
+        assert!(db.get_timer(user_id.clone()).await.unwrap().is_none());
+
+        // a deleted timer is stopped, so another can start
+        let deleted = timer();
+        assert!(db.start_timer(deleted.clone()).await.unwrap());
+        let payload = DeleteActivityPayload {
+            id: deleted.id.to_hex(),
+            v: None,
+        };
+        db.delete_activity_by_id(payload, user_id.clone())
+            .await
+            .unwrap();
+        assert!(db.start_timer(timer()).await.unwrap());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a live MongoDB instance"]
     async fn activity_update_one() {
         let db = init_db().await;
         let (new_id, user_id) = create_test_activity(&db).await;
 
         let update_payload = PatchActivityPayload {
             id: new_id.to_hex(),
+            v: None,
             title: Some("test update".to_string()),
             variant: Some(ActivityVariant::Default),
             group: Some("update group".to_string()),
@@ -647,10 +2514,11 @@ mod tests {
         assert!(activity.group == "update group");
         assert!(activity.notes == "update 2");
         assert!(activity.start == DateTime::parse_rfc3339_str("2000-01-01T09:00:00.000Z").unwrap());
-        assert!(activity.end == DateTime::parse_rfc3339_str("2000-01-01T10:30:00.000Z").unwrap());
+        assert!(activity.end == DateTime::parse_rfc3339_str("2000-01-01T10:30:00.000Z").ok());
 
         let update_payload = PatchActivityPayload {
             id: new_id.to_hex(),
+            v: None,
             title: None,
             variant: None,
             group: None,
@@ -682,10 +2550,11 @@ mod tests {
         };
 
         assert!(activity.data.is_some());
-        assert!(activity.data.unwrap().exercise.unwrap().len() == 0);
+        assert!(activity.data.unwrap().exercise.unwrap().is_empty());
 
         let update_payload = PatchActivityPayload {
             id: new_id.to_hex(),
+            v: None,
             title: None,
             variant: None,
             group: None,
@@ -748,6 +2617,7 @@ mod tests {
 
         let update_payload = PatchActivityPayload {
             id: new_id.to_hex(),
+            v: None,
             title: None,
             variant: None,
             group: None,
@@ -794,20 +2664,22 @@ mod tests {
     }
 
     #[tokio::test]
+    #[ignore = "requires a live MongoDB instance"]
     async fn activity_delete_one() {
         let db = init_db().await;
         let (new_id, user_id) = create_test_activity(&db).await;
 
         let payload = DeleteActivityPayload {
             id: new_id.to_hex(),
+            v: None,
         };
 
         let deleted_activity = db.delete_activity_by_id(payload, user_id).await;
-        assert!(deleted_activity.is_ok());
-        assert!(deleted_activity.unwrap().id == new_id);
+        assert!(matches!(deleted_activity.unwrap(), VersionedWrite::Done(d) if d.id == new_id));
     }
 
     #[tokio::test]
+    #[ignore = "requires a live MongoDB instance"]
     async fn user_create() {
         let db = init_db().await;
 
@@ -816,6 +2688,7 @@ mod tests {
             pass: "TEST".to_string(),
             given_name: "test".to_string(),
             family_name: "testerton".to_string(),
+            generated_pass: false,
         };
 
         let inserted_user = db.create_user(payload).await;
@@ -826,6 +2699,7 @@ mod tests {
     }
 
     #[tokio::test]
+    #[ignore = "requires a live MongoDB instance"]
     async fn user_get_by_email() {
         let db = init_db().await;
 
@@ -842,6 +2716,7 @@ mod tests {
     }
 
     #[tokio::test]
+    #[ignore = "requires a live MongoDB instance"]
     async fn token_create() {
         let db = init_db().await;
 
@@ -865,6 +2740,7 @@ mod tests {
     }
 
     #[tokio::test]
+    #[ignore = "requires a live MongoDB instance"]
     async fn token_get() {
         let db = init_db().await;
 
@@ -894,6 +2770,7 @@ mod tests {
     }
 
     #[tokio::test]
+    #[ignore = "requires a live MongoDB instance"]
     async fn token_blacklist_one() {
         let db = init_db().await;
 
@@ -919,7 +2796,7 @@ mod tests {
         assert!(token.is_ok());
 
         match token.unwrap() {
-            Some(t) => assert!(t.black == true),
+            Some(t) => assert!(t.black),
             None => panic!("failed to fetch token"),
         }
 
@@ -930,6 +2807,7 @@ mod tests {
     }
 
     #[tokio::test]
+    #[ignore = "requires a live MongoDB instance"]
     async fn token_blacklist_many() {
         let db = init_db().await;
 
@@ -957,9 +2835,7 @@ mod tests {
         assert!(res.is_ok());
 
         let res = db
-            .blacklist_user_tokens(
-                ObjectId::parse_str("5f25a16b81fad94530820f39".to_string()).unwrap(),
-            )
+            .blacklist_user_tokens(ObjectId::parse_str("5f25a16b81fad94530820f39").unwrap())
             .await;
         assert!(res.is_ok());
 
@@ -979,7 +2855,7 @@ mod tests {
         };
 
         while let Some(doc) = res.try_next().await.unwrap() {
-            assert!(doc.black == true);
+            assert!(doc.black);
         }
 
         let filter = doc! {
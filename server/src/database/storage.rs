@@ -0,0 +1,285 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+};
+
+use axum::async_trait;
+use futures::stream::BoxStream;
+use mongodb::{
+    bson::{oid::ObjectId, DateTime},
+    error::Error,
+};
+
+use crate::models::{
+    activity_model::{
+        Activity, ActivityDelete, ActivityWrite, ActivityWriteResult, DeleteActivityPayload,
+        GetActivitiesPayload, GetActivityPayload, PatchActivityPayload, PostActivityPayload, Set,
+        VersionedWrite,
+    },
+    auth_model::{ResetTokenDB, TokenDB},
+    idempotency_model::{IdempotencyRecord, StoredResponse},
+    progress_model::{ExerciseProgress, ExerciseProgressPayload, ExerciseSummary},
+    record_model::PersonalRecord,
+    series_model::Series,
+    stats_model::{ActivityStats, StatsPayload},
+    template_model::{PatchTemplatePayload, PostTemplatePayload, Template},
+    user_model::{AccountErasure, PatchUserPayload, RegisterUserPayload, User},
+};
+
+/// Persistence operations used by the handlers. Implemented by `MongoDatabase`
+/// for deployments and by `MemoryDatabase` for offline use and tests.
+#[async_trait]
+pub trait Storage: Debug + Send + Sync {
+    // users
+    async fn create_user(&self, payload: RegisterUserPayload) -> Result<Option<User>, Error>;
+    async fn get_user_by_email(&self, email: &str) -> Result<Option<User>, Error>;
+    async fn get_user_by_id(&self, uid: ObjectId) -> Result<Option<User>, Error>;
+    /// `pass` must already be hashed. Also marks the password as chosen by the user.
+    async fn update_user_password(&self, uid: ObjectId, pass: String) -> Result<(), Error>;
+    /// Applies the given profile fields and returns the updated user.
+    async fn update_user_profile(
+        &self,
+        uid: ObjectId,
+        payload: PatchUserPayload,
+    ) -> Result<Option<User>, Error>;
+    /// Deactivates the user until `purge_at`, when `erase_user` should be run.
+    async fn deactivate_user(&self, uid: ObjectId, purge_at: DateTime) -> Result<(), Error>;
+    /// Ids of deactivated users whose purge date is before `now`.
+    async fn get_users_to_purge(&self, now: DateTime) -> Result<Vec<ObjectId>, Error>;
+    /// Removes the user and everything stored for them. The user document
+    /// goes last so an interrupted erase can be run again.
+    async fn erase_user(&self, uid: ObjectId) -> Result<AccountErasure, Error>;
+    /// Sets the color of each title in the user's color map.
+    async fn set_activity_colors(
+        &self,
+        uid: ObjectId,
+        colors: HashMap<String, String>,
+    ) -> Result<(), Error>;
+    /// Marks the user verified if their email is still `email`. Returns false
+    /// when no such user exists.
+    async fn set_user_verified(&self, uid: ObjectId, email: &str) -> Result<bool, Error>;
+    /// Replaces the hash of the user's calendar feed token, `None` revokes it.
+    /// Returns false when no such user exists.
+    async fn set_calendar_token(&self, uid: ObjectId, hash: Option<String>) -> Result<bool, Error>;
+    async fn get_user_by_calendar_token(&self, hash: &str) -> Result<Option<User>, Error>;
+
+    // tokens
+    async fn create_token(&self, token: TokenDB) -> Result<Option<ObjectId>, Error>;
+    async fn get_token(&self, jti: &str) -> Result<Option<TokenDB>, Error>;
+    async fn blacklist_user_token(&self, jti: &str) -> Result<(), Error>;
+    async fn blacklist_user_tokens(&self, uid: ObjectId) -> Result<(), Error>;
+    async fn create_reset_token(&self, token: ResetTokenDB) -> Result<(), Error>;
+    /// Marks an unused, unexpired token as used and returns it. Returns `None`
+    /// for any other token, so each one can only be consumed once.
+    async fn consume_reset_token(&self, hash: &str) -> Result<Option<ResetTokenDB>, Error>;
+
+    // idempotency keys
+    /// Stores the record unless the user already has a live one for the key,
+    /// in which case that one is returned and nothing is written.
+    async fn reserve_idempotency_key(
+        &self,
+        record: IdempotencyRecord,
+    ) -> Result<Option<IdempotencyRecord>, Error>;
+    /// Stores the response and keeps the key for the retention window, see
+    /// `IdempotencyRecord::retained_until`.
+    async fn complete_idempotency_key(
+        &self,
+        uid: ObjectId,
+        key: &str,
+        response: StoredResponse,
+    ) -> Result<(), Error>;
+    /// Extends the lease of a key whose request is still being handled, see
+    /// `IdempotencyRecord::leased_until`. A completed key is left alone.
+    async fn renew_idempotency_key(&self, uid: ObjectId, key: &str) -> Result<(), Error>;
+    async fn release_idempotency_key(&self, uid: ObjectId, key: &str) -> Result<(), Error>;
+
+    // activities
+    async fn create_activity(
+        &self,
+        payload: PostActivityPayload,
+        user_id: String,
+    ) -> Result<Option<Activity>, Error>;
+    async fn update_activity_by_id(
+        &self,
+        payload: PatchActivityPayload,
+        user_id: String,
+    ) -> Result<VersionedWrite<Activity>, Error>;
+    /// Deleting a running timer also stops it.
+    async fn delete_activity_by_id(
+        &self,
+        payload: DeleteActivityPayload,
+        user_id: String,
+    ) -> Result<VersionedWrite<ActivityDelete>, Error>;
+    async fn get_activity_by_id(
+        &self,
+        payload: GetActivityPayload,
+        user_id: String,
+    ) -> Result<Option<Activity>, Error>;
+    async fn get_activities(
+        &self,
+        payload: GetActivitiesPayload,
+        user_id: String,
+    ) -> Result<Vec<Activity>, Error>;
+    /// Activities updated after `since`, tombstones included, oldest change first.
+    /// Without `since` only live activities are returned.
+    async fn get_activity_changes(
+        &self,
+        since: Option<DateTime>,
+        user_id: String,
+    ) -> Result<Vec<Activity>, Error>;
+
+    /// Durations and counts of the user's live activities within the payload's
+    /// range, split into buckets by `group_by`. Time buckets are in each
+    /// activity's own offset unless the payload sets one.
+    async fn get_stats(
+        &self,
+        payload: StatsPayload,
+        user_id: String,
+    ) -> Result<ActivityStats, Error>;
+
+    /// The exercise titles in the user's live activities, by title then variant.
+    async fn get_exercises(&self, user_id: String) -> Result<Vec<ExerciseSummary>, Error>;
+    /// One entry per live activity within the payload's range that has the
+    /// exercise title in it.
+    async fn get_exercise_progress(
+        &self,
+        payload: ExerciseProgressPayload,
+        user_id: String,
+    ) -> Result<ExerciseProgress, Error>;
+
+    /// Live exercise activities with any of `titles` in them.
+    async fn find_exercise_activities(
+        &self,
+        user_id: String,
+        titles: Vec<String>,
+    ) -> Result<Vec<Activity>, Error>;
+
+    /// The sets each of `titles` had the last time it was done as a strength
+    /// exercise with a weight.
+    async fn get_last_sets(
+        &self,
+        user_id: String,
+        titles: Vec<String>,
+    ) -> Result<HashMap<String, Vec<Set>>, Error>;
+
+    // records
+    async fn get_records(&self, user_id: String) -> Result<Vec<PersonalRecord>, Error>;
+    /// Replaces the user's records for `titles` with `records`.
+    async fn replace_records(
+        &self,
+        user_id: String,
+        titles: Vec<String>,
+        records: Vec<PersonalRecord>,
+    ) -> Result<(), Error>;
+
+    /// Which of `keys` are the `icalUid` of one of the user's activities,
+    /// deleted ones included.
+    async fn find_ical_uids(
+        &self,
+        user_id: String,
+        keys: Vec<String>,
+    ) -> Result<HashSet<String>, Error>;
+
+    /// Every live activity of the user, oldest first, read lazily so a whole
+    /// history can be streamed without holding it in memory.
+    async fn stream_activities(
+        &self,
+        user_id: String,
+    ) -> Result<BoxStream<'static, Result<Activity, Error>>, Error>;
+
+    /// Applies the writes in order. A failed write does not stop the ones after it.
+    async fn write_activities(
+        &self,
+        writes: Vec<ActivityWrite>,
+        user_id: String,
+    ) -> Result<Vec<Result<ActivityWriteResult, Error>>, Error> {
+        let mut results = Vec::with_capacity(writes.len());
+
+        for write in writes {
+            let res = match write {
+                ActivityWrite::Create(payload) => self
+                    .create_activity(payload, user_id.clone())
+                    .await
+                    .and_then(|v| v.ok_or_else(|| Error::custom("failed to create activity")))
+                    .map(ActivityWriteResult::Created),
+                ActivityWrite::Update(payload) => self
+                    .update_activity_by_id(payload, user_id.clone())
+                    .await
+                    .map(ActivityWriteResult::Updated),
+                ActivityWrite::Delete(payload) => self
+                    .delete_activity_by_id(payload, user_id.clone())
+                    .await
+                    .map(ActivityWriteResult::Deleted),
+            };
+            results.push(res);
+        }
+
+        Ok(results)
+    }
+
+    // templates
+    async fn create_template(
+        &self,
+        payload: PostTemplatePayload,
+        user_id: String,
+    ) -> Result<Template, Error>;
+    /// By name.
+    async fn get_templates(&self, user_id: String) -> Result<Vec<Template>, Error>;
+    async fn get_template_by_id(
+        &self,
+        id: ObjectId,
+        user_id: String,
+    ) -> Result<Option<Template>, Error>;
+    async fn update_template_by_id(
+        &self,
+        id: ObjectId,
+        payload: PatchTemplatePayload,
+        user_id: String,
+    ) -> Result<Option<Template>, Error>;
+    /// Whether there was a template to delete.
+    async fn delete_template_by_id(&self, id: ObjectId, user_id: String) -> Result<bool, Error>;
+
+    // series
+    /// Whether it was stored, `false` when the user already has `MAX_SERIES`.
+    async fn create_series(&self, series: Series) -> Result<bool, Error>;
+    /// By start.
+    async fn get_series(&self, user_id: String) -> Result<Vec<Series>, Error>;
+    async fn get_series_by_id(
+        &self,
+        id: ObjectId,
+        user_id: String,
+    ) -> Result<Option<Series>, Error>;
+    /// Whether there was a series at `series.v` to replace, the stored
+    /// version is bumped.
+    async fn replace_series(&self, series: Series) -> Result<bool, Error>;
+    /// Leaves the occurrence at `at` out of the series. Whether there was a
+    /// series that didn't leave it out yet.
+    async fn add_series_exception(
+        &self,
+        id: ObjectId,
+        user_id: String,
+        at: DateTime,
+    ) -> Result<bool, Error>;
+    /// Whether there was a series to delete. It is kept as a tombstone.
+    async fn delete_series_by_id(&self, id: ObjectId, user_id: String) -> Result<bool, Error>;
+    /// Series updated after `since`, tombstones included, oldest change first.
+    /// Without `since` only live series are returned.
+    async fn get_series_changes(
+        &self,
+        since: Option<DateTime>,
+        user_id: String,
+    ) -> Result<Vec<Series>, Error>;
+
+    // timers
+    /// Stores the activity, which has no end, unless the user already has a
+    /// running timer. Whether it was stored.
+    async fn start_timer(&self, activity: Activity) -> Result<bool, Error>;
+    async fn get_timer(&self, user_id: String) -> Result<Option<Activity>, Error>;
+    /// Ends the user's running timer at `end` and returns it. `OutOfRange`
+    /// when `end` is before the timer's start, `NotFound` when none is running.
+    async fn stop_timer(
+        &self,
+        user_id: String,
+        end: DateTime,
+    ) -> Result<VersionedWrite<Activity>, Error>;
+}
@@ -1,70 +1,281 @@
 use axum::{http::StatusCode, response::IntoResponse, Json};
+use mongodb::error::{ErrorKind, WriteFailure};
 use serde::Serialize;
+use serde_json::Value;
 
-#[derive(Serialize)]
-struct ResponseMessage {
+use crate::models::series_model::MAX_SERIES;
+use crate::utils::request_id::current_request_id;
+
+// mongo server error code for a unique index violation
+pub const DUPLICATE_KEY: i32 = 11000;
+
+/// The JSON body of an error, also embedded in per-operation batch results.
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    code: &'static str,
     message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    fields: Vec<FieldError>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current: Option<Value>,
+    #[serde(rename = "requestId", skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
 }
 
-#[derive(Serialize)]
-struct ErrorMessage {
-    error: String,
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
 }
 
-#[derive(Debug)]
-pub struct AppError {
-    code: StatusCode,
-    message: String,
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Every error the api returns. Serialized as `{ code, message, fields?, requestId? }`
+/// where `code` is stable and safe for clients to branch on.
+#[derive(Debug, PartialEq)]
+pub enum AppError {
+    // request
+    ValidationFailed(Vec<FieldError>),
+    InvalidBody(String),
+    PayloadTooLarge,
+    UnsupportedMediaType,
+
+    // auth
+    MissingCredentials,
+    WrongCredentials,
+    MissingToken,
+    InvalidToken,
+    TokenReused,
+    InvalidResetToken,
+    InvalidVerificationToken,
+    EmailNotVerified,
+    AccountDeleted,
+    Forbidden,
+
+    // resources
+    ActivityNotFound,
+    UserNotFound,
+    CalendarNotFound,
+    TemplateNotFound,
+    SeriesNotFound,
+    OccurrenceNotFound,
+    SeriesLimitReached,
+    TimerNotFound,
+    TimerRunning,
+    Conflict,
+    /// Holds the current document so the client can rebase its change.
+    VersionConflict(Value),
+    IdempotencyKeyReused,
+    RequestInProgress,
+
+    // infrastructure
+    DbUnavailable,
+    Internal(String),
 }
 
 impl AppError {
-    pub fn new(code: StatusCode, message: impl Into<String>) -> Self {
-        Self {
+    pub fn internal(message: impl Into<String>) -> Self {
+        AppError::Internal(message.into())
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::ValidationFailed(_) => StatusCode::BAD_REQUEST,
+            AppError::InvalidBody(_) => StatusCode::BAD_REQUEST,
+            AppError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::MissingCredentials => StatusCode::BAD_REQUEST,
+            AppError::WrongCredentials => StatusCode::UNAUTHORIZED,
+            AppError::MissingToken => StatusCode::UNAUTHORIZED,
+            AppError::InvalidToken => StatusCode::UNAUTHORIZED,
+            AppError::TokenReused => StatusCode::FORBIDDEN,
+            AppError::InvalidResetToken => StatusCode::BAD_REQUEST,
+            AppError::InvalidVerificationToken => StatusCode::BAD_REQUEST,
+            AppError::EmailNotVerified => StatusCode::FORBIDDEN,
+            AppError::AccountDeleted => StatusCode::FORBIDDEN,
+            AppError::Forbidden => StatusCode::FORBIDDEN,
+            AppError::ActivityNotFound => StatusCode::NOT_FOUND,
+            AppError::UserNotFound => StatusCode::NOT_FOUND,
+            AppError::CalendarNotFound => StatusCode::NOT_FOUND,
+            AppError::TemplateNotFound => StatusCode::NOT_FOUND,
+            AppError::SeriesNotFound => StatusCode::NOT_FOUND,
+            AppError::OccurrenceNotFound => StatusCode::NOT_FOUND,
+            AppError::SeriesLimitReached => StatusCode::CONFLICT,
+            AppError::TimerNotFound => StatusCode::NOT_FOUND,
+            AppError::TimerRunning => StatusCode::CONFLICT,
+            AppError::Conflict => StatusCode::CONFLICT,
+            AppError::VersionConflict(_) => StatusCode::CONFLICT,
+            AppError::IdempotencyKeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::RequestInProgress => StatusCode::CONFLICT,
+            AppError::DbUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::ValidationFailed(_) => "validation_failed",
+            AppError::InvalidBody(_) => "invalid_body",
+            AppError::PayloadTooLarge => "payload_too_large",
+            AppError::UnsupportedMediaType => "unsupported_media_type",
+            AppError::MissingCredentials => "missing_credentials",
+            AppError::WrongCredentials => "wrong_credentials",
+            AppError::MissingToken => "missing_token",
+            AppError::InvalidToken => "invalid_token",
+            AppError::TokenReused => "token_reused",
+            AppError::InvalidResetToken => "invalid_reset_token",
+            AppError::InvalidVerificationToken => "invalid_verification_token",
+            AppError::EmailNotVerified => "email_not_verified",
+            AppError::AccountDeleted => "account_deleted",
+            AppError::Forbidden => "forbidden",
+            AppError::ActivityNotFound => "activity_not_found",
+            AppError::UserNotFound => "user_not_found",
+            AppError::CalendarNotFound => "calendar_not_found",
+            AppError::TemplateNotFound => "template_not_found",
+            AppError::SeriesNotFound => "series_not_found",
+            AppError::OccurrenceNotFound => "occurrence_not_found",
+            AppError::SeriesLimitReached => "series_limit_reached",
+            AppError::TimerNotFound => "timer_not_found",
+            AppError::TimerRunning => "timer_running",
+            AppError::Conflict => "conflict",
+            AppError::VersionConflict(_) => "version_conflict",
+            AppError::IdempotencyKeyReused => "idempotency_key_reused",
+            AppError::RequestInProgress => "request_in_progress",
+            AppError::DbUnavailable => "db_unavailable",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            AppError::ValidationFailed(_) => "validation failed".to_string(),
+            AppError::InvalidBody(message) => message.clone(),
+            AppError::PayloadTooLarge => "request body is too large".to_string(),
+            AppError::UnsupportedMediaType => "unsupported content type".to_string(),
+            AppError::MissingCredentials => "missing credentials".to_string(),
+            AppError::WrongCredentials => "wrong credentials".to_string(),
+            AppError::MissingToken => "missing token".to_string(),
+            AppError::InvalidToken => "invalid token".to_string(),
+            AppError::TokenReused => "refresh token reused, all sessions revoked".to_string(),
+            AppError::InvalidResetToken => {
+                "reset token is invalid, expired or already used".to_string()
+            }
+            AppError::InvalidVerificationToken => {
+                "verification token is invalid or expired".to_string()
+            }
+            AppError::EmailNotVerified => "verify your email address to make changes".to_string(),
+            AppError::AccountDeleted => "account is scheduled for deletion".to_string(),
+            AppError::Forbidden => "access forbidden".to_string(),
+            AppError::ActivityNotFound => "activity not found".to_string(),
+            AppError::UserNotFound => "user not found".to_string(),
+            AppError::CalendarNotFound => "calendar not found".to_string(),
+            AppError::TemplateNotFound => "template not found".to_string(),
+            AppError::SeriesNotFound => "series not found".to_string(),
+            AppError::OccurrenceNotFound => "occurrence not found".to_string(),
+            AppError::SeriesLimitReached => {
+                format!("a user can have at most {} series", MAX_SERIES)
+            }
+            AppError::TimerNotFound => "no timer is running".to_string(),
+            AppError::TimerRunning => "a timer is already running".to_string(),
+            AppError::Conflict => "resource already exists".to_string(),
+            AppError::VersionConflict(_) => {
+                "resource was modified since the given version".to_string()
+            }
+            AppError::IdempotencyKeyReused => {
+                "idempotency key was already used with a different request".to_string()
+            }
+            AppError::RequestInProgress => {
+                "a request with this idempotency key is still in progress".to_string()
+            }
+            AppError::DbUnavailable => "database unavailable".to_string(),
+            AppError::Internal(message) => message.clone(),
+        }
+    }
+}
+
+impl From<AppError> for ErrorResponse {
+    fn from(err: AppError) -> Self {
+        let request_id = current_request_id();
+
+        if err.status().is_server_error() {
+            tracing::error!(request_id, code = err.code(), "{}", err.message());
+        }
+
+        let code = err.code();
+        let message = err.message();
+
+        let (fields, current) = match err {
+            AppError::ValidationFailed(fields) => (fields, None),
+            AppError::VersionConflict(current) => (vec![], Some(current)),
+            _ => (vec![], None),
+        };
+
+        ErrorResponse {
             code,
-            message: message.into(),
+            message,
+            fields,
+            current,
+            request_id,
         }
     }
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> axum::response::Response {
-        (
-            self.code,
-            Json(ResponseMessage {
-                message: self.message,
-            }),
-        )
-            .into_response()
+        (self.status(), Json(ErrorResponse::from(self))).into_response()
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub enum AuthError {
-    InvalidToken,
-    WrongCredentials,
-    MissingCredentials,
-    MissingToken,
-    InternalError,
-    Forbidden,
+impl From<mongodb::error::Error> for AppError {
+    fn from(err: mongodb::error::Error) -> Self {
+        tracing::error!(request_id = current_request_id(), "database error: {}", err);
+
+        match err.kind.as_ref() {
+            ErrorKind::Write(WriteFailure::WriteError(e)) if e.code == DUPLICATE_KEY => {
+                AppError::Conflict
+            }
+            ErrorKind::Command(e) if e.code == DUPLICATE_KEY => AppError::Conflict,
+            ErrorKind::InsertMany(e)
+                if e.write_errors
+                    .iter()
+                    .flatten()
+                    .any(|e| e.code == DUPLICATE_KEY) =>
+            {
+                AppError::Conflict
+            }
+            ErrorKind::ServerSelection { .. }
+            | ErrorKind::ConnectionPoolCleared { .. }
+            | ErrorKind::DnsResolve { .. }
+            | ErrorKind::Io(_)
+            | ErrorKind::Shutdown => AppError::DbUnavailable,
+            _ => AppError::internal("database error"),
+        }
+    }
 }
 
-impl IntoResponse for AuthError {
-    fn into_response(self) -> axum::response::Response {
-        let (status, error_message) = match self {
-            AuthError::WrongCredentials => (StatusCode::UNAUTHORIZED, "Wrong credentials"),
-            AuthError::MissingCredentials => (StatusCode::BAD_REQUEST, "Missing credentials"),
-            AuthError::MissingToken => (StatusCode::UNAUTHORIZED, "Missing token"),
-            AuthError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid token"),
-            AuthError::InternalError => (StatusCode::INTERNAL_SERVER_ERROR, "Internal Error"),
-            AuthError::Forbidden => (StatusCode::FORBIDDEN, "Access forbidden"),
-        };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::error::Error;
+
+    #[test]
+    fn from_mongodb_error() {
+        let err = Error::from(ErrorKind::Io(std::sync::Arc::new(std::io::Error::from(
+            std::io::ErrorKind::TimedOut,
+        ))));
+        assert_eq!(
+            AppError::from(err).status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
 
-        (
-            status,
-            Json(ErrorMessage {
-                error: error_message.to_string(),
-            }),
-        )
-            .into_response()
+        let err = Error::custom("oops");
+        assert_eq!(AppError::from(err).code(), "internal_error");
     }
 }
@@ -1,22 +1,94 @@
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
+    extract::{Path, State},
+    http::{header::ETAG, HeaderMap, HeaderName, StatusCode},
+    response::{IntoResponse, Response},
     Extension, Json,
 };
 use axum_extra::extract::PrivateCookieJar;
 
 use crate::{
-    error::error::AppError,
+    error::error::{AppError, FieldError},
     models::{
         activity_model::{
-            ActivityDeleteResponse, ActivityResponse, DeleteActivityPayload, GetActivitiesPayload,
-            GetActivityPayload, PatchActivityBody, PatchActivityPayload, PostActivityPayload,
+            ActivitiesResponse, Activity, ActivityCursor, ActivityDeleteResponse,
+            ActivityPageResponse, ActivityResponse, ActivityWrite, ActivityWriteResult,
+            BatchActivityPayload, BatchActivityResponse, BatchOperation, BatchResult,
+            DeleteActivityPayload, GetActivitiesPayload, GetActivityPayload, PatchActivityBody,
+            PatchActivityPayload, PostActivityPayload, SavedActivityResponse, VersionedWrite,
+            MAX_ACTIVITIES_LIMIT,
         },
         auth_model::AccessClaims,
     },
+    utils::{
+        auth::require_write_access,
+        idempotency::{fingerprint, idempotent, IdempotencyKey},
+        records::{try_refresh_records, WrittenActivities},
+        series::{merge_occurrences, series_occurrences},
+        utils::parse_date,
+        validation::{
+            expected_version, validate_object_id, Validate, ValidatedJson, ValidatedQuery,
+        },
+    },
     AppState,
 };
 
+pub type ActivityWithEtag = (
+    [(HeaderName, String); 1],
+    (StatusCode, Json<ActivityResponse>),
+);
+
+pub type SavedActivityWithEtag = (
+    [(HeaderName, String); 1],
+    (StatusCode, Json<SavedActivityResponse>),
+);
+
+/// The response to a create or update, after refreshing the records the
+/// activity can hold.
+pub async fn saved_activity(
+    app_state: &AppState,
+    user_id: &str,
+    status: StatusCode,
+    activity: Activity,
+) -> SavedActivityWithEtag {
+    let new_records = try_refresh_records(
+        &*app_state.db,
+        user_id,
+        WrittenActivities::activity(&activity),
+    )
+    .await;
+
+    (
+        etag(&activity),
+        (
+            status,
+            Json(SavedActivityResponse {
+                activity: ActivityResponse::from(activity),
+                new_records: new_records.into_iter().map(Into::into).collect(),
+            }),
+        ),
+    )
+}
+
+/// `ETag` header for an activity, clients send it back as `If-Match`.
+pub fn etag(activity: &Activity) -> [(HeaderName, String); 1] {
+    [(ETAG, format!("\"{}\"", activity.v))]
+}
+
+pub fn version_conflict(current: Activity) -> AppError {
+    match serde_json::to_value(ActivityResponse::from(current)) {
+        Ok(current) => AppError::VersionConflict(current),
+        Err(_) => AppError::internal("failed to serialize activity"),
+    }
+}
+
+fn out_of_range(field: &str) -> AppError {
+    let message = match field {
+        "end" => "must not be before start",
+        _ => "must not be after end",
+    };
+    AppError::ValidationFailed(vec![FieldError::new(field, message)])
+}
+
 // curl -X POST http://localhost:8000/api/v1/activity -H "Content-Type: application/json" -d '{
 //   "title": "My New Activity",
 //   "variant": "Default",
@@ -31,23 +103,35 @@ pub async fn create_activity_handler(
     claims: AccessClaims,
     Extension(jar): Extension<PrivateCookieJar>,
     State(app_state): State<AppState>,
-    Json(body): Json<PostActivityPayload>,
-) -> Result<(PrivateCookieJar, (StatusCode, Json<ActivityResponse>)), AppError> {
-    match app_state.db.create_activity(body, claims.sub).await {
+    IdempotencyKey(key): IdempotencyKey,
+    ValidatedJson(body): ValidatedJson<PostActivityPayload>,
+) -> Result<(PrivateCookieJar, Response), AppError> {
+    require_write_access(&app_state, &claims.sub).await?;
+    let fingerprint = fingerprint("POST /api/v1/activity", &body)?;
+
+    let res = idempotent(&*app_state.db, &claims.sub, key, fingerprint, async {
+        create_activity(&app_state, body, claims.sub.clone())
+            .await
+            .into_response()
+    })
+    .await?;
+
+    Ok((jar, res))
+}
+
+pub async fn create_activity(
+    app_state: &AppState,
+    body: PostActivityPayload,
+    user_id: String,
+) -> Result<SavedActivityWithEtag, AppError> {
+    match app_state.db.create_activity(body, user_id.clone()).await {
         Ok(res) => match res {
-            Some(activity) => Ok((
-                jar,
-                (StatusCode::CREATED, Json(ActivityResponse::from(activity))),
-            )),
-            None => Err(AppError::new(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "failed to create activity!",
-            )),
+            Some(activity) => {
+                Ok(saved_activity(app_state, &user_id, StatusCode::CREATED, activity).await)
+            }
+            None => Err(AppError::internal("failed to create activity")),
         },
-        Err(_) => Err(AppError::new(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "failed to create activity!",
-        )),
+        Err(e) => Err(AppError::from(e)),
     }
 }
 
@@ -58,46 +142,93 @@ pub async fn get_activity_handler(
     Extension(jar): Extension<PrivateCookieJar>,
     Path(id): Path<String>,
     State(app_state): State<AppState>,
-) -> Result<(PrivateCookieJar, (StatusCode, Json<ActivityResponse>)), AppError> {
+) -> Result<(PrivateCookieJar, ActivityWithEtag), AppError> {
+    validate_object_id("id", &id)?;
+
     let payload = GetActivityPayload { id };
     match app_state.db.get_activity_by_id(payload, claims.sub).await {
         Ok(res) => match res {
-            Some(v) => {
-                let activity = ActivityResponse::from(v);
-                Ok((jar, (StatusCode::OK, Json(activity))))
-            }
-            None => Err(AppError::new(StatusCode::NOT_FOUND, "activity not found!")),
+            Some(v) => Ok((
+                jar,
+                (etag(&v), (StatusCode::OK, Json(ActivityResponse::from(v)))),
+            )),
+            None => Err(AppError::ActivityNotFound),
         },
-        Err(_) => Err(AppError::new(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "failed to get activity!",
-        )),
+        Err(e) => Err(AppError::from(e)),
     }
 }
 
 // curl -GET "http://localhost:8000/api/v1/activity" --data-urlencode "title=My New Activity"
 // curl -GET "http://localhost:8000/api/v1/activity"
+// curl -GET "http://localhost:8000/api/v1/activity" --data-urlencode "limit=50" --data-urlencode "after=946717200000_66cc8f30ef7a9d4f94f9ad03"
 
 pub async fn get_activities_handler(
     claims: AccessClaims,
     Extension(jar): Extension<PrivateCookieJar>,
-    query: Option<Query<GetActivitiesPayload>>,
+    ValidatedQuery(mut query): ValidatedQuery<GetActivitiesPayload>,
     State(app_state): State<AppState>,
-) -> Result<(PrivateCookieJar, (StatusCode, Json<Vec<ActivityResponse>>)), AppError> {
-    let Query(query) = query.unwrap();
-
-    match app_state.db.get_activities(query, claims.sub).await {
-        Ok(res) => Ok((
-            jar,
-            (
-                StatusCode::OK,
-                Json(res.iter().map(|a| ActivityResponse::from(a)).collect()),
-            ),
-        )),
-        Err(_) => Err(AppError::new(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "failed to get activities!",
-        )),
+) -> Result<(PrivateCookieJar, (StatusCode, Json<ActivitiesResponse>)), AppError> {
+    // without limit or after the full list is returned as a bare array
+    let limit = match (query.limit, &query.after) {
+        (Some(limit), _) => Some(limit.clamp(1, MAX_ACTIVITIES_LIMIT)),
+        (None, Some(_)) => Some(MAX_ACTIVITIES_LIMIT),
+        (None, None) => None,
+    };
+
+    // fetch one extra to tell whether there is a next page
+    query.limit = limit.map(|l| l + 1);
+
+    // a series can repeat forever, so only ranges with both bounds are expanded
+    let occurrences = match (&query.start, &query.end) {
+        (Some(start), Some(end)) => {
+            let (start, end) = (parse_date(start)?, parse_date(end)?);
+            series_occurrences(&*app_state.db, &claims.sub, &query, start, end).await?
+        }
+        _ => vec![],
+    };
+    let fetch = query.limit;
+
+    let mut res = match app_state.db.get_activities(query, claims.sub).await {
+        Ok(res) => res,
+        Err(e) => return Err(AppError::from(e)),
+    };
+    merge_occurrences(&mut res, occurrences, fetch);
+
+    let body = match limit {
+        Some(limit) => {
+            let next = if res.len() > limit as usize {
+                res.truncate(limit as usize);
+                res.last().map(|a| ActivityCursor::from(a).encode())
+            } else {
+                None
+            };
+
+            ActivitiesResponse::Page(ActivityPageResponse {
+                data: res.iter().map(ActivityResponse::from).collect(),
+                next,
+            })
+        }
+        None => ActivitiesResponse::List(res.iter().map(ActivityResponse::from).collect()),
+    };
+
+    Ok((jar, (StatusCode::OK, Json(body))))
+}
+
+/// Builds the storage payload for a PATCH. When only one bound changes,
+/// storage checks it against the stored other bound as part of the write.
+fn patch_payload(id: String, body: PatchActivityBody, v: Option<u32>) -> PatchActivityPayload {
+    PatchActivityPayload {
+        id,
+        variant: body.variant,
+        title: body.title,
+        group: body.group,
+        notes: body.notes,
+        start: body.start,
+        end: body.end,
+        timezone: body.timezone,
+        data: body.data,
+        color: body.color,
+        v,
     }
 }
 
@@ -116,36 +247,30 @@ pub async fn update_activity_handler(
     Extension(jar): Extension<PrivateCookieJar>,
     Path(id): Path<String>,
     State(app_state): State<AppState>,
-    Json(body): Json<PatchActivityBody>,
-) -> Result<(PrivateCookieJar, (StatusCode, Json<ActivityResponse>)), AppError> {
-    let payload = PatchActivityPayload {
-        id,
-        variant: body.variant,
-        title: body.title,
-        group: body.group,
-        notes: body.notes,
-        start: body.start,
-        end: body.end,
-        timezone: body.timezone,
-        data: body.data,
-        color: body.color,
-    };
+    headers: HeaderMap,
+    ValidatedJson(body): ValidatedJson<PatchActivityBody>,
+) -> Result<(PrivateCookieJar, SavedActivityWithEtag), AppError> {
+    validate_object_id("id", &id)?;
+    require_write_access(&app_state, &claims.sub).await?;
+    let v = expected_version(&headers, body.v)?;
+
+    let payload = patch_payload(id, body, v);
+
     match app_state
         .db
-        .update_activity_by_id(payload, claims.sub)
+        .update_activity_by_id(payload, claims.sub.clone())
         .await
     {
         Ok(v) => match v {
-            Some(res) => Ok((jar, (StatusCode::OK, Json(ActivityResponse::from(res))))),
-            None => Err(AppError::new(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "failed to update activity!",
+            VersionedWrite::Done(res) => Ok((
+                jar,
+                saved_activity(&app_state, &claims.sub, StatusCode::OK, res).await,
             )),
+            VersionedWrite::NotFound => Err(AppError::ActivityNotFound),
+            VersionedWrite::Conflict(current) => Err(version_conflict(*current)),
+            VersionedWrite::OutOfRange(field) => Err(out_of_range(field)),
         },
-        Err(_) => Err(AppError::new(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "failed to update activity!",
-        )),
+        Err(e) => Err(AppError::from(e)),
     }
 }
 
@@ -156,20 +281,153 @@ pub async fn delete_activity_handler(
     Extension(jar): Extension<PrivateCookieJar>,
     Path(id): Path<String>,
     State(app_state): State<AppState>,
+    headers: HeaderMap,
 ) -> Result<(PrivateCookieJar, (StatusCode, Json<ActivityDeleteResponse>)), AppError> {
-    let payload = DeleteActivityPayload { id };
+    validate_object_id("id", &id)?;
+    require_write_access(&app_state, &claims.sub).await?;
+
+    let payload = DeleteActivityPayload {
+        id,
+        v: expected_version(&headers, None)?,
+    };
     match app_state
         .db
-        .delete_activity_by_id(payload, claims.sub)
+        .delete_activity_by_id(payload, claims.sub.clone())
         .await
     {
-        Ok(res) => Ok((
-            jar,
-            (StatusCode::OK, Json(ActivityDeleteResponse::from(res))),
-        )),
-        Err(_) => Err(AppError::new(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "failed to delete activity!",
-        )),
+        Ok(res) => match res {
+            VersionedWrite::Done(res) => {
+                // a deleted record holder passes its records on
+                try_refresh_records(
+                    &*app_state.db,
+                    &claims.sub,
+                    WrittenActivities::deleted(res.id),
+                )
+                .await;
+
+                Ok((
+                    jar,
+                    (StatusCode::OK, Json(ActivityDeleteResponse::from(res))),
+                ))
+            }
+            VersionedWrite::NotFound => Err(AppError::ActivityNotFound),
+            VersionedWrite::Conflict(current) => Err(version_conflict(*current)),
+            VersionedWrite::OutOfRange(field) => Err(out_of_range(field)),
+        },
+        Err(e) => Err(AppError::from(e)),
     }
 }
+
+fn batch_result(res: ActivityWriteResult) -> BatchResult {
+    let written = match res {
+        ActivityWriteResult::Created(activity) => {
+            return BatchResult::activity(StatusCode::CREATED, activity)
+        }
+        ActivityWriteResult::Updated(res) => res.map(|a| BatchResult::activity(StatusCode::OK, a)),
+        ActivityWriteResult::Deleted(res) => res.map(BatchResult::deleted),
+    };
+
+    match written {
+        VersionedWrite::Done(res) => res,
+        VersionedWrite::NotFound => BatchResult::from(AppError::ActivityNotFound),
+        VersionedWrite::Conflict(current) => BatchResult::from(version_conflict(*current)),
+        VersionedWrite::OutOfRange(field) => BatchResult::from(out_of_range(field)),
+    }
+}
+
+// curl -X POST http://localhost:8000/api/v1/activity/batch -H "Content-Type: application/json" -d '{
+//   "operations": [
+//     { "op": "create", "activity": { "title": "Run", "variant": "Default", "group": "Sport", "start": "2024-08-25T14:00:00Z", "end": "2024-08-25T15:00:00Z", "timezone": 0 } },
+//     { "op": "update", "id": "66cc8f30ef7a9d4f94f9ad03", "activity": { "title": "UPDATED", "v": 1 } },
+//     { "op": "delete", "id": "66cc8f30ef7a9d4f94f9ad04", "v": 2 }
+//   ]
+// }'
+
+pub async fn batch_activities_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    State(app_state): State<AppState>,
+    IdempotencyKey(key): IdempotencyKey,
+    ValidatedJson(body): ValidatedJson<BatchActivityPayload>,
+) -> Result<(PrivateCookieJar, Response), AppError> {
+    require_write_access(&app_state, &claims.sub).await?;
+    let fingerprint = fingerprint("POST /api/v1/activity/batch", &body)?;
+
+    let res = idempotent(&*app_state.db, &claims.sub, key, fingerprint, async {
+        batch_activities(&app_state, body, claims.sub.clone())
+            .await
+            .into_response()
+    })
+    .await?;
+
+    Ok((jar, res))
+}
+
+async fn batch_activities(
+    app_state: &AppState,
+    body: BatchActivityPayload,
+    user_id: String,
+) -> Result<(StatusCode, Json<BatchActivityResponse>), AppError> {
+    // operations that fail validation get their error in place and are not sent to storage
+    let mut results: Vec<Option<BatchResult>> = Vec::with_capacity(body.operations.len());
+    let mut writes = vec![];
+
+    for operation in body.operations {
+        let write = match operation {
+            BatchOperation::Create { activity } => activity
+                .validate()
+                .map_err(AppError::ValidationFailed)
+                .map(|_| ActivityWrite::Create(activity)),
+            BatchOperation::Update { id, activity } => {
+                let checked = validate_object_id("id", &id)
+                    .and_then(|_| activity.validate().map_err(AppError::ValidationFailed));
+
+                match checked {
+                    Ok(_) => {
+                        let v = activity.v;
+                        Ok(ActivityWrite::Update(patch_payload(id, activity, v)))
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            BatchOperation::Delete { id, v } => validate_object_id("id", &id)
+                .map(|_| ActivityWrite::Delete(DeleteActivityPayload { id, v })),
+        };
+
+        match write {
+            Ok(write) => {
+                writes.push(write);
+                results.push(None);
+            }
+            Err(e) => results.push(Some(BatchResult::from(e))),
+        }
+    }
+
+    let written = app_state
+        .db
+        .write_activities(writes, user_id.clone())
+        .await?;
+
+    try_refresh_records(
+        &*app_state.db,
+        &user_id,
+        WrittenActivities::from_results(&written),
+    )
+    .await;
+
+    let mut written = written.into_iter();
+
+    let results = results
+        .into_iter()
+        .map(|res| match res {
+            Some(res) => res,
+            None => match written.next() {
+                Some(Ok(res)) => batch_result(res),
+                Some(Err(e)) => BatchResult::from(AppError::from(e)),
+                None => BatchResult::from(AppError::internal("missing batch result")),
+            },
+        })
+        .collect();
+
+    Ok((StatusCode::OK, Json(BatchActivityResponse { results })))
+}
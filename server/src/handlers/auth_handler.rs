@@ -1,5 +1,3 @@
-use std::usize;
-
 use axum::extract::State;
 use axum::http::{HeaderMap, StatusCode};
 use axum::{Extension, Json};
@@ -13,13 +11,20 @@ use regex::Regex;
 use reqwest::header::CACHE_CONTROL;
 use time::OffsetDateTime;
 
-use crate::error::error::AuthError;
+use crate::error::error::{AppError, FieldError};
+use crate::mail::mailer::Mail;
 use crate::models::auth_model::{
-    AccessToken, AuthPayload, GoogleCertsResponse, GoogleClaims, RefreshToken, TokenDB,
+    AccessClaims, AccessToken, AuthPayload, ForgotPasswordPayload, GoogleCertsResponse,
+    GoogleClaims, RefreshToken, ResetPasswordPayload, ResetTokenDB, TokenDB, VerifyEmailClaims,
+    VerifyEmailPayload, RESET_TOKEN_TTL_MINUTES, VERIFY_TOKEN_TTL_HOURS,
 };
 use crate::models::state_model::GoogleCerts;
-use crate::models::user_model::{RegisterUserPayload, UserResponse};
-use crate::utils::utils::generate_password;
+use crate::models::user_model::{
+    hash_password, ChangePasswordPayload, RegisterUserPayload, User, UserResponse,
+};
+use crate::utils::request_id::current_request_id;
+use crate::utils::utils::{generate_password, hash_token};
+use crate::utils::validation::ValidatedJson;
 use crate::{AppState, GoogleCertsState};
 
 /*
@@ -67,29 +72,33 @@ pub async fn authorize(
     State(app_state): State<AppState>,
     private_jar: PrivateCookieJar,
     Json(body): Json<AuthPayload>,
-) -> Result<(PrivateCookieJar, (StatusCode, Json<UserResponse>)), AuthError> {
+) -> Result<(PrivateCookieJar, (StatusCode, Json<UserResponse>)), AppError> {
     // Check if the user sent the credentials
     if body.email.is_empty() || body.pass.is_empty() {
-        return Err(AuthError::MissingCredentials);
+        return Err(AppError::MissingCredentials);
     }
 
     // fetch user
     let user = match app_state.db.get_user_by_email(&body.email).await {
         Ok(res) => match res {
             Some(v) => v,
-            None => return Err(AuthError::WrongCredentials),
+            None => return Err(AppError::WrongCredentials),
         },
-        Err(_) => return Err(AuthError::InternalError),
+        Err(e) => return Err(AppError::from(e)),
     };
 
     // verify password
     match bcrypt::verify(&body.pass, &user.pass) {
         Ok(v) => {
-            if v == false {
-                return Err(AuthError::WrongCredentials);
+            if !v {
+                return Err(AppError::WrongCredentials);
             }
         }
-        Err(_) => return Err(AuthError::InternalError),
+        Err(_) => return Err(AppError::internal("failed to verify password")),
+    }
+
+    if !user.active {
+        return Err(AppError::AccountDeleted);
     }
 
     // create tokens
@@ -109,9 +118,9 @@ pub async fn authorize(
     let user = match app_state.db.get_user_by_email(&body.email).await {
         Ok(user) => match user {
             Some(u) => Ok(u),
-            None => Err(AuthError::InternalError),
+            None => Err(AppError::UserNotFound),
         },
-        Err(_) => Err(AuthError::InternalError),
+        Err(e) => Err(AppError::from(e)),
     }?;
 
     Ok((
@@ -150,14 +159,14 @@ pub async fn authorize_oauth(
     Extension(google_certs): Extension<GoogleCertsState>,
     private_jar: PrivateCookieJar,
     headers: HeaderMap,
-) -> Result<(PrivateCookieJar, (StatusCode, Json<UserResponse>)), AuthError> {
+) -> Result<(PrivateCookieJar, (StatusCode, Json<UserResponse>)), AppError> {
     // get jwt from headers and decode it
     let jwt = match headers.get("Authorization") {
         Some(v) => match v.to_str() {
             Ok(v) => Ok(v),
-            Err(_) => Err(AuthError::InternalError),
+            Err(_) => Err(AppError::InvalidToken),
         },
-        _ => Err(AuthError::InternalError),
+        _ => Err(AppError::MissingToken),
     }?;
 
     let now_timestamp = (Utc::now().naive_utc()).and_utc().timestamp() as usize;
@@ -180,7 +189,7 @@ pub async fn authorize_oauth(
         _ => {
             let res = match app_state.client.get(google_certs_url).send().await {
                 Ok(v) => Ok(v),
-                Err(_) => Err(AuthError::InternalError),
+                Err(_) => Err(AppError::internal("failed to fetch google certs")),
             }?;
 
             // extract the Cache-Control header
@@ -190,22 +199,21 @@ pub async fn authorize_oauth(
                 .and_then(|value| value.to_str().ok());
 
             // extract the max-age value
-            let exp = match cache_control_header.and_then(|cache_control| {
-                let re = Regex::new(r"max-age=(\d+)").ok()?;
-                re.captures(cache_control)
-                    .and_then(|cap| cap.get(1)?.as_str().parse::<i64>().ok())
-            }) {
-                Some(v) => Some(
+            let exp = cache_control_header
+                .and_then(|cache_control| {
+                    let re = Regex::new(r"max-age=(\d+)").ok()?;
+                    re.captures(cache_control)
+                        .and_then(|cap| cap.get(1)?.as_str().parse::<i64>().ok())
+                })
+                .map(|v| {
                     (Utc::now().naive_utc() + chrono::Duration::seconds(v))
                         .and_utc()
-                        .timestamp() as usize,
-                ),
-                None => None,
-            };
+                        .timestamp() as usize
+                });
 
             let new_certs = match res.json::<GoogleCertsResponse>().await {
                 Ok(v) => Ok(v),
-                Err(_) => Err(AuthError::InternalError),
+                Err(_) => Err(AppError::internal("failed to parse google certs")),
             }?;
 
             {
@@ -221,9 +229,9 @@ pub async fn authorize_oauth(
     };
 
     // verify jwt using google public jwk keys
-    let header = match decode_header(&jwt) {
+    let header = match decode_header(jwt) {
         Ok(v) => Ok(v),
-        Err(_) => Err(AuthError::InternalError),
+        Err(_) => Err(AppError::InvalidToken),
     }?;
 
     let jwk = match certs.keys.into_iter().find(|c| {
@@ -234,21 +242,21 @@ pub async fn authorize_oauth(
             }
     }) {
         Some(v) => Ok(v),
-        _ => Err(AuthError::InternalError),
+        _ => Err(AppError::InvalidToken),
     }?;
 
     let decoding_key = match DecodingKey::from_rsa_components(&jwk.n, &jwk.e) {
         Ok(v) => Ok(v),
-        Err(_) => Err(AuthError::InternalError),
+        Err(_) => Err(AppError::internal("invalid google cert")),
     }?;
 
     let mut validation = Validation::new(Algorithm::RS256);
     validation.set_audience(&[&app_state.env.google_token_aud]);
     validation.set_required_spec_claims(&["exp", "sub", "aud"]);
 
-    let decoded_token = match decode::<GoogleClaims>(&jwt, &decoding_key, &validation) {
+    let decoded_token = match decode::<GoogleClaims>(jwt, &decoding_key, &validation) {
         Ok(v) => Ok(v),
-        Err(_) => Err(AuthError::InternalError),
+        Err(_) => Err(AppError::InvalidToken),
     }?;
 
     // attempt to use email to get user from db
@@ -266,23 +274,34 @@ pub async fn authorize_oauth(
                     pass: generate_password(20),
                     given_name: decoded_token.claims.given_name,
                     family_name: decoded_token.claims.family_name,
+                    generated_pass: true,
                 };
 
                 match app_state.db.create_user(payload).await {
                     Ok(v) => match v {
                         Some(v) => Ok(v),
-                        None => return Err(AuthError::InternalError),
+                        None => return Err(AppError::internal("failed to create user")),
                     },
-                    Err(_) => Err(AuthError::InternalError),
+                    Err(e) => Err(AppError::from(e)),
                 }
             }
         },
-        Err(e) => {
-            println!("{:?}", e);
-            Err(AuthError::InternalError)
-        }
+        Err(e) => Err(AppError::from(e)),
     }?;
 
+    if !user.active {
+        return Err(AppError::AccountDeleted);
+    }
+
+    // google has confirmed the address, so the user doesn't need to
+    let mut user = user;
+    if decoded_token.claims.email_verified
+        && !user.verified
+        && app_state.db.set_user_verified(user.id, &user.email).await?
+    {
+        user.verified = true;
+    }
+
     // create tokens
     let access_token = AccessToken::new(&user.id.to_string())?;
     let refresh_token = RefreshToken::new(&user.id.to_string(), None)?;
@@ -309,18 +328,13 @@ pub async fn logout(
     State(app_state): State<AppState>,
     private_jar: PrivateCookieJar,
     public_jar: CookieJar,
-) -> Result<(StatusCode, PrivateCookieJar, CookieJar), AuthError> {
+) -> Result<(StatusCode, PrivateCookieJar, CookieJar), AppError> {
     // extract tokens from cookies
     let refresh_token = RefreshToken::try_from(&private_jar)?;
 
     // blacklist all associated user tokens
-    let _ = app_state
-        .db
-        .blacklist_user_tokens(
-            ObjectId::parse_str(&refresh_token.claims.sub)
-                .expect("failed to parse string to ObjectId"),
-        )
-        .await;
+    let uid = ObjectId::parse_str(&refresh_token.claims.sub).map_err(|_| AppError::InvalidToken)?;
+    let _ = app_state.db.blacklist_user_tokens(uid).await;
 
     let exp = match OffsetDateTime::from_unix_timestamp(
         (Utc::now().naive_utc() - chrono::Duration::days(7))
@@ -328,30 +342,25 @@ pub async fn logout(
             .timestamp(),
     ) {
         Ok(v) => Ok(v),
-        Err(_) => Err(AuthError::InternalError),
-    }
-    .unwrap();
+        Err(_) => Err(AppError::internal("failed to expire cookies")),
+    }?;
 
     let access_token = match AccessToken::try_from(&private_jar) {
         Ok(token) => Ok(Some(token)),
-        Err(err) if err == AuthError::MissingToken => Ok(None),
+        Err(AppError::MissingToken) => Ok(None),
         Err(err) => Err(err),
     }?;
 
     let mut updated_jar = private_jar;
 
     // invalidate cookies
-    match access_token {
-        Some(token) => {
-            let mut invalid_access_cookie = Cookie::from(&token);
-            invalid_access_cookie.set_value("");
-            invalid_access_cookie.set_expires(exp);
-
-            updated_jar = updated_jar.remove(Cookie::from(&token));
-            updated_jar = updated_jar.add(invalid_access_cookie);
-            ()
-        }
-        None => (),
+    if let Some(token) = access_token {
+        let mut invalid_access_cookie = Cookie::from(&token);
+        invalid_access_cookie.set_value("");
+        invalid_access_cookie.set_expires(exp);
+
+        updated_jar = updated_jar.remove(Cookie::from(&token));
+        updated_jar = updated_jar.add(invalid_access_cookie);
     }
 
     let mut invalid_refresh_cookie = Cookie::from(&refresh_token);
@@ -373,14 +382,14 @@ pub async fn register_user(
     State(app_state): State<AppState>,
     private_jar: PrivateCookieJar,
     Json(body): Json<RegisterUserPayload>,
-) -> Result<(PrivateCookieJar, (StatusCode, Json<UserResponse>)), AuthError> {
+) -> Result<(PrivateCookieJar, (StatusCode, Json<UserResponse>)), AppError> {
     // create user account
     let new_user = match app_state.db.create_user(body).await {
         Ok(user) => match user {
             Some(u) => Ok(u),
-            _ => Err(AuthError::InternalError),
+            _ => Err(AppError::internal("failed to create user")),
         },
-        Err(_) => Err(AuthError::InternalError),
+        Err(e) => Err(AppError::from(e)),
     }?;
 
     // create tokens
@@ -397,6 +406,8 @@ pub async fn register_user(
         .create_token(TokenDB::from(&refresh_token))
         .await;
 
+    send_verification_mail(&app_state, &new_user).await?;
+
     Ok((
         private_jar
             .add(Cookie::from(&access_token))
@@ -405,5 +416,191 @@ pub async fn register_user(
     ))
 }
 
-// todo:: update_password
-// todo:: reset_password
+// a failed send is only logged, the user can ask for another mail
+async fn send_verification_mail(app_state: &AppState, user: &User) -> Result<(), AppError> {
+    let token = VerifyEmailClaims::new(&user.id, &user.email).encode()?;
+
+    let mail = Mail {
+        to: user.email.clone(),
+        subject: "Verify your email address".to_string(),
+        body: format!(
+            "Follow this link to verify your email address, it expires in {} hours:\n\n\
+             {}/verify-email?token={}",
+            VERIFY_TOKEN_TTL_HOURS, app_state.env.app_url, token
+        ),
+    };
+
+    if let Err(e) = app_state.mailer.send(mail).await {
+        tracing::error!(
+            request_id = current_request_id(),
+            "failed to send verification mail: {}",
+            e
+        );
+    }
+
+    Ok(())
+}
+
+/// Confirms the email address a verification token was mailed to. Works
+/// without a session so the link can be opened on any device.
+pub async fn verify_email(
+    State(app_state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<VerifyEmailPayload>,
+) -> Result<StatusCode, AppError> {
+    let claims = VerifyEmailClaims::decode(&body.token)?;
+    let uid = ObjectId::parse_str(&claims.sub).map_err(|_| AppError::InvalidVerificationToken)?;
+
+    if !app_state.db.set_user_verified(uid, &claims.email).await? {
+        return Err(AppError::InvalidVerificationToken);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Mails a new verification link to the signed in user, unless they are
+/// already verified.
+pub async fn resend_verification(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    State(app_state): State<AppState>,
+) -> Result<(PrivateCookieJar, StatusCode), AppError> {
+    let uid = ObjectId::parse_str(&claims.sub).map_err(|_| AppError::InvalidToken)?;
+
+    let user = app_state
+        .db
+        .get_user_by_id(uid)
+        .await?
+        .ok_or(AppError::UserNotFound)?;
+
+    if !user.verified {
+        send_verification_mail(&app_state, &user).await?;
+    }
+
+    Ok((jar, StatusCode::NO_CONTENT))
+}
+
+/// Changes the password of the signed in user, or sets one for oauth users
+/// who have never chosen their own. Every other session is signed out and
+/// this one carries on with fresh tokens.
+pub async fn change_password(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    State(app_state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<ChangePasswordPayload>,
+) -> Result<(PrivateCookieJar, StatusCode), AppError> {
+    let uid = ObjectId::parse_str(&claims.sub).map_err(|_| AppError::InvalidToken)?;
+
+    let user = app_state
+        .db
+        .get_user_by_id(uid)
+        .await?
+        .ok_or(AppError::UserNotFound)?;
+
+    if user.password_set {
+        let current = match &body.current {
+            Some(v) if !v.is_empty() => v,
+            _ => {
+                return Err(AppError::ValidationFailed(vec![FieldError::new(
+                    "current",
+                    "must not be empty",
+                )]))
+            }
+        };
+
+        match bcrypt::verify(current, &user.pass) {
+            Ok(true) => {}
+            Ok(false) => return Err(AppError::WrongCredentials),
+            Err(_) => return Err(AppError::internal("failed to verify password")),
+        }
+    }
+
+    let hash = hash_password(&body.pass)?;
+    app_state.db.update_user_password(uid, hash).await?;
+
+    // revoke every session, including this one, then issue new tokens for it
+    app_state.db.blacklist_user_tokens(uid).await?;
+
+    let access_token = AccessToken::new(&claims.sub)?;
+    let refresh_token = RefreshToken::new(&claims.sub, None)?;
+
+    app_state
+        .db
+        .create_token(TokenDB::from(&access_token))
+        .await?;
+    app_state
+        .db
+        .create_token(TokenDB::from(&refresh_token))
+        .await?;
+
+    Ok((
+        jar.add(Cookie::from(&access_token))
+            .add(Cookie::from(&refresh_token)),
+        StatusCode::NO_CONTENT,
+    ))
+}
+
+/// Mails a reset link if the email belongs to a user. Always answers 204 so
+/// the endpoint can't be used to find out which emails are registered.
+pub async fn forgot_password(
+    State(app_state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<ForgotPasswordPayload>,
+) -> Result<StatusCode, AppError> {
+    let user = match app_state.db.get_user_by_email(body.email.trim()).await? {
+        Some(user) if user.active => user,
+        _ => return Ok(StatusCode::NO_CONTENT),
+    };
+
+    let (record, token) = ResetTokenDB::new(user.id);
+    app_state.db.create_reset_token(record).await?;
+
+    let mail = Mail {
+        to: user.email.clone(),
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Follow this link to choose a new password, it expires in {} minutes:\n\n\
+             {}/reset-password?token={}\n\n\
+             If you did not ask to reset your password you can ignore this email.",
+            RESET_TOKEN_TTL_MINUTES, app_state.env.app_url, token
+        ),
+    };
+
+    if let Err(e) = app_state.mailer.send(mail).await {
+        tracing::error!(
+            request_id = current_request_id(),
+            "failed to send password reset mail: {}",
+            e
+        );
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Sets a new password using a mailed reset token and signs out every session.
+pub async fn reset_password(
+    State(app_state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<ResetPasswordPayload>,
+) -> Result<StatusCode, AppError> {
+    let record = app_state
+        .db
+        .consume_reset_token(&hash_token(&body.token))
+        .await?
+        .ok_or(AppError::InvalidResetToken)?;
+
+    // the account may have been deleted since the token was mailed
+    let user = app_state
+        .db
+        .get_user_by_id(record.uid)
+        .await?
+        .ok_or(AppError::InvalidResetToken)?;
+
+    if !user.active {
+        return Err(AppError::AccountDeleted);
+    }
+
+    let hash = hash_password(&body.pass)?;
+
+    app_state.db.update_user_password(record.uid, hash).await?;
+    app_state.db.blacklist_user_tokens(record.uid).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
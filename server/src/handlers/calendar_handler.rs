@@ -0,0 +1,116 @@
+use axum::{
+    extract::{Path, State},
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::IntoResponse,
+    Extension, Json,
+};
+use axum_extra::extract::PrivateCookieJar;
+use mongodb::bson::{oid::ObjectId, DateTime};
+
+use crate::{
+    error::error::AppError,
+    models::{
+        activity_model::GetActivitiesPayload,
+        auth_model::AccessClaims,
+        calendar_model::{CalendarTokenResponse, CALENDAR_NAME, FEED_SERIES_DAYS},
+    },
+    utils::{
+        ical::render_calendar,
+        series::{merge_occurrences, series_occurrences},
+        utils::{generate_token, hash_token, parse_date},
+        validation::ValidatedQuery,
+    },
+    AppState,
+};
+
+// curl -X POST http://localhost:8000/api/v1/calendar
+
+/// Creates the user's calendar feed token, replacing and so revoking any
+/// previous one.
+pub async fn create_calendar_token_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    State(app_state): State<AppState>,
+) -> Result<(PrivateCookieJar, (StatusCode, Json<CalendarTokenResponse>)), AppError> {
+    let uid = ObjectId::parse_str(&claims.sub).map_err(|_| AppError::InvalidToken)?;
+
+    let token = generate_token();
+    if !app_state
+        .db
+        .set_calendar_token(uid, Some(hash_token(&token)))
+        .await?
+    {
+        return Err(AppError::UserNotFound);
+    }
+
+    Ok((
+        jar,
+        (StatusCode::CREATED, Json(CalendarTokenResponse::new(token))),
+    ))
+}
+
+// curl -X DELETE http://localhost:8000/api/v1/calendar
+
+pub async fn revoke_calendar_token_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    State(app_state): State<AppState>,
+) -> Result<(PrivateCookieJar, StatusCode), AppError> {
+    let uid = ObjectId::parse_str(&claims.sub).map_err(|_| AppError::InvalidToken)?;
+
+    if !app_state.db.set_calendar_token(uid, None).await? {
+        return Err(AppError::UserNotFound);
+    }
+
+    Ok((jar, StatusCode::NO_CONTENT))
+}
+
+// curl -X GET "http://localhost:8000/api/v1/calendar/<token>.ics" --data-urlencode "group=sport"
+
+/// The user's activities as an iCalendar feed. Authenticated by the token in
+/// the path alone, since calendar apps can't send cookies. Takes the same
+/// filters as the activity list, and `timezone` renders every event in that
+/// offset instead of its own. Occurrences of series are listed as events.
+pub async fn calendar_feed_handler(
+    Path(file): Path<String>,
+    State(app_state): State<AppState>,
+    ValidatedQuery(query): ValidatedQuery<GetActivitiesPayload>,
+) -> Result<impl IntoResponse, AppError> {
+    let token = file
+        .strip_suffix(".ics")
+        .ok_or(AppError::CalendarNotFound)?;
+
+    let user = match app_state
+        .db
+        .get_user_by_calendar_token(&hash_token(token))
+        .await?
+    {
+        Some(user) if user.active => user,
+        _ => return Err(AppError::CalendarNotFound),
+    };
+
+    // series repeat forever, so a missing bound only expands them so far from now
+    let now = DateTime::now().timestamp_millis();
+    let window = FEED_SERIES_DAYS * 24 * 60 * 60 * 1000;
+    let start = match &query.start {
+        Some(start) => parse_date(start)?,
+        None => DateTime::from_millis(now - window),
+    };
+    let end = match &query.end {
+        Some(end) => parse_date(end)?,
+        None => DateTime::from_millis(now + window),
+    };
+    let user_id = user.id.to_hex();
+    let occurrences = series_occurrences(&*app_state.db, &user_id, &query, start, end).await?;
+
+    let timezone = query.timezone;
+    let limit = query.limit;
+    let mut activities = app_state.db.get_activities(query, user_id).await?;
+    merge_occurrences(&mut activities, occurrences, limit);
+
+    Ok((
+        StatusCode::OK,
+        [(CONTENT_TYPE, "text/calendar; charset=utf-8")],
+        render_calendar(CALENDAR_NAME, &activities, timezone),
+    ))
+}
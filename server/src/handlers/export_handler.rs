@@ -0,0 +1,217 @@
+use std::io;
+
+use async_zip::{
+    base::write::ZipFileWriter, tokio::write::ZipFileWriter as TokioZipFileWriter, Compression,
+    ZipEntryBuilder,
+};
+use axum::{
+    body::Body,
+    extract::State,
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use axum_extra::extract::PrivateCookieJar;
+use futures::{future, stream, stream::BoxStream, AsyncWriteExt, StreamExt, TryStreamExt};
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::Serialize;
+use tokio::io::{AsyncWriteExt as _, DuplexStream};
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    error::error::AppError,
+    models::{
+        activity_model::{Activity, ActivityResponse},
+        auth_model::AccessClaims,
+        export_model::{
+            ActivityRow, ColorRow, ExportFormat, ExportHeader, ExportPayload, ProfileRow, SetRow,
+            SplitRow,
+        },
+        user_model::{User, UserResponse},
+    },
+    utils::{request_id::current_request_id, validation::ValidatedQuery},
+    AppState,
+};
+
+// bytes buffered between the task writing the export and the response body
+const EXPORT_BUFFER_SIZE: usize = 64 * 1024;
+
+// curl -GET "http://localhost:8000/api/v1/me/export"
+// curl -GET "http://localhost:8000/api/v1/me/export" --data-urlencode "format=zip" -o export.zip
+
+/// Streams all of the user's data as it is read from storage. If reading fails
+/// part way the response is cut off with an error rather than ending cleanly.
+pub async fn export_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    State(app_state): State<AppState>,
+    ValidatedQuery(query): ValidatedQuery<ExportPayload>,
+) -> Result<(PrivateCookieJar, Response), AppError> {
+    let uid = ObjectId::parse_str(&claims.sub).map_err(|_| AppError::InvalidToken)?;
+
+    let user = app_state
+        .db
+        .get_user_by_id(uid)
+        .await?
+        .ok_or(AppError::UserNotFound)?;
+
+    let (reader, writer) = tokio::io::duplex(EXPORT_BUFFER_SIZE);
+    let request_id = current_request_id();
+
+    let task = match query.format {
+        ExportFormat::Json => tokio::spawn(write_json(app_state.clone(), user, writer)),
+        ExportFormat::Zip => tokio::spawn(write_zip(app_state.clone(), user, writer)),
+    };
+
+    // once everything written has been sent, fail the body if the writer did
+    let result = stream::once(async move {
+        let err = match task.await {
+            Ok(Ok(())) => return None,
+            Ok(Err(e)) => e,
+            Err(e) => io::Error::other(e),
+        };
+
+        tracing::error!(request_id, "failed to export user data: {}", err);
+        Some(Err(err))
+    })
+    .filter_map(future::ready);
+
+    let body = Body::from_stream(ReaderStream::new(reader).chain(result));
+
+    let date = DateTime::now().try_to_rfc3339_string().unwrap_or_default();
+    let (content_type, extension) = match query.format {
+        ExportFormat::Json => ("application/json", "json"),
+        ExportFormat::Zip => ("application/zip", "zip"),
+    };
+
+    let headers = [
+        (CONTENT_TYPE, content_type.to_string()),
+        (
+            CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"chrono-export-{}.{}\"",
+                &date[..10.min(date.len())],
+                extension
+            ),
+        ),
+    ];
+
+    Ok((jar, (headers, body).into_response()))
+}
+
+async fn activities(
+    app_state: &AppState,
+    user: &User,
+) -> io::Result<BoxStream<'static, Result<Activity, mongodb::error::Error>>> {
+    app_state
+        .db
+        .stream_activities(user.id.to_hex())
+        .await
+        .map_err(io::Error::other)
+}
+
+async fn write_json(app_state: AppState, user: User, mut out: DuplexStream) -> io::Result<()> {
+    let mut activities = activities(&app_state, &user).await?;
+
+    let header = serde_json::to_vec(&ExportHeader {
+        exported_at: DateTime::now().try_to_rfc3339_string().unwrap_or_default(),
+        user: UserResponse::from(user),
+    })?;
+
+    // reopen the header object to append the activities array to it
+    out.write_all(&header[..header.len() - 1]).await?;
+    out.write_all(b",\"activities\":[").await?;
+
+    let mut first = true;
+    while let Some(activity) = activities.try_next().await.map_err(io::Error::other)? {
+        if !first {
+            out.write_all(b",").await?;
+        }
+        first = false;
+
+        let activity = serde_json::to_vec(&ActivityResponse::from(activity))?;
+        out.write_all(&activity).await?;
+    }
+
+    out.write_all(b"]}").await?;
+    out.shutdown().await
+}
+
+fn zip_entry(name: &str) -> ZipEntryBuilder {
+    ZipEntryBuilder::new(name.to_string().into(), Compression::Deflate)
+}
+
+// the header row is taken from the field names of the first row
+fn csv_bytes<R: Serialize>(
+    rows: impl IntoIterator<Item = R>,
+    headers: bool,
+) -> io::Result<Vec<u8>> {
+    let mut csv = csv::WriterBuilder::new()
+        .has_headers(headers)
+        .from_writer(vec![]);
+
+    for row in rows {
+        csv.serialize(row)?;
+    }
+
+    csv.into_inner().map_err(|e| e.into_error())
+}
+
+// the activities are read once per csv, so a user writing during the export
+// can see a change in one file and not another
+async fn write_zip(app_state: AppState, user: User, out: DuplexStream) -> io::Result<()> {
+    let mut zip = ZipFileWriter::with_tokio(out);
+
+    zip.write_entry_whole(
+        zip_entry("profile.csv"),
+        &csv_bytes([ProfileRow::from(&user)], true)?,
+    )
+    .await
+    .map_err(io::Error::other)?;
+    zip.write_entry_whole(
+        zip_entry("colors.csv"),
+        &csv_bytes(ColorRow::rows(&user), true)?,
+    )
+    .await
+    .map_err(io::Error::other)?;
+
+    let stream = activities(&app_state, &user).await?;
+    write_csv_entry(&mut zip, "activities.csv", stream, |a| {
+        vec![ActivityRow::from(a)]
+    })
+    .await?;
+
+    let stream = activities(&app_state, &user).await?;
+    write_csv_entry(&mut zip, "sets.csv", stream, SetRow::rows).await?;
+
+    let stream = activities(&app_state, &user).await?;
+    write_csv_entry(&mut zip, "splits.csv", stream, SplitRow::rows).await?;
+
+    let mut out = zip.close().await.map_err(io::Error::other)?.into_inner();
+    out.shutdown().await
+}
+
+async fn write_csv_entry<R: Serialize>(
+    zip: &mut TokioZipFileWriter<DuplexStream>,
+    name: &str,
+    mut activities: BoxStream<'static, Result<Activity, mongodb::error::Error>>,
+    rows: impl Fn(&Activity) -> Vec<R>,
+) -> io::Result<()> {
+    let mut entry = zip
+        .write_entry_stream(zip_entry(name))
+        .await
+        .map_err(io::Error::other)?;
+    let mut headers = true;
+
+    while let Some(activity) = activities.try_next().await.map_err(io::Error::other)? {
+        let rows = rows(&activity);
+        if rows.is_empty() {
+            continue;
+        }
+
+        entry.write_all(&csv_bytes(rows, headers)?).await?;
+        headers = false;
+    }
+
+    entry.close().await.map_err(io::Error::other)
+}
@@ -0,0 +1,737 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
+    time::Duration as StdDuration,
+};
+
+use axum::{
+    body::Bytes,
+    extract::{rejection::BytesRejection, State},
+    http::{header::CONTENT_TYPE, HeaderMap, StatusCode},
+    Extension, Json,
+};
+use axum_extra::extract::PrivateCookieJar;
+use chrono::{Duration, FixedOffset, NaiveDateTime, NaiveTime, SecondsFormat};
+use chrono_tz::Tz;
+use mongodb::bson::{oid::ObjectId, DateTime};
+use reqwest::{header::LOCATION, redirect::Policy, Url};
+use tokio::net::lookup_host;
+
+use crate::{
+    error::error::{AppError, FieldError},
+    models::{
+        activity_model::{ActivityVariant, ActivityWrite, PostActivityPayload},
+        auth_model::AccessClaims,
+        import_model::{
+            CsvActivityRow, IcalField, IcalImportPayload, IcalImportResponse, ImportDocument,
+            ImportPayload, ImportResponse, ICAL_FETCH_TIMEOUT_SECS, MAX_ICAL_OCCURRENCES,
+            MAX_ICAL_REDIRECTS, MAX_IMPORT_BYTES, MAX_IMPORT_ROWS,
+        },
+    },
+    utils::{
+        auth::require_write_access,
+        ical::{
+            parse_calendar, parse_duration, split_text_list, timezone_of, unescape_text, Component,
+            IcalTime, RecurrenceRule, Zone,
+        },
+        records::{try_refresh_records, WrittenActivities},
+        request_id::current_request_id,
+        validation::{Validate, ValidatedQuery},
+    },
+    AppState,
+};
+
+type Row = Result<PostActivityPayload, Vec<FieldError>>;
+
+fn body_error(rejection: BytesRejection) -> AppError {
+    match rejection.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge,
+        _ => AppError::InvalidBody(rejection.body_text()),
+    }
+}
+
+// the media type without parameters such as charset
+fn content_type(headers: &HeaderMap) -> &str {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .unwrap_or_default()
+        .trim()
+}
+
+// prefixes each error with the row it belongs to
+fn row_errors(idx: usize, errors: Vec<FieldError>) -> impl Iterator<Item = FieldError> {
+    errors.into_iter().map(move |e| {
+        let field = match e.field.as_str() {
+            "body" | "" => format!("rows[{}]", idx),
+            field => format!("rows[{}].{}", idx, field),
+        };
+        FieldError::new(field, e.message)
+    })
+}
+
+fn parse_json(body: &[u8]) -> Result<(Vec<Row>, HashMap<String, String>), AppError> {
+    let document: ImportDocument = serde_json::from_slice(body)
+        .map_err(|e| AppError::InvalidBody(format!("invalid import document: {}", e)))?;
+
+    let rows = document
+        .activities
+        .into_iter()
+        .map(|value| {
+            serde_path_to_error::deserialize(value).map_err(|e| {
+                let field = match e.path().to_string().as_str() {
+                    "." => "body".to_string(),
+                    p => p.to_string(),
+                };
+                vec![FieldError::new(field, e.inner().to_string())]
+            })
+        })
+        .collect();
+
+    let colors = document.user.map(|u| u.activities).unwrap_or_default();
+
+    Ok((rows, colors))
+}
+
+const CSV_REQUIRED_COLUMNS: [&str; 4] = ["title", "group", "start", "end"];
+
+fn parse_csv(body: &[u8]) -> Result<(Vec<Row>, HashMap<String, String>), AppError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::Headers)
+        .from_reader(body);
+
+    let headers = reader
+        .headers()
+        .map_err(|e| AppError::InvalidBody(format!("invalid csv: {}", e)))?
+        .clone();
+
+    let missing: Vec<FieldError> = CSV_REQUIRED_COLUMNS
+        .iter()
+        .filter(|c| !headers.iter().any(|h| h == **c))
+        .map(|c| FieldError::new("columns", format!("missing column {}", c)))
+        .collect();
+    if !missing.is_empty() {
+        return Err(AppError::ValidationFailed(missing));
+    }
+
+    let rows = reader
+        .deserialize::<CsvActivityRow>()
+        .map(|row| {
+            let row = row.map_err(|e| {
+                let (field, message) = match e.kind() {
+                    csv::ErrorKind::Deserialize { err, .. } => (
+                        err.field()
+                            .and_then(|idx| headers.get(idx as usize))
+                            .unwrap_or("body"),
+                        err.kind().to_string(),
+                    ),
+                    _ => ("body", e.to_string()),
+                };
+                vec![FieldError::new(field, message)]
+            })?;
+
+            PostActivityPayload::try_from(row).map_err(|e| vec![e])
+        })
+        .collect();
+
+    Ok((rows, HashMap::new()))
+}
+
+// curl -X POST "http://localhost:8000/api/v1/me/import?dryRun=true" -H "Content-Type: text/csv" --data-binary @activities.csv
+// curl -X POST "http://localhost:8000/api/v1/me/import" -H "Content-Type: application/json" --data-binary @export.json
+
+/// Imports activities from a json export or a csv. Every row is validated
+/// first and nothing is written unless they all pass.
+pub async fn import_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    State(app_state): State<AppState>,
+    ValidatedQuery(query): ValidatedQuery<ImportPayload>,
+    headers: HeaderMap,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<(PrivateCookieJar, (StatusCode, Json<ImportResponse>)), AppError> {
+    require_write_access(&app_state, &claims.sub).await?;
+
+    let body = body.map_err(body_error)?;
+
+    let (rows, mut colors) = match content_type(&headers) {
+        "application/json" => parse_json(&body)?,
+        "text/csv" => parse_csv(&body)?,
+        _ => return Err(AppError::UnsupportedMediaType),
+    };
+
+    if rows.len() > MAX_IMPORT_ROWS {
+        return Err(AppError::ValidationFailed(vec![FieldError::new(
+            "rows",
+            format!("must not contain more than {}", MAX_IMPORT_ROWS),
+        )]));
+    }
+
+    let row_count = rows.len();
+    let mut payloads = Vec::with_capacity(row_count);
+    let mut errors = vec![];
+
+    for (idx, row) in rows.into_iter().enumerate() {
+        match row.and_then(|payload| payload.validate().map(|_| payload)) {
+            Ok(payload) => payloads.push(payload),
+            Err(e) => errors.extend(row_errors(idx, e)),
+        }
+    }
+
+    // colors given with an activity win over the imported map
+    for payload in payloads.iter_mut() {
+        if let Some(color) = payload.color.take() {
+            colors.insert(payload.title.clone(), color);
+        }
+    }
+
+    let mut res = ImportResponse {
+        dry_run: query.dry_run,
+        rows: row_count,
+        valid: payloads.len(),
+        imported: 0,
+        colors: colors.len(),
+        errors: vec![],
+    };
+
+    if query.dry_run {
+        res.errors = errors;
+        return Ok((jar, (StatusCode::OK, Json(res))));
+    }
+
+    if !errors.is_empty() {
+        return Err(AppError::ValidationFailed(errors));
+    }
+
+    let uid = ObjectId::parse_str(&claims.sub).map_err(|_| AppError::InvalidToken)?;
+    let writes = payloads.into_iter().map(ActivityWrite::Create).collect();
+
+    let results = app_state
+        .db
+        .write_activities(writes, claims.sub.clone())
+        .await?;
+    res.imported = results.iter().filter(|r| r.is_ok()).count();
+
+    try_refresh_records(
+        &*app_state.db,
+        &claims.sub,
+        WrittenActivities::from_results(&results),
+    )
+    .await;
+
+    // rows written before a failure are kept, there is no transaction to undo them
+    if let Some(Err(e)) = results.into_iter().find(|r| r.is_err()) {
+        tracing::error!(
+            request_id = current_request_id(),
+            imported = res.imported,
+            rows = res.rows,
+            "import failed part way"
+        );
+        return Err(AppError::from(e));
+    }
+
+    app_state.db.set_activity_colors(uid, colors).await?;
+
+    Ok((jar, (StatusCode::CREATED, Json(res))))
+}
+
+fn rfc3339(utc: NaiveDateTime) -> String {
+    utc.and_utc().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+// identifies an occurrence by its date if all-day, so the key doesn't depend
+// on the timezone it is imported in, and by its utc start otherwise
+fn occurrence_key(uid: &str, zone: &Zone, all_day: bool, wall: NaiveDateTime) -> String {
+    match all_day {
+        true => format!("{}/{}", uid, wall.format("%Y%m%d")),
+        false => format!("{}/{}", uid, zone.resolve(wall).0.format("%Y%m%dT%H%M%SZ")),
+    }
+}
+
+fn field_error(field: &'static str) -> impl Fn(String) -> FieldError {
+    move |message| FieldError::new(field, message)
+}
+
+/// Turns the VEVENTs of a calendar into activities, as configured by the
+/// import query.
+struct IcalReader<'a> {
+    query: &'a IcalImportPayload,
+    zones: HashMap<String, Zone>,
+    /// For all-day and floating events, and to show utc ones in.
+    default_zone: Zone,
+    /// Keys of occurrences replaced by an event with a RECURRENCE-ID.
+    overrides: HashSet<String>,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+}
+
+impl<'a> IcalReader<'a> {
+    fn new(
+        calendar: &Component,
+        query: &'a IcalImportPayload,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Self {
+        let zones = calendar
+            .components("VTIMEZONE")
+            .filter_map(|c| {
+                let tzid = c.property("TZID")?.value.trim().to_string();
+                Zone::from_vtimezone(c).ok().map(|zone| (tzid, zone))
+            })
+            .collect();
+
+        let default_zone = match query.timezone {
+            Some(timezone) => Zone::from_timezone(timezone),
+            None => calendar
+                .property("X-WR-TIMEZONE")
+                .and_then(|p| p.value.trim().parse::<Tz>().ok())
+                .map(Zone::Named)
+                .unwrap_or(Zone::from_timezone(0)),
+        };
+
+        let mut reader = Self {
+            query,
+            zones,
+            default_zone,
+            overrides: HashSet::new(),
+            from,
+            to,
+        };
+
+        reader.overrides = calendar
+            .components("VEVENT")
+            .filter_map(|event| {
+                let uid = event.property("UID")?.value.trim();
+                let id = IcalTime::parse(event.property("RECURRENCE-ID")?).ok()?;
+                reader.occurrence_key(uid, &id).ok()
+            })
+            .collect();
+
+        reader
+    }
+
+    // the zone a time's wall clock is in. utc times recur in utc
+    fn zone_of(&self, time: &IcalTime) -> Result<Zone, String> {
+        match time {
+            IcalTime::Utc(_) => Ok(Zone::from_timezone(0)),
+            IcalTime::Zoned(_, tzid) => match tzid.parse::<Tz>() {
+                Ok(tz) => Ok(Zone::Named(tz)),
+                Err(_) => self
+                    .zones
+                    .get(tzid)
+                    .cloned()
+                    .ok_or_else(|| format!("unknown TZID {}", tzid)),
+            },
+            IcalTime::Date(_) | IcalTime::Floating(_) => Ok(self.default_zone.clone()),
+        }
+    }
+
+    // the utc time of a wall clock time, and the offset to store with it
+    fn locate(&self, zone: &Zone, utc: bool, wall: NaiveDateTime) -> (NaiveDateTime, FixedOffset) {
+        let (at, offset) = zone.resolve(wall);
+
+        match utc {
+            true => (at, self.default_zone.offset_at(at)),
+            false => (at, offset),
+        }
+    }
+
+    fn occurrence_key(&self, uid: &str, time: &IcalTime) -> Result<String, String> {
+        let zone = self.zone_of(time)?;
+        Ok(occurrence_key(uid, &zone, time.is_date(), time.naive()))
+    }
+
+    fn text(&self, event: &Component, field: IcalField) -> String {
+        let name = match field {
+            IcalField::Summary => "SUMMARY",
+            IcalField::Categories => "CATEGORIES",
+            IcalField::Description => "DESCRIPTION",
+            IcalField::Location => "LOCATION",
+            IcalField::None => return String::new(),
+        };
+
+        match event.property(name) {
+            Some(p) if field == IcalField::Categories => split_text_list(&p.value)
+                .into_iter()
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string(),
+            Some(p) => unescape_text(&p.value).trim().to_string(),
+            None => String::new(),
+        }
+    }
+
+    /// The activities an event becomes, one per occurrence in the window if
+    /// it recurs. Cancelled events become none.
+    fn event_rows(&self, event: &Component) -> Result<Vec<PostActivityPayload>, FieldError> {
+        let uid = event
+            .property("UID")
+            .map(|p| p.value.trim())
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| FieldError::new("uid", "is required"))?;
+
+        if event
+            .property("STATUS")
+            .is_some_and(|p| p.value.trim().eq_ignore_ascii_case("CANCELLED"))
+        {
+            return Ok(vec![]);
+        }
+
+        let start = event
+            .property("DTSTART")
+            .ok_or_else(|| "is required".to_string())
+            .and_then(IcalTime::parse)
+            .map_err(field_error("dtstart"))?;
+        let zone = self.zone_of(&start).map_err(field_error("dtstart"))?;
+        let utc = matches!(start, IcalTime::Utc(_));
+
+        let length = match (event.property("DTEND"), event.property("DURATION")) {
+            (Some(end), _) => {
+                let end = IcalTime::parse(end).map_err(field_error("dtend"))?;
+                match (&start, &end) {
+                    (IcalTime::Date(start), IcalTime::Date(end)) => *end - *start,
+                    _ => {
+                        let end_zone = self.zone_of(&end).map_err(field_error("dtend"))?;
+                        end_zone.resolve(end.naive()).0 - zone.resolve(start.naive()).0
+                    }
+                }
+            }
+            (None, Some(duration)) => {
+                parse_duration(&duration.value).map_err(field_error("duration"))?
+            }
+            (None, None) if start.is_date() => Duration::days(1),
+            (None, None) => Duration::zero(),
+        };
+
+        let occurrences: Vec<(NaiveDateTime, String)> =
+            match (event.property("RECURRENCE-ID"), event.property("RRULE")) {
+                (Some(id), _) => {
+                    let id = IcalTime::parse(id).map_err(field_error("recurrence-id"))?;
+                    let key = self
+                        .occurrence_key(uid, &id)
+                        .map_err(field_error("recurrence-id"))?;
+                    vec![(start.naive(), key)]
+                }
+                (None, Some(rule)) => {
+                    let rule = RecurrenceRule::parse(&rule.value).map_err(field_error("rrule"))?;
+                    let until = rule.until.as_ref().map(|until| match until {
+                        IcalTime::Utc(t) => *t + zone.offset_at(*t),
+                        IcalTime::Date(d) => d.and_time(NaiveTime::MIN) + Duration::days(1),
+                        until => until.naive(),
+                    });
+
+                    let mut excluded = self.overrides.clone();
+                    for exdate in event.properties("EXDATE") {
+                        for time in IcalTime::parse_list(exdate).map_err(field_error("exdate"))? {
+                            excluded.insert(
+                                self.occurrence_key(uid, &time)
+                                    .map_err(field_error("exdate"))?,
+                            );
+                        }
+                    }
+
+                    let from = self.from + zone.offset_at(self.from);
+                    let to = self.to + zone.offset_at(self.to);
+
+                    let expanded =
+                        rule.expand(start.naive(), until, from, to, MAX_ICAL_OCCURRENCES + 1);
+                    if expanded.len() > MAX_ICAL_OCCURRENCES {
+                        return Err(FieldError::new(
+                            "rrule",
+                            format!(
+                                "must not repeat more than {} times within the window",
+                                MAX_ICAL_OCCURRENCES
+                            ),
+                        ));
+                    }
+
+                    expanded
+                        .into_iter()
+                        .map(|at| (at, occurrence_key(uid, &zone, start.is_date(), at)))
+                        .filter(|(_, key)| !excluded.contains(key))
+                        .collect()
+                }
+                (None, None) => vec![(start.naive(), uid.to_string())],
+            };
+
+        let title = self.text(event, self.query.title);
+        let group = self.text(event, self.query.group);
+        let notes = Some(self.text(event, self.query.notes)).filter(|n| !n.is_empty());
+
+        Ok(occurrences
+            .into_iter()
+            .map(|(wall, key)| {
+                let (at, offset) = self.locate(&zone, utc, wall);
+                // all-day events keep their length in days across dst changes
+                let end = match start.is_date() {
+                    true => zone.resolve(wall + length).0,
+                    false => at + length,
+                };
+
+                PostActivityPayload {
+                    title: title.clone(),
+                    variant: ActivityVariant::Default,
+                    group: group.clone(),
+                    notes: notes.clone(),
+                    start: rfc3339(at),
+                    end: rfc3339(end),
+                    timezone: timezone_of(offset),
+                    data: None,
+                    color: None,
+                    ical_uid: Some(key),
+                    occurrence: None,
+                }
+            })
+            .collect())
+    }
+}
+
+// `webcal` is how calendar links ask to be subscribed to, it's served as https
+fn calendar_url(url: &str) -> Option<Url> {
+    let url = match url.split_once("://") {
+        Some((scheme, rest)) if scheme.eq_ignore_ascii_case("webcal") => {
+            format!("https://{}", rest)
+        }
+        _ => url.to_string(),
+    };
+
+    Url::parse(&url)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// Whether an address is on the public internet. Calendars are fetched on a
+/// user's behalf, anything else could be a service only the server can reach.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                // "this network", shared address space and reserved
+                || a == 0
+                || (a == 100 && (64..128).contains(&b))
+                || a >= 240)
+        }
+        // covers v4-mapped and v4-compatible addresses
+        IpAddr::V6(ip) => match ip.to_ipv4() {
+            Some(v4) => is_public(IpAddr::V4(v4)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_unspecified()
+                    || ip.is_loopback()
+                    || ip.is_multicast()
+                    // unique local and link-local
+                    || (first & 0xfe00) == 0xfc00
+                    || (first & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+/// The addresses `url` resolves to, unless one of them is not public.
+async fn resolve_public(url: &Url, allow_private: bool) -> Option<Vec<SocketAddr>> {
+    let host = url
+        .host_str()?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let port = url.port_or_known_default()?;
+
+    let addrs: Vec<SocketAddr> = lookup_host((host, port)).await.ok()?.collect();
+    let allowed = allow_private || addrs.iter().all(|addr| is_public(addr.ip()));
+
+    (!addrs.is_empty() && allowed).then_some(addrs)
+}
+
+/// Fetches the calendar at `url`, following redirects to public addresses
+/// only. Non-public addresses are allowed with `ALLOW_PRIVATE_CALENDAR_URLS`.
+async fn fetch_calendar(url: &str, allow_private: bool) -> Result<Bytes, AppError> {
+    // one error for every failure, so imports can't probe what the server reaches
+    let unreachable =
+        || AppError::ValidationFailed(vec![FieldError::new("url", "could not be fetched")]);
+
+    let mut url = calendar_url(url).ok_or_else(unreachable)?;
+
+    for _ in 0..=MAX_ICAL_REDIRECTS {
+        let addrs = resolve_public(&url, allow_private)
+            .await
+            .ok_or_else(unreachable)?;
+        let host = url.host_str().ok_or_else(unreachable)?;
+
+        // connect to the addresses checked, not to whatever the host resolves to next
+        let client = reqwest::Client::builder()
+            .redirect(Policy::none())
+            .resolve_to_addrs(host, &addrs)
+            .timeout(StdDuration::from_secs(ICAL_FETCH_TIMEOUT_SECS))
+            .build()
+            .map_err(|_| unreachable())?;
+
+        let mut res = client
+            .get(url.clone())
+            .send()
+            .await
+            .map_err(|_| unreachable())?;
+
+        if res.status().is_redirection() {
+            url = res
+                .headers()
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| url.join(location).ok())
+                .filter(|url| matches!(url.scheme(), "http" | "https"))
+                .ok_or_else(unreachable)?;
+            continue;
+        }
+
+        if !res.status().is_success() {
+            return Err(unreachable());
+        }
+
+        let mut body = vec![];
+        while let Some(chunk) = res.chunk().await.map_err(|_| unreachable())? {
+            if body.len() + chunk.len() > MAX_IMPORT_BYTES {
+                return Err(AppError::PayloadTooLarge);
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        return Ok(Bytes::from(body));
+    }
+
+    Err(unreachable())
+}
+
+// curl -X POST "http://localhost:8000/api/v1/me/import/ical?dryRun=true" -H "Content-Type: text/calendar" --data-binary @calendar.ics
+// curl -X POST "http://localhost:8000/api/v1/me/import/ical" --data-urlencode "url=https://example.com/calendar.ics" --data-urlencode "group=location"
+
+/// Imports the events of an iCalendar file, uploaded or fetched from `url`.
+/// Events already imported, by UID, are skipped so a calendar can be imported
+/// again to pick up new events.
+pub async fn import_ical_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    State(app_state): State<AppState>,
+    ValidatedQuery(query): ValidatedQuery<IcalImportPayload>,
+    headers: HeaderMap,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<(PrivateCookieJar, (StatusCode, Json<IcalImportResponse>)), AppError> {
+    require_write_access(&app_state, &claims.sub).await?;
+
+    let body = match &query.url {
+        Some(url) => fetch_calendar(url, app_state.env.allow_private_calendar_urls).await?,
+        None => {
+            let body = body.map_err(body_error)?;
+            if content_type(&headers) != "text/calendar" {
+                return Err(AppError::UnsupportedMediaType);
+            }
+            body
+        }
+    };
+
+    let text = std::str::from_utf8(&body)
+        .map_err(|_| AppError::InvalidBody("calendar must be utf-8".to_string()))?;
+    let calendar = parse_calendar(text)
+        .map_err(|e| AppError::InvalidBody(format!("invalid calendar: {}", e)))?;
+
+    // validated already
+    let (from, to) = query
+        .window()
+        .ok_or_else(|| AppError::internal("failed to parse window"))?;
+    let naive = |date: DateTime| {
+        chrono::DateTime::from_timestamp_millis(date.timestamp_millis())
+            .unwrap_or_default()
+            .naive_utc()
+    };
+    let (from, to) = (naive(from), naive(to));
+
+    let reader = IcalReader::new(&calendar, &query, from, to);
+
+    let mut events = 0;
+    let mut rows = 0;
+    let mut payloads = vec![];
+    let mut errors: Vec<FieldError> = vec![];
+
+    for (idx, event) in calendar.components("VEVENT").enumerate() {
+        events += 1;
+
+        let mut event_errors = vec![];
+        match reader.event_rows(event) {
+            Ok(occurrences) => {
+                rows += occurrences.len();
+                for payload in occurrences {
+                    match payload.validate() {
+                        Ok(()) => payloads.push(payload),
+                        Err(e) => event_errors.extend(e),
+                    }
+                }
+            }
+            Err(e) => event_errors.push(e),
+        }
+
+        // occurrences of one event tend to fail the same way
+        for e in event_errors {
+            let e = FieldError::new(format!("events[{}].{}", idx, e.field), e.message);
+            if !errors.contains(&e) {
+                errors.push(e);
+            }
+        }
+
+        if rows > MAX_IMPORT_ROWS {
+            return Err(AppError::ValidationFailed(vec![FieldError::new(
+                "rows",
+                format!("must not contain more than {}", MAX_IMPORT_ROWS),
+            )]));
+        }
+    }
+
+    let valid = payloads.len();
+    let keys = payloads.iter().filter_map(|p| p.ical_uid.clone()).collect();
+    let mut seen = app_state
+        .db
+        .find_ical_uids(claims.sub.clone(), keys)
+        .await?;
+    payloads.retain(|p| p.ical_uid.as_ref().is_some_and(|k| seen.insert(k.clone())));
+
+    let mut res = IcalImportResponse {
+        dry_run: query.dry_run,
+        events,
+        rows,
+        valid,
+        skipped: valid - payloads.len(),
+        imported: 0,
+        errors: vec![],
+    };
+
+    if query.dry_run {
+        res.errors = errors;
+        return Ok((jar, (StatusCode::OK, Json(res))));
+    }
+
+    if !errors.is_empty() {
+        return Err(AppError::ValidationFailed(errors));
+    }
+
+    let writes = payloads.into_iter().map(ActivityWrite::Create).collect();
+
+    let results = app_state.db.write_activities(writes, claims.sub).await?;
+    res.imported = results.iter().filter(|r| r.is_ok()).count();
+
+    if let Some(Err(e)) = results.into_iter().find(|r| r.is_err()) {
+        tracing::error!(
+            request_id = current_request_id(),
+            imported = res.imported,
+            rows = res.rows,
+            "calendar import failed part way"
+        );
+        return Err(AppError::from(e));
+    }
+
+    Ok((jar, (StatusCode::CREATED, Json(res))))
+}
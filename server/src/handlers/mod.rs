@@ -1,2 +1,13 @@
 pub mod activity_handler;
 pub mod auth_handler;
+pub mod calendar_handler;
+pub mod export_handler;
+pub mod import_handler;
+pub mod progress_handler;
+pub mod record_handler;
+pub mod series_handler;
+pub mod stats_handler;
+pub mod sync_handler;
+pub mod template_handler;
+pub mod timer_handler;
+pub mod user_handler;
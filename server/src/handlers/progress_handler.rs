@@ -0,0 +1,55 @@
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use axum_extra::extract::PrivateCookieJar;
+
+use crate::{
+    error::error::AppError,
+    models::{
+        auth_model::AccessClaims,
+        progress_model::{ExerciseProgressPayload, ExerciseProgressResponse, ExerciseSummary},
+    },
+    utils::validation::ValidatedQuery,
+    AppState,
+};
+
+// curl -GET "http://localhost:8000/api/v1/exercise"
+
+pub async fn get_exercises_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    State(app_state): State<AppState>,
+) -> Result<(PrivateCookieJar, (StatusCode, Json<Vec<ExerciseSummary>>)), AppError> {
+    let res = app_state.db.get_exercises(claims.sub).await?;
+
+    Ok((jar, (StatusCode::OK, Json(res))))
+}
+
+// curl -GET "http://localhost:8000/api/v1/exercise/progress" --data-urlencode "title=squat"
+// curl -GET "http://localhost:8000/api/v1/exercise/progress" --data-urlencode "title=row" --data-urlencode "start=2024-01-01T00:00:00Z"
+
+/// Per session history of an exercise, as strength and cardio sessions.
+pub async fn exercise_progress_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    ValidatedQuery(query): ValidatedQuery<ExerciseProgressPayload>,
+    State(app_state): State<AppState>,
+) -> Result<
+    (
+        PrivateCookieJar,
+        (StatusCode, Json<ExerciseProgressResponse>),
+    ),
+    AppError,
+> {
+    let title = query.title.clone();
+    let progress = app_state
+        .db
+        .get_exercise_progress(query, claims.sub)
+        .await?;
+
+    Ok((
+        jar,
+        (
+            StatusCode::OK,
+            Json(ExerciseProgressResponse { title, progress }),
+        ),
+    ))
+}
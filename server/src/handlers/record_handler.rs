@@ -0,0 +1,35 @@
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use axum_extra::extract::PrivateCookieJar;
+
+use crate::{
+    error::error::AppError,
+    models::{
+        auth_model::AccessClaims,
+        record_model::{sort_records, GetRecordsPayload, RecordResponse},
+    },
+    utils::validation::ValidatedQuery,
+    AppState,
+};
+
+// curl -GET "http://localhost:8000/api/v1/records"
+// curl -GET "http://localhost:8000/api/v1/records" --data-urlencode "title=squat"
+
+/// The user's current personal records, by exercise title.
+pub async fn get_records_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    ValidatedQuery(query): ValidatedQuery<GetRecordsPayload>,
+    State(app_state): State<AppState>,
+) -> Result<(PrivateCookieJar, (StatusCode, Json<Vec<RecordResponse>>)), AppError> {
+    let mut records = app_state.db.get_records(claims.sub).await?;
+    records.retain(|r| query.title.as_ref().is_none_or(|t| &r.title == t));
+    sort_records(&mut records);
+
+    Ok((
+        jar,
+        (
+            StatusCode::OK,
+            Json(records.into_iter().map(Into::into).collect()),
+        ),
+    ))
+}
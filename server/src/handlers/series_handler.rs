@@ -0,0 +1,362 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use axum_extra::extract::PrivateCookieJar;
+use mongodb::bson::DateTime;
+
+use crate::{
+    error::error::{AppError, FieldError},
+    handlers::activity_handler::saved_activity,
+    models::{
+        activity_model::{DeleteActivityPayload, Occurrence, PostActivityPayload},
+        auth_model::AccessClaims,
+        series_model::{
+            DeleteOccurrencePayload, PatchSeriesBody, PostSeriesPayload, Series, SeriesResponse,
+            SeriesScope,
+        },
+    },
+    utils::{
+        auth::require_write_access,
+        idempotency::{fingerprint, idempotent, IdempotencyKey},
+        utils::{parse_date, parse_object_id},
+        validation::{validate_object_id, ValidatedJson, ValidatedQuery},
+    },
+    AppState,
+};
+
+async fn find_series(app_state: &AppState, id: &str, user_id: &str) -> Result<Series, AppError> {
+    validate_object_id("id", id)?;
+
+    app_state
+        .db
+        .get_series_by_id(parse_object_id(id)?, user_id.to_string())
+        .await?
+        .ok_or(AppError::SeriesNotFound)
+}
+
+/// The occurrence in the path, by the start it has in the series.
+fn parse_occurrence(occurrence: &str) -> Result<DateTime, AppError> {
+    DateTime::parse_rfc3339_str(occurrence).map_err(|_| {
+        AppError::ValidationFailed(vec![FieldError::new(
+            "occurrence",
+            "must be the RFC 3339 start of an occurrence",
+        )])
+    })
+}
+
+fn rfc3339(date: DateTime) -> Result<String, AppError> {
+    date.try_to_rfc3339_string()
+        .map_err(|_| AppError::internal("failed to format date"))
+}
+
+fn series_conflict(current: Series) -> AppError {
+    match serde_json::to_value(SeriesResponse::from(current)) {
+        Ok(current) => AppError::VersionConflict(current),
+        Err(_) => AppError::internal("failed to serialize series"),
+    }
+}
+
+/// Replaces the series, as long as it wasn't written since it was read.
+async fn save_series(app_state: &AppState, series: Series) -> Result<Series, AppError> {
+    let user_id = series.user.to_hex();
+    if app_state.db.replace_series(series.clone()).await? {
+        return Ok(Series {
+            v: series.v + 1,
+            ..series
+        });
+    }
+
+    match app_state.db.get_series_by_id(series.id, user_id).await? {
+        Some(current) => Err(series_conflict(current)),
+        None => Err(AppError::SeriesNotFound),
+    }
+}
+
+/// Leaves an occurrence out of the series, unless it already is.
+async fn leave_out(app_state: &AppState, series: &Series, at: DateTime) -> Result<(), AppError> {
+    let user_id = series.user.to_hex();
+    if app_state
+        .db
+        .add_series_exception(series.id, user_id.clone(), at)
+        .await?
+    {
+        return Ok(());
+    }
+
+    match app_state.db.get_series_by_id(series.id, user_id).await? {
+        Some(_) => Err(AppError::OccurrenceNotFound),
+        None => Err(AppError::SeriesNotFound),
+    }
+}
+
+// curl -X POST http://localhost:8000/api/v1/series -H "Content-Type: application/json" -d '{
+//   "title": "Standup",
+//   "variant": "Default",
+//   "group": "Work",
+//   "start": "2024-08-26T07:00:00Z",
+//   "duration": 900,
+//   "timezone": -120,
+//   "rrule": "FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR"
+// }'
+
+pub async fn create_series_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    State(app_state): State<AppState>,
+    IdempotencyKey(key): IdempotencyKey,
+    ValidatedJson(body): ValidatedJson<PostSeriesPayload>,
+) -> Result<(PrivateCookieJar, Response), AppError> {
+    require_write_access(&app_state, &claims.sub).await?;
+    let fingerprint = fingerprint("POST /api/v1/series", &body)?;
+
+    let start = parse_date(&body.start)?;
+    let series = Series::new(body, start, parse_object_id(&claims.sub)?);
+
+    let res = idempotent(&*app_state.db, &claims.sub, key, fingerprint, async {
+        match app_state.db.create_series(series.clone()).await {
+            Ok(true) => (StatusCode::CREATED, Json(SeriesResponse::from(series))).into_response(),
+            Ok(false) => AppError::SeriesLimitReached.into_response(),
+            Err(e) => AppError::from(e).into_response(),
+        }
+    })
+    .await?;
+
+    Ok((jar, res))
+}
+
+// curl -GET "http://localhost:8000/api/v1/series"
+
+/// The user's series, by start. Their occurrences are listed with activities.
+pub async fn get_series_list_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    State(app_state): State<AppState>,
+) -> Result<(PrivateCookieJar, (StatusCode, Json<Vec<SeriesResponse>>)), AppError> {
+    let series = app_state.db.get_series(claims.sub).await?;
+
+    Ok((
+        jar,
+        (
+            StatusCode::OK,
+            Json(series.into_iter().map(Into::into).collect()),
+        ),
+    ))
+}
+
+// curl -X GET http://localhost:8000/api/v1/series/66cc8f30ef7a9d4f94f9ad03
+
+pub async fn get_series_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    Path(id): Path<String>,
+    State(app_state): State<AppState>,
+) -> Result<(PrivateCookieJar, (StatusCode, Json<SeriesResponse>)), AppError> {
+    let series = find_series(&app_state, &id, &claims.sub).await?;
+
+    Ok((jar, (StatusCode::OK, Json(series.into()))))
+}
+
+// curl -X PATCH http://localhost:8000/api/v1/series/66cc8f30ef7a9d4f94f9ad03 -H "Content-Type: application/json" -d '{
+//   "title": "Daily",
+//   "rrule": "FREQ=DAILY"
+// }'
+
+/// Edits every occurrence. `scope` doesn't apply here and `start` is the new
+/// start of the first occurrence.
+pub async fn update_series_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    Path(id): Path<String>,
+    State(app_state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<PatchSeriesBody>,
+) -> Result<(PrivateCookieJar, (StatusCode, Json<SeriesResponse>)), AppError> {
+    let mut series = find_series(&app_state, &id, &claims.sub).await?;
+    require_write_access(&app_state, &claims.sub).await?;
+
+    let shift = match &body.start {
+        Some(start) => parse_date(start)?.timestamp_millis() - series.start.timestamp_millis(),
+        None => 0,
+    };
+    series.apply(body, shift);
+
+    let series = save_series(&app_state, series).await?;
+
+    Ok((jar, (StatusCode::OK, Json(series.into()))))
+}
+
+// curl -X DELETE http://localhost:8000/api/v1/series/66cc8f30ef7a9d4f94f9ad03
+
+/// Occurrences that were edited on their own are kept as activities.
+pub async fn delete_series_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    Path(id): Path<String>,
+    State(app_state): State<AppState>,
+) -> Result<(PrivateCookieJar, StatusCode), AppError> {
+    validate_object_id("id", &id)?;
+    require_write_access(&app_state, &claims.sub).await?;
+
+    match app_state
+        .db
+        .delete_series_by_id(parse_object_id(&id)?, claims.sub)
+        .await?
+    {
+        true => Ok((jar, StatusCode::NO_CONTENT)),
+        false => Err(AppError::SeriesNotFound),
+    }
+}
+
+// curl -X PATCH http://localhost:8000/api/v1/series/66cc8f30ef7a9d4f94f9ad03/occurrences/2024-08-27T07:00:00Z -H "Content-Type: application/json" -d '{
+//   "scope": "this",
+//   "start": "2024-08-27T07:30:00Z"
+// }'
+
+/// Edits an occurrence, by the start it has in the series. With `this` scope
+/// the occurrence is stored as an activity of its own and returned as one,
+/// with `following` the series is split at the occurrence and the new series
+/// is returned, and with `all` the whole series is edited.
+pub async fn update_occurrence_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    Path((id, occurrence)): Path<(String, String)>,
+    State(app_state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<PatchSeriesBody>,
+) -> Result<(PrivateCookieJar, Response), AppError> {
+    let mut series = find_series(&app_state, &id, &claims.sub).await?;
+    let at = parse_occurrence(&occurrence)?;
+    require_write_access(&app_state, &claims.sub).await?;
+
+    if !series.has_occurrence(at) {
+        return Err(AppError::OccurrenceNotFound);
+    }
+
+    let start = body.start.as_deref().map(parse_date).transpose()?;
+    let shift = start.map_or(0, |s| s.timestamp_millis() - at.timestamp_millis());
+
+    let res = match body.scope {
+        SeriesScope::This => {
+            let start = start.unwrap_or(at);
+            let duration = i64::from(body.duration.unwrap_or(series.duration));
+            let end = DateTime::from_millis(start.timestamp_millis() + duration * 1000);
+
+            let payload = PostActivityPayload {
+                title: body.title.unwrap_or_else(|| series.title.clone()),
+                variant: body.variant.unwrap_or(series.variant),
+                group: body.group.unwrap_or_else(|| series.group.clone()),
+                notes: Some(body.notes.unwrap_or_else(|| series.notes.clone())),
+                start: rfc3339(start)?,
+                end: rfc3339(end)?,
+                timezone: body.timezone.unwrap_or(series.timezone),
+                data: body.data.or_else(|| series.data.clone()),
+                color: None,
+                ical_uid: None,
+                occurrence: Some(Occurrence {
+                    series: series.id,
+                    start: at,
+                }),
+            };
+            let activity = app_state
+                .db
+                .create_activity(payload, claims.sub.clone())
+                .await?
+                .ok_or_else(|| AppError::internal("failed to create activity"))?;
+
+            // an occurrence is overridden once, so the activity is removed
+            // again if it can't be left out of the series
+            if let Err(e) = leave_out(&app_state, &series, at).await {
+                let payload = DeleteActivityPayload {
+                    id: activity.id.to_hex(),
+                    v: None,
+                };
+                if let Err(e) = app_state
+                    .db
+                    .delete_activity_by_id(payload, claims.sub)
+                    .await
+                {
+                    tracing::error!("failed to remove occurrence activity: {}", e);
+                }
+                return Err(e);
+            }
+
+            saved_activity(&app_state, &claims.sub, StatusCode::CREATED, activity)
+                .await
+                .into_response()
+        }
+        SeriesScope::Following if at != series.start => {
+            let mut rest = series.split(at);
+            rest.apply(body, shift);
+
+            // the new series goes in first, so a failed split briefly doubles
+            // occurrences instead of losing them
+            if !app_state.db.create_series(rest.clone()).await? {
+                return Err(AppError::SeriesLimitReached);
+            }
+            if let Err(e) = save_series(&app_state, series).await {
+                if let Err(e) = app_state.db.delete_series_by_id(rest.id, claims.sub).await {
+                    tracing::error!("failed to remove split series: {}", e);
+                }
+                return Err(e);
+            }
+
+            (StatusCode::CREATED, Json(SeriesResponse::from(rest))).into_response()
+        }
+        // following from the first occurrence is every occurrence
+        SeriesScope::Following | SeriesScope::All => {
+            series.apply(body, shift);
+            let series = save_series(&app_state, series).await?;
+
+            (StatusCode::OK, Json(SeriesResponse::from(series))).into_response()
+        }
+    };
+
+    Ok((jar, res))
+}
+
+// curl -X DELETE "http://localhost:8000/api/v1/series/66cc8f30ef7a9d4f94f9ad03/occurrences/2024-08-27T07:00:00Z?scope=following"
+
+/// Deletes an occurrence, or it and the ones after it. Deleting the first
+/// occurrence and the ones after it deletes the series.
+pub async fn delete_occurrence_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    Path((id, occurrence)): Path<(String, String)>,
+    ValidatedQuery(query): ValidatedQuery<DeleteOccurrencePayload>,
+    State(app_state): State<AppState>,
+) -> Result<(PrivateCookieJar, StatusCode), AppError> {
+    let mut series = find_series(&app_state, &id, &claims.sub).await?;
+    let at = parse_occurrence(&occurrence)?;
+    require_write_access(&app_state, &claims.sub).await?;
+
+    if !series.has_occurrence(at) {
+        return Err(AppError::OccurrenceNotFound);
+    }
+
+    match query.scope {
+        SeriesScope::This => {
+            leave_out(&app_state, &series, at).await?;
+            return Ok((jar, StatusCode::NO_CONTENT));
+        }
+        SeriesScope::Following if at != series.start => {
+            series.until = Some(at);
+            series.exceptions.retain(|e| *e < at);
+        }
+        SeriesScope::Following | SeriesScope::All => {
+            // it may have been deleted since it was read
+            return match app_state
+                .db
+                .delete_series_by_id(series.id, claims.sub)
+                .await?
+            {
+                true => Ok((jar, StatusCode::NO_CONTENT)),
+                false => Err(AppError::SeriesNotFound),
+            };
+        }
+    }
+    series.updated_at = DateTime::now();
+    save_series(&app_state, series).await?;
+
+    Ok((jar, StatusCode::NO_CONTENT))
+}
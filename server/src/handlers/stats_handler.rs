@@ -0,0 +1,49 @@
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use axum_extra::extract::PrivateCookieJar;
+
+use crate::{
+    error::error::AppError,
+    models::{
+        activity_model::GetActivitiesPayload,
+        auth_model::AccessClaims,
+        stats_model::{ActivityStats, StatsPayload, StatsResponse},
+    },
+    utils::{series::series_occurrences, utils::parse_date, validation::ValidatedQuery},
+    AppState,
+};
+
+// curl -GET "http://localhost:8000/api/v1/stats" --data-urlencode "start=2024-01-01T00:00:00Z" --data-urlencode "end=2024-02-01T00:00:00Z"
+// curl -GET "http://localhost:8000/api/v1/stats" --data-urlencode "start=2024-01-01T00:00:00Z" --data-urlencode "end=2025-01-01T00:00:00Z" --data-urlencode "groupBy=week" --data-urlencode "timezone=-60"
+
+/// Total, count and average duration of the user's activities in a range,
+/// per title, group, variant or local day, week or month. Occurrences of
+/// series count as activities.
+pub async fn stats_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    ValidatedQuery(query): ValidatedQuery<StatsPayload>,
+    State(app_state): State<AppState>,
+) -> Result<(PrivateCookieJar, (StatusCode, Json<StatsResponse>)), AppError> {
+    let (start, end) = (parse_date(&query.start)?, parse_date(&query.end)?);
+    let filter = GetActivitiesPayload {
+        timezone: None,
+        variant: query.variant,
+        title: query.title.clone(),
+        group: query.group.clone(),
+        start: None,
+        end: None,
+        limit: None,
+        after: None,
+    };
+    let occurrences = series_occurrences(&*app_state.db, &claims.sub, &filter, start, end).await?;
+    let occurrences = ActivityStats::of(&occurrences, start, end, query.group_by, query.timezone);
+
+    let group_by = query.group_by;
+    let mut stats = app_state.db.get_stats(query, claims.sub).await?;
+    stats.merge(occurrences);
+
+    Ok((
+        jar,
+        (StatusCode::OK, Json(StatsResponse::new(group_by, stats))),
+    ))
+}
@@ -0,0 +1,55 @@
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use axum_extra::extract::PrivateCookieJar;
+
+use crate::{
+    error::error::AppError,
+    models::{
+        activity_model::{ActivityResponse, SyncPayload, SyncResponse, SyncToken},
+        auth_model::AccessClaims,
+    },
+    utils::validation::ValidatedQuery,
+    AppState,
+};
+
+// curl -GET "http://localhost:8000/api/v1/sync"
+// curl -GET "http://localhost:8000/api/v1/sync" --data-urlencode "since=1724594400000"
+
+pub async fn sync_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    ValidatedQuery(query): ValidatedQuery<SyncPayload>,
+    State(app_state): State<AppState>,
+) -> Result<(PrivateCookieJar, (StatusCode, Json<SyncResponse>)), AppError> {
+    // taken before reading so nothing written during the read is skipped next time
+    let next = SyncToken::now();
+
+    let since = query
+        .since
+        .as_deref()
+        .and_then(SyncToken::decode)
+        .map(|token| token.changes_since());
+
+    let res = app_state
+        .db
+        .get_activity_changes(since, claims.sub.clone())
+        .await?;
+    let series = app_state.db.get_series_changes(since, claims.sub).await?;
+
+    let (deleted, changed): (Vec<_>, Vec<_>) = res.into_iter().partition(|a| a.is_deleted());
+    let (deleted_series, changed_series): (Vec<_>, Vec<_>) =
+        series.into_iter().partition(|s| s.is_deleted());
+
+    Ok((
+        jar,
+        (
+            StatusCode::OK,
+            Json(SyncResponse {
+                changed: changed.iter().map(ActivityResponse::from).collect(),
+                deleted: deleted.iter().map(|a| a.id.to_hex()).collect(),
+                changed_series: changed_series.into_iter().map(Into::into).collect(),
+                deleted_series: deleted_series.iter().map(|s| s.id.to_hex()).collect(),
+                next: next.encode(),
+            }),
+        ),
+    ))
+}
@@ -0,0 +1,228 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use axum_extra::extract::PrivateCookieJar;
+use mongodb::bson::DateTime;
+
+use crate::{
+    error::error::AppError,
+    handlers::activity_handler::create_activity,
+    models::{
+        activity_model::{ActivityData, ActivityVariant, Exercise, PostActivityPayload},
+        auth_model::AccessClaims,
+        template_model::{
+            InstantiateTemplatePayload, PatchTemplatePayload, PostTemplatePayload, Template,
+            TemplateResponse,
+        },
+    },
+    utils::{
+        auth::require_write_access,
+        idempotency::{fingerprint, idempotent, IdempotencyKey},
+        utils::parse_object_id,
+        validation::{validate_object_id, ValidatedJson},
+    },
+    AppState,
+};
+
+async fn find_template(
+    app_state: &AppState,
+    id: &str,
+    user_id: &str,
+) -> Result<Template, AppError> {
+    validate_object_id("id", id)?;
+
+    app_state
+        .db
+        .get_template_by_id(parse_object_id(id)?, user_id.to_string())
+        .await?
+        .ok_or(AppError::TemplateNotFound)
+}
+
+// curl -X POST http://localhost:8000/api/v1/templates -H "Content-Type: application/json" -d '{
+//   "name": "Leg day",
+//   "group": "Gym",
+//   "duration": 3600,
+//   "exercises": [
+//     { "variant": "Strength", "title": "Squat", "sets": [{ "idx": 0, "reps": 5 }, { "idx": 1, "reps": 5 }] }
+//   ]
+// }'
+
+pub async fn create_template_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    State(app_state): State<AppState>,
+    IdempotencyKey(key): IdempotencyKey,
+    ValidatedJson(body): ValidatedJson<PostTemplatePayload>,
+) -> Result<(PrivateCookieJar, Response), AppError> {
+    require_write_access(&app_state, &claims.sub).await?;
+    let fingerprint = fingerprint("POST /api/v1/templates", &body)?;
+
+    let res = idempotent(&*app_state.db, &claims.sub, key, fingerprint, async {
+        match app_state.db.create_template(body, claims.sub.clone()).await {
+            Ok(template) => {
+                (StatusCode::CREATED, Json(TemplateResponse::from(template))).into_response()
+            }
+            Err(e) => AppError::from(e).into_response(),
+        }
+    })
+    .await?;
+
+    Ok((jar, res))
+}
+
+// curl -GET "http://localhost:8000/api/v1/templates"
+
+/// The user's templates, by name.
+pub async fn get_templates_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    State(app_state): State<AppState>,
+) -> Result<(PrivateCookieJar, (StatusCode, Json<Vec<TemplateResponse>>)), AppError> {
+    let templates = app_state.db.get_templates(claims.sub).await?;
+
+    Ok((
+        jar,
+        (
+            StatusCode::OK,
+            Json(templates.into_iter().map(Into::into).collect()),
+        ),
+    ))
+}
+
+// curl -X GET http://localhost:8000/api/v1/templates/66cc8f30ef7a9d4f94f9ad03
+
+pub async fn get_template_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    Path(id): Path<String>,
+    State(app_state): State<AppState>,
+) -> Result<(PrivateCookieJar, (StatusCode, Json<TemplateResponse>)), AppError> {
+    let template = find_template(&app_state, &id, &claims.sub).await?;
+
+    Ok((jar, (StatusCode::OK, Json(template.into()))))
+}
+
+// curl -X PATCH http://localhost:8000/api/v1/templates/66cc8f30ef7a9d4f94f9ad03 -H "Content-Type: application/json" -d '{
+//   "name": "Heavy leg day"
+// }'
+
+pub async fn update_template_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    Path(id): Path<String>,
+    State(app_state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<PatchTemplatePayload>,
+) -> Result<(PrivateCookieJar, (StatusCode, Json<TemplateResponse>)), AppError> {
+    validate_object_id("id", &id)?;
+    require_write_access(&app_state, &claims.sub).await?;
+
+    let template = app_state
+        .db
+        .update_template_by_id(parse_object_id(&id)?, body, claims.sub)
+        .await?
+        .ok_or(AppError::TemplateNotFound)?;
+
+    Ok((jar, (StatusCode::OK, Json(template.into()))))
+}
+
+// curl -X DELETE http://localhost:8000/api/v1/templates/66cc8f30ef7a9d4f94f9ad03
+
+pub async fn delete_template_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    Path(id): Path<String>,
+    State(app_state): State<AppState>,
+) -> Result<(PrivateCookieJar, StatusCode), AppError> {
+    validate_object_id("id", &id)?;
+    require_write_access(&app_state, &claims.sub).await?;
+
+    match app_state
+        .db
+        .delete_template_by_id(parse_object_id(&id)?, claims.sub)
+        .await?
+    {
+        true => Ok((jar, StatusCode::NO_CONTENT)),
+        false => Err(AppError::TemplateNotFound),
+    }
+}
+
+// curl -X POST http://localhost:8000/api/v1/templates/66cc8f30ef7a9d4f94f9ad03/instantiate -H "Content-Type: application/json" -d '{
+//   "start": "2024-08-25T18:00:00Z",
+//   "timezone": -120
+// }'
+
+/// Creates an `Exercise` activity from a template, with strength sets that
+/// have no weight given the one last used for the exercise.
+pub async fn instantiate_template_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    Path(id): Path<String>,
+    State(app_state): State<AppState>,
+    IdempotencyKey(key): IdempotencyKey,
+    ValidatedJson(body): ValidatedJson<InstantiateTemplatePayload>,
+) -> Result<(PrivateCookieJar, Response), AppError> {
+    let template = find_template(&app_state, &id, &claims.sub).await?;
+    require_write_access(&app_state, &claims.sub).await?;
+    let fingerprint = fingerprint(&format!("POST /api/v1/templates/{}/instantiate", id), &body)?;
+
+    let res = idempotent(&*app_state.db, &claims.sub, key, fingerprint, async {
+        instantiate(&app_state, template, body, claims.sub.clone())
+            .await
+            .into_response()
+    })
+    .await?;
+
+    Ok((jar, res))
+}
+
+async fn instantiate(
+    app_state: &AppState,
+    template: Template,
+    body: InstantiateTemplatePayload,
+    user_id: String,
+) -> Result<Response, AppError> {
+    let titles = template
+        .exercises
+        .iter()
+        .filter_map(|e| match e {
+            Exercise::Strength(e) => Some(e.title.clone()),
+            _ => None,
+        })
+        .collect();
+    let last_sets = app_state.db.get_last_sets(user_id.clone(), titles).await?;
+
+    let end = match body.end {
+        Some(end) => end,
+        None => {
+            let start = DateTime::parse_rfc3339_str(&body.start)
+                .map_err(|_| AppError::internal("failed to parse start"))?;
+            let duration = i64::from(template.duration.unwrap_or(0)) * 1000;
+            DateTime::from_millis(start.timestamp_millis() + duration)
+                .try_to_rfc3339_string()
+                .map_err(|_| AppError::internal("failed to format end"))?
+        }
+    };
+
+    let payload = PostActivityPayload {
+        title: template.name.clone(),
+        variant: ActivityVariant::Exercise,
+        group: template.group.clone(),
+        notes: Some(template.notes.clone()).filter(|n| !n.is_empty()),
+        start: body.start,
+        end,
+        timezone: body.timezone,
+        data: Some(ActivityData {
+            exercise: Some(template.prefilled_exercises(&last_sets)),
+        }),
+        color: None,
+        ical_uid: None,
+        occurrence: None,
+    };
+
+    Ok(create_activity(app_state, payload, user_id)
+        .await?
+        .into_response())
+}
@@ -0,0 +1,135 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use axum_extra::extract::PrivateCookieJar;
+use mongodb::bson::DateTime;
+
+use crate::{
+    error::error::{AppError, FieldError},
+    handlers::activity_handler::{etag, saved_activity, ActivityWithEtag, SavedActivityWithEtag},
+    models::{
+        activity_model::{Activity, ActivityResponse, VersionedWrite},
+        auth_model::AccessClaims,
+        timer_model::{StartTimerPayload, StopTimerPayload},
+    },
+    utils::{
+        auth::require_write_access,
+        idempotency::{fingerprint, idempotent, IdempotencyKey},
+        utils::{parse_date, parse_object_id},
+        validation::ValidatedJson,
+    },
+    AppState,
+};
+
+// curl -X POST http://localhost:8000/api/v1/timer/start -H "Content-Type: application/json" -d '{
+//   "title": "Reading",
+//   "variant": "Default",
+//   "group": "Leisure",
+//   "timezone": -120
+// }'
+
+/// Starts an activity with no end. A user has at most one running.
+pub async fn start_timer_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    State(app_state): State<AppState>,
+    IdempotencyKey(key): IdempotencyKey,
+    ValidatedJson(body): ValidatedJson<StartTimerPayload>,
+) -> Result<(PrivateCookieJar, Response), AppError> {
+    require_write_access(&app_state, &claims.sub).await?;
+    let fingerprint = fingerprint("POST /api/v1/timer/start", &body)?;
+
+    let start = match &body.start {
+        Some(start) => parse_date(start)?,
+        None => DateTime::now(),
+    };
+    let activity = Activity::new(
+        body.variant,
+        body.title,
+        body.group,
+        body.notes.unwrap_or_default(),
+        start,
+        None,
+        body.timezone,
+        body.data,
+        parse_object_id(&claims.sub)?,
+    );
+
+    let res = idempotent(&*app_state.db, &claims.sub, key, fingerprint, async {
+        match app_state.db.start_timer(activity.clone()).await {
+            Ok(true) => (
+                etag(&activity),
+                (StatusCode::CREATED, Json(ActivityResponse::from(activity))),
+            )
+                .into_response(),
+            Ok(false) => AppError::TimerRunning.into_response(),
+            Err(e) => AppError::from(e).into_response(),
+        }
+    })
+    .await?;
+
+    Ok((jar, res))
+}
+
+// curl -X GET http://localhost:8000/api/v1/timer
+
+pub async fn get_timer_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    State(app_state): State<AppState>,
+) -> Result<(PrivateCookieJar, ActivityWithEtag), AppError> {
+    let activity = app_state
+        .db
+        .get_timer(claims.sub)
+        .await?
+        .ok_or(AppError::TimerNotFound)?;
+
+    Ok((
+        jar,
+        (
+            etag(&activity),
+            (StatusCode::OK, Json(ActivityResponse::from(activity))),
+        ),
+    ))
+}
+
+// curl -X POST http://localhost:8000/api/v1/timer/stop -H "Content-Type: application/json" -d '{
+//   "end": "2024-08-25T16:00:00Z"
+// }'
+
+/// Ends the running timer, now unless the body gives an end.
+pub async fn stop_timer_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    State(app_state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<StopTimerPayload>,
+) -> Result<(PrivateCookieJar, SavedActivityWithEtag), AppError> {
+    require_write_access(&app_state, &claims.sub).await?;
+
+    let end = match &body.end {
+        Some(end) => parse_date(end)?,
+        None => DateTime::now(),
+    };
+
+    // storage checks the end against the start as it stops the timer
+    let activity = match app_state.db.stop_timer(claims.sub.clone(), end).await? {
+        VersionedWrite::Done(activity) => activity,
+        VersionedWrite::OutOfRange(field) => {
+            return Err(AppError::ValidationFailed(vec![FieldError::new(
+                field,
+                "must not be before start",
+            )]))
+        }
+        VersionedWrite::NotFound | VersionedWrite::Conflict(_) => {
+            return Err(AppError::TimerNotFound)
+        }
+    };
+
+    Ok((
+        jar,
+        saved_activity(&app_state, &claims.sub, StatusCode::OK, activity).await,
+    ))
+}
@@ -0,0 +1,140 @@
+use axum::{extract::State, http::StatusCode, Extension, Json};
+use axum_extra::extract::{cookie::Cookie, PrivateCookieJar};
+use mongodb::bson::{oid::ObjectId, DateTime};
+
+use crate::{
+    error::error::{AppError, FieldError},
+    models::{
+        auth_model::AccessClaims,
+        user_model::{
+            DeleteAccountPayload, DeleteAccountResponse, DeletionStatus, PatchUserPayload, User,
+            UserResponse,
+        },
+    },
+    utils::validation::ValidatedJson,
+    AppState,
+};
+
+// curl -X GET http://localhost:8000/api/v1/me
+
+pub async fn get_me_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    State(app_state): State<AppState>,
+) -> Result<(PrivateCookieJar, (StatusCode, Json<UserResponse>)), AppError> {
+    let uid = ObjectId::parse_str(&claims.sub).map_err(|_| AppError::InvalidToken)?;
+
+    match app_state.db.get_user_by_id(uid).await? {
+        Some(user) => Ok((jar, (StatusCode::OK, Json(UserResponse::from(user))))),
+        None => Err(AppError::UserNotFound),
+    }
+}
+
+// curl -X PATCH http://localhost:8000/api/v1/me -H "Content-Type: application/json" -d '{
+//   "givenName": "Testy",
+//   "img": "https://example.com/me.png"
+// }'
+
+pub async fn update_me_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    State(app_state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<PatchUserPayload>,
+) -> Result<(PrivateCookieJar, (StatusCode, Json<UserResponse>)), AppError> {
+    let uid = ObjectId::parse_str(&claims.sub).map_err(|_| AppError::InvalidToken)?;
+
+    match app_state.db.update_user_profile(uid, body).await? {
+        Some(user) => Ok((jar, (StatusCode::OK, Json(UserResponse::from(user))))),
+        None => Err(AppError::UserNotFound),
+    }
+}
+
+// deleting is only allowed with proof the request comes from the user, not just their session
+fn confirm_deletion(user: &User, body: &DeleteAccountPayload) -> Result<(), AppError> {
+    if !user.password_set {
+        return match &body.email {
+            Some(email) if email.trim().eq_ignore_ascii_case(&user.email) => Ok(()),
+            _ => Err(AppError::ValidationFailed(vec![FieldError::new(
+                "email",
+                "must match the email of your account",
+            )])),
+        };
+    }
+
+    let pass = match &body.pass {
+        Some(v) if !v.is_empty() => v,
+        _ => {
+            return Err(AppError::ValidationFailed(vec![FieldError::new(
+                "pass",
+                "must not be empty",
+            )]))
+        }
+    };
+
+    match bcrypt::verify(pass, &user.pass) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(AppError::WrongCredentials),
+        Err(_) => Err(AppError::internal("failed to verify password")),
+    }
+}
+
+// curl -X DELETE http://localhost:8000/api/v1/me -H "Content-Type: application/json" -d '{
+//   "pass": "password"
+// }'
+
+/// Deletes the signed in user. With a grace period configured the account is
+/// deactivated and erased later by the purge task, otherwise it is erased now.
+pub async fn delete_me_handler(
+    claims: AccessClaims,
+    Extension(jar): Extension<PrivateCookieJar>,
+    State(app_state): State<AppState>,
+    ValidatedJson(body): ValidatedJson<DeleteAccountPayload>,
+) -> Result<(PrivateCookieJar, (StatusCode, Json<DeleteAccountResponse>)), AppError> {
+    let uid = ObjectId::parse_str(&claims.sub).map_err(|_| AppError::InvalidToken)?;
+
+    let user = app_state
+        .db
+        .get_user_by_id(uid)
+        .await?
+        .ok_or(AppError::UserNotFound)?;
+
+    confirm_deletion(&user, &body)?;
+
+    let (status, res) = match app_state.env.account_deletion_grace_days {
+        Some(days) => {
+            let purge_at = DateTime::from_millis(
+                DateTime::now().timestamp_millis() + i64::from(days) * 24 * 60 * 60 * 1000,
+            );
+
+            app_state.db.deactivate_user(uid, purge_at).await?;
+            app_state.db.blacklist_user_tokens(uid).await?;
+
+            (
+                StatusCode::ACCEPTED,
+                DeleteAccountResponse {
+                    status: DeletionStatus::Scheduled,
+                    erased: None,
+                    purge_at: purge_at.try_to_rfc3339_string().ok(),
+                },
+            )
+        }
+        None => {
+            let erased = app_state.db.erase_user(uid).await?;
+
+            (
+                StatusCode::OK,
+                DeleteAccountResponse {
+                    status: DeletionStatus::Deleted,
+                    erased: Some(erased),
+                    purge_at: None,
+                },
+            )
+        }
+    };
+
+    let jar = jar
+        .remove(Cookie::build("access").path("/"))
+        .remove(Cookie::build("refresh").path("/"));
+
+    Ok((jar, (status, Json(res))))
+}
@@ -0,0 +1,69 @@
+use std::path::PathBuf;
+
+use axum::async_trait;
+use tokio::io::AsyncWriteExt;
+
+use super::mailer::{Mail, Mailer};
+
+/// Writes mail to the log, and appends it to `outbox` when set, so links can
+/// be followed during local development. Set the outbox with `MAIL_OUTBOX`.
+#[derive(Debug, Default)]
+pub struct LogMailer {
+    outbox: Option<PathBuf>,
+}
+
+impl LogMailer {
+    pub fn new(outbox: Option<PathBuf>) -> Self {
+        Self { outbox }
+    }
+}
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, mail: Mail) -> Result<(), std::io::Error> {
+        tracing::info!(to = mail.to, subject = mail.subject, "{}", mail.body);
+
+        if let Some(outbox) = &self.outbox {
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(outbox)
+                .await?;
+
+            let entry = format!(
+                "To: {}\nSubject: {}\n\n{}\n\n---\n\n",
+                mail.to, mail.subject, mail.body
+            );
+            file.write_all(entry.as_bytes()).await?;
+            file.flush().await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn appends_to_outbox() {
+        let outbox = std::env::temp_dir().join(format!("outbox-{}.txt", uuid::Uuid::new_v4()));
+        let mailer = LogMailer::new(Some(outbox.clone()));
+
+        for subject in ["first", "second"] {
+            let mail = Mail {
+                to: "test@test.com".to_string(),
+                subject: subject.to_string(),
+                body: "body".to_string(),
+            };
+            mailer.send(mail).await.unwrap();
+        }
+
+        let contents = std::fs::read_to_string(&outbox).unwrap();
+        assert!(contents.contains("Subject: first"));
+        assert!(contents.contains("Subject: second"));
+
+        std::fs::remove_file(outbox).unwrap();
+    }
+}
@@ -0,0 +1,16 @@
+use std::fmt::Debug;
+
+use axum::async_trait;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers mail to users. `LogMailer` is used until a real provider is configured.
+#[async_trait]
+pub trait Mailer: Debug + Send + Sync {
+    async fn send(&self, mail: Mail) -> Result<(), std::io::Error>;
+}
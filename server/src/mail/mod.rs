@@ -0,0 +1,2 @@
+pub mod log_mailer;
+pub mod mailer;
@@ -1,36 +1,74 @@
-use std::{env, sync::Arc};
+use std::{env, path::PathBuf, sync::Arc};
 
 use axum::{
-    extract::{MatchedPath, Request},
+    extract::{DefaultBodyLimit, MatchedPath, Request},
     http::{
-        header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
+        header::{ACCEPT, AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_TYPE, ETAG, IF_MATCH},
         HeaderValue, Method,
     },
-    routing::{get, post},
+    middleware,
+    routing::{get, patch, post},
     Json, Router,
 };
 use axum_extra::extract::cookie::Key;
-use database::mongodb_database::MongoDatabase;
+use database::{
+    memory_database::MemoryDatabase, mongodb_database::MongoDatabase, storage::Storage,
+};
 use serde_json::Value;
 use tower_http::{add_extension::AddExtensionLayer, cors::CorsLayer, trace::TraceLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use self::{
     handlers::activity_handler::{
-        create_activity_handler, delete_activity_handler, get_activities_handler,
-        get_activity_handler, update_activity_handler,
+        batch_activities_handler, create_activity_handler, delete_activity_handler,
+        get_activities_handler, get_activity_handler, update_activity_handler,
+    },
+    models::{
+        import_model::MAX_IMPORT_BYTES,
+        state_model::{AppState, EnvironmentVariables, GoogleCertsState, StorageBackend},
     },
-    models::state_model::{AppState, EnvironmentVariables, GoogleCertsState},
 };
 use self::{
-    handlers::auth_handler::{authorize, authorize_oauth, logout, register_user},
+    handlers::auth_handler::{
+        authorize, authorize_oauth, change_password, forgot_password, logout, register_user,
+        resend_verification, reset_password, verify_email,
+    },
+    handlers::calendar_handler::{
+        calendar_feed_handler, create_calendar_token_handler, revoke_calendar_token_handler,
+    },
+    handlers::export_handler::export_handler,
+    handlers::import_handler::{import_handler, import_ical_handler},
+    handlers::progress_handler::{exercise_progress_handler, get_exercises_handler},
+    handlers::record_handler::get_records_handler,
+    handlers::series_handler::{
+        create_series_handler, delete_occurrence_handler, delete_series_handler,
+        get_series_handler, get_series_list_handler, update_occurrence_handler,
+        update_series_handler,
+    },
+    handlers::stats_handler::stats_handler,
+    handlers::sync_handler::sync_handler,
+    handlers::template_handler::{
+        create_template_handler, delete_template_handler, get_template_handler,
+        get_templates_handler, instantiate_template_handler, update_template_handler,
+    },
+    handlers::timer_handler::{get_timer_handler, start_timer_handler, stop_timer_handler},
+    handlers::user_handler::{delete_me_handler, get_me_handler, update_me_handler},
+    mail::log_mailer::LogMailer,
     models::state_model::InnerState,
+    utils::{
+        idempotency::{IDEMPOTENCY_KEY_HEADER, IDEMPOTENT_REPLAYED_HEADER},
+        purge::spawn_purge_task,
+        request_id::{current_request_id, request_id, REQUEST_ID_HEADER},
+    },
 };
 
 mod database;
 mod error;
 mod handlers;
+mod mail;
 mod models;
+#[cfg(test)]
+mod tests;
 mod utils;
 
 async fn health_check_handler() -> Json<Value> {
@@ -41,43 +79,8 @@ async fn health_check_handler() -> Json<Value> {
     Json(json_response)
 }
 
-#[tokio::main]
-async fn main() {
-    let env = EnvironmentVariables::from_env();
-
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| {
-                format!("{}=debug,tower_http=debug", env!("CARGO_CRATE_NAME")).into()
-            }),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-
-    let db_uri = env
-        .db_url
-        .replace("%USER%", &env.db_user)
-        .replace("%PASS%", &env.db_pass);
-
-    let db = match MongoDatabase::init(&db_uri, &env.db_name).await {
-        Ok(v) => {
-            println!("Database connected");
-            v
-        }
-        Err(_) => panic!("Fatal: database connection failed to initialize"),
-    };
-
-    let client = reqwest::Client::new();
-
-    let port = &env.port.clone();
-
-    let app_state = AppState(Arc::new(InnerState {
-        db,
-        client,
-        env,
-        key: Key::generate(),
-    }));
-
+/// Builds the api router. Shared by `main` and the integration tests.
+fn app(app_state: AppState) -> Router {
     let cors = CorsLayer::new()
         .allow_origin("http://localhost:3000".parse::<HeaderValue>().unwrap())
         // .allow_origin(
@@ -87,22 +90,97 @@ async fn main() {
         // )
         .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
         .allow_credentials(true)
-        .allow_headers([AUTHORIZATION, ACCEPT, CONTENT_TYPE]);
+        .allow_headers([
+            AUTHORIZATION,
+            ACCEPT,
+            CONTENT_TYPE,
+            IF_MATCH,
+            REQUEST_ID_HEADER,
+            IDEMPOTENCY_KEY_HEADER,
+        ])
+        .expose_headers([
+            CONTENT_DISPOSITION,
+            ETAG,
+            REQUEST_ID_HEADER,
+            IDEMPOTENT_REPLAYED_HEADER,
+        ]);
 
-    let app = Router::new()
+    Router::new()
         .route("/api/health-check", get(health_check_handler))
         .route("/api/v1/login", post(authorize))
         .route("/api/v1/oauth", post(authorize_oauth))
         .route("/api/v1/logout", post(logout))
         .route("/api/v1/register", post(register_user))
+        .route("/api/v1/password", post(change_password))
+        .route("/api/v1/password/forgot", post(forgot_password))
+        .route("/api/v1/password/reset", post(reset_password))
+        .route("/api/v1/verify-email", post(verify_email))
+        .route("/api/v1/verify-email/resend", post(resend_verification))
         .route("/api/v1/activity", get(get_activities_handler))
         .route("/api/v1/activity", post(create_activity_handler))
+        .route("/api/v1/activity/batch", post(batch_activities_handler))
         .route(
             "/api/v1/activity/:id",
             get(get_activity_handler)
                 .patch(update_activity_handler)
                 .delete(delete_activity_handler),
         )
+        .route("/api/v1/sync", get(sync_handler))
+        .route("/api/v1/stats", get(stats_handler))
+        .route("/api/v1/exercise", get(get_exercises_handler))
+        .route("/api/v1/exercise/progress", get(exercise_progress_handler))
+        .route("/api/v1/records", get(get_records_handler))
+        .route(
+            "/api/v1/series",
+            get(get_series_list_handler).post(create_series_handler),
+        )
+        .route(
+            "/api/v1/series/:id",
+            get(get_series_handler)
+                .patch(update_series_handler)
+                .delete(delete_series_handler),
+        )
+        .route(
+            "/api/v1/series/:id/occurrences/:occurrence",
+            patch(update_occurrence_handler).delete(delete_occurrence_handler),
+        )
+        .route(
+            "/api/v1/templates",
+            get(get_templates_handler).post(create_template_handler),
+        )
+        .route(
+            "/api/v1/templates/:id",
+            get(get_template_handler)
+                .patch(update_template_handler)
+                .delete(delete_template_handler),
+        )
+        .route(
+            "/api/v1/templates/:id/instantiate",
+            post(instantiate_template_handler),
+        )
+        .route("/api/v1/timer", get(get_timer_handler))
+        .route("/api/v1/timer/start", post(start_timer_handler))
+        .route("/api/v1/timer/stop", post(stop_timer_handler))
+        .route(
+            "/api/v1/me",
+            get(get_me_handler)
+                .patch(update_me_handler)
+                .delete(delete_me_handler),
+        )
+        .route("/api/v1/me/export", get(export_handler))
+        .route(
+            "/api/v1/calendar",
+            post(create_calendar_token_handler).delete(revoke_calendar_token_handler),
+        )
+        .route("/api/v1/calendar/:token", get(calendar_feed_handler))
+        .route(
+            "/api/v1/me/import",
+            post(import_handler).layer(DefaultBodyLimit::max(MAX_IMPORT_BYTES)),
+        )
+        .route(
+            "/api/v1/me/import/ical",
+            post(import_ical_handler).layer(DefaultBodyLimit::max(MAX_IMPORT_BYTES)),
+        )
         .layer(cors)
         .layer(
             TraceLayer::new_for_http()
@@ -118,12 +196,70 @@ async fn main() {
                         .get::<MatchedPath>()
                         .map(|matched_path| matched_path.as_str());
 
-                    tracing::debug_span!("request", %method, %uri, matched_path)
+                    let request_id = current_request_id();
+
+                    tracing::debug_span!("request", %method, %uri, matched_path, request_id)
                 })
                 .on_failure(()),
         )
+        .layer(middleware::from_fn(request_id))
         .layer(AddExtensionLayer::new(GoogleCertsState::default()))
-        .with_state(app_state.into());
+        .with_state(app_state)
+}
+
+#[tokio::main]
+async fn main() {
+    let env = EnvironmentVariables::from_env();
+
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+                format!("{}=debug,tower_http=debug", env!("CARGO_CRATE_NAME")).into()
+            }),
+        )
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+
+    let db: Box<dyn Storage> = match env.storage {
+        StorageBackend::Mongo => {
+            let db_uri = env
+                .db_url
+                .replace("%USER%", &env.db_user)
+                .replace("%PASS%", &env.db_pass);
+
+            match MongoDatabase::init(&db_uri, &env.db_name).await {
+                Ok(v) => {
+                    println!("Database connected");
+                    Box::new(v)
+                }
+                Err(_) => panic!("Fatal: database connection failed to initialize"),
+            }
+        }
+        StorageBackend::Memory => {
+            println!("Using in-memory storage, data will not be persisted");
+            Box::new(MemoryDatabase::new())
+        }
+    };
+
+    let client = reqwest::Client::new();
+
+    let port = &env.port.clone();
+
+    let mailer = Box::new(LogMailer::new(env.mail_outbox.as_ref().map(PathBuf::from)));
+
+    let app_state = AppState(Arc::new(InnerState {
+        db,
+        client,
+        env,
+        key: Key::generate(),
+        mailer,
+    }));
+
+    if app_state.env.account_deletion_grace_days.is_some() {
+        spawn_purge_task(app_state.clone());
+    }
+
+    let app = app(app_state);
 
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port))
         .await
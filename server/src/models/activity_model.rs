@@ -1,9 +1,14 @@
+use axum::http::StatusCode;
 use mongodb::bson::oid::ObjectId;
 use mongodb::bson::serde_helpers::{
     serialize_bson_datetime_as_rfc3339_string, serialize_object_id_as_hex_string,
 };
 use mongodb::bson::Bson;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
+
+use super::record_model::RecordResponse;
+use super::series_model::SeriesResponse;
+use crate::error::error::{AppError, ErrorResponse};
 
 #[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum ActivityVariant {
@@ -11,12 +16,14 @@ pub enum ActivityVariant {
     Exercise,
 }
 
-impl From<&str> for ActivityVariant {
-    fn from(s: &str) -> ActivityVariant {
+impl TryFrom<&str> for ActivityVariant {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<ActivityVariant, Self::Error> {
         match s {
-            "default" => ActivityVariant::Default,
-            "exercise" => ActivityVariant::Exercise,
-            _ => panic!("invalid activity variant!"),
+            "default" => Ok(ActivityVariant::Default),
+            "exercise" => Ok(ActivityVariant::Exercise),
+            _ => Err(format!("invalid activity variant: {}", s)),
         }
     }
 }
@@ -30,37 +37,40 @@ impl From<ActivityVariant> for String {
     }
 }
 
-impl From<Bson> for ActivityVariant {
-    fn from(bson: Bson) -> ActivityVariant {
-        if let Bson::String(s) = bson {
-            s.as_str().into()
-        } else {
-            panic!("expected Bson::String")
+impl TryFrom<Bson> for ActivityVariant {
+    type Error = String;
+
+    fn try_from(bson: Bson) -> Result<ActivityVariant, Self::Error> {
+        match bson {
+            Bson::String(s) => s.as_str().try_into(),
+            _ => Err("expected Bson::String".to_string()),
         }
     }
 }
 
-impl Into<Bson> for ActivityVariant {
-    fn into(self) -> Bson {
-        let s: String = self.into();
+impl From<ActivityVariant> for Bson {
+    fn from(variant: ActivityVariant) -> Bson {
+        let s: String = variant.into();
         Bson::String(s)
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum ExerciseVariant {
     Strength,
     Mobility,
     Cardio,
 }
 
-impl From<&str> for ExerciseVariant {
-    fn from(s: &str) -> ExerciseVariant {
+impl TryFrom<&str> for ExerciseVariant {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<ExerciseVariant, Self::Error> {
         match s {
-            "strength" => ExerciseVariant::Strength,
-            "mobility" => ExerciseVariant::Mobility,
-            "cardio" => ExerciseVariant::Cardio,
-            _ => panic!("invalid activity variant!"),
+            "strength" => Ok(ExerciseVariant::Strength),
+            "mobility" => Ok(ExerciseVariant::Mobility),
+            "cardio" => Ok(ExerciseVariant::Cardio),
+            _ => Err(format!("invalid exercise variant: {}", s)),
         }
     }
 }
@@ -151,9 +161,110 @@ pub struct GetActivitiesPayload {
     pub start: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub end: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+}
+
+pub const MAX_ACTIVITIES_LIMIT: u32 = 500;
+
+// offsets are in minutes as returned by js `Date.getTimezoneOffset`,
+// i.e. UTC+14 is -840 and UTC-12 is 720
+pub const MIN_TIMEZONE: i16 = -840;
+pub const MAX_TIMEZONE: i16 = 720;
+
+/// Position in the `start`, `_id` ordering of a user's activities. Encoded as
+/// `<start millis>_<id hex>` for the `after` query param and `next` token.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct ActivityCursor {
+    pub start: mongodb::bson::DateTime,
+    pub id: ObjectId,
+}
+
+impl ActivityCursor {
+    pub fn encode(&self) -> String {
+        format!("{}_{}", self.start.timestamp_millis(), self.id.to_hex())
+    }
+
+    pub fn decode(s: &str) -> Option<Self> {
+        let (start, id) = s.split_once('_')?;
+
+        Some(Self {
+            start: mongodb::bson::DateTime::from_millis(start.parse().ok()?),
+            id: ObjectId::parse_str(id).ok()?,
+        })
+    }
+}
+
+impl From<&Activity> for ActivityCursor {
+    fn from(activity: &Activity) -> ActivityCursor {
+        ActivityCursor {
+            start: activity.start,
+            id: activity.id,
+        }
+    }
+}
+
+/// Changes are re-sent for this long before a sync token, so a write whose
+/// `updatedAt` was taken just before the token but committed after is not missed.
+pub const SYNC_OVERLAP_MS: i64 = 5_000;
+
+/// Position in a user's change feed, issued as `next` by the sync endpoint.
+/// Encoded as `<updatedAt millis>`, clients should treat it as opaque.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct SyncToken {
+    pub at: mongodb::bson::DateTime,
+}
+
+impl SyncToken {
+    pub fn now() -> Self {
+        Self {
+            at: mongodb::bson::DateTime::now(),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        self.at.timestamp_millis().to_string()
+    }
+
+    pub fn decode(s: &str) -> Option<Self> {
+        let millis: i64 = s.parse().ok()?;
+        if millis < 0 {
+            return None;
+        }
+
+        Some(Self {
+            at: mongodb::bson::DateTime::from_millis(millis),
+        })
+    }
+
+    /// Lower bound for changes to return, see `SYNC_OVERLAP_MS`.
+    pub fn changes_since(&self) -> mongodb::bson::DateTime {
+        mongodb::bson::DateTime::from_millis(self.at.timestamp_millis() - SYNC_OVERLAP_MS)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+pub struct SyncPayload {
+    pub since: Option<String>,
+}
+
+/// Without `since` every live activity and series is returned and the
+/// deleted lists are empty. Changes can repeat across syncs, clients should
+/// apply them by id. Occurrences of series are left to clients to expand.
+#[derive(Debug, Serialize)]
+pub struct SyncResponse {
+    pub changed: Vec<ActivityResponse>,
+    pub deleted: Vec<String>,
+    #[serde(rename = "changedSeries")]
+    pub changed_series: Vec<SeriesResponse>,
+    #[serde(rename = "deletedSeries")]
+    pub deleted_series: Vec<String>,
+    pub next: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PostActivityPayload {
     pub title: String,
     pub variant: ActivityVariant,
@@ -164,6 +275,13 @@ pub struct PostActivityPayload {
     pub timezone: i16,
     pub data: Option<ActivityData>,
     pub color: Option<String>,
+    /// Set by the calendar import, see `Activity::ical_uid`.
+    #[serde(skip)]
+    pub ical_uid: Option<String>,
+    /// Set when an occurrence of a series is materialized, see
+    /// `Activity::occurrence`.
+    #[serde(skip)]
+    pub occurrence: Option<Occurrence>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -177,9 +295,10 @@ pub struct PatchActivityBody {
     pub timezone: Option<i16>,
     pub data: Option<ActivityData>,
     pub color: Option<String>,
+    pub v: Option<u32>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PatchActivityPayload {
     #[serde(rename = "_id")]
     pub id: String,
@@ -192,16 +311,110 @@ pub struct PatchActivityPayload {
     pub timezone: Option<i16>,
     pub data: Option<ActivityData>,
     pub color: Option<String>,
+    /// Expected `__v`, the update is only applied if it still matches.
+    pub v: Option<u32>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DeleteActivityPayload {
     #[serde(rename = "_id")]
     pub id: String,
+    /// Expected `__v`, the delete is only applied if it still matches.
+    pub v: Option<u32>,
+}
+
+pub const MAX_BATCH_OPERATIONS: usize = 100;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum BatchOperation {
+    Create {
+        activity: PostActivityPayload,
+    },
+    Update {
+        id: String,
+        activity: PatchActivityBody,
+    },
+    Delete {
+        id: String,
+        v: Option<u32>,
+    },
 }
 
-#[non_exhaustive]
 #[derive(Debug, Serialize, Deserialize)]
+pub struct BatchActivityPayload {
+    pub operations: Vec<BatchOperation>,
+}
+
+/// A validated batch operation, as handed to storage.
+#[derive(Clone, Debug)]
+pub enum ActivityWrite {
+    Create(PostActivityPayload),
+    Update(PatchActivityPayload),
+    Delete(DeleteActivityPayload),
+}
+
+#[derive(Debug)]
+pub enum ActivityWriteResult {
+    Created(Activity),
+    Updated(VersionedWrite<Activity>),
+    Deleted(VersionedWrite<ActivityDelete>),
+}
+
+/// Outcome of an update or delete that is conditional on the stored version.
+#[derive(Debug)]
+pub enum VersionedWrite<T> {
+    Done(T),
+    NotFound,
+    /// The stored version did not match, holds the current document.
+    Conflict(Box<Activity>),
+    /// Only one bound was given and it falls on the wrong side of the stored
+    /// other one, holds the field of the given bound.
+    OutOfRange(&'static str),
+}
+
+impl<T> VersionedWrite<T> {
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> VersionedWrite<U> {
+        match self {
+            VersionedWrite::Done(v) => VersionedWrite::Done(f(v)),
+            VersionedWrite::NotFound => VersionedWrite::NotFound,
+            VersionedWrite::Conflict(current) => VersionedWrite::Conflict(current),
+            VersionedWrite::OutOfRange(field) => VersionedWrite::OutOfRange(field),
+        }
+    }
+}
+
+/// The occurrence of a recurring series an activity stands for.
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct Occurrence {
+    pub series: ObjectId,
+    /// The start the occurrence has in the series, which identifies it even
+    /// after the activity is moved.
+    pub start: mongodb::bson::DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OccurrenceResponse {
+    #[serde(
+        rename = "seriesId",
+        serialize_with = "serialize_object_id_as_hex_string"
+    )]
+    pub series_id: ObjectId,
+    #[serde(serialize_with = "serialize_bson_datetime_as_rfc3339_string")]
+    pub start: mongodb::bson::DateTime,
+}
+
+impl From<Occurrence> for OccurrenceResponse {
+    fn from(occurrence: Occurrence) -> Self {
+        Self {
+            series_id: occurrence.series,
+            start: occurrence.start,
+        }
+    }
+}
+
+#[non_exhaustive]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Activity {
     #[serde(rename = "_id")]
     pub id: ObjectId,
@@ -210,31 +423,55 @@ pub struct Activity {
     pub group: String,
     pub notes: String,
     pub start: mongodb::bson::DateTime,
-    pub end: mongodb::bson::DateTime,
+    /// Null while the activity is a running timer. Range queries treat it as
+    /// lasting until now.
+    #[serde(default)]
+    pub end: Option<mongodb::bson::DateTime>,
     pub timezone: i16,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub data: Option<ActivityData>,
     #[serde(rename = "createdAt")]
     pub created_at: mongodb::bson::DateTime,
+    /// Documents written before this field existed read as the epoch.
+    #[serde(rename = "updatedAt", default = "epoch")]
+    pub updated_at: mongodb::bson::DateTime,
+    /// Set when deleted. The document is kept as a tombstone for sync.
+    /// Stored as null while live, so partial indexes can match live documents.
+    #[serde(rename = "deletedAt", default)]
+    pub deleted_at: Option<mongodb::bson::DateTime>,
+    /// The event an imported activity came from, as its UID, followed by
+    /// `/<start>` for an occurrence of a recurring event. Imports skip events
+    /// already imported, including ones deleted since.
+    #[serde(rename = "icalUid", default, skip_serializing_if = "Option::is_none")]
+    pub ical_uid: Option<String>,
+    /// Set on an occurrence of a series, either one that was edited on its
+    /// own and stored, or one expanded from the series for a range query.
+    /// Expanded ones have an id derived from the series and are not stored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub occurrence: Option<Occurrence>,
     pub user: ObjectId,
     #[serde(rename = "__v")]
     pub v: u32,
 }
 
+fn epoch() -> mongodb::bson::DateTime {
+    mongodb::bson::DateTime::from_millis(0)
+}
+
 impl Activity {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         variant: ActivityVariant,
         title: String,
         group: String,
         notes: String,
-        start: String,
-        end: String,
+        start: mongodb::bson::DateTime,
+        end: Option<mongodb::bson::DateTime>,
         timezone: i16,
         data: Option<ActivityData>,
-        user: String,
+        user: ObjectId,
     ) -> Self {
-        let start = mongodb::bson::DateTime::parse_rfc3339_str(start).unwrap();
-        let end = mongodb::bson::DateTime::parse_rfc3339_str(end).unwrap();
+        let now = mongodb::bson::DateTime::now();
 
         Self {
             id: ObjectId::new(),
@@ -246,11 +483,43 @@ impl Activity {
             end,
             timezone,
             data,
-            created_at: mongodb::bson::DateTime::now(),
-            user: ObjectId::parse_str(user).expect("failed to parse string to ObjectId"),
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            ical_uid: None,
+            occurrence: None,
+            user,
             v: 1,
         }
     }
+
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.end.is_none()
+    }
+
+    /// The end, or now for a running timer.
+    pub fn end_or_now(&self) -> mongodb::bson::DateTime {
+        self.end.unwrap_or_else(mongodb::bson::DateTime::now)
+    }
+
+    /// Checks an edit of only one bound against the other stored bound, and
+    /// returns the field of the bound that doesn't fit.
+    pub fn out_of_range(
+        &self,
+        start: Option<mongodb::bson::DateTime>,
+        end: Option<mongodb::bson::DateTime>,
+    ) -> Option<&'static str> {
+        match (start, end) {
+            // a running timer has no end to check against
+            (Some(start), None) => self.end.is_some_and(|end| end < start).then_some("start"),
+            (None, Some(end)) => (end < self.start).then_some("end"),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -263,22 +532,49 @@ pub struct ActivityResponse {
     pub notes: String,
     #[serde(serialize_with = "serialize_bson_datetime_as_rfc3339_string")]
     pub start: mongodb::bson::DateTime,
-    #[serde(serialize_with = "serialize_bson_datetime_as_rfc3339_string")]
-    pub end: mongodb::bson::DateTime,
+    /// Null while the activity is a running timer.
+    #[serde(serialize_with = "serialize_optional_date")]
+    pub end: Option<mongodb::bson::DateTime>,
     pub timezone: i16,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub data: Option<ActivityData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub occurrence: Option<OccurrenceResponse>,
     #[serde(
         rename = "createdAt",
         serialize_with = "serialize_bson_datetime_as_rfc3339_string"
     )]
     pub created_at: mongodb::bson::DateTime,
+    #[serde(
+        rename = "updatedAt",
+        serialize_with = "serialize_bson_datetime_as_rfc3339_string"
+    )]
+    pub updated_at: mongodb::bson::DateTime,
     #[serde(serialize_with = "serialize_object_id_as_hex_string")]
     pub user: ObjectId,
     #[serde(rename = "v")]
     pub __v: u32,
 }
 
+fn serialize_optional_date<S: Serializer>(
+    date: &Option<mongodb::bson::DateTime>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match date {
+        Some(date) => serialize_bson_datetime_as_rfc3339_string(date, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// A created or updated activity, with the personal records it set.
+#[derive(Debug, Serialize)]
+pub struct SavedActivityResponse {
+    #[serde(flatten)]
+    pub activity: ActivityResponse,
+    #[serde(rename = "newRecords")]
+    pub new_records: Vec<RecordResponse>,
+}
+
 impl From<Activity> for ActivityResponse {
     fn from(activity: Activity) -> ActivityResponse {
         ActivityResponse {
@@ -291,7 +587,9 @@ impl From<Activity> for ActivityResponse {
             end: activity.end,
             timezone: activity.timezone,
             data: activity.data,
+            occurrence: activity.occurrence.map(Into::into),
             created_at: activity.created_at,
+            updated_at: activity.updated_at,
             user: activity.user,
             __v: activity.v,
         }
@@ -310,13 +608,30 @@ impl From<&Activity> for ActivityResponse {
             end: activity.end,
             timezone: activity.timezone,
             data: activity.data.clone(),
+            occurrence: activity.occurrence.map(Into::into),
             created_at: activity.created_at,
+            updated_at: activity.updated_at,
             user: activity.user,
             __v: activity.v,
         }
     }
 }
 
+#[derive(Debug, Serialize)]
+pub struct ActivityPageResponse {
+    pub data: Vec<ActivityResponse>,
+    pub next: Option<String>,
+}
+
+/// Bare list when no `limit` or `after` is requested, so existing clients keep
+/// working, otherwise a page with a `next` cursor.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum ActivitiesResponse {
+    List(Vec<ActivityResponse>),
+    Page(ActivityPageResponse),
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ActivityDelete {
     #[serde(rename = "_id")]
@@ -340,3 +655,51 @@ impl From<&ActivityDelete> for ActivityDeleteResponse {
         ActivityDeleteResponse { id: activity.id }
     }
 }
+
+/// Result of one batch operation, in the same position as the request.
+#[derive(Debug, Serialize)]
+pub struct BatchResult {
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub activity: Option<ActivityResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted: Option<ActivityDeleteResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorResponse>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchActivityResponse {
+    pub results: Vec<BatchResult>,
+}
+
+impl BatchResult {
+    pub fn activity(status: StatusCode, activity: Activity) -> Self {
+        Self {
+            status: status.as_u16(),
+            activity: Some(ActivityResponse::from(activity)),
+            deleted: None,
+            error: None,
+        }
+    }
+
+    pub fn deleted(deleted: ActivityDelete) -> Self {
+        Self {
+            status: StatusCode::OK.as_u16(),
+            activity: None,
+            deleted: Some(ActivityDeleteResponse::from(deleted)),
+            error: None,
+        }
+    }
+}
+
+impl From<AppError> for BatchResult {
+    fn from(err: AppError) -> Self {
+        Self {
+            status: err.status().as_u16(),
+            activity: None,
+            deleted: None,
+            error: Some(ErrorResponse::from(err)),
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::{env, usize};
+use std::env;
 
 use axum_extra::extract::cookie::{Cookie, SameSite};
 use axum_extra::extract::PrivateCookieJar;
@@ -9,8 +9,9 @@ use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
 
-use crate::error::error::AuthError;
+use crate::error::error::AppError;
 use crate::utils::auth::generate_jti;
+use crate::utils::utils::{generate_token, hash_token};
 
 #[derive(Debug, Deserialize)]
 pub struct AuthPayload {
@@ -64,19 +65,19 @@ pub struct AccessToken {
 }
 
 impl AccessToken {
-    pub fn new(sub: &String) -> Result<Self, AuthError> {
+    pub fn new(sub: &String) -> Result<Self, AppError> {
         let claims = AccessClaims::new(sub);
 
         let hmac_key = match env::var("HMAC_KEY") {
             Ok(v) => v,
-            Err(_) => return Err(AuthError::InternalError),
+            Err(_) => return Err(AppError::internal("HMAC_KEY is not set")),
         };
 
         let keys = Keys::new(hmac_key.as_bytes());
 
         let token = match encode(&Header::default(), &claims, &keys.encoding) {
             Ok(token) => Ok(token),
-            Err(_) => Err(AuthError::InternalError),
+            Err(_) => Err(AppError::internal("failed to sign token")),
         }?;
 
         Ok(Self {
@@ -111,19 +112,19 @@ impl From<&AccessToken> for Cookie<'_> {
 }
 
 impl TryFrom<&PrivateCookieJar> for AccessToken {
-    type Error = AuthError;
+    type Error = AppError;
 
-    fn try_from(jar: &PrivateCookieJar) -> Result<Self, AuthError> {
+    fn try_from(jar: &PrivateCookieJar) -> Result<Self, AppError> {
         let hmac_key = match env::var("HMAC_KEY") {
             Ok(v) => v,
-            Err(_) => return Err(AuthError::InternalError),
+            Err(_) => return Err(AppError::internal("HMAC_KEY is not set")),
         };
 
         let keys = Keys::new(hmac_key.as_bytes());
 
         let token = match jar.get("access").map(|cookie| cookie.value().to_owned()) {
             Some(value) => Ok(value),
-            None => Err(AuthError::MissingToken),
+            None => Err(AppError::MissingToken),
         }?;
 
         // Decode the user data
@@ -138,11 +139,11 @@ impl TryFrom<&PrivateCookieJar> for AccessToken {
 
                         let token_data =
                             decode::<AccessClaims>(&token, &keys.decoding, &validation)
-                                .map_err(|_| AuthError::InvalidToken)?;
+                                .map_err(|_| AppError::InvalidToken)?;
 
                         Ok((token_data, true))
                     }
-                    _ => Err(AuthError::InvalidToken),
+                    _ => Err(AppError::InvalidToken),
                 },
             }?;
 
@@ -193,19 +194,19 @@ pub struct RefreshToken {
 }
 
 impl RefreshToken {
-    pub fn new(sub: &String, exp: Option<usize>) -> Result<Self, AuthError> {
+    pub fn new(sub: &String, exp: Option<usize>) -> Result<Self, AppError> {
         let claims = RefreshClaims::new(sub, exp);
 
         let hmac_key = match env::var("HMAC_KEY") {
             Ok(v) => v,
-            Err(_) => return Err(AuthError::InternalError),
+            Err(_) => return Err(AppError::internal("HMAC_KEY is not set")),
         };
 
         let keys = Keys::new(hmac_key.as_bytes());
 
         let token = match encode(&Header::default(), &claims, &keys.encoding) {
             Ok(token) => Ok(token),
-            Err(_) => Err(AuthError::Forbidden),
+            Err(_) => Err(AppError::internal("failed to sign token")),
         }?;
 
         Ok(Self { token, claims })
@@ -236,26 +237,26 @@ impl From<&RefreshToken> for Cookie<'_> {
 }
 
 impl TryFrom<&PrivateCookieJar> for RefreshToken {
-    type Error = AuthError;
+    type Error = AppError;
 
-    fn try_from(jar: &PrivateCookieJar) -> Result<Self, AuthError> {
+    fn try_from(jar: &PrivateCookieJar) -> Result<Self, AppError> {
         let hmac_key = match env::var("HMAC_KEY") {
             Ok(v) => v,
-            Err(_) => return Err(AuthError::InternalError),
+            Err(_) => return Err(AppError::internal("HMAC_KEY is not set")),
         };
 
         let keys = Keys::new(hmac_key.as_bytes());
 
         let token = match jar.get("refresh").map(|cookie| cookie.value().to_owned()) {
             Some(value) => Ok(value),
-            None => Err(AuthError::MissingToken),
+            None => Err(AppError::MissingToken),
         }?;
 
         // Decode the user data
         let token_data =
             match decode::<RefreshClaims>(&token, &keys.decoding, &Validation::default()) {
                 Ok(token) => Ok(token),
-                Err(_) => Err(AuthError::InvalidToken),
+                Err(_) => Err(AppError::InvalidToken),
             }?;
 
         Ok(Self {
@@ -270,12 +271,12 @@ impl TryFrom<&PrivateCookieJar> for RefreshToken {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TokenDB {
-    id: ObjectId,
-    uid: ObjectId,
-    jti: String,
-    exp: mongodb::bson::DateTime,
+    pub id: ObjectId,
+    pub uid: ObjectId,
+    pub jti: String,
+    pub exp: mongodb::bson::DateTime,
     pub black: bool,
 }
 
@@ -315,6 +316,112 @@ impl From<&RefreshToken> for TokenDB {
     }
 }
 
+/// How long a mailed password reset link stays valid.
+pub const RESET_TOKEN_TTL_MINUTES: i64 = 30;
+
+/// A single-use password reset token. Only the hash is stored, the token
+/// itself is mailed to the user.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResetTokenDB {
+    pub id: ObjectId,
+    pub uid: ObjectId,
+    pub hash: String,
+    pub exp: mongodb::bson::DateTime,
+    pub used: bool,
+}
+
+impl ResetTokenDB {
+    /// Returns the record to store and the token to send.
+    pub fn new(uid: ObjectId) -> (Self, String) {
+        let token = generate_token();
+        let exp =
+            (Utc::now() + chrono::Duration::minutes(RESET_TOKEN_TTL_MINUTES)).timestamp_millis();
+
+        let record = Self {
+            id: ObjectId::new(),
+            uid,
+            hash: hash_token(&token),
+            exp: mongodb::bson::DateTime::from_millis(exp),
+            used: false,
+        };
+
+        (record, token)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForgotPasswordPayload {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResetPasswordPayload {
+    pub token: String,
+    pub pass: String,
+}
+
+/// How long a mailed email verification link stays valid.
+pub const VERIFY_TOKEN_TTL_HOURS: i64 = 48;
+
+// keeps session tokens, signed with the same key, from passing as verification tokens
+const VERIFY_TOKEN_AUD: &str = "verify-email";
+
+/// Claims of the signed token mailed to confirm an email address. The token
+/// stops working once the user's email changes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyEmailClaims {
+    pub sub: String,
+    pub email: String,
+    pub aud: String,
+    pub exp: usize,
+}
+
+impl VerifyEmailClaims {
+    pub fn new(sub: &ObjectId, email: &str) -> Self {
+        let exp = (Utc::now() + chrono::Duration::hours(VERIFY_TOKEN_TTL_HOURS)).timestamp();
+
+        Self {
+            sub: sub.to_hex(),
+            email: email.to_string(),
+            aud: VERIFY_TOKEN_AUD.to_string(),
+            exp: exp as usize,
+        }
+    }
+
+    pub fn encode(&self) -> Result<String, AppError> {
+        let hmac_key = match env::var("HMAC_KEY") {
+            Ok(v) => v,
+            Err(_) => return Err(AppError::internal("HMAC_KEY is not set")),
+        };
+
+        let keys = Keys::new(hmac_key.as_bytes());
+
+        encode(&Header::default(), self, &keys.encoding)
+            .map_err(|_| AppError::internal("failed to sign token"))
+    }
+
+    pub fn decode(token: &str) -> Result<Self, AppError> {
+        let hmac_key = match env::var("HMAC_KEY") {
+            Ok(v) => v,
+            Err(_) => return Err(AppError::internal("HMAC_KEY is not set")),
+        };
+
+        let keys = Keys::new(hmac_key.as_bytes());
+
+        let mut validation = Validation::default();
+        validation.set_audience(&[VERIFY_TOKEN_AUD]);
+
+        decode::<Self>(token, &keys.decoding, &validation)
+            .map(|data| data.claims)
+            .map_err(|_| AppError::InvalidVerificationToken)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyEmailPayload {
+    pub token: String,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct GoogleCertsResponse {
     pub keys: Vec<GoogleCert>,
@@ -0,0 +1,24 @@
+use serde::Serialize;
+
+pub const CALENDAR_NAME: &str = "Chrono";
+
+/// Days either side of now that series are expanded for, when the feed is
+/// asked for no range.
+pub const FEED_SERIES_DAYS: i64 = 365;
+
+/// Returned once when a feed token is created, only its hash is stored.
+#[derive(Debug, Serialize)]
+pub struct CalendarTokenResponse {
+    pub token: String,
+    /// Feed path relative to the api host, for calendar apps to subscribe to.
+    pub path: String,
+}
+
+impl CalendarTokenResponse {
+    pub fn new(token: String) -> Self {
+        Self {
+            path: format!("/api/v1/calendar/{}.ics", token),
+            token,
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+use super::activity_model::{Activity, ActivityVariant, Exercise};
+use super::user_model::{User, UserResponse};
+
+#[derive(PartialEq, Copy, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    /// A zip of csv files, see the `*Row` types for their columns.
+    Zip,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportPayload {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// Everything in the json export except the activities, which are streamed
+/// after it.
+#[derive(Debug, Serialize)]
+pub struct ExportHeader {
+    #[serde(rename = "exportedAt")]
+    pub exported_at: String,
+    pub user: UserResponse,
+}
+
+fn rfc3339(date: DateTime) -> String {
+    date.try_to_rfc3339_string().unwrap_or_default()
+}
+
+/// `profile.csv`, a single row.
+#[derive(Debug, Serialize)]
+pub struct ProfileRow {
+    pub id: String,
+    pub email: String,
+    #[serde(rename = "givenName")]
+    pub given_name: String,
+    #[serde(rename = "familyName")]
+    pub family_name: String,
+    pub img: String,
+    pub verified: bool,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+}
+
+impl From<&User> for ProfileRow {
+    fn from(user: &User) -> Self {
+        Self {
+            id: user.id.to_hex(),
+            email: user.email.clone(),
+            given_name: user.given_name.clone(),
+            family_name: user.family_name.clone(),
+            img: user.img.clone(),
+            verified: user.verified,
+            created_at: rfc3339(user.created_at),
+        }
+    }
+}
+
+/// `colors.csv`, the color picked for each activity title.
+#[derive(Debug, Serialize)]
+pub struct ColorRow {
+    pub title: String,
+    pub color: String,
+}
+
+impl ColorRow {
+    pub fn rows(user: &User) -> Vec<Self> {
+        let mut rows: Vec<Self> = user
+            .activities
+            .iter()
+            .map(|(title, color)| Self {
+                title: title.clone(),
+                color: color.clone(),
+            })
+            .collect();
+
+        rows.sort_by(|a, b| a.title.cmp(&b.title));
+        rows
+    }
+}
+
+/// `activities.csv`, one row per activity.
+#[derive(Debug, Serialize)]
+pub struct ActivityRow {
+    pub id: String,
+    pub title: String,
+    pub variant: ActivityVariant,
+    pub group: String,
+    pub notes: String,
+    pub start: String,
+    pub end: String,
+    pub timezone: i16,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: String,
+    pub v: u32,
+}
+
+impl From<&Activity> for ActivityRow {
+    fn from(activity: &Activity) -> Self {
+        Self {
+            id: activity.id.to_hex(),
+            title: activity.title.clone(),
+            variant: activity.variant,
+            group: activity.group.clone(),
+            notes: activity.notes.clone(),
+            start: rfc3339(activity.start),
+            end: activity.end.map(rfc3339).unwrap_or_default(),
+            timezone: activity.timezone,
+            created_at: rfc3339(activity.created_at),
+            updated_at: rfc3339(activity.updated_at),
+            v: activity.v,
+        }
+    }
+}
+
+fn exercises(activity: &Activity) -> impl Iterator<Item = (usize, &Exercise)> {
+    activity
+        .data
+        .iter()
+        .flat_map(|data| data.exercise.iter().flatten())
+        .enumerate()
+}
+
+/// `sets.csv`, one row per set of a strength or mobility exercise.
+/// `exercise` is the position of the exercise within its activity.
+#[derive(Debug, Serialize)]
+pub struct SetRow {
+    pub activity: String,
+    pub exercise: usize,
+    pub title: String,
+    pub variant: &'static str,
+    pub set: u8,
+    pub reps: Option<u32>,
+    pub weight: Option<u32>,
+    pub rest: Option<u32>,
+    pub duration: Option<u32>,
+}
+
+impl SetRow {
+    pub fn rows(activity: &Activity) -> Vec<Self> {
+        let mut rows = vec![];
+
+        for (idx, exercise) in exercises(activity) {
+            let (variant, title, sets) = match exercise {
+                Exercise::Strength(e) => ("Strength", &e.title, &e.sets),
+                Exercise::Mobility(e) => ("Mobility", &e.title, &e.sets),
+                Exercise::Cardio(_) => continue,
+            };
+
+            rows.extend(sets.iter().map(|set| Self {
+                activity: activity.id.to_hex(),
+                exercise: idx,
+                title: title.clone(),
+                variant,
+                set: set.idx,
+                reps: set.reps,
+                weight: set.weight,
+                rest: set.rest,
+                duration: set.duration,
+            }));
+        }
+
+        rows
+    }
+}
+
+/// `splits.csv`, one row per split of a cardio exercise. A cardio exercise
+/// without splits gets one row with the split columns empty, so its totals
+/// are not lost.
+#[derive(Debug, Serialize)]
+pub struct SplitRow {
+    pub activity: String,
+    pub exercise: usize,
+    pub title: String,
+    pub duration: u32,
+    pub distance: u32,
+    pub split: Option<u8>,
+    #[serde(rename = "splitDistance")]
+    pub split_distance: Option<u32>,
+    #[serde(rename = "splitDuration")]
+    pub split_duration: Option<u32>,
+}
+
+impl SplitRow {
+    pub fn rows(activity: &Activity) -> Vec<Self> {
+        let mut rows = vec![];
+
+        for (idx, exercise) in exercises(activity) {
+            let cardio = match exercise {
+                Exercise::Cardio(e) => e,
+                _ => continue,
+            };
+
+            let row = |split: Option<u8>, distance: Option<u32>, duration: Option<u32>| Self {
+                activity: activity.id.to_hex(),
+                exercise: idx,
+                title: cardio.title.clone(),
+                duration: cardio.duration,
+                distance: cardio.distance,
+                split,
+                split_distance: distance,
+                split_duration: duration,
+            };
+
+            match cardio.splits.as_deref() {
+                Some(splits) if !splits.is_empty() => rows.extend(
+                    splits
+                        .iter()
+                        .map(|s| row(Some(s.idx), s.distance, s.duration)),
+                ),
+                _ => rows.push(row(None, None, None)),
+            }
+        }
+
+        rows
+    }
+}
@@ -0,0 +1,66 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// How long a key and its response are kept for replay.
+pub const IDEMPOTENCY_RETENTION_HOURS: i64 = 24;
+
+/// How long a key is held while its first request is handled. A request that
+/// never finishes, e.g. dropped when the client disconnects, frees the key
+/// once this runs out.
+pub const IDEMPOTENCY_LEASE_SECS: i64 = 60;
+
+/// How often a request that is still being handled renews its lease.
+pub const IDEMPOTENCY_LEASE_RENEW_SECS: u64 = 20;
+
+/// Max length of an `Idempotency-Key` header value.
+pub const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub etag: Option<String>,
+    pub body: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IdempotencyRecord {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub user: ObjectId,
+    pub key: String,
+    /// Hash of the request the key was first used with.
+    pub fingerprint: String,
+    /// `None` while the first request is still being handled.
+    pub response: Option<StoredResponse>,
+    /// The end of the lease until there is a response, then of the retention.
+    pub exp: mongodb::bson::DateTime,
+}
+
+impl IdempotencyRecord {
+    pub fn new(user: ObjectId, key: String, fingerprint: String) -> Self {
+        Self {
+            id: ObjectId::new(),
+            user,
+            key,
+            fingerprint,
+            response: None,
+            exp: Self::leased_until(),
+        }
+    }
+
+    /// `exp` while the first request is being handled.
+    pub fn leased_until() -> mongodb::bson::DateTime {
+        let exp = chrono::Utc::now() + chrono::Duration::seconds(IDEMPOTENCY_LEASE_SECS);
+        mongodb::bson::DateTime::from_millis(exp.timestamp_millis())
+    }
+
+    /// `exp` once the response is stored.
+    pub fn retained_until() -> mongodb::bson::DateTime {
+        let exp = chrono::Utc::now() + chrono::Duration::hours(IDEMPOTENCY_RETENTION_HOURS);
+        mongodb::bson::DateTime::from_millis(exp.timestamp_millis())
+    }
+
+    pub fn expired(&self) -> bool {
+        self.exp <= mongodb::bson::DateTime::now()
+    }
+}
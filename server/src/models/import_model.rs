@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+use super::activity_model::{ActivityVariant, PostActivityPayload};
+use crate::error::error::FieldError;
+
+pub const MAX_IMPORT_ROWS: usize = 10_000;
+pub const MAX_IMPORT_BYTES: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportPayload {
+    /// Validate and report without writing anything.
+    #[serde(rename = "dryRun", default)]
+    pub dry_run: bool,
+}
+
+/// The parts of the json export that are imported. Activities are kept raw
+/// so each one can fail to parse on its own.
+#[derive(Debug, Deserialize)]
+pub struct ImportDocument {
+    #[serde(default)]
+    pub user: Option<ImportUser>,
+    pub activities: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportUser {
+    /// The title to color map.
+    #[serde(default)]
+    pub activities: HashMap<String, String>,
+}
+
+/// A row of an imported csv. Other columns, such as those of an exported
+/// `activities.csv`, are ignored. An empty variant is `Default` and an empty
+/// timezone is UTC.
+#[derive(Debug, Deserialize)]
+pub struct CsvActivityRow {
+    pub title: String,
+    pub group: String,
+    #[serde(default)]
+    pub variant: Option<String>,
+    pub start: String,
+    pub end: String,
+    #[serde(default)]
+    pub timezone: Option<i16>,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+impl TryFrom<CsvActivityRow> for PostActivityPayload {
+    type Error = FieldError;
+
+    fn try_from(row: CsvActivityRow) -> Result<Self, Self::Error> {
+        let variant = match row.variant.as_deref().map(str::trim) {
+            None | Some("") => ActivityVariant::Default,
+            Some(v) => ActivityVariant::try_from(v.to_lowercase().as_str())
+                .map_err(|_| FieldError::new("variant", "must be Default or Exercise"))?,
+        };
+
+        Ok(PostActivityPayload {
+            title: row.title,
+            variant,
+            group: row.group,
+            notes: row.notes,
+            start: row.start,
+            end: row.end,
+            timezone: row.timezone.unwrap_or(0),
+            data: None,
+            color: None,
+            ical_uid: None,
+            occurrence: None,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportResponse {
+    #[serde(rename = "dryRun")]
+    pub dry_run: bool,
+    /// Rows read from the upload.
+    pub rows: usize,
+    /// Rows that passed validation.
+    pub valid: usize,
+    pub imported: usize,
+    /// Titles whose color was set.
+    pub colors: usize,
+    /// Per-row problems, named `rows[<index>].<field>`. Only reported on a
+    /// dry run; a real import with problems fails as a whole.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+/// Recurring events are expanded at most this far, and an event that repeats
+/// more than this many times within the window is an error.
+pub const MAX_ICAL_WINDOW_DAYS: i64 = 366 * 5;
+pub const MAX_ICAL_OCCURRENCES: usize = 1_000;
+/// Default expansion window either side of now.
+pub const DEFAULT_ICAL_WINDOW_DAYS: i64 = 365;
+pub const ICAL_FETCH_TIMEOUT_SECS: u64 = 10;
+/// Redirects followed when fetching a calendar, each checked like the url.
+pub const MAX_ICAL_REDIRECTS: usize = 5;
+
+/// An event property an activity field can be taken from.
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IcalField {
+    Summary,
+    /// The first category.
+    Categories,
+    Description,
+    Location,
+    /// Leaves the field empty.
+    None,
+}
+
+fn default_title_field() -> IcalField {
+    IcalField::Summary
+}
+
+fn default_group_field() -> IcalField {
+    IcalField::Categories
+}
+
+fn default_notes_field() -> IcalField {
+    IcalField::Description
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IcalImportPayload {
+    /// Validate and report without writing anything.
+    #[serde(rename = "dryRun", default)]
+    pub dry_run: bool,
+    /// Fetch the calendar from here instead of reading the body.
+    pub url: Option<String>,
+    #[serde(default = "default_title_field")]
+    pub title: IcalField,
+    #[serde(default = "default_group_field")]
+    pub group: IcalField,
+    #[serde(default = "default_notes_field")]
+    pub notes: IcalField,
+    /// Offset for all-day and floating events, and the offset stored for
+    /// events in utc. Defaults to the calendar's X-WR-TIMEZONE, then utc.
+    pub timezone: Option<i16>,
+    /// Window recurring events are expanded in, a year either side of now
+    /// by default. Single events are imported whenever they are.
+    pub from: Option<String>,
+    pub until: Option<String>,
+}
+
+impl IcalImportPayload {
+    /// `from` and `until`, defaulted, or `None` if either is not a date.
+    pub fn window(&self) -> Option<(DateTime, DateTime)> {
+        let now = DateTime::now().timestamp_millis();
+        let bound = |date: &Option<String>, days: i64| match date {
+            Some(date) => DateTime::parse_rfc3339_str(date).ok(),
+            None => Some(DateTime::from_millis(now + days * 24 * 60 * 60 * 1000)),
+        };
+
+        Some((
+            bound(&self.from, -DEFAULT_ICAL_WINDOW_DAYS)?,
+            bound(&self.until, DEFAULT_ICAL_WINDOW_DAYS)?,
+        ))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct IcalImportResponse {
+    #[serde(rename = "dryRun")]
+    pub dry_run: bool,
+    /// VEVENTs in the calendar.
+    pub events: usize,
+    /// Activities the events expand to.
+    pub rows: usize,
+    pub valid: usize,
+    /// Valid rows whose UID was imported before.
+    pub skipped: usize,
+    pub imported: usize,
+    /// Per-event problems, named `events[<index>].<field>`. Only reported on
+    /// a dry run; a real import with problems fails as a whole.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
@@ -1,4 +1,14 @@
 pub mod activity_model;
 pub mod auth_model;
+pub mod calendar_model;
+pub mod export_model;
+pub mod idempotency_model;
+pub mod import_model;
+pub mod progress_model;
+pub mod record_model;
+pub mod series_model;
 pub mod state_model;
+pub mod stats_model;
+pub mod template_model;
+pub mod timer_model;
 pub mod user_model;
@@ -0,0 +1,279 @@
+use mongodb::bson::serde_helpers::{
+    serialize_bson_datetime_as_rfc3339_string, serialize_object_id_as_hex_string,
+};
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+use super::activity_model::{CardioExercise, ExerciseVariant, Set};
+
+/// Every exercise title the user has logged, per variant.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExerciseSummary {
+    pub title: String,
+    pub variant: ExerciseVariant,
+    /// Activities the exercise is in.
+    pub sessions: u64,
+    #[serde(serialize_with = "serialize_bson_datetime_as_rfc3339_string")]
+    pub last: DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExerciseProgressPayload {
+    pub title: String,
+    pub start: Option<String>,
+    pub end: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BestSet {
+    pub reps: u32,
+    pub weight: u32,
+}
+
+/// Epley's estimate of the one rep max, `None` for a set that can't say.
+pub fn estimated_one_rep_max(reps: u32, weight: u32) -> Option<f64> {
+    match (reps, weight) {
+        (0, _) | (_, 0) => None,
+        (1, weight) => Some(weight.into()),
+        (reps, weight) => Some(f64::from(weight) * (1.0 + f64::from(reps) / 30.0)),
+    }
+}
+
+/// The sets of an exercise in one activity. Sets missing reps or weight count
+/// as zero of it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StrengthSession {
+    #[serde(
+        rename = "activityId",
+        serialize_with = "serialize_object_id_as_hex_string"
+    )]
+    pub activity_id: ObjectId,
+    #[serde(serialize_with = "serialize_bson_datetime_as_rfc3339_string")]
+    pub start: DateTime,
+    pub sets: u64,
+    pub reps: u64,
+    /// Sum of reps times weight.
+    pub volume: u64,
+    /// Highest of the sets.
+    #[serde(rename = "estimated1rm")]
+    pub estimated_1rm: Option<f64>,
+    /// The set with the highest estimated one rep max, then weight, then reps.
+    #[serde(rename = "bestSet")]
+    pub best_set: BestSet,
+}
+
+impl StrengthSession {
+    /// `None` without sets.
+    pub fn new(activity_id: ObjectId, start: DateTime, sets: &[&Set]) -> Option<Self> {
+        let sets: Vec<(u32, u32)> = sets
+            .iter()
+            .map(|s| (s.reps.unwrap_or(0), s.weight.unwrap_or(0)))
+            .collect();
+
+        let best = sets.iter().copied().max_by(|a, b| {
+            let a_1rm = estimated_one_rep_max(a.0, a.1).unwrap_or(0.0);
+            let b_1rm = estimated_one_rep_max(b.0, b.1).unwrap_or(0.0);
+            a_1rm
+                .total_cmp(&b_1rm)
+                .then(a.1.cmp(&b.1))
+                .then(a.0.cmp(&b.0))
+        })?;
+
+        Some(Self {
+            activity_id,
+            start,
+            sets: sets.len() as u64,
+            reps: sets.iter().map(|s| u64::from(s.0)).sum(),
+            volume: sets.iter().map(|s| u64::from(s.0) * u64::from(s.1)).sum(),
+            estimated_1rm: estimated_one_rep_max(best.0, best.1),
+            best_set: BestSet {
+                reps: best.0,
+                weight: best.1,
+            },
+        })
+    }
+}
+
+/// The cardio exercises of a title in one activity, taken together. Durations
+/// are in seconds and distances in metres.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CardioSession {
+    #[serde(
+        rename = "activityId",
+        serialize_with = "serialize_object_id_as_hex_string"
+    )]
+    pub activity_id: ObjectId,
+    #[serde(serialize_with = "serialize_bson_datetime_as_rfc3339_string")]
+    pub start: DateTime,
+    pub duration: u64,
+    pub distance: u64,
+    /// Seconds per kilometre, `None` without distance.
+    pub pace: Option<f64>,
+    /// Splits with both a distance and a duration.
+    pub splits: u64,
+    /// Coefficient of variation of the split paces, 0 for perfectly even
+    /// splits. `None` with fewer than two splits.
+    #[serde(rename = "splitVariation")]
+    pub split_variation: Option<f64>,
+}
+
+fn pace(duration: u64, distance: u64) -> Option<f64> {
+    match distance {
+        0 => None,
+        distance => Some(duration as f64 * 1000.0 / distance as f64),
+    }
+}
+
+impl CardioSession {
+    pub fn new(activity_id: ObjectId, start: DateTime, exercises: &[&CardioExercise]) -> Self {
+        let duration = exercises.iter().map(|e| u64::from(e.duration)).sum();
+        let distance = exercises.iter().map(|e| u64::from(e.distance)).sum();
+
+        let paces: Vec<f64> = exercises
+            .iter()
+            .flat_map(|e| e.splits.iter().flatten())
+            .filter_map(|s| match (s.duration, s.distance) {
+                (Some(duration), Some(distance)) if duration > 0 => {
+                    pace(duration.into(), distance.into())
+                }
+                _ => None,
+            })
+            .collect();
+
+        let split_variation = match paces.len() {
+            0 | 1 => None,
+            n => {
+                let mean = paces.iter().sum::<f64>() / n as f64;
+                let variance = paces.iter().map(|p| (p - mean).powi(2)).sum::<f64>() / n as f64;
+                Some(variance.sqrt() / mean)
+            }
+        };
+
+        Self {
+            activity_id,
+            start,
+            duration,
+            distance,
+            pace: pace(duration, distance),
+            splits: paces.len() as u64,
+            split_variation,
+        }
+    }
+}
+
+/// Sessions are oldest first. Mobility exercises are not included.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExerciseProgress {
+    pub strength: Vec<StrengthSession>,
+    pub cardio: Vec<CardioSession>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExerciseProgressResponse {
+    pub title: String,
+    #[serde(flatten)]
+    pub progress: ExerciseProgress,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::activity_model::Split;
+
+    fn set(reps: Option<u32>, weight: Option<u32>) -> Set {
+        Set {
+            idx: 0,
+            reps,
+            weight,
+            rest: None,
+            duration: None,
+        }
+    }
+
+    fn split(distance: u32, duration: u32) -> Split {
+        Split {
+            idx: 0,
+            distance: Some(distance),
+            duration: Some(duration),
+        }
+    }
+
+    #[test]
+    fn strength_session() {
+        let sets = [
+            set(Some(5), Some(100)),
+            set(Some(1), Some(110)),
+            set(Some(8), Some(90)),
+            set(None, Some(120)),
+        ];
+        let session = StrengthSession::new(
+            ObjectId::new(),
+            DateTime::now(),
+            &sets.iter().collect::<Vec<_>>(),
+        )
+        .unwrap();
+
+        assert_eq!(session.sets, 4);
+        assert_eq!(session.reps, 14);
+        assert_eq!(session.volume, 5 * 100 + 110 + 8 * 90);
+        // 100 * (1 + 5 / 30) beats 90 * (1 + 8 / 30) and a single at 110
+        assert_eq!(
+            session.best_set,
+            BestSet {
+                reps: 5,
+                weight: 100
+            }
+        );
+        assert!((session.estimated_1rm.unwrap() - 350.0 / 3.0).abs() < 1e-9);
+
+        // bodyweight sets are ranked by reps
+        let sets = [set(Some(10), None), set(Some(12), None)];
+        let session = StrengthSession::new(
+            ObjectId::new(),
+            DateTime::now(),
+            &sets.iter().collect::<Vec<_>>(),
+        )
+        .unwrap();
+        assert_eq!(session.volume, 0);
+        assert_eq!(session.estimated_1rm, None);
+        assert_eq!(
+            session.best_set,
+            BestSet {
+                reps: 12,
+                weight: 0
+            }
+        );
+
+        assert!(StrengthSession::new(ObjectId::new(), DateTime::now(), &[]).is_none());
+    }
+
+    #[test]
+    fn cardio_session() {
+        let run = CardioExercise {
+            title: "run".to_string(),
+            duration: 600,
+            distance: 2000,
+            splits: Some(vec![split(1000, 280), split(1000, 320)]),
+        };
+        let session = CardioSession::new(ObjectId::new(), DateTime::now(), &[&run]);
+
+        assert_eq!(session.pace, Some(300.0));
+        assert_eq!(session.splits, 2);
+        assert_eq!(session.split_variation, Some(20.0 / 300.0));
+
+        let even = CardioExercise {
+            title: "run".to_string(),
+            duration: 0,
+            distance: 0,
+            splits: Some(vec![split(500, 150), split(1000, 300)]),
+        };
+        let session = CardioSession::new(ObjectId::new(), DateTime::now(), &[&run, &even]);
+        assert_eq!(session.distance, 2000);
+        assert_eq!(session.splits, 4);
+        assert!(session.split_variation.unwrap() > 0.0);
+
+        let session = CardioSession::new(ObjectId::new(), DateTime::now(), &[&even]);
+        assert_eq!(session.pace, None);
+        assert_eq!(session.split_variation, Some(0.0));
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::{HashMap, HashSet};
+
+use mongodb::bson::serde_helpers::{
+    serialize_bson_datetime_as_rfc3339_string, serialize_object_id_as_hex_string,
+};
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    activity_model::{Activity, ActivityVariant, Exercise},
+    progress_model::estimated_one_rep_max,
+};
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum RecordKind {
+    #[serde(rename = "heaviestWeight")]
+    HeaviestWeight,
+    /// Kept per weight.
+    #[serde(rename = "mostReps")]
+    MostReps,
+    #[serde(rename = "estimated1rm")]
+    Estimated1rm,
+    /// Metres, of a single cardio exercise.
+    #[serde(rename = "longestDistance")]
+    LongestDistance,
+    /// Seconds per kilometre, lower is better.
+    #[serde(rename = "fastestPace")]
+    FastestPace,
+}
+
+/// A personal best for an exercise title. Records are derived from the user's
+/// activities and recomputed whenever one that could hold one is written.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PersonalRecord {
+    pub user: ObjectId,
+    pub title: String,
+    pub kind: RecordKind,
+    pub value: f64,
+    /// The set a strength record was set with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reps: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u32>,
+    pub activity: ObjectId,
+    #[serde(rename = "achievedAt")]
+    pub achieved_at: DateTime,
+}
+
+type RecordKey = (String, RecordKind, Option<u32>);
+
+impl PersonalRecord {
+    /// Records with the same key compete with each other.
+    fn key(&self) -> RecordKey {
+        let weight = match self.kind {
+            RecordKind::MostReps => self.weight,
+            _ => None,
+        };
+        (self.title.clone(), self.kind, weight)
+    }
+
+    fn beats(&self, other: &PersonalRecord) -> bool {
+        match self.kind {
+            RecordKind::FastestPace => self.value < other.value,
+            _ => self.value > other.value,
+        }
+    }
+
+    /// Whether this is the same record as one of `records`, held by the same
+    /// activity.
+    pub fn is_in(&self, records: &[PersonalRecord]) -> bool {
+        records
+            .iter()
+            .any(|r| r.key() == self.key() && r.value == self.value && r.activity == self.activity)
+    }
+}
+
+/// The exercise titles an activity can hold records for.
+pub fn record_titles(activity: &Activity) -> HashSet<String> {
+    if activity.variant != ActivityVariant::Exercise || activity.is_deleted() {
+        return HashSet::new();
+    }
+
+    activity
+        .data
+        .iter()
+        .flat_map(|d| d.exercise.iter().flatten())
+        .filter_map(|e| match e {
+            Exercise::Strength(e) => Some(e.title.clone()),
+            Exercise::Cardio(e) => Some(e.title.clone()),
+            Exercise::Mobility(_) => None,
+        })
+        .collect()
+}
+
+/// The user's records for `titles` among `activities`. When several tie the
+/// earliest activity holds the record.
+pub fn compute_records(
+    user: ObjectId,
+    titles: &HashSet<String>,
+    activities: &[Activity],
+) -> Vec<PersonalRecord> {
+    let mut activities: Vec<&Activity> = activities
+        .iter()
+        .filter(|a| a.user == user && a.variant == ActivityVariant::Exercise && !a.is_deleted())
+        .collect();
+    activities.sort_by(|a, b| a.start.cmp(&b.start).then(a.id.cmp(&b.id)));
+
+    let mut best: HashMap<RecordKey, PersonalRecord> = HashMap::new();
+
+    for a in activities {
+        let record = |title: &str, kind, value, set: Option<(u32, u32)>| PersonalRecord {
+            user,
+            title: title.to_string(),
+            kind,
+            value,
+            reps: set.map(|s| s.0),
+            weight: set.map(|s| s.1),
+            activity: a.id,
+            achieved_at: a.start,
+        };
+
+        let mut candidates = vec![];
+
+        for exercise in a.data.iter().flat_map(|d| d.exercise.iter().flatten()) {
+            match exercise {
+                Exercise::Strength(e) if titles.contains(&e.title) => {
+                    for set in &e.sets {
+                        let (reps, weight) = (set.reps.unwrap_or(0), set.weight.unwrap_or(0));
+                        if reps == 0 {
+                            continue;
+                        }
+
+                        let set = Some((reps, weight));
+                        if weight > 0 {
+                            candidates.push(record(
+                                &e.title,
+                                RecordKind::HeaviestWeight,
+                                weight.into(),
+                                set,
+                            ));
+                        }
+                        candidates.push(record(&e.title, RecordKind::MostReps, reps.into(), set));
+                        if let Some(e1rm) = estimated_one_rep_max(reps, weight) {
+                            candidates.push(record(&e.title, RecordKind::Estimated1rm, e1rm, set));
+                        }
+                    }
+                }
+                Exercise::Cardio(e) if titles.contains(&e.title) && e.distance > 0 => {
+                    candidates.push(record(
+                        &e.title,
+                        RecordKind::LongestDistance,
+                        e.distance.into(),
+                        None,
+                    ));
+                    if e.duration > 0 {
+                        let pace = f64::from(e.duration) * 1000.0 / f64::from(e.distance);
+                        candidates.push(record(&e.title, RecordKind::FastestPace, pace, None));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for candidate in candidates {
+            let key = candidate.key();
+            if best.get(&key).is_none_or(|b| candidate.beats(b)) {
+                best.insert(key, candidate);
+            }
+        }
+    }
+
+    let mut res: Vec<_> = best.into_values().collect();
+    sort_records(&mut res);
+
+    res
+}
+
+/// By title, then kind, then weight.
+pub fn sort_records(records: &mut [PersonalRecord]) {
+    records.sort_by(|a, b| {
+        a.title
+            .cmp(&b.title)
+            .then(a.kind.cmp(&b.kind))
+            .then(a.weight.cmp(&b.weight))
+    });
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetRecordsPayload {
+    pub title: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RecordResponse {
+    pub title: String,
+    pub kind: RecordKind,
+    pub value: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reps: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u32>,
+    #[serde(
+        rename = "activityId",
+        serialize_with = "serialize_object_id_as_hex_string"
+    )]
+    pub activity_id: ObjectId,
+    #[serde(
+        rename = "achievedAt",
+        serialize_with = "serialize_bson_datetime_as_rfc3339_string"
+    )]
+    pub achieved_at: DateTime,
+}
+
+impl From<PersonalRecord> for RecordResponse {
+    fn from(record: PersonalRecord) -> Self {
+        Self {
+            title: record.title,
+            kind: record.kind,
+            value: record.value,
+            reps: record.reps,
+            weight: record.weight,
+            activity_id: record.activity,
+            achieved_at: record.achieved_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::activity_model::{ActivityData, CardioExercise, Set, StrengthExercise};
+
+    fn activity(user: ObjectId, start: &str, exercise: Vec<Exercise>) -> Activity {
+        Activity::new(
+            ActivityVariant::Exercise,
+            "gym".to_string(),
+            "sport".to_string(),
+            String::new(),
+            DateTime::parse_rfc3339_str(start).unwrap(),
+            DateTime::parse_rfc3339_str(start).ok(),
+            0,
+            Some(ActivityData {
+                exercise: Some(exercise),
+            }),
+            user,
+        )
+    }
+
+    fn squat(sets: &[(u32, u32)]) -> Exercise {
+        Exercise::Strength(StrengthExercise {
+            title: "squat".to_string(),
+            sets: sets
+                .iter()
+                .map(|&(reps, weight)| Set {
+                    idx: 0,
+                    reps: Some(reps),
+                    weight: Some(weight),
+                    rest: None,
+                    duration: None,
+                })
+                .collect(),
+        })
+    }
+
+    fn run(distance: u32, duration: u32) -> Exercise {
+        Exercise::Cardio(CardioExercise {
+            title: "run".to_string(),
+            duration,
+            distance,
+            splits: None,
+        })
+    }
+
+    fn held(records: &[PersonalRecord], kind: RecordKind) -> Vec<(f64, ObjectId)> {
+        records
+            .iter()
+            .filter(|r| r.kind == kind)
+            .map(|r| (r.value, r.activity))
+            .collect()
+    }
+
+    #[test]
+    fn computes_records() {
+        let user = ObjectId::new();
+        let first = activity(
+            user,
+            "2024-01-01T09:00:00Z",
+            vec![squat(&[(5, 100), (8, 80)]), run(5000, 1500)],
+        );
+        // ties don't take a record from an earlier activity
+        let second = activity(
+            user,
+            "2024-01-08T09:00:00Z",
+            vec![squat(&[(5, 100), (1, 120), (10, 80)]), run(3000, 840)],
+        );
+
+        let titles = HashSet::from(["squat".to_string(), "run".to_string()]);
+        let records = compute_records(user, &titles, &[second.clone(), first.clone()]);
+
+        assert_eq!(
+            held(&records, RecordKind::HeaviestWeight),
+            vec![(120.0, second.id)]
+        );
+        assert_eq!(
+            held(&records, RecordKind::MostReps),
+            vec![(10.0, second.id), (5.0, first.id), (1.0, second.id)]
+        );
+        assert_eq!(held(&records, RecordKind::Estimated1rm)[0].1, second.id);
+        assert_eq!(
+            held(&records, RecordKind::LongestDistance),
+            vec![(5000.0, first.id)]
+        );
+        assert_eq!(
+            held(&records, RecordKind::FastestPace),
+            vec![(280.0, second.id)]
+        );
+
+        // only the titles asked for
+        let titles = HashSet::from(["run".to_string()]);
+        let records = compute_records(user, &titles, &[first.clone(), second.clone()]);
+        assert!(records.iter().all(|r| r.title == "run"));
+
+        assert!(compute_records(ObjectId::new(), &titles, &[first, second]).is_empty());
+    }
+}
@@ -0,0 +1,438 @@
+use chrono::{Duration, NaiveDateTime, NaiveTime};
+use mongodb::bson::serde_helpers::{
+    serialize_bson_datetime_as_rfc3339_string, serialize_object_id_as_hex_string,
+};
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+use super::activity_model::{Activity, ActivityData, ActivityVariant, Occurrence};
+use super::stats_model::local_time;
+use crate::utils::ical::{IcalTime, RecurrenceRule};
+
+/// Occurrences expanded per series for one range query.
+pub const MAX_SERIES_OCCURRENCES: usize = 1_000;
+
+/// Live series per user. With the per series cap above, this bounds how many
+/// occurrences one range query expands.
+pub const MAX_SERIES: u64 = 100;
+
+/// An activity that repeats by an RFC 5545 RRULE, such as standup on weekdays.
+/// The rule is applied to the wall clock time of `start` at `timezone`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Series {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub variant: ActivityVariant,
+    pub title: String,
+    pub group: String,
+    pub notes: String,
+    /// The first occurrence.
+    pub start: DateTime,
+    /// Seconds each occurrence lasts.
+    pub duration: u32,
+    pub timezone: i16,
+    pub rrule: String,
+    /// Starts of occurrences left out, because they were deleted or edited on
+    /// their own.
+    pub exceptions: Vec<DateTime>,
+    /// Occurrences from here on belong to another series, set when the series
+    /// is split by a "this and following" edit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<DateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<ActivityData>,
+    pub user: ObjectId,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime,
+    /// Set when deleted. The document is kept as a tombstone for sync.
+    #[serde(rename = "deletedAt", default)]
+    pub deleted_at: Option<DateTime>,
+    /// Bumped on every write, a replace only applies to the version it read.
+    #[serde(rename = "__v")]
+    pub v: u32,
+}
+
+fn add_seconds(date: DateTime, seconds: i64) -> DateTime {
+    DateTime::from_millis(date.timestamp_millis() + seconds * 1000)
+}
+
+/// Replaces the COUNT of a rule, whose other parts are kept as written.
+fn with_count(rrule: &str, count: u32) -> String {
+    rrule
+        .split(';')
+        .map(|part| match part.split_once('=') {
+            Some((key, _)) if key.eq_ignore_ascii_case("COUNT") => format!("COUNT={}", count),
+            _ => part.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+impl Series {
+    /// `start` is the payload's, parsed.
+    pub fn new(payload: PostSeriesPayload, start: DateTime, user: ObjectId) -> Self {
+        let now = DateTime::now();
+
+        Self {
+            id: ObjectId::new(),
+            variant: payload.variant,
+            title: payload.title,
+            group: payload.group,
+            notes: payload.notes.unwrap_or_default(),
+            start,
+            duration: payload.duration,
+            timezone: payload.timezone,
+            rrule: payload.rrule,
+            exceptions: vec![],
+            until: None,
+            data: payload.data,
+            user,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            v: 1,
+        }
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+
+    fn wall_time(&self, date: DateTime) -> NaiveDateTime {
+        local_time(date, self.timezone)
+    }
+
+    fn utc_time(&self, wall: NaiveDateTime) -> DateTime {
+        let utc = wall + Duration::minutes(self.timezone.into());
+        DateTime::from_millis(utc.and_utc().timestamp_millis())
+    }
+
+    /// Starts of occurrences from the rule, exceptions included, that start
+    /// within `from..=to`.
+    fn rule_starts(&self, from: DateTime, to: DateTime, limit: usize) -> Vec<DateTime> {
+        // rules are checked when they are written
+        let Ok(rule) = RecurrenceRule::parse(&self.rrule) else {
+            return vec![];
+        };
+        let until = rule.until.as_ref().map(|until| match until {
+            IcalTime::Utc(t) => *t - Duration::minutes(self.timezone.into()),
+            IcalTime::Date(d) => d.and_time(NaiveTime::MIN) + Duration::days(1),
+            until => until.naive(),
+        });
+
+        rule.expand(
+            self.wall_time(self.start),
+            until,
+            self.wall_time(from),
+            self.wall_time(to),
+            limit,
+        )
+        .into_iter()
+        .map(|at| self.utc_time(at))
+        .filter(|at| self.until.is_none_or(|until| *at < until))
+        .collect()
+    }
+
+    /// Starts of the occurrences that overlap `from..=to`, oldest first.
+    pub fn occurrence_starts(&self, from: DateTime, to: DateTime, limit: usize) -> Vec<DateTime> {
+        let from = add_seconds(from, -i64::from(self.duration));
+
+        self.rule_starts(from, to, limit + self.exceptions.len())
+            .into_iter()
+            .filter(|at| !self.exceptions.contains(at))
+            .take(limit)
+            .collect()
+    }
+
+    /// Whether an occurrence starts at `start` that hasn't been left out.
+    pub fn has_occurrence(&self, start: DateTime) -> bool {
+        !self.exceptions.contains(&start) && self.rule_starts(start, start, 1) == vec![start]
+    }
+
+    /// A stable id for the occurrence at `start`, the series' id with its
+    /// timestamp replaced by the start, so no two occurrences share one.
+    pub fn occurrence_id(&self, start: DateTime) -> ObjectId {
+        let mut bytes = self.id.bytes();
+        let seconds = start.timestamp_millis().div_euclid(1000) as u32;
+        bytes[..4].copy_from_slice(&seconds.to_be_bytes());
+
+        ObjectId::from_bytes(bytes)
+    }
+
+    /// An occurrence as an activity that isn't stored, see `occurrence_id`.
+    pub fn occurrence(&self, start: DateTime) -> Activity {
+        let mut activity = Activity::new(
+            self.variant,
+            self.title.clone(),
+            self.group.clone(),
+            self.notes.clone(),
+            start,
+            Some(add_seconds(start, self.duration.into())),
+            self.timezone,
+            self.data.clone(),
+            self.user,
+        );
+        activity.id = self.occurrence_id(start);
+        activity.created_at = self.created_at;
+        activity.updated_at = self.updated_at;
+        activity.occurrence = Some(Occurrence {
+            series: self.id,
+            start,
+        });
+
+        activity
+    }
+
+    /// Applies an edit to the whole series, moving it and its exceptions by
+    /// `shift` milliseconds.
+    pub fn apply(&mut self, body: PatchSeriesBody, shift: i64) {
+        self.start = DateTime::from_millis(self.start.timestamp_millis() + shift);
+        for exception in &mut self.exceptions {
+            *exception = DateTime::from_millis(exception.timestamp_millis() + shift);
+        }
+        if let Some(variant) = body.variant {
+            self.variant = variant;
+        }
+        if let Some(title) = body.title {
+            self.title = title;
+        }
+        if let Some(group) = body.group {
+            self.group = group;
+        }
+        if let Some(notes) = body.notes {
+            self.notes = notes;
+        }
+        if let Some(duration) = body.duration {
+            self.duration = duration;
+        }
+        if let Some(timezone) = body.timezone {
+            self.timezone = timezone;
+        }
+        if let Some(rrule) = body.rrule {
+            self.rrule = rrule;
+        }
+        if let Some(data) = body.data {
+            self.data = Some(data);
+        }
+        self.updated_at = DateTime::now();
+    }
+
+    /// Ends the series before `at` and returns a new one with the occurrences
+    /// from `at` on, exceptions and what is left of a COUNT included.
+    pub fn split(&mut self, at: DateTime) -> Series {
+        let mut rest = self.clone();
+        rest.id = ObjectId::new();
+        rest.start = at;
+        rest.exceptions.retain(|e| *e >= at);
+        rest.created_at = DateTime::now();
+        rest.updated_at = rest.created_at;
+        rest.v = 1;
+
+        if let Ok(RecurrenceRule {
+            count: Some(count), ..
+        }) = RecurrenceRule::parse(&self.rrule)
+        {
+            let before = self
+                .rule_starts(self.start, add_seconds(at, -1), count as usize)
+                .len() as u32;
+            rest.rrule = with_count(&self.rrule, count.saturating_sub(before).max(1));
+        }
+
+        self.until = Some(at);
+        self.exceptions.retain(|e| *e < at);
+        self.updated_at = rest.created_at;
+
+        rest
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PostSeriesPayload {
+    pub title: String,
+    pub variant: ActivityVariant,
+    pub group: String,
+    pub notes: Option<String>,
+    /// The first occurrence.
+    pub start: String,
+    pub duration: u32,
+    pub timezone: i16,
+    pub rrule: String,
+    pub data: Option<ActivityData>,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SeriesScope {
+    /// The occurrence alone, which is stored as an activity of its own.
+    #[default]
+    This,
+    /// The occurrence and the ones after it, which become a new series.
+    Following,
+    All,
+}
+
+/// An edit to a series from one of its occurrences. `start` is the new start
+/// of that occurrence.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PatchSeriesBody {
+    #[serde(default)]
+    pub scope: SeriesScope,
+    pub variant: Option<ActivityVariant>,
+    pub title: Option<String>,
+    pub group: Option<String>,
+    pub notes: Option<String>,
+    pub start: Option<String>,
+    pub duration: Option<u32>,
+    pub timezone: Option<i16>,
+    pub rrule: Option<String>,
+    pub data: Option<ActivityData>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteOccurrencePayload {
+    #[serde(default)]
+    pub scope: SeriesScope,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SeriesResponse {
+    #[serde(rename = "id", serialize_with = "serialize_object_id_as_hex_string")]
+    pub id: ObjectId,
+    pub variant: ActivityVariant,
+    pub title: String,
+    pub group: String,
+    pub notes: String,
+    #[serde(serialize_with = "serialize_bson_datetime_as_rfc3339_string")]
+    pub start: DateTime,
+    pub duration: u32,
+    pub timezone: i16,
+    pub rrule: String,
+    pub exceptions: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<ActivityData>,
+    #[serde(
+        rename = "createdAt",
+        serialize_with = "serialize_bson_datetime_as_rfc3339_string"
+    )]
+    pub created_at: DateTime,
+    #[serde(
+        rename = "updatedAt",
+        serialize_with = "serialize_bson_datetime_as_rfc3339_string"
+    )]
+    pub updated_at: DateTime,
+}
+
+fn rfc3339(date: DateTime) -> String {
+    date.try_to_rfc3339_string().unwrap_or_default()
+}
+
+impl From<Series> for SeriesResponse {
+    fn from(series: Series) -> Self {
+        Self {
+            id: series.id,
+            variant: series.variant,
+            title: series.title,
+            group: series.group,
+            notes: series.notes,
+            start: series.start,
+            duration: series.duration,
+            timezone: series.timezone,
+            rrule: series.rrule,
+            exceptions: series.exceptions.into_iter().map(rfc3339).collect(),
+            until: series.until.map(rfc3339),
+            data: series.data,
+            created_at: series.created_at,
+            updated_at: series.updated_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime {
+        DateTime::parse_rfc3339_str(s).unwrap()
+    }
+
+    fn standup(rrule: &str) -> Series {
+        Series::new(
+            PostSeriesPayload {
+                title: "standup".to_string(),
+                variant: ActivityVariant::Default,
+                group: "work".to_string(),
+                notes: None,
+                start: String::new(),
+                duration: 15 * 60,
+                // UTC+1
+                timezone: -60,
+                rrule: rrule.to_string(),
+                data: None,
+            },
+            at("2024-01-01T08:00:00Z"),
+            ObjectId::new(),
+        )
+    }
+
+    #[test]
+    fn expands_occurrences() {
+        let mut series = standup("FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR");
+        series.exceptions.push(at("2024-01-03T08:00:00Z"));
+
+        // an occurrence that started before the range but is still going counts
+        let starts = series.occurrence_starts(
+            at("2024-01-01T08:10:00Z"),
+            at("2024-01-08T08:00:00Z"),
+            MAX_SERIES_OCCURRENCES,
+        );
+        assert_eq!(
+            starts,
+            vec![
+                at("2024-01-01T08:00:00Z"),
+                at("2024-01-02T08:00:00Z"),
+                at("2024-01-04T08:00:00Z"),
+                at("2024-01-05T08:00:00Z"),
+                at("2024-01-08T08:00:00Z"),
+            ]
+        );
+
+        // ids are stable and differ between occurrences
+        let occurrence = series.occurrence(starts[0]);
+        assert_eq!(occurrence.id, series.occurrence(starts[0]).id);
+        assert_ne!(occurrence.id, series.occurrence(starts[1]).id);
+        assert_ne!(occurrence.id, series.id);
+        assert_eq!(occurrence.end, Some(at("2024-01-01T08:15:00Z")));
+
+        assert!(series.has_occurrence(at("2024-01-02T08:00:00Z")));
+        assert!(!series.has_occurrence(at("2024-01-03T08:00:00Z")));
+        assert!(!series.has_occurrence(at("2024-01-06T08:00:00Z")));
+        assert!(!series.has_occurrence(at("2024-01-02T08:01:00Z")));
+    }
+
+    #[test]
+    fn splits_series() {
+        let mut series = standup("FREQ=DAILY;COUNT=5");
+        series.exceptions.push(at("2024-01-02T08:00:00Z"));
+        series.exceptions.push(at("2024-01-04T08:00:00Z"));
+
+        let rest = series.split(at("2024-01-03T08:00:00Z"));
+
+        let all = |s: &Series| {
+            s.occurrence_starts(
+                at("2024-01-01T00:00:00Z"),
+                at("2024-02-01T00:00:00Z"),
+                MAX_SERIES_OCCURRENCES,
+            )
+        };
+        assert_eq!(all(&series), vec![at("2024-01-01T08:00:00Z")]);
+        assert_eq!(rest.rrule, "FREQ=DAILY;COUNT=3");
+        assert_eq!(
+            all(&rest),
+            vec![at("2024-01-03T08:00:00Z"), at("2024-01-05T08:00:00Z")]
+        );
+    }
+}
@@ -1,5 +1,7 @@
 use std::borrow::Cow;
+use std::env;
 use std::ops::Deref;
+use std::str::FromStr;
 use std::sync::Arc;
 
 use axum::async_trait;
@@ -8,8 +10,52 @@ use axum::http::request::Parts;
 use axum_extra::extract::cookie::Key;
 use tokio::sync::RwLock;
 
-use crate::database::mongodb_database::MongoDatabase;
+use crate::database::storage::Storage;
 use crate::error::error::AppError;
+use crate::mail::mailer::Mailer;
+
+/// Where the server keeps its data. `Memory` needs no database and is lost on
+/// restart; select it with `--storage memory` or `STORAGE=memory`.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum StorageBackend {
+    Mongo,
+    Memory,
+}
+
+impl FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mongo" | "mongodb" => Ok(StorageBackend::Mongo),
+            "memory" => Ok(StorageBackend::Memory),
+            _ => Err(format!("invalid storage backend: {}", s)),
+        }
+    }
+}
+
+impl StorageBackend {
+    // reads `--storage <backend>` or `--storage=<backend>` from command line args
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Option<Self> {
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let value = match arg.strip_prefix("--storage") {
+                Some("") => args.next(),
+                Some(v) if v.starts_with('=') => Some(v[1..].to_string()),
+                _ => continue,
+            };
+
+            return match value.map(|v| v.parse()) {
+                Some(Ok(backend)) => Some(backend),
+                Some(Err(e)) => panic!("Fatal: {}", e),
+                None => panic!("Fatal: --storage requires a value"),
+            };
+        }
+
+        None
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct EnvironmentVariables {
@@ -22,29 +68,59 @@ pub struct EnvironmentVariables {
     pub hmac_key: Cow<'static, str>,
     pub port: u16,
     pub env: String,
+    pub storage: StorageBackend,
+    /// Base url of the web client, used to build links sent by mail.
+    pub app_url: Cow<'static, str>,
+    pub mail_outbox: Option<String>,
+    /// Days an unverified account keeps write access. Unset means forever.
+    pub unverified_grace_days: Option<u32>,
+    /// Days a deleted account is kept, deactivated, before it is purged.
+    /// Unset means accounts are erased as soon as they are deleted.
+    pub account_deletion_grace_days: Option<u32>,
+    /// Lets calendar imports fetch from loopback and private addresses, for
+    /// development only.
+    pub allow_private_calendar_urls: bool,
 }
 
 impl EnvironmentVariables {
     pub fn from_env() -> Self {
         dotenv::dotenv().ok();
 
-        Self {
-            db_url: match dotenv::var("DATABASE_URL") {
-                Ok(url) => url.into(),
-                Err(_) => panic!("Fatal: ensure DATABASE_URL env var is set"),
-            },
-            db_name: match dotenv::var("DATABASE_NAME") {
-                Ok(url) => url.into(),
-                Err(_) => panic!("Fatal: ensure DATABASE_NAME env var is set"),
-            },
-            db_user: match dotenv::var("DATABASE_USER") {
-                Ok(url) => url.into(),
-                Err(_) => panic!("Fatal: ensure DATABASE_USER env var is set"),
-            },
-            db_pass: match dotenv::var("DATABASE_PASS") {
-                Ok(url) => url.into(),
-                Err(_) => panic!("Fatal: ensure DATABASE_PASS env var is set"),
+        // the command line flag takes precedence over the STORAGE env var
+        let storage = match StorageBackend::from_args(env::args().skip(1)) {
+            Some(v) => v,
+            None => match dotenv::var("STORAGE") {
+                Ok(v) => v.parse().unwrap_or_else(|e| panic!("Fatal: {}", e)),
+                Err(_) => StorageBackend::Mongo,
             },
+        };
+
+        // database vars are only needed when running against mongo
+        let db_var = |key: &str| -> Cow<'static, str> {
+            match (dotenv::var(key), storage) {
+                (Ok(v), _) => v.into(),
+                (Err(_), StorageBackend::Memory) => "".into(),
+                (Err(_), StorageBackend::Mongo) => {
+                    panic!("Fatal: ensure {} env var is set", key)
+                }
+            }
+        };
+
+        let days_var = |key: &str| -> Option<u32> {
+            match dotenv::var(key) {
+                Ok(days) => match days.parse() {
+                    Ok(v) => Some(v),
+                    Err(_) => panic!("Fatal: {} must be a number of days", key),
+                },
+                Err(_) => None,
+            }
+        };
+
+        Self {
+            db_url: db_var("DATABASE_URL"),
+            db_name: db_var("DATABASE_NAME"),
+            db_user: db_var("DATABASE_USER"),
+            db_pass: db_var("DATABASE_PASS"),
             google_certs_urls: match dotenv::var("GOOGLE_CERTS_URL") {
                 Ok(url) => url.into(),
                 Err(_) => panic!("Fatal: ensure GOOGLE_CERTS_URL env var is set"),
@@ -65,6 +141,18 @@ impl EnvironmentVariables {
                 Ok(env) => env.parse().unwrap_or("dev".to_string()),
                 _ => "dev".to_string(),
             },
+            storage,
+            app_url: match dotenv::var("APP_URL") {
+                Ok(url) => url.into(),
+                Err(_) => "http://localhost:3000".into(),
+            },
+            mail_outbox: dotenv::var("MAIL_OUTBOX").ok(),
+            unverified_grace_days: days_var("UNVERIFIED_GRACE_DAYS"),
+            account_deletion_grace_days: days_var("ACCOUNT_DELETION_GRACE_DAYS"),
+            allow_private_calendar_urls: matches!(
+                dotenv::var("ALLOW_PRIVATE_CALENDAR_URLS").as_deref(),
+                Ok("true" | "1")
+            ),
         }
     }
 }
@@ -93,10 +181,11 @@ impl Deref for AppState {
 
 #[derive(Debug)]
 pub struct InnerState {
-    pub db: MongoDatabase,
+    pub db: Box<dyn Storage>,
     pub key: Key,
     pub client: reqwest::Client,
     pub env: EnvironmentVariables,
+    pub mailer: Box<dyn Mailer>,
 }
 
 impl FromRef<AppState> for Key {
@@ -117,3 +206,32 @@ where
         Ok(Self::from_ref(state))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn storage_backend_from_args() {
+        assert_eq!(StorageBackend::from_args(args(&[])), None);
+        assert_eq!(
+            StorageBackend::from_args(args(&["--storage", "memory"])),
+            Some(StorageBackend::Memory)
+        );
+        assert_eq!(
+            StorageBackend::from_args(args(&["--storage=mongo"])),
+            Some(StorageBackend::Mongo)
+        );
+        assert_eq!(StorageBackend::from_args(args(&["--storages"])), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn storage_backend_from_args_invalid() {
+        StorageBackend::from_args(args(&["--storage", "postgres"]));
+    }
+}
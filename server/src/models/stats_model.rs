@@ -0,0 +1,306 @@
+use chrono::{Datelike, Days, Duration, Months, NaiveDateTime, NaiveTime};
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+
+use super::activity_model::{Activity, ActivityVariant};
+
+pub const MAX_STATS_RANGE_DAYS: i64 = 3660;
+
+#[derive(PartialEq, Copy, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsGroupBy {
+    Title,
+    Group,
+    Variant,
+    #[default]
+    Day,
+    /// ISO week, starting on monday.
+    Week,
+    Month,
+}
+
+impl StatsGroupBy {
+    /// The activity field buckets are keyed by, `None` for time buckets.
+    pub fn field(&self) -> Option<&'static str> {
+        match self {
+            StatsGroupBy::Title => Some("title"),
+            StatsGroupBy::Group => Some("group"),
+            StatsGroupBy::Variant => Some("variant"),
+            _ => None,
+        }
+    }
+
+    /// Start of the time bucket containing a local time.
+    pub fn bucket_start(&self, local: NaiveDateTime) -> NaiveDateTime {
+        let date = local.date();
+        let date = match self {
+            StatsGroupBy::Week => date - Days::new(date.weekday().num_days_from_monday().into()),
+            StatsGroupBy::Month => date.with_day(1).unwrap_or(date),
+            _ => date,
+        };
+
+        date.and_time(NaiveTime::MIN)
+    }
+
+    pub fn next_bucket(&self, start: NaiveDateTime) -> NaiveDateTime {
+        match self {
+            StatsGroupBy::Week => start + Days::new(7),
+            StatsGroupBy::Month => start + Months::new(1),
+            _ => start + Days::new(1),
+        }
+    }
+
+    /// `2024-01-31`, `2024-W05` or `2024-01`.
+    pub fn bucket_key(&self, start: NaiveDateTime) -> String {
+        match self {
+            StatsGroupBy::Week => {
+                let week = start.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            }
+            StatsGroupBy::Month => start.format("%Y-%m").to_string(),
+            _ => start.format("%Y-%m-%d").to_string(),
+        }
+    }
+
+    /// Splits a local time span into the time buckets it covers, as keys and
+    /// milliseconds. A span with no length still counts in the bucket it is in.
+    pub fn split(&self, from: NaiveDateTime, to: NaiveDateTime) -> Vec<(String, i64)> {
+        let mut res = vec![];
+        let mut bucket = self.bucket_start(from);
+
+        loop {
+            let next = self.next_bucket(bucket);
+            let overlap = (to.min(next) - from.max(bucket)).num_milliseconds();
+
+            if overlap > 0 || res.is_empty() {
+                res.push((self.bucket_key(bucket), overlap.max(0)));
+            }
+            if to <= next {
+                break;
+            }
+            bucket = next;
+        }
+
+        res
+    }
+}
+
+/// Wall clock time at an offset, in minutes as stored on activities.
+pub fn local_time(date: DateTime, timezone: i16) -> NaiveDateTime {
+    let utc = chrono::DateTime::from_timestamp_millis(date.timestamp_millis()).unwrap_or_default();
+    utc.naive_utc() - Duration::minutes(timezone.into())
+}
+
+/// The key of a variant bucket, as the variant is stored.
+pub fn variant_key(variant: ActivityVariant) -> String {
+    match variant {
+        ActivityVariant::Default => "Default".to_string(),
+        ActivityVariant::Exercise => "Exercise".to_string(),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatsPayload {
+    pub start: String,
+    pub end: String,
+    #[serde(rename = "groupBy", alias = "group_by", default)]
+    pub group_by: StatsGroupBy,
+    /// Offset to bucket every activity in, by default each one's own.
+    pub timezone: Option<i16>,
+    pub title: Option<String>,
+    pub group: Option<String>,
+    pub variant: Option<ActivityVariant>,
+}
+
+/// Durations are in milliseconds. An activity that covers several time
+/// buckets counts in each of them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatsBucket {
+    pub key: String,
+    pub total: i64,
+    pub count: u64,
+}
+
+/// Durations are clipped to the requested range.
+#[derive(Debug, Default, PartialEq)]
+pub struct ActivityStats {
+    pub total: i64,
+    pub count: u64,
+    pub buckets: Vec<StatsBucket>,
+}
+
+impl ActivityStats {
+    /// Stats of activities that overlap `start..=end`.
+    pub fn of<'a>(
+        activities: impl IntoIterator<Item = &'a Activity>,
+        start: DateTime,
+        end: DateTime,
+        group_by: StatsGroupBy,
+        timezone: Option<i16>,
+    ) -> Self {
+        let mut stats = ActivityStats::default();
+        let mut buckets: HashMap<String, StatsBucket> = HashMap::new();
+
+        for a in activities {
+            // only the part within the range counts
+            let from = a.start.max(start);
+            let to = a.end_or_now().min(end);
+            let total = to.timestamp_millis() - from.timestamp_millis();
+
+            stats.total += total;
+            stats.count += 1;
+
+            let split = match group_by {
+                StatsGroupBy::Title => vec![(a.title.clone(), total)],
+                StatsGroupBy::Group => vec![(a.group.clone(), total)],
+                StatsGroupBy::Variant => vec![(variant_key(a.variant), total)],
+                group_by => {
+                    let timezone = timezone.unwrap_or(a.timezone);
+                    group_by.split(local_time(from, timezone), local_time(to, timezone))
+                }
+            };
+
+            for (key, total) in split {
+                let bucket = buckets.entry(key.clone()).or_insert(StatsBucket {
+                    key,
+                    total: 0,
+                    count: 0,
+                });
+                bucket.total += total;
+                bucket.count += 1;
+            }
+        }
+
+        stats.buckets = buckets.into_values().collect();
+
+        stats
+    }
+
+    /// Adds the stats of other activities over the same range and buckets.
+    pub fn merge(&mut self, other: ActivityStats) {
+        self.total += other.total;
+        self.count += other.count;
+
+        for bucket in other.buckets {
+            match self.buckets.iter_mut().find(|b| b.key == bucket.key) {
+                Some(b) => {
+                    b.total += bucket.total;
+                    b.count += bucket.count;
+                }
+                None => self.buckets.push(bucket),
+            }
+        }
+    }
+}
+
+fn average(total: i64, count: u64) -> i64 {
+    match count {
+        0 => 0,
+        count => total / count as i64,
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatsBucketResponse {
+    pub key: String,
+    pub total: i64,
+    pub count: u64,
+    pub average: i64,
+}
+
+impl From<StatsBucket> for StatsBucketResponse {
+    fn from(bucket: StatsBucket) -> Self {
+        Self {
+            average: average(bucket.total, bucket.count),
+            key: bucket.key,
+            total: bucket.total,
+            count: bucket.count,
+        }
+    }
+}
+
+/// Time buckets are in order and only those with activities are included.
+/// Other buckets are ordered by total, largest first.
+#[derive(Debug, Serialize)]
+pub struct StatsResponse {
+    #[serde(rename = "groupBy")]
+    pub group_by: StatsGroupBy,
+    pub total: i64,
+    pub count: u64,
+    pub average: i64,
+    pub buckets: Vec<StatsBucketResponse>,
+}
+
+impl StatsResponse {
+    pub fn new(group_by: StatsGroupBy, mut stats: ActivityStats) -> Self {
+        match group_by.field() {
+            Some(_) => stats
+                .buckets
+                .sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.key.cmp(&b.key))),
+            None => stats.buckets.sort_by(|a, b| a.key.cmp(&b.key)),
+        }
+
+        Self {
+            group_by,
+            total: stats.total,
+            count: stats.count,
+            average: average(stats.total, stats.count),
+            buckets: stats.buckets.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(y: i32, m: u32, d: u32, h: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(h, 0, 0)
+            .unwrap()
+    }
+
+    const HOUR: i64 = 60 * 60 * 1000;
+
+    #[test]
+    fn splits_across_buckets() {
+        assert_eq!(
+            StatsGroupBy::Day.split(at(2024, 1, 1, 22), at(2024, 1, 3, 1)),
+            vec![
+                ("2024-01-01".to_string(), 2 * HOUR),
+                ("2024-01-02".to_string(), 24 * HOUR),
+                ("2024-01-03".to_string(), HOUR),
+            ]
+        );
+
+        // ending on a boundary doesn't touch the next bucket
+        assert_eq!(
+            StatsGroupBy::Day.split(at(2024, 1, 1, 22), at(2024, 1, 2, 0)),
+            vec![("2024-01-01".to_string(), 2 * HOUR)]
+        );
+        assert_eq!(
+            StatsGroupBy::Day.split(at(2024, 1, 1, 22), at(2024, 1, 1, 22)),
+            vec![("2024-01-01".to_string(), 0)]
+        );
+
+        // 2024-12-30 is in the first ISO week of 2025
+        assert_eq!(
+            StatsGroupBy::Week.split(at(2024, 12, 29, 23), at(2024, 12, 30, 1)),
+            vec![
+                ("2024-W52".to_string(), HOUR),
+                ("2025-W01".to_string(), HOUR),
+            ]
+        );
+        assert_eq!(
+            StatsGroupBy::Month.split(at(2024, 1, 31, 23), at(2024, 2, 1, 2)),
+            vec![
+                ("2024-01".to_string(), HOUR),
+                ("2024-02".to_string(), 2 * HOUR),
+            ]
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use mongodb::bson::serde_helpers::{
+    serialize_bson_datetime_as_rfc3339_string, serialize_object_id_as_hex_string,
+};
+use mongodb::bson::{oid::ObjectId, DateTime};
+use serde::{Deserialize, Serialize};
+
+use super::activity_model::{Exercise, Set};
+
+pub const MAX_TEMPLATE_EXERCISES: usize = 100;
+
+/// A named routine of exercises with target sets, instantiated as an
+/// `Exercise` activity.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Template {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub name: String,
+    pub group: String,
+    pub notes: String,
+    /// Seconds an instance lasts unless it is given an end.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u32>,
+    pub exercises: Vec<Exercise>,
+    pub user: ObjectId,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime,
+}
+
+impl Template {
+    pub fn new(payload: PostTemplatePayload, user: ObjectId) -> Self {
+        let now = DateTime::now();
+
+        Self {
+            id: ObjectId::new(),
+            name: payload.name,
+            group: payload.group,
+            notes: payload.notes.unwrap_or_default(),
+            duration: payload.duration,
+            exercises: payload.exercises,
+            user,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn apply(&mut self, payload: PatchTemplatePayload) {
+        if let Some(name) = payload.name {
+            self.name = name;
+        }
+        if let Some(group) = payload.group {
+            self.group = group;
+        }
+        if let Some(notes) = payload.notes {
+            self.notes = notes;
+        }
+        if let Some(duration) = payload.duration {
+            self.duration = Some(duration);
+        }
+        if let Some(exercises) = payload.exercises {
+            self.exercises = exercises;
+        }
+        self.updated_at = DateTime::now();
+    }
+
+    /// The template's exercises, with sets that have no weight of their own
+    /// given the weight of the same set the last time the exercise was done,
+    /// or of its last set if it had fewer.
+    pub fn prefilled_exercises(&self, last_sets: &HashMap<String, Vec<Set>>) -> Vec<Exercise> {
+        let mut exercises = self.exercises.clone();
+
+        for exercise in &mut exercises {
+            let Exercise::Strength(exercise) = exercise else {
+                continue;
+            };
+            let Some(last) = last_sets.get(&exercise.title) else {
+                continue;
+            };
+
+            for (i, set) in exercise.sets.iter_mut().enumerate() {
+                if set.weight.is_none() {
+                    set.weight = last.get(i).or(last.last()).and_then(|s| s.weight);
+                }
+            }
+        }
+
+        exercises
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PostTemplatePayload {
+    pub name: String,
+    pub group: String,
+    pub notes: Option<String>,
+    pub duration: Option<u32>,
+    pub exercises: Vec<Exercise>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PatchTemplatePayload {
+    pub name: Option<String>,
+    pub group: Option<String>,
+    pub notes: Option<String>,
+    pub duration: Option<u32>,
+    pub exercises: Option<Vec<Exercise>>,
+}
+
+/// `end` defaults to `start` plus the template's duration.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InstantiateTemplatePayload {
+    pub start: String,
+    pub end: Option<String>,
+    pub timezone: i16,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TemplateResponse {
+    #[serde(rename = "id", serialize_with = "serialize_object_id_as_hex_string")]
+    pub id: ObjectId,
+    pub name: String,
+    pub group: String,
+    pub notes: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u32>,
+    pub exercises: Vec<Exercise>,
+    #[serde(
+        rename = "createdAt",
+        serialize_with = "serialize_bson_datetime_as_rfc3339_string"
+    )]
+    pub created_at: DateTime,
+    #[serde(
+        rename = "updatedAt",
+        serialize_with = "serialize_bson_datetime_as_rfc3339_string"
+    )]
+    pub updated_at: DateTime,
+}
+
+impl From<Template> for TemplateResponse {
+    fn from(template: Template) -> Self {
+        Self {
+            id: template.id,
+            name: template.name,
+            group: template.group,
+            notes: template.notes,
+            duration: template.duration,
+            exercises: template.exercises,
+            created_at: template.created_at,
+            updated_at: template.updated_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::activity_model::{MobilityExercise, StrengthExercise};
+
+    fn set(reps: u32, weight: Option<u32>) -> Set {
+        Set {
+            idx: 0,
+            reps: Some(reps),
+            weight,
+            rest: None,
+            duration: None,
+        }
+    }
+
+    fn weights(exercise: &Exercise) -> Vec<Option<u32>> {
+        match exercise {
+            Exercise::Strength(e) => e.sets.iter().map(|s| s.weight).collect(),
+            Exercise::Mobility(e) => e.sets.iter().map(|s| s.weight).collect(),
+            Exercise::Cardio(_) => vec![],
+        }
+    }
+
+    #[test]
+    fn prefills_weights() {
+        let template = Template::new(
+            PostTemplatePayload {
+                name: "legs".to_string(),
+                group: "gym".to_string(),
+                notes: None,
+                duration: None,
+                exercises: vec![
+                    Exercise::Strength(StrengthExercise {
+                        title: "squat".to_string(),
+                        sets: vec![set(5, None), set(5, Some(60)), set(5, None)],
+                    }),
+                    Exercise::Strength(StrengthExercise {
+                        title: "lunge".to_string(),
+                        sets: vec![set(10, None)],
+                    }),
+                    Exercise::Mobility(MobilityExercise {
+                        title: "squat".to_string(),
+                        sets: vec![set(1, None)],
+                    }),
+                ],
+            },
+            ObjectId::new(),
+        );
+
+        let last_sets = HashMap::from([(
+            "squat".to_string(),
+            vec![set(5, Some(100)), set(5, Some(105))],
+        )]);
+
+        let exercises = template.prefilled_exercises(&last_sets);
+        // set weights are kept, sets past the last session's take its last set
+        assert_eq!(weights(&exercises[0]), vec![Some(100), Some(60), Some(105)]);
+        assert_eq!(weights(&exercises[1]), vec![None]);
+        assert_eq!(weights(&exercises[2]), vec![None]);
+    }
+}
@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+use super::activity_model::{ActivityData, ActivityVariant};
+
+/// Starts an activity with no end. `start` defaults to now.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StartTimerPayload {
+    pub title: String,
+    pub variant: ActivityVariant,
+    pub group: String,
+    pub notes: Option<String>,
+    pub start: Option<String>,
+    pub timezone: i16,
+    pub data: Option<ActivityData>,
+}
+
+/// `end` defaults to now.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StopTimerPayload {
+    pub end: Option<String>,
+}
@@ -1,7 +1,5 @@
 use std::collections::HashMap;
-use std::{u32, usize};
 
-use axum::http::StatusCode;
 use mongodb::bson::oid::ObjectId;
 use mongodb::bson::serde_helpers::serialize_object_id_as_hex_string;
 use mongodb::bson::Bson;
@@ -15,14 +13,16 @@ pub enum Role {
     User,
 }
 
-impl From<&str> for Role {
-    fn from(s: &str) -> Role {
+impl TryFrom<&str> for Role {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Role, Self::Error> {
         match s {
-            "admin" => Role::Admin,
-            "user" => Role::User,
-            "Admin" => Role::Admin,
-            "User" => Role::User,
-            _ => panic!("invalid role!"),
+            "admin" => Ok(Role::Admin),
+            "user" => Ok(Role::User),
+            "Admin" => Ok(Role::Admin),
+            "User" => Ok(Role::User),
+            _ => Err(format!("invalid role: {}", s)),
         }
     }
 }
@@ -36,25 +36,36 @@ impl From<Role> for String {
     }
 }
 
-impl From<Bson> for Role {
-    fn from(bson: Bson) -> Role {
-        if let Bson::String(s) = bson {
-            s.as_str().into()
-        } else {
-            panic!("expected Bson::String")
+impl TryFrom<Bson> for Role {
+    type Error = String;
+
+    fn try_from(bson: Bson) -> Result<Role, Self::Error> {
+        match bson {
+            Bson::String(s) => s.as_str().try_into(),
+            _ => Err("expected Bson::String".to_string()),
         }
     }
 }
 
-impl Into<Bson> for Role {
-    fn into(self) -> Bson {
-        let s: String = self.into();
+impl From<Role> for Bson {
+    fn from(role: Role) -> Bson {
+        let s: String = role.into();
         Bson::String(s)
     }
 }
 
+const BCRYPT_COST: u32 = 12;
+
+pub const MIN_PASSWORD_LEN: usize = 8;
+// bcrypt ignores everything past the first 72 bytes
+pub const MAX_PASSWORD_BYTES: usize = 72;
+
+pub fn hash_password(pass: &str) -> Result<String, AppError> {
+    bcrypt::hash(pass, BCRYPT_COST).map_err(|_| AppError::internal("failed to hash password"))
+}
+
 #[non_exhaustive]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct User {
     #[serde(rename = "_id")]
     pub id: ObjectId,
@@ -69,12 +80,31 @@ pub struct User {
     #[serde(rename = "familyName")]
     pub family_name: String,
     pub img: String,
+    /// False while the user only has the random password given to oauth
+    /// sign ups. Users stored before this existed are assumed to have one.
+    #[serde(rename = "passwordSet", default = "default_password_set")]
+    pub password_set: bool,
     #[serde(rename = "createdAt")]
     pub created_at: mongodb::bson::DateTime,
+    /// Set, along with `active = false`, when the user deletes their account
+    /// with a grace period. The account is erased once this has passed.
+    #[serde(rename = "purgeAt", default, skip_serializing_if = "Option::is_none")]
+    pub purge_at: Option<mongodb::bson::DateTime>,
+    /// Hash of the secret in the user's calendar feed url, if they have one.
+    #[serde(
+        rename = "calendarToken",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub calendar_token: Option<String>,
     #[serde(rename = "__v")]
     pub v: u32,
 }
 
+fn default_password_set() -> bool {
+    true
+}
+
 impl User {
     pub fn new(
         email: String,
@@ -82,7 +112,7 @@ impl User {
         given_name: String,
         family_name: String,
     ) -> Result<Self, AppError> {
-        match bcrypt::hash(pass, 12) {
+        match hash_password(&pass) {
             Ok(hash) => Ok(Self {
                 id: ObjectId::new(),
                 given_name,
@@ -94,13 +124,13 @@ impl User {
                 activities: HashMap::new(),
                 verified: false,
                 img: String::from(""),
+                password_set: true,
                 created_at: mongodb::bson::DateTime::now(),
+                purge_at: None,
+                calendar_token: None,
                 v: 1,
             }),
-            Err(_) => Err(AppError::new(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "failed to create user",
-            )),
+            Err(e) => Err(e),
         }
     }
 }
@@ -118,6 +148,8 @@ pub struct UserResponse {
     #[serde(rename = "familyName")]
     pub family_name: String,
     pub img: String,
+    #[serde(rename = "passwordSet")]
+    pub password_set: bool,
 }
 
 impl From<User> for UserResponse {
@@ -131,19 +163,37 @@ impl From<User> for UserResponse {
             given_name: value.given_name,
             family_name: value.family_name,
             img: value.img,
+            password_set: value.password_set,
         }
     }
 }
 
 impl From<RegisterUserPayload> for User {
     fn from(payload: RegisterUserPayload) -> User {
-        User::new(
+        let mut user = User::new(
             payload.email,
             payload.pass,
             payload.given_name,
             payload.family_name,
         )
-        .expect("")
+        .expect("");
+        user.password_set = !payload.generated_pass;
+        user
+    }
+}
+
+impl User {
+    /// Whether the user may only read, because their email is still unverified
+    /// `grace_days` after sign up. No grace period means no restriction.
+    pub fn is_read_only(&self, grace_days: Option<u32>) -> bool {
+        match grace_days {
+            Some(days) if !self.verified => {
+                let deadline =
+                    self.created_at.timestamp_millis() + i64::from(days) * 24 * 60 * 60 * 1000;
+                mongodb::bson::DateTime::now().timestamp_millis() >= deadline
+            }
+            _ => false,
+        }
     }
 }
 
@@ -155,4 +205,58 @@ pub struct RegisterUserPayload {
     pub given_name: String,
     #[serde(rename = "familyName")]
     pub family_name: String,
+    /// Set for oauth sign ups, whose password the user never sees.
+    #[serde(skip)]
+    pub generated_pass: bool,
+}
+
+pub const MAX_NAME_LEN: usize = 100;
+pub const MAX_IMG_LEN: usize = 2048;
+
+/// Profile fields a user can change themselves. Absent fields are left as is.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PatchUserPayload {
+    #[serde(rename = "givenName")]
+    pub given_name: Option<String>,
+    #[serde(rename = "familyName")]
+    pub family_name: Option<String>,
+    pub img: Option<String>,
+}
+
+/// Users with a password confirm with it, oauth users who never set one
+/// confirm by repeating their email.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteAccountPayload {
+    pub pass: Option<String>,
+    pub email: Option<String>,
+}
+
+/// What was erased for a user.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AccountErasure {
+    pub activities: u64,
+    pub tokens: u64,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeletionStatus {
+    Deleted,
+    Scheduled,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteAccountResponse {
+    pub status: DeletionStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub erased: Option<AccountErasure>,
+    #[serde(rename = "purgeAt", skip_serializing_if = "Option::is_none")]
+    pub purge_at: Option<String>,
+}
+
+/// `current` may be left out while the user has no password of their own.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangePasswordPayload {
+    pub current: Option<String>,
+    pub pass: String,
 }
@@ -0,0 +1,429 @@
+use axum::http::{header::IF_MATCH, HeaderValue, StatusCode};
+use serde_json::{json, Value};
+
+use super::{activity_body, TestApp};
+
+#[tokio::test]
+async fn activity_crud() {
+    let mut app = TestApp::new();
+    let user = app.register("activity@test.com", "password").await.json;
+
+    // create
+    let mut body = activity_body(
+        "create",
+        "2000-01-01T09:00:00.000Z",
+        "2000-01-01T09:30:00.000Z",
+    );
+    body["notes"] = json!("notes");
+    body["color"] = json!("#ffffff");
+    let res = app.post("/api/v1/activity", body).await;
+    assert_eq!(res.status, StatusCode::CREATED);
+
+    let id = res.json["id"].as_str().unwrap().to_string();
+    assert_eq!(res.json["user"], user["id"]);
+    assert_eq!(res.json["title"], "create");
+    assert_eq!(res.json["variant"], "Default");
+    assert_eq!(res.json["group"], "group");
+    assert_eq!(res.json["notes"], "notes");
+    assert_eq!(res.json["start"], "2000-01-01T09:00:00Z");
+    assert_eq!(res.json["end"], "2000-01-01T09:30:00Z");
+    assert_eq!(res.json["timezone"], 0);
+    assert_eq!(res.json["v"], 1);
+    assert!(res.json["createdAt"].is_string());
+    assert!(res.json.get("data").is_none());
+
+    // get one
+    let res = app.get(&format!("/api/v1/activity/{}", id)).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["id"], id);
+
+    // update
+    let res = app
+        .patch(
+            &format!("/api/v1/activity/{}", id),
+            json!({
+                "title": "update",
+                "end": "2000-01-01T10:30:00.000Z",
+                "data": { "exercise": [
+                    { "variant": "Cardio", "title": "running", "duration": 30, "distance": 5000, "splits": null }
+                ] },
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["title"], "update");
+    assert_eq!(res.json["start"], "2000-01-01T09:00:00Z");
+    assert_eq!(res.json["end"], "2000-01-01T10:30:00Z");
+    assert_eq!(res.json["data"]["exercise"][0]["title"], "running");
+
+    // delete
+    let res = app.delete(&format!("/api/v1/activity/{}", id)).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json, json!({ "_id": id }));
+
+    let res = app.get(&format!("/api/v1/activity/{}", id)).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    assert_eq!(res.json["code"], "activity_not_found");
+    assert!(res.json["requestId"].is_string());
+}
+
+#[tokio::test]
+async fn activity_get_many() {
+    let mut app = TestApp::new();
+    app.register("activities@test.com", "password").await;
+
+    for day in ["03", "01", "02"] {
+        let start = format!("2000-01-{}T09:00:00.000Z", day);
+        let end = format!("2000-01-{}T09:30:00.000Z", day);
+        let res = app
+            .post("/api/v1/activity", activity_body("many", &start, &end))
+            .await;
+        assert_eq!(res.status, StatusCode::CREATED);
+    }
+    app.post(
+        "/api/v1/activity",
+        activity_body(
+            "other",
+            "2000-01-01T10:00:00.000Z",
+            "2000-01-01T11:00:00.000Z",
+        ),
+    )
+    .await;
+
+    let res = app.get("/api/v1/activity").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json.as_array().unwrap().len(), 4);
+
+    let res = app
+        .get("/api/v1/activity?title=many&start=2000-01-01T00:00:00.000Z&end=2000-01-02T23:59:59.999Z")
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    let starts: Vec<&str> = res
+        .json
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|a| a["start"].as_str().unwrap())
+        .collect();
+    assert_eq!(starts, vec!["2000-01-01T09:00:00Z", "2000-01-02T09:00:00Z"]);
+}
+
+#[tokio::test]
+async fn activities_are_scoped_to_user() {
+    let mut app = TestApp::new();
+    app.register("owner@test.com", "password").await;
+
+    let res = app
+        .post(
+            "/api/v1/activity",
+            activity_body(
+                "private",
+                "2000-01-01T09:00:00.000Z",
+                "2000-01-01T09:30:00.000Z",
+            ),
+        )
+        .await;
+    let id = res.json["id"].as_str().unwrap().to_string();
+
+    let mut other = app.new_session();
+    other.register("other@test.com", "password").await;
+    let res = other.get(&format!("/api/v1/activity/{}", id)).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn activity_get_paginated() {
+    let mut app = TestApp::new();
+    app.register("paginated@test.com", "password").await;
+
+    for day in ["01", "02", "03", "04", "05"] {
+        let start = format!("2000-01-{}T09:00:00.000Z", day);
+        let end = format!("2000-01-{}T09:30:00.000Z", day);
+        app.post("/api/v1/activity", activity_body("page", &start, &end))
+            .await;
+    }
+
+    let res = app.get("/api/v1/activity?limit=2").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["data"].as_array().unwrap().len(), 2);
+    assert_eq!(res.json["data"][0]["start"], "2000-01-01T09:00:00Z");
+    let next = res.json["next"].as_str().unwrap().to_string();
+
+    let res = app
+        .get(&format!("/api/v1/activity?limit=2&after={}", next))
+        .await;
+    assert_eq!(res.json["data"][0]["start"], "2000-01-03T09:00:00Z");
+    let next = res.json["next"].as_str().unwrap().to_string();
+
+    let res = app
+        .get(&format!("/api/v1/activity?limit=2&after={}", next))
+        .await;
+    assert_eq!(res.json["data"].as_array().unwrap().len(), 1);
+    assert_eq!(res.json["next"], Value::Null);
+
+    let res = app.get("/api/v1/activity?after=garbage").await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn activity_validation() {
+    let mut app = TestApp::new();
+    app.register("validation@test.com", "password").await;
+
+    let res = app.get("/api/v1/activity/not-an-id").await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "id");
+
+    let mut body = activity_body(
+        "invalid",
+        "2000-01-01T09:00:00.000Z",
+        "2000-01-01T08:00:00.000Z",
+    );
+    body["timezone"] = json!(2000);
+    let res = app.post("/api/v1/activity", body).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["code"], "validation_failed");
+    assert_eq!(res.json["fields"][0]["field"], "end");
+    assert_eq!(res.json["fields"][1]["field"], "timezone");
+
+    let mut body = activity_body(
+        "invalid",
+        "2000-01-01T09:00:00.000Z",
+        "2000-01-01T10:00:00.000Z",
+    );
+    body["variant"] = json!("Unknown");
+    let res = app.post("/api/v1/activity", body).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "variant");
+
+    let res = app.get("/api/v1/activity?variant=Unknown").await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "variant");
+
+    let res = app.get("/api/v1/activity?start=yesterday").await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "start");
+
+    // a partial update is checked against the stored bounds
+    let res = app
+        .post(
+            "/api/v1/activity",
+            activity_body(
+                "valid",
+                "2000-01-01T09:00:00.000Z",
+                "2000-01-01T10:00:00.000Z",
+            ),
+        )
+        .await;
+    let id = res.json["id"].as_str().unwrap().to_string();
+    let res = app
+        .patch(
+            &format!("/api/v1/activity/{}", id),
+            json!({ "end": "2000-01-01T08:00:00.000Z" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "end");
+}
+
+#[tokio::test]
+async fn activity_version_conflict() {
+    let mut app = TestApp::new();
+    app.register("version@test.com", "password").await;
+
+    let res = app
+        .post(
+            "/api/v1/activity",
+            activity_body(
+                "version",
+                "2000-01-01T09:00:00.000Z",
+                "2000-01-01T10:00:00.000Z",
+            ),
+        )
+        .await;
+    let id = res.json["id"].as_str().unwrap().to_string();
+    assert_eq!(res.headers["etag"], "\"1\"");
+
+    // a body version bumps on every write
+    let uri = format!("/api/v1/activity/{}", id);
+    let res = app.patch(&uri, json!({ "title": "first", "v": 1 })).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["v"], 2);
+    assert_eq!(res.headers["etag"], "\"2\"");
+
+    // replaying the stale edit returns the current document
+    let res = app.patch(&uri, json!({ "title": "stale", "v": 1 })).await;
+    assert_eq!(res.status, StatusCode::CONFLICT);
+    assert_eq!(res.json["code"], "version_conflict");
+    assert_eq!(res.json["current"]["title"], "first");
+    assert_eq!(res.json["current"]["v"], 2);
+
+    // If-Match works the same way, for both PATCH and DELETE
+    app.headers
+        .insert(IF_MATCH, HeaderValue::from_static("\"1\""));
+    let res = app.delete(&uri).await;
+    assert_eq!(res.status, StatusCode::CONFLICT);
+
+    app.headers
+        .insert(IF_MATCH, HeaderValue::from_static("\"2\""));
+    let res = app.patch(&uri, json!({ "title": "second" })).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["v"], 3);
+
+    app.headers
+        .insert(IF_MATCH, HeaderValue::from_static("\"3\""));
+    let res = app.delete(&uri).await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    // the delete went through
+    app.headers.clear();
+    let res = app.get(&uri).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn activity_batch() {
+    let mut app = TestApp::new();
+    app.register("batch@test.com", "password").await;
+
+    let res = app
+        .post(
+            "/api/v1/activity",
+            activity_body(
+                "existing",
+                "2000-01-01T09:00:00.000Z",
+                "2000-01-01T10:00:00.000Z",
+            ),
+        )
+        .await;
+    let existing = res.json["id"].as_str().unwrap().to_string();
+
+    let res = app
+        .post(
+            "/api/v1/activity/batch",
+            json!({ "operations": [
+                { "op": "create", "activity": activity_body("first", "2000-01-02T09:00:00.000Z", "2000-01-02T10:00:00.000Z") },
+                { "op": "create", "activity": activity_body("", "2000-01-02T09:00:00.000Z", "2000-01-02T10:00:00.000Z") },
+                { "op": "update", "id": existing, "activity": { "title": "renamed", "v": 1 } },
+                { "op": "update", "id": existing, "activity": { "title": "stale", "v": 1 } },
+                { "op": "create", "activity": activity_body("second", "2000-01-03T09:00:00.000Z", "2000-01-03T10:00:00.000Z") },
+                { "op": "delete", "id": "66cc8f30ef7a9d4f94f9ad03" },
+                { "op": "delete", "id": existing, "v": 2 },
+            ] }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let results = res.json["results"].as_array().unwrap();
+    let statuses: Vec<u64> = results
+        .iter()
+        .map(|r| r["status"].as_u64().unwrap())
+        .collect();
+    assert_eq!(statuses, vec![201, 400, 200, 409, 201, 404, 200]);
+
+    assert_eq!(results[0]["activity"]["title"], "first");
+    assert_eq!(results[1]["error"]["code"], "validation_failed");
+    assert_eq!(results[1]["error"]["fields"][0]["field"], "title");
+    assert_eq!(results[2]["activity"]["v"], 2);
+    assert_eq!(results[3]["error"]["current"]["title"], "renamed");
+    assert_eq!(results[5]["error"]["code"], "activity_not_found");
+    assert_eq!(results[6]["deleted"]["_id"], existing);
+
+    let res = app.get("/api/v1/activity").await;
+    let titles: Vec<&str> = res
+        .json
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|a| a["title"].as_str().unwrap())
+        .collect();
+    assert_eq!(titles, vec!["first", "second"]);
+
+    // one-sided edits are checked against the bounds left by earlier operations
+    let first = results[0]["activity"]["id"].as_str().unwrap();
+    let res = app
+        .post(
+            "/api/v1/activity/batch",
+            json!({ "operations": [
+                { "op": "update", "id": first, "activity": { "end": "2000-01-02T12:00:00.000Z" } },
+                { "op": "update", "id": first, "activity": { "start": "2000-01-02T11:00:00.000Z" } },
+                { "op": "update", "id": first, "activity": { "start": "2000-01-02T13:00:00.000Z" } },
+            ] }),
+        )
+        .await;
+    let results = res.json["results"].as_array().unwrap();
+    let statuses: Vec<u64> = results
+        .iter()
+        .map(|r| r["status"].as_u64().unwrap())
+        .collect();
+    assert_eq!(statuses, vec![200, 200, 400]);
+    assert_eq!(results[1]["activity"]["start"], "2000-01-02T11:00:00Z");
+    assert_eq!(results[2]["error"]["fields"][0]["field"], "start");
+
+    let res = app
+        .post("/api/v1/activity/batch", json!({ "operations": [] }))
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+
+    let res = app
+        .post(
+            "/api/v1/activity/batch",
+            json!({ "operations": [{ "op": "upsert" }] }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn activity_idempotency_key() {
+    let mut app = TestApp::new();
+    app.register("idempotency@test.com", "password").await;
+    app.headers
+        .insert("idempotency-key", HeaderValue::from_static("create-1"));
+
+    let body = activity_body(
+        "once",
+        "2000-01-01T09:00:00.000Z",
+        "2000-01-01T10:00:00.000Z",
+    );
+    let first = app.post("/api/v1/activity", body.clone()).await;
+    assert_eq!(first.status, StatusCode::CREATED);
+    assert!(first.headers.get("idempotent-replayed").is_none());
+
+    // a retry gets the original response and writes nothing
+    let replay = app.post("/api/v1/activity", body.clone()).await;
+    assert_eq!(replay.status, StatusCode::CREATED);
+    assert_eq!(replay.json, first.json);
+    assert_eq!(replay.headers["idempotent-replayed"], "true");
+    assert_eq!(replay.headers["etag"], "\"1\"");
+
+    let res = app.get("/api/v1/activity").await;
+    assert_eq!(res.json.as_array().unwrap().len(), 1);
+
+    // the key can't be reused for a different request
+    let mut other = body.clone();
+    other["title"] = json!("twice");
+    let res = app.post("/api/v1/activity", other).await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.json["code"], "idempotency_key_reused");
+
+    // keys are scoped to the user
+    let mut session = app.new_session();
+    session.headers = app.headers.clone();
+    session.register("idempotency2@test.com", "password").await;
+    let res = session.post("/api/v1/activity", body.clone()).await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_ne!(res.json["id"], first.json["id"]);
+
+    // and cover the batch endpoint too
+    app.headers
+        .insert("idempotency-key", HeaderValue::from_static("batch-1"));
+    let batch = json!({ "operations": [{ "op": "create", "activity": body }] });
+    let first = app.post("/api/v1/activity/batch", batch.clone()).await;
+    let replay = app.post("/api/v1/activity/batch", batch).await;
+    assert_eq!(replay.status, StatusCode::OK);
+    assert_eq!(replay.json, first.json);
+
+    let res = app.get("/api/v1/activity").await;
+    assert_eq!(res.json.as_array().unwrap().len(), 2);
+}
@@ -0,0 +1,118 @@
+use axum::http::StatusCode;
+use serde_json::json;
+
+use super::TestApp;
+
+#[tokio::test]
+async fn register_sets_session_cookies() {
+    let mut app = TestApp::new();
+
+    let res = app.register("register@test.com", "password").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert!(app.cookie("access").is_some());
+    assert!(app.cookie("refresh").is_some());
+
+    assert_eq!(res.json["email"], "register@test.com");
+    assert_eq!(res.json["givenName"], "testy");
+    assert_eq!(res.json["familyName"], "mctestface");
+    assert_eq!(res.json["role"], "User");
+    assert_eq!(res.json["verified"], false);
+    assert!(res.json["id"].is_string());
+    assert!(res.json["activities"].is_object());
+    assert!(res.json.get("pass").is_none());
+}
+
+#[tokio::test]
+async fn login() {
+    let mut app = TestApp::new();
+    app.register("login@test.com", "password").await;
+    app.remove_cookie("access");
+    app.remove_cookie("refresh");
+
+    let res = app
+        .post("/api/v1/login", json!({ "email": "", "pass": "" }))
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["code"], "missing_credentials");
+
+    let res = app
+        .post(
+            "/api/v1/login",
+            json!({ "email": "login@test.com", "pass": "wrong" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(res.json["code"], "wrong_credentials");
+    assert!(app.cookie("access").is_none());
+
+    let res = app
+        .post(
+            "/api/v1/login",
+            json!({ "email": "login@test.com", "pass": "password" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["email"], "login@test.com");
+    assert!(app.cookie("access").is_some());
+    assert!(app.cookie("refresh").is_some());
+
+    let res = app.get("/api/v1/activity").await;
+    assert_eq!(res.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn missing_session_is_unauthorized() {
+    let mut app = TestApp::new();
+
+    let res = app.get("/api/v1/activity").await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(res.json["code"], "missing_token");
+}
+
+#[tokio::test]
+async fn refresh_rotation() {
+    let mut app = TestApp::new();
+    app.register("refresh@test.com", "password").await;
+
+    // without an access token the refresh token is used to issue new tokens
+    let old_refresh = app.cookie("refresh").unwrap();
+    app.remove_cookie("access");
+
+    let res = app.get("/api/v1/activity").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert!(app.cookie("access").is_some());
+    let new_refresh = app.cookie("refresh").unwrap();
+    assert_ne!(old_refresh, new_refresh);
+
+    // replaying a used refresh token revokes every session for the user
+    app.remove_cookie("access");
+    app.set_cookie("refresh", old_refresh);
+    let res = app.get("/api/v1/activity").await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    assert_eq!(res.json["code"], "token_reused");
+
+    app.remove_cookie("access");
+    app.set_cookie("refresh", new_refresh);
+    let res = app.get("/api/v1/activity").await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn logout() {
+    let mut app = TestApp::new();
+    app.register("logout@test.com", "password").await;
+
+    let access = app.cookie("access").unwrap();
+    let refresh = app.cookie("refresh").unwrap();
+
+    let res = app.post("/api/v1/logout", json!({})).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert!(app.cookie("access").is_none());
+    assert!(app.cookie("refresh").is_none());
+
+    // tokens from the old session are blacklisted
+    app.set_cookie("access", access);
+    app.set_cookie("refresh", refresh);
+    let res = app.get("/api/v1/activity").await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+}
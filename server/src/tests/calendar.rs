@@ -0,0 +1,88 @@
+use axum::http::{header, StatusCode};
+use serde_json::json;
+
+use super::TestApp;
+
+#[tokio::test]
+async fn calendar_feed() {
+    let mut app = TestApp::new();
+    app.register("calendar@test.com", "password").await;
+
+    for (title, group, start, end) in [
+        (
+            "read",
+            "hobby",
+            "2000-01-01T09:00:00.000Z",
+            "2000-01-01T10:00:00.000Z",
+        ),
+        (
+            "gym",
+            "sport",
+            "2000-01-02T09:00:00.000Z",
+            "2000-01-02T10:00:00.000Z",
+        ),
+    ] {
+        app.post(
+            "/api/v1/activity",
+            json!({
+                "title": title,
+                "variant": "Default",
+                "group": group,
+                "notes": "line one\nline two",
+                "start": start,
+                "end": end,
+                "timezone": -60,
+            }),
+        )
+        .await;
+    }
+
+    let res = app.post("/api/v1/calendar", json!({})).await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    let path = res.json["path"].as_str().unwrap().to_string();
+    assert!(path.ends_with(".ics"));
+
+    // the feed needs no session
+    let mut feed = app.new_session();
+    let res = feed.get(&path).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        res.headers[header::CONTENT_TYPE],
+        "text/calendar; charset=utf-8"
+    );
+
+    let ics = String::from_utf8(res.bytes).unwrap();
+    assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+    assert_eq!(ics.matches("BEGIN:VEVENT").count(), 2);
+    assert!(ics.contains("DTSTART;TZID=UTC+0100:20000101T100000\r\n"));
+    assert!(ics.contains("CATEGORIES:sport\r\n"));
+    assert!(ics.contains("DESCRIPTION:line one\\nline two\r\n"));
+
+    let res = feed.get(&format!("{}?group=sport", path)).await;
+    let ics = String::from_utf8(res.bytes).unwrap();
+    assert_eq!(ics.matches("BEGIN:VEVENT").count(), 1);
+    assert!(ics.contains("SUMMARY:gym\r\n"));
+
+    let res = feed.get(&format!("{}?timezone=0", path)).await;
+    let ics = String::from_utf8(res.bytes).unwrap();
+    assert!(ics.contains("DTSTART:20000101T090000Z\r\n"));
+
+    let res = feed.get(&format!("{}?timezone=1000", path)).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+
+    let res = feed.get(path.trim_end_matches(".ics")).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    // a new token replaces the old one
+    let res = app.post("/api/v1/calendar", json!({})).await;
+    let new_path = res.json["path"].as_str().unwrap().to_string();
+    let res = feed.get(&path).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    assert_eq!(res.json["code"], "calendar_not_found");
+    assert_eq!(feed.get(&new_path).await.status, StatusCode::OK);
+
+    let res = app.delete("/api/v1/calendar").await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+    let res = feed.get(&new_path).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,35 @@
+use axum::http::{HeaderValue, StatusCode};
+
+use super::TestApp;
+
+#[tokio::test]
+async fn error_body() {
+    let mut app = TestApp::new();
+
+    let res = app.get("/api/v1/activity").await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(res.json["code"], "missing_token");
+    assert_eq!(res.json["message"], "missing token");
+    assert!(res.json.get("fields").is_none());
+
+    // the generated request id is returned both as a header and in the body
+    let header = res.headers["x-request-id"].to_str().unwrap();
+    assert_eq!(res.json["requestId"], header);
+}
+
+#[tokio::test]
+async fn request_id_is_propagated() {
+    let mut app = TestApp::new();
+    app.headers.insert(
+        "x-request-id",
+        HeaderValue::from_static("client-supplied-id"),
+    );
+
+    let res = app.get("/api/v1/activity").await;
+    assert_eq!(res.headers["x-request-id"], "client-supplied-id");
+    assert_eq!(res.json["requestId"], "client-supplied-id");
+
+    let res = app.get("/api/health-check").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.headers["x-request-id"], "client-supplied-id");
+}
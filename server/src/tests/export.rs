@@ -0,0 +1,140 @@
+use async_zip::base::read::mem::ZipFileReader;
+use axum::http::{header, StatusCode};
+use serde_json::{json, Value};
+
+use super::{activity_body, TestApp};
+
+fn activities() -> Vec<Value> {
+    let mut read = activity_body(
+        "read",
+        "2000-01-01T09:00:00.000Z",
+        "2000-01-01T09:30:00.000Z",
+    );
+    read["group"] = json!("hobby");
+    read["notes"] = json!("a, \"quoted\" note");
+    read["color"] = json!("#ff0000");
+
+    let mut gym = activity_body(
+        "gym",
+        "2000-01-02T09:00:00.000Z",
+        "2000-01-02T10:00:00.000Z",
+    );
+    gym["variant"] = json!("Exercise");
+    gym["group"] = json!("sport");
+    gym["timezone"] = json!(-60);
+    gym["data"] = json!({ "exercise": [
+        { "variant": "Strength", "title": "squat", "sets": [
+            { "idx": 0, "reps": 5, "weight": 100 },
+            { "idx": 1, "reps": 5, "weight": 105 },
+        ] },
+        { "variant": "Cardio", "title": "row", "duration": 600, "distance": 2000, "splits": [
+            { "idx": 0, "distance": 1000, "duration": 290 },
+            { "idx": 1, "distance": 1000, "duration": 310 },
+        ] },
+        { "variant": "Cardio", "title": "bike", "duration": 300, "distance": 1500 },
+    ] });
+
+    vec![read, gym]
+}
+
+#[tokio::test]
+async fn export_json() {
+    let mut app = TestApp::seeded("export@test.com", activities()).await;
+
+    // other users' data is not included
+    let mut other = app.new_session();
+    other.register("other@test.com", "password").await;
+
+    let res = app.get("/api/v1/me/export").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.headers[header::CONTENT_TYPE], "application/json");
+    assert!(res.headers[header::CONTENT_DISPOSITION]
+        .to_str()
+        .unwrap()
+        .starts_with("attachment; filename=\"chrono-export-"));
+
+    assert_eq!(res.json["user"]["email"], "export@test.com");
+    assert_eq!(res.json["user"]["activities"]["read"], "#ff0000");
+    assert!(res.json["exportedAt"].is_string());
+
+    let activities = res.json["activities"].as_array().unwrap();
+    let titles: Vec<&Value> = activities.iter().map(|a| &a["title"]).collect();
+    assert_eq!(titles, vec!["read", "gym"]);
+    assert_eq!(
+        activities[1]["data"]["exercise"][1]["splits"][1]["duration"],
+        310
+    );
+
+    let res = other.get("/api/v1/me/export").await;
+    assert_eq!(res.json["activities"], json!([]));
+
+    let res = app.get("/api/v1/me/export?format=pdf").await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "format");
+}
+
+#[tokio::test]
+async fn export_zip() {
+    let mut app = TestApp::seeded("export@test.com", activities()).await;
+
+    let res = app.get("/api/v1/me/export?format=zip").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.headers[header::CONTENT_TYPE], "application/zip");
+
+    let zip = ZipFileReader::new(res.bytes).await.unwrap();
+    let mut files = vec![];
+    for idx in 0..zip.file().entries().len() {
+        let name = zip.file().entries()[idx]
+            .filename()
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let mut contents = String::new();
+        let mut reader = zip.reader_with_entry(idx).await.unwrap();
+        reader.read_to_string_checked(&mut contents).await.unwrap();
+
+        files.push((name, contents));
+    }
+
+    let file = |name: &str| -> Vec<String> {
+        let (_, contents) = files.iter().find(|(n, _)| n == name).unwrap();
+        contents.lines().map(String::from).collect()
+    };
+
+    let names: Vec<&str> = files.iter().map(|(n, _)| n.as_str()).collect();
+    assert_eq!(
+        names,
+        vec![
+            "profile.csv",
+            "colors.csv",
+            "activities.csv",
+            "sets.csv",
+            "splits.csv"
+        ]
+    );
+
+    assert_eq!(file("profile.csv").len(), 2);
+    assert_eq!(file("colors.csv"), vec!["title,color", "read,#ff0000"]);
+
+    let activities = file("activities.csv");
+    assert_eq!(
+        activities[0],
+        "id,title,variant,group,notes,start,end,timezone,createdAt,updatedAt,v"
+    );
+    assert_eq!(activities.len(), 3);
+    assert!(activities[1].contains(",read,Default,hobby,\"a, \"\"quoted\"\" note\","));
+
+    let sets = file("sets.csv");
+    assert_eq!(
+        sets[0],
+        "activity,exercise,title,variant,set,reps,weight,rest,duration"
+    );
+    assert_eq!(sets.len(), 3);
+    assert!(sets[2].ends_with(",0,squat,Strength,1,5,105,,"));
+
+    let splits = file("splits.csv");
+    assert_eq!(splits.len(), 4);
+    assert!(splits[2].ends_with(",1,row,600,2000,1,1000,310"));
+    assert!(splits[3].ends_with(",2,bike,300,1500,,,"));
+}
@@ -0,0 +1,332 @@
+use axum::{
+    http::{Method, StatusCode},
+    routing::get,
+    Router,
+};
+use serde_json::{json, Value};
+
+use super::TestApp;
+
+const CALENDAR: &str = include_str!("fixtures/calendar.ics");
+const ICAL_WINDOW: &str = "from=2023-12-01T00:00:00Z&until=2024-12-31T00:00:00Z";
+
+async fn import_csv(app: &mut TestApp, uri: &str, csv: &str) -> super::TestResponse {
+    app.send(
+        Method::POST,
+        uri,
+        Some(("text/csv", csv.as_bytes().to_vec())),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn import_csv_rows() {
+    let mut app = TestApp::new();
+    app.register("import@test.com", "password").await;
+
+    let csv = "\
+title,group,variant,start,end,timezone,notes
+read,hobby,,2000-01-01T09:00:00Z,2000-01-01T09:30:00Z,-60,chapter 1
+gym,sport,exercise,2000-01-02T09:00:00Z,2000-01-02T08:00:00Z,0,
+,sport,Default,yesterday,2000-01-03T09:00:00Z,lots,
+";
+
+    let res = import_csv(&mut app, "/api/v1/me/import?dryRun=true", csv).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["dryRun"], true);
+    assert_eq!(res.json["rows"], 3);
+    assert_eq!(res.json["valid"], 1);
+    assert_eq!(res.json["imported"], 0);
+    let fields: Vec<&str> = res.json["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, vec!["rows[1].end", "rows[2].timezone"]);
+
+    // nothing is written on a dry run, or when any row is invalid
+    let res = import_csv(&mut app, "/api/v1/me/import", csv).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["code"], "validation_failed");
+
+    let res = app.get("/api/v1/activity").await;
+    assert_eq!(res.json, json!([]));
+
+    let csv = csv.lines().take(2).collect::<Vec<_>>().join("\n");
+    let res = import_csv(&mut app, "/api/v1/me/import", &csv).await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_eq!(res.json["imported"], 1);
+
+    let res = app.get("/api/v1/activity").await;
+    assert_eq!(res.json[0]["title"], "read");
+    assert_eq!(res.json[0]["variant"], "Default");
+    assert_eq!(res.json[0]["timezone"], -60);
+    assert_eq!(res.json[0]["notes"], "chapter 1");
+
+    let res = import_csv(&mut app, "/api/v1/me/import", "title,start\nread,now\n").await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["message"], "missing column group");
+
+    let res = app
+        .send(
+            Method::POST,
+            "/api/v1/me/import",
+            Some(("text/plain", b"hello".to_vec())),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[tokio::test]
+async fn import_json_export() {
+    let mut app = TestApp::new();
+    app.register("source@test.com", "password").await;
+    app.post(
+        "/api/v1/activity",
+        json!({
+            "title": "gym",
+            "variant": "Exercise",
+            "group": "sport",
+            "start": "2000-01-02T09:00:00.000Z",
+            "end": "2000-01-02T10:00:00.000Z",
+            "timezone": 0,
+            "color": "#00ff00",
+            "data": { "exercise": [
+                { "variant": "Strength", "title": "squat", "sets": [{ "idx": 0, "reps": 5 }] },
+            ] },
+        }),
+    )
+    .await;
+
+    let export = app.get("/api/v1/me/export").await.json;
+
+    // a fresh account gets the same activities and colors
+    let mut target = app.new_session();
+    target.register("target@test.com", "password").await;
+
+    let res = target.post("/api/v1/me/import", export.clone()).await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_eq!(res.json["imported"], 1);
+    assert_eq!(res.json["colors"], 1);
+
+    let res = target.get("/api/v1/activity").await;
+    assert_eq!(res.json[0]["title"], "gym");
+    assert_eq!(res.json[0]["data"]["exercise"][0]["sets"][0]["reps"], 5);
+
+    let res = target.get("/api/v1/me").await;
+    assert_eq!(res.json["activities"]["gym"], "#00ff00");
+
+    let mut broken = export.clone();
+    broken["activities"][0]["start"] = json!(42);
+    let res = target.post("/api/v1/me/import?dryRun=true", broken).await;
+    assert_eq!(res.json["errors"][0]["field"], "rows[0].start");
+}
+
+async fn import_ical(app: &mut TestApp, query: &str, ics: &str) -> super::TestResponse {
+    app.send(
+        Method::POST,
+        &format!("/api/v1/me/import/ical?{}", query),
+        Some(("text/calendar", ics.as_bytes().to_vec())),
+    )
+    .await
+}
+
+// serves the calendar from a local url, like a calendar app would
+async fn serve_calendar(ics: &'static str) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = Router::new().route("/calendar.ics", get(move || async move { ics }));
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    format!("http://{}/calendar.ics", addr)
+}
+
+fn by_title<'a>(activities: &'a Value, title: &str) -> Vec<&'a Value> {
+    activities
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|a| a["title"] == title)
+        .collect()
+}
+
+#[tokio::test]
+async fn import_ical_events() {
+    // the calendar is served from loopback below
+    let mut app = TestApp::with_env(|env| env.allow_private_calendar_urls = true);
+    app.register("ical@test.com", "password").await;
+
+    let res = import_ical(&mut app, &format!("dryRun=true&{}", ICAL_WINDOW), CALENDAR).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["events"], 6);
+    assert_eq!(res.json["rows"], 8);
+    assert_eq!(res.json["valid"], 8);
+    assert_eq!(res.json["imported"], 0);
+    assert_eq!(app.get("/api/v1/activity").await.json, json!([]));
+
+    let res = import_ical(&mut app, ICAL_WINDOW, CALENDAR).await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_eq!(res.json["imported"], 8);
+
+    let activities = app.get("/api/v1/activity").await.json;
+
+    let dentist = by_title(&activities, "Dentist, checkup")[0];
+    assert_eq!(dentist["start"], "2024-07-04T07:00:00Z");
+    assert_eq!(dentist["end"], "2024-07-04T08:30:00Z");
+    assert_eq!(dentist["timezone"], -120);
+    assert_eq!(dentist["group"], "health");
+    assert_eq!(dentist["notes"], "bring card\nand id");
+
+    // all-day events are in the calendar's timezone
+    let conference = by_title(&activities, "Conference")[0];
+    assert_eq!(conference["start"], "2024-01-09T23:00:00Z");
+    assert_eq!(conference["end"], "2024-01-11T23:00:00Z");
+    assert_eq!(conference["timezone"], -60);
+
+    let runs: Vec<&str> = by_title(&activities, "Run")
+        .iter()
+        .map(|a| a["start"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        runs,
+        vec![
+            "2024-01-01T18:00:00Z",
+            "2024-01-10T18:00:00Z",
+            "2024-01-15T18:00:00Z",
+            "2024-01-17T18:00:00Z",
+        ]
+    );
+    let late = by_title(&activities, "Run (late)")[0];
+    assert_eq!(late["start"], "2024-01-08T19:00:00Z");
+    assert_eq!(late["timezone"], -60);
+
+    let standup = activities
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|a| a["title"].as_str().unwrap().starts_with("Standup"))
+        .unwrap();
+    assert!(standup["title"].as_str().unwrap().ends_with("folded"));
+    assert_eq!(standup["start"], "2024-02-01T03:30:00Z");
+    assert_eq!(standup["timezone"], -330);
+
+    assert!(by_title(&activities, "Nope").is_empty());
+
+    // importing again, here from a url, skips what is already there
+    let url = serve_calendar(CALENDAR).await;
+    let query = format!("url={}&{}", url, ICAL_WINDOW);
+    let res = app
+        .post(&format!("/api/v1/me/import/ical?{}", query), json!({}))
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_eq!(res.json["skipped"], 8);
+    assert_eq!(res.json["imported"], 0);
+    assert_eq!(
+        app.get("/api/v1/activity")
+            .await
+            .json
+            .as_array()
+            .unwrap()
+            .len(),
+        8
+    );
+
+    let res = app
+        .send(
+            Method::POST,
+            "/api/v1/me/import/ical",
+            Some(("text/plain", CALENDAR.as_bytes().to_vec())),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let res = import_ical(&mut app, "", "BEGIN:VCALENDAR\r\n").await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["code"], "invalid_body");
+}
+
+#[tokio::test]
+async fn import_ical_mapping() {
+    let mut app = TestApp::new();
+    app.register("mapping@test.com", "password").await;
+
+    let query = format!("group=location&notes=none&timezone=0&{}", ICAL_WINDOW);
+    let res = import_ical(&mut app, &query, CALENDAR).await;
+    assert_eq!(res.status, StatusCode::CREATED);
+
+    let activities = app.get("/api/v1/activity").await.json;
+    let dentist = by_title(&activities, "Dentist, checkup")[0];
+    assert_eq!(dentist["group"], "Main St");
+    assert_eq!(dentist["notes"], "");
+
+    let conference = by_title(&activities, "Conference")[0];
+    assert_eq!(conference["start"], "2024-01-10T00:00:00Z");
+    assert_eq!(conference["timezone"], 0);
+
+    let res = import_ical(&mut app, "title=none", CALENDAR).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "title");
+
+    let res = import_ical(&mut app, "url=file:///etc/passwd", "").await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "url");
+
+    // only public addresses are fetched, and failures all look the same
+    let local = serve_calendar(CALENDAR).await;
+    for url in [
+        local.as_str(),
+        "http://169.254.169.254/latest/meta-data",
+        "http://[::1]:27017/",
+        "webcal://localhost:27017/calendar.ics",
+    ] {
+        let res = import_ical(&mut app, &format!("url={}", url), "").await;
+        assert_eq!(res.status, StatusCode::BAD_REQUEST);
+        assert_eq!(res.json["fields"][0]["field"], "url");
+        assert_eq!(res.json["fields"][0]["message"], "could not be fetched");
+    }
+
+    let broken = "BEGIN:VCALENDAR\r\n\
+        BEGIN:VEVENT\r\nUID:a\r\nSUMMARY:no start\r\nEND:VEVENT\r\n\
+        BEGIN:VEVENT\r\nUID:b\r\nDTSTART;TZID=Nowhere/City:20240101T090000\r\nSUMMARY:x\r\nEND:VEVENT\r\n\
+        BEGIN:VEVENT\r\nUID:c\r\nDTSTART:20240101T090000Z\r\nRRULE:FREQ=HOURLY\r\nSUMMARY:x\r\nEND:VEVENT\r\n\
+        END:VCALENDAR\r\n";
+
+    let res = import_ical(&mut app, "dryRun=true", broken).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let errors: Vec<(&str, &str)> = res.json["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| (e["field"].as_str().unwrap(), e["message"].as_str().unwrap()))
+        .collect();
+    assert_eq!(
+        errors,
+        vec![
+            ("events[0].dtstart", "is required"),
+            ("events[1].dtstart", "unknown TZID Nowhere/City"),
+            ("events[2].rrule", "unsupported FREQ HOURLY"),
+        ]
+    );
+
+    let res = import_ical(&mut app, "", broken).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+
+    // a bound left out still limits the window
+    let res = import_ical(&mut app, "until=2999-01-01T00:00:00Z", CALENDAR).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "until");
+
+    let res = import_ical(&mut app, "from=1999-01-01T00:00:00Z", CALENDAR).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "from");
+
+    let daily = "BEGIN:VCALENDAR\r\n\
+        BEGIN:VEVENT\r\nUID:d\r\nDTSTART:20200101T090000Z\r\nRRULE:FREQ=DAILY\r\nSUMMARY:x\r\nEND:VEVENT\r\n\
+        END:VCALENDAR\r\n";
+    let query = "dryRun=true&from=2020-01-01T00:00:00Z&until=2024-12-31T00:00:00Z";
+    let res = import_ical(&mut app, query, daily).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["rows"], 0);
+    assert_eq!(res.json["errors"][0]["field"], "events[0].rrule");
+}
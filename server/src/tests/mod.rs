@@ -0,0 +1,290 @@
+use std::{
+    collections::HashMap,
+    env,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    async_trait,
+    body::{to_bytes, Body},
+    http::{header, HeaderMap, Method, Request, StatusCode},
+    Router,
+};
+use axum_extra::extract::cookie::{Cookie, Key};
+use serde_json::Value;
+use time::OffsetDateTime;
+use tower::ServiceExt;
+
+use crate::{
+    app,
+    database::memory_database::MemoryDatabase,
+    mail::mailer::{Mail, Mailer},
+    models::state_model::{AppState, EnvironmentVariables, InnerState, StorageBackend},
+};
+
+mod activity;
+mod auth;
+mod calendar;
+mod error;
+mod export;
+mod import;
+mod password;
+mod progress;
+mod records;
+mod series;
+mod stats;
+mod sync;
+mod templates;
+mod timer;
+mod user;
+mod verify;
+
+const HMAC_KEY: &str = "test-hmac-key";
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub json: Value,
+    /// The raw body, for responses that aren't json.
+    pub bytes: Vec<u8>,
+}
+
+/// Keeps sent mail in memory so tests can follow the links in it.
+#[derive(Debug, Default, Clone)]
+pub struct TestMailer {
+    sent: Arc<Mutex<Vec<Mail>>>,
+}
+
+#[async_trait]
+impl Mailer for TestMailer {
+    async fn send(&self, mail: Mail) -> Result<(), std::io::Error> {
+        self.sent.lock().unwrap().push(mail);
+        Ok(())
+    }
+}
+
+/// Drives the real router in-process against `MemoryDatabase`, carrying cookies
+/// between requests the way a browser would.
+pub struct TestApp {
+    router: Router,
+    cookies: HashMap<String, String>,
+    mailer: TestMailer,
+    /// Sent with every request.
+    pub headers: HeaderMap,
+}
+
+impl TestApp {
+    pub fn new() -> Self {
+        Self::with_env(|_| {})
+    }
+
+    /// Like `new`, with settings changed from the test defaults.
+    pub fn with_env(configure: impl FnOnce(&mut EnvironmentVariables)) -> Self {
+        // tokens read the signing key straight from the environment
+        env::set_var("HMAC_KEY", HMAC_KEY);
+
+        let mut env = EnvironmentVariables {
+            db_url: "".into(),
+            db_name: "".into(),
+            db_user: "".into(),
+            db_pass: "".into(),
+            google_certs_urls: "".into(),
+            google_token_aud: "".into(),
+            hmac_key: HMAC_KEY.into(),
+            port: 0,
+            env: "dev".to_string(),
+            storage: StorageBackend::Memory,
+            app_url: "http://localhost:3000".into(),
+            mail_outbox: None,
+            unverified_grace_days: None,
+            account_deletion_grace_days: None,
+            allow_private_calendar_urls: false,
+        };
+        configure(&mut env);
+
+        let mailer = TestMailer::default();
+
+        let app_state = AppState(Arc::new(InnerState {
+            db: Box::new(MemoryDatabase::new()),
+            client: reqwest::Client::new(),
+            env,
+            key: Key::generate(),
+            mailer: Box::new(mailer.clone()),
+        }));
+
+        Self {
+            router: app(app_state),
+            cookies: HashMap::new(),
+            mailer,
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// A second client against the same app and store, with its own cookies.
+    pub fn new_session(&self) -> Self {
+        Self {
+            router: self.router.clone(),
+            cookies: HashMap::new(),
+            mailer: self.mailer.clone(),
+            headers: HeaderMap::new(),
+        }
+    }
+
+    pub async fn request(
+        &mut self,
+        method: Method,
+        uri: &str,
+        body: Option<Value>,
+    ) -> TestResponse {
+        let body = body.map(|v| ("application/json", v.to_string().into_bytes()));
+        self.send(method, uri, body).await
+    }
+
+    /// Like `request`, with a body of any content type.
+    pub async fn send(
+        &mut self,
+        method: Method,
+        uri: &str,
+        body: Option<(&str, Vec<u8>)>,
+    ) -> TestResponse {
+        let mut req = Request::builder().method(method).uri(uri);
+
+        for (name, value) in &self.headers {
+            req = req.header(name, value);
+        }
+
+        if !self.cookies.is_empty() {
+            let cookie = self
+                .cookies
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>()
+                .join("; ");
+            req = req.header(header::COOKIE, cookie);
+        }
+
+        let req = match body {
+            Some((content_type, bytes)) => req
+                .header(header::CONTENT_TYPE, content_type)
+                .body(Body::from(bytes)),
+            None => req.body(Body::empty()),
+        }
+        .unwrap();
+
+        let res = self.router.clone().oneshot(req).await.unwrap();
+
+        let status = res.status();
+        let headers = res.headers().clone();
+
+        // expired cookies are dropped, as a browser would
+        for set_cookie in res.headers().get_all(header::SET_COOKIE) {
+            let cookie = Cookie::parse(set_cookie.to_str().unwrap().to_string()).unwrap();
+            let expired = cookie
+                .expires_datetime()
+                .is_some_and(|exp| exp <= OffsetDateTime::now_utc());
+
+            if cookie.value().is_empty() || expired {
+                self.cookies.remove(cookie.name());
+            } else {
+                self.cookies
+                    .insert(cookie.name().to_string(), cookie.value().to_string());
+            }
+        }
+
+        let bytes = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let json = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+
+        TestResponse {
+            status,
+            headers,
+            json,
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    pub async fn get(&mut self, uri: &str) -> TestResponse {
+        self.request(Method::GET, uri, None).await
+    }
+
+    pub async fn post(&mut self, uri: &str, body: Value) -> TestResponse {
+        self.request(Method::POST, uri, Some(body)).await
+    }
+
+    pub async fn patch(&mut self, uri: &str, body: Value) -> TestResponse {
+        self.request(Method::PATCH, uri, Some(body)).await
+    }
+
+    pub async fn delete(&mut self, uri: &str) -> TestResponse {
+        self.request(Method::DELETE, uri, None).await
+    }
+
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies.get(name).cloned()
+    }
+
+    pub fn set_cookie(&mut self, name: &str, value: String) {
+        self.cookies.insert(name.to_string(), value);
+    }
+
+    pub fn remove_cookie(&mut self, name: &str) {
+        self.cookies.remove(name);
+    }
+
+    /// Mail sent by the app so far, oldest first.
+    pub fn sent_mail(&self) -> Vec<Mail> {
+        self.mailer.sent.lock().unwrap().clone()
+    }
+
+    /// The token from the last `/<path>?token=` link mailed to `to`.
+    pub fn mailed_token(&self, to: &str, path: &str) -> String {
+        let link = format!("/{}?token=", path);
+        let mail = self
+            .sent_mail()
+            .into_iter()
+            .rev()
+            .find(|m| m.to == to && m.body.contains(&link))
+            .expect("no mail sent");
+
+        let (_, rest) = mail.body.split_once(&link).unwrap();
+        rest.split_whitespace().next().unwrap().to_string()
+    }
+
+    /// Registers a fresh user and keeps their session cookies.
+    pub async fn register(&mut self, email: &str, pass: &str) -> TestResponse {
+        self.post(
+            "/api/v1/register",
+            serde_json::json!({
+                "email": email,
+                "pass": pass,
+                "givenName": "testy",
+                "familyName": "mctestface",
+            }),
+        )
+        .await
+    }
+
+    /// A new app with a registered user who has created `activities`.
+    pub async fn seeded(email: &str, activities: impl IntoIterator<Item = Value>) -> Self {
+        let mut app = Self::new();
+        app.register(email, "password").await;
+
+        for activity in activities {
+            let res = app.post("/api/v1/activity", activity).await;
+            assert_eq!(res.status, StatusCode::CREATED, "{}", res.json);
+        }
+
+        app
+    }
+}
+
+/// A body for `POST /api/v1/activity`, other fields can be set on it.
+pub fn activity_body(title: &str, start: &str, end: &str) -> Value {
+    serde_json::json!({
+        "title": title,
+        "variant": "Default",
+        "group": "group",
+        "start": start,
+        "end": end,
+        "timezone": 0,
+    })
+}
@@ -0,0 +1,183 @@
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+
+use super::TestApp;
+
+#[tokio::test]
+async fn password_reset() {
+    let mut app = TestApp::new();
+    app.register("reset@test.com", "password").await;
+
+    // unknown emails look the same as known ones
+    let mut anon = app.new_session();
+    let res = anon
+        .post(
+            "/api/v1/password/forgot",
+            json!({ "email": "nobody@test.com" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+    assert!(app.sent_mail().iter().all(|m| m.to != "nobody@test.com"));
+
+    let res = anon
+        .post(
+            "/api/v1/password/forgot",
+            json!({ "email": "reset@test.com" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+    let token = app.mailed_token("reset@test.com", "reset-password");
+
+    let res = anon
+        .post(
+            "/api/v1/password/reset",
+            json!({ "token": token, "pass": "short" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "pass");
+
+    let res = anon
+        .post(
+            "/api/v1/password/reset",
+            json!({ "token": token, "pass": "new-password" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+
+    // tokens are single use
+    let res = anon
+        .post(
+            "/api/v1/password/reset",
+            json!({ "token": token, "pass": "other-password" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["code"], "invalid_reset_token");
+
+    // existing sessions are signed out
+    let res = app.get("/api/v1/activity").await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+
+    let res = anon
+        .post(
+            "/api/v1/login",
+            json!({ "email": "reset@test.com", "pass": "password" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+
+    let res = anon
+        .post(
+            "/api/v1/login",
+            json!({ "email": "reset@test.com", "pass": "new-password" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn password_reset_after_delete() {
+    let mut app = TestApp::with_env(|env| env.account_deletion_grace_days = Some(30));
+    app.register("reset@test.com", "password").await;
+
+    let mut anon = app.new_session();
+    anon.post(
+        "/api/v1/password/forgot",
+        json!({ "email": "reset@test.com" }),
+    )
+    .await;
+    let token = app.mailed_token("reset@test.com", "reset-password");
+
+    let res = app
+        .request(
+            Method::DELETE,
+            "/api/v1/me",
+            Some(json!({ "pass": "password" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::ACCEPTED);
+
+    // a deleted account can't be taken back over with an earlier link
+    let res = anon
+        .post(
+            "/api/v1/password/reset",
+            json!({ "token": token, "pass": "new-password" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    assert_eq!(res.json["code"], "account_deleted");
+
+    let res = anon
+        .post(
+            "/api/v1/login",
+            json!({ "email": "reset@test.com", "pass": "new-password" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn change_password() {
+    let mut app = TestApp::new();
+    let res = app.register("change@test.com", "password").await;
+    assert_eq!(res.json["passwordSet"], true);
+
+    let mut other = app.new_session();
+    let res = other
+        .post(
+            "/api/v1/login",
+            json!({ "email": "change@test.com", "pass": "password" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app
+        .post("/api/v1/password", json!({ "pass": "new-password" }))
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "current");
+
+    let res = app
+        .post(
+            "/api/v1/password",
+            json!({ "current": "password", "pass": "password" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "pass");
+
+    let res = app
+        .post(
+            "/api/v1/password",
+            json!({ "current": "wrong-password", "pass": "new-password" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(res.json["code"], "wrong_credentials");
+
+    let access = app.cookie("access").unwrap();
+    let res = app
+        .post(
+            "/api/v1/password",
+            json!({ "current": "password", "pass": "new-password" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+    assert_ne!(app.cookie("access").unwrap(), access);
+
+    // this session carries on, every other one is signed out
+    let res = app.get("/api/v1/activity").await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = other.get("/api/v1/activity").await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+
+    let res = other
+        .post(
+            "/api/v1/login",
+            json!({ "email": "change@test.com", "pass": "new-password" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+}
@@ -0,0 +1,146 @@
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+
+use super::{activity_body, TestApp};
+
+fn activities() -> Vec<Value> {
+    let sessions = [
+        (
+            "2024-01-01T09:00:00Z",
+            json!([
+                { "variant": "Strength", "title": "squat", "sets": [
+                    { "idx": 0, "reps": 5, "weight": 100 },
+                    { "idx": 1, "reps": 5, "weight": 105 },
+                ] },
+                { "variant": "Cardio", "title": "row", "duration": 600, "distance": 2000, "splits": [
+                    { "idx": 0, "distance": 1000, "duration": 290 },
+                    { "idx": 1, "distance": 1000, "duration": 310 },
+                ] },
+                { "variant": "Cardio", "title": "bike", "duration": 300, "distance": 1500 },
+            ]),
+        ),
+        (
+            "2024-01-08T09:00:00Z",
+            json!([
+                { "variant": "Strength", "title": "squat", "sets": [
+                    { "idx": 0, "reps": 3, "weight": 110 },
+                    { "idx": 1, "reps": 8, "weight": 90 },
+                ] },
+                { "variant": "Mobility", "title": "stretch", "sets": [
+                    { "idx": 0, "duration": 60 },
+                ] },
+                { "variant": "Cardio", "title": "row", "duration": 240, "distance": 1000 },
+                // a second block of the same exercise is part of the same session
+                { "variant": "Strength", "title": "squat", "sets": [
+                    { "idx": 0, "reps": 1, "weight": 120 },
+                ] },
+            ]),
+        ),
+    ];
+
+    let mut activities: Vec<Value> = sessions
+        .into_iter()
+        .map(|(start, exercise)| {
+            let mut gym = activity_body("gym", start, &start.replace("T09", "T10"));
+            gym["variant"] = json!("Exercise");
+            gym["group"] = json!("sport");
+            gym["data"] = json!({ "exercise": exercise });
+            gym
+        })
+        .collect();
+
+    let mut read = activity_body("read", "2024-01-02T09:00:00Z", "2024-01-02T10:00:00Z");
+    read["group"] = json!("hobby");
+    activities.push(read);
+
+    activities
+}
+
+fn approx(value: &Value, expected: f64) -> bool {
+    (value.as_f64().unwrap() - expected).abs() < 1e-9
+}
+
+#[tokio::test]
+async fn list_exercises() {
+    let mut app = TestApp::seeded("progress@test.com", activities()).await;
+
+    let res = app.get("/api/v1/exercise").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        res.json,
+        json!([
+            { "title": "bike", "variant": "Cardio", "sessions": 1, "last": "2024-01-01T09:00:00Z" },
+            { "title": "row", "variant": "Cardio", "sessions": 2, "last": "2024-01-08T09:00:00Z" },
+            { "title": "squat", "variant": "Strength", "sessions": 2, "last": "2024-01-08T09:00:00Z" },
+            { "title": "stretch", "variant": "Mobility", "sessions": 1, "last": "2024-01-08T09:00:00Z" },
+        ])
+    );
+}
+
+#[tokio::test]
+async fn strength_progress() {
+    let mut app = TestApp::seeded("progress@test.com", activities()).await;
+
+    let res = app.get("/api/v1/exercise/progress?title=squat").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["title"], "squat");
+    assert_eq!(res.json["cardio"], json!([]));
+
+    let sessions = res.json["strength"].as_array().unwrap();
+    assert_eq!(sessions.len(), 2);
+
+    assert_eq!(sessions[0]["start"], "2024-01-01T09:00:00Z");
+    assert_eq!(sessions[0]["sets"], 2);
+    assert_eq!(sessions[0]["reps"], 10);
+    assert_eq!(sessions[0]["volume"], 5 * 100 + 5 * 105);
+    assert_eq!(sessions[0]["bestSet"], json!({ "reps": 5, "weight": 105 }));
+    assert!(approx(&sessions[0]["estimated1rm"], 122.5));
+
+    assert_eq!(sessions[1]["sets"], 3);
+    assert_eq!(sessions[1]["reps"], 12);
+    assert_eq!(sessions[1]["volume"], 3 * 110 + 8 * 90 + 120);
+    assert_eq!(sessions[1]["bestSet"], json!({ "reps": 3, "weight": 110 }));
+    assert!(approx(&sessions[1]["estimated1rm"], 121.0));
+
+    let res = app
+        .get("/api/v1/exercise/progress?title=squat&start=2024-01-05T00:00:00Z")
+        .await;
+    assert_eq!(res.json["strength"].as_array().unwrap().len(), 1);
+    assert_eq!(res.json["strength"][0]["start"], "2024-01-08T09:00:00Z");
+}
+
+#[tokio::test]
+async fn cardio_progress() {
+    let mut app = TestApp::seeded("progress@test.com", activities()).await;
+
+    let res = app.get("/api/v1/exercise/progress?title=row").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["strength"], json!([]));
+
+    let sessions = res.json["cardio"].as_array().unwrap();
+    assert_eq!(sessions.len(), 2);
+
+    assert_eq!(sessions[0]["duration"], 600);
+    assert_eq!(sessions[0]["distance"], 2000);
+    assert!(approx(&sessions[0]["pace"], 300.0));
+    assert_eq!(sessions[0]["splits"], 2);
+    assert!(approx(&sessions[0]["splitVariation"], 10.0 / 300.0));
+
+    assert!(approx(&sessions[1]["pace"], 240.0));
+    assert_eq!(sessions[1]["splits"], 0);
+    assert_eq!(sessions[1]["splitVariation"], Value::Null);
+
+    // mobility is listed but has no progress
+    let res = app.get("/api/v1/exercise/progress?title=stretch").await;
+    assert_eq!(
+        res.json,
+        json!({ "title": "stretch", "strength": [], "cardio": [] })
+    );
+
+    let res = app.get("/api/v1/exercise/progress?title=%20").await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "title");
+
+    let res = app.get("/api/v1/exercise/progress").await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,258 @@
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+
+use super::TestApp;
+
+fn squat(start: &str, sets: &[(u32, u32)]) -> Value {
+    let sets: Vec<Value> = sets
+        .iter()
+        .enumerate()
+        .map(|(idx, (reps, weight))| json!({ "idx": idx, "reps": reps, "weight": weight }))
+        .collect();
+
+    json!({
+        "title": "gym",
+        "variant": "Exercise",
+        "group": "sport",
+        "start": start,
+        "end": start.replace("T09", "T10"),
+        "timezone": 0,
+        "data": { "exercise": [{ "variant": "Strength", "title": "squat", "sets": sets }] },
+    })
+}
+
+/// `(kind, value, weight)` of each record, as returned. Values are rounded as
+/// json floats may not parse back exactly.
+fn records(res: &Value) -> Vec<(String, f64, Option<u64>)> {
+    res.as_array()
+        .unwrap()
+        .iter()
+        .map(|r| {
+            (
+                r["kind"].as_str().unwrap().to_string(),
+                (r["value"].as_f64().unwrap() * 1e6).round() / 1e6,
+                r["weight"].as_u64(),
+            )
+        })
+        .collect()
+}
+
+fn kinds(res: &Value) -> Vec<String> {
+    records(res).into_iter().map(|r| r.0).collect()
+}
+
+fn holder(res: &Value, kind: &str) -> String {
+    res.as_array()
+        .unwrap()
+        .iter()
+        .find(|r| r["kind"] == kind)
+        .map(|r| r["activityId"].as_str().unwrap().to_string())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn records_on_write() {
+    let mut app = TestApp::new();
+    app.register("records@test.com", "password").await;
+
+    // the first time an exercise is logged sets its records
+    let res = app
+        .post(
+            "/api/v1/activity",
+            squat("2024-01-01T09:00:00Z", &[(5, 100)]),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_eq!(res.json["title"], "gym");
+    assert_eq!(
+        records(&res.json["newRecords"]),
+        vec![
+            ("heaviestWeight".to_string(), 100.0, Some(100)),
+            ("mostReps".to_string(), 5.0, Some(100)),
+            ("estimated1rm".to_string(), 116.666667, Some(100)),
+        ]
+    );
+    assert_eq!(res.json["newRecords"][0]["reps"], 5);
+    assert_eq!(
+        res.json["newRecords"][0]["achievedAt"],
+        "2024-01-01T09:00:00Z"
+    );
+
+    let res = app
+        .post(
+            "/api/v1/activity",
+            squat("2024-01-08T09:00:00Z", &[(5, 105), (3, 100)]),
+        )
+        .await;
+    assert_eq!(
+        kinds(&res.json["newRecords"]),
+        vec!["heaviestWeight", "mostReps", "estimated1rm"]
+    );
+    assert_eq!(res.json["newRecords"][1]["weight"], 105);
+
+    // matching a record doesn't take it
+    let res = app
+        .post(
+            "/api/v1/activity",
+            squat("2024-01-15T09:00:00Z", &[(5, 100)]),
+        )
+        .await;
+    assert_eq!(res.json["newRecords"], json!([]));
+
+    let res = app
+        .post(
+            "/api/v1/activity",
+            json!({
+                "title": "run",
+                "variant": "Exercise",
+                "group": "sport",
+                "start": "2024-01-16T09:00:00Z",
+                "end": "2024-01-16T10:00:00Z",
+                "timezone": 0,
+                "data": { "exercise": [
+                    { "variant": "Cardio", "title": "run", "duration": 1500, "distance": 5000 },
+                ] },
+            }),
+        )
+        .await;
+    assert_eq!(
+        records(&res.json["newRecords"]),
+        vec![
+            ("longestDistance".to_string(), 5000.0, None),
+            ("fastestPace".to_string(), 300.0, None),
+        ]
+    );
+
+    let res = app
+        .post(
+            "/api/v1/activity",
+            json!({
+                "title": "read",
+                "variant": "Default",
+                "group": "hobby",
+                "start": "2024-01-17T09:00:00Z",
+                "end": "2024-01-17T10:00:00Z",
+                "timezone": 0,
+            }),
+        )
+        .await;
+    assert_eq!(res.json["newRecords"], json!([]));
+
+    let res = app.get("/api/v1/records").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        kinds(&res.json),
+        vec![
+            "longestDistance",
+            "fastestPace",
+            "heaviestWeight",
+            "mostReps",
+            "mostReps",
+            "estimated1rm",
+        ]
+    );
+
+    let res = app.get("/api/v1/records?title=run").await;
+    assert_eq!(res.json.as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn records_on_edit_and_delete() {
+    let mut app = TestApp::new();
+    app.register("records@test.com", "password").await;
+
+    let first = app
+        .post(
+            "/api/v1/activity",
+            squat("2024-01-01T09:00:00Z", &[(5, 100)]),
+        )
+        .await
+        .json["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let second = app
+        .post(
+            "/api/v1/activity",
+            squat("2024-01-08T09:00:00Z", &[(5, 105)]),
+        )
+        .await
+        .json["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let third = app
+        .post(
+            "/api/v1/activity",
+            squat("2024-01-15T09:00:00Z", &[(3, 100)]),
+        )
+        .await
+        .json["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let res = app.get("/api/v1/records").await;
+    assert_eq!(holder(&res.json, "heaviestWeight"), second);
+
+    // edits that change nothing set no records
+    let res = app
+        .patch(
+            &format!("/api/v1/activity/{}", second),
+            json!({ "notes": "deep" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["newRecords"], json!([]));
+
+    // lowering the weight hands the record back
+    let res = app
+        .patch(
+            &format!("/api/v1/activity/{}", second),
+            json!({ "data": squat("2024-01-08T09:00:00Z", &[(5, 95)])["data"] }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        records(&res.json["newRecords"]),
+        vec![("mostReps".to_string(), 5.0, Some(95))]
+    );
+
+    let res = app.get("/api/v1/records").await;
+    assert_eq!(holder(&res.json, "heaviestWeight"), first);
+    assert_eq!(holder(&res.json, "estimated1rm"), first);
+
+    // deleting the holder passes its records on
+    let res = app.delete(&format!("/api/v1/activity/{}", first)).await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.get("/api/v1/records").await;
+    assert_eq!(holder(&res.json, "heaviestWeight"), third);
+    // 95 * (1 + 5 / 30) beats 100 * (1 + 3 / 30)
+    assert_eq!(holder(&res.json, "estimated1rm"), second);
+    assert_eq!(
+        records(&res.json)
+            .into_iter()
+            .filter(|r| r.0 == "mostReps")
+            .collect::<Vec<_>>(),
+        vec![
+            ("mostReps".to_string(), 5.0, Some(95)),
+            ("mostReps".to_string(), 3.0, Some(100)),
+        ]
+    );
+
+    // as does turning it into something else
+    let res = app
+        .post(
+            "/api/v1/activity/batch",
+            json!({ "operations": [
+                { "op": "update", "id": second, "activity": { "variant": "Default" } },
+                { "op": "delete", "id": third },
+            ] }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.get("/api/v1/records").await;
+    assert_eq!(res.json, json!([]));
+}
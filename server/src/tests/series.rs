@@ -0,0 +1,406 @@
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+
+use super::TestApp;
+use crate::models::series_model::MAX_SERIES;
+
+const WEEK: &str = "start=2024-01-01T00:00:00Z&end=2024-01-07T23:59:59Z";
+
+/// Weekdays 09:00-09:15 at UTC+1.
+async fn standup(app: &mut TestApp) -> String {
+    let res = app
+        .post(
+            "/api/v1/series",
+            json!({
+                "title": "standup",
+                "variant": "Default",
+                "group": "work",
+                "start": "2024-01-01T08:00:00Z",
+                "duration": 900,
+                "timezone": -60,
+                "rrule": "FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR",
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+
+    res.json["id"].as_str().unwrap().to_string()
+}
+
+/// `(start, title)` of each activity.
+fn listed(res: &Value) -> Vec<(String, String)> {
+    res.as_array()
+        .unwrap()
+        .iter()
+        .map(|a| {
+            (
+                a["start"].as_str().unwrap().to_string(),
+                a["title"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect()
+}
+
+fn starts(res: &Value) -> Vec<String> {
+    listed(res).into_iter().map(|a| a.0).collect()
+}
+
+#[tokio::test]
+async fn series_expand_in_range() {
+    let mut app = TestApp::new();
+    app.register("series@test.com", "password").await;
+    let id = standup(&mut app).await;
+
+    let res = app
+        .post(
+            "/api/v1/activity",
+            json!({
+                "title": "lunch",
+                "variant": "Default",
+                "group": "food",
+                "start": "2024-01-02T11:00:00Z",
+                "end": "2024-01-02T12:00:00Z",
+                "timezone": -60,
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+
+    let res = app.get(&format!("/api/v1/activity?{}", WEEK)).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(
+        listed(&res.json),
+        vec![
+            ("2024-01-01T08:00:00Z".to_string(), "standup".to_string()),
+            ("2024-01-02T08:00:00Z".to_string(), "standup".to_string()),
+            ("2024-01-02T11:00:00Z".to_string(), "lunch".to_string()),
+            ("2024-01-03T08:00:00Z".to_string(), "standup".to_string()),
+            ("2024-01-04T08:00:00Z".to_string(), "standup".to_string()),
+            ("2024-01-05T08:00:00Z".to_string(), "standup".to_string()),
+        ]
+    );
+    // every occurrence has an id of its own
+    let occurrence = &res.json[0];
+    assert_ne!(occurrence["id"], id.as_str());
+    assert_ne!(occurrence["id"], res.json[1]["id"]);
+    assert_eq!(occurrence["end"], "2024-01-01T08:15:00Z");
+    assert_eq!(occurrence["occurrence"]["seriesId"], id.as_str());
+    assert_eq!(occurrence["occurrence"]["start"], "2024-01-01T08:00:00Z");
+    assert!(res.json[2].get("occurrence").is_none());
+
+    // filters apply to occurrences too
+    let res = app
+        .get(&format!("/api/v1/activity?{}&group=food", WEEK))
+        .await;
+    assert_eq!(starts(&res.json), vec!["2024-01-02T11:00:00Z"]);
+
+    // an open range isn't expanded
+    let res = app.get("/api/v1/activity").await;
+    assert_eq!(res.json.as_array().unwrap().len(), 1);
+
+    // pages continue between occurrences
+    let mut paged = vec![];
+    let mut uri = format!("/api/v1/activity?{}&limit=2", WEEK);
+    loop {
+        let res = app.get(&uri).await;
+        assert_eq!(res.status, StatusCode::OK);
+        paged.extend(starts(&res.json["data"]));
+
+        match res.json["next"].as_str() {
+            Some(next) => uri = format!("/api/v1/activity?{}&limit=2&after={}", WEEK, next),
+            None => break,
+        }
+    }
+    assert_eq!(paged.len(), 6);
+    assert_eq!(paged[2], "2024-01-02T11:00:00Z");
+}
+
+#[tokio::test]
+async fn edit_one_occurrence() {
+    let mut app = TestApp::new();
+    app.register("series@test.com", "password").await;
+    let id = standup(&mut app).await;
+
+    let res = app
+        .patch(
+            &format!("/api/v1/series/{}/occurrences/2024-01-02T08:00:00Z", id),
+            json!({ "start": "2024-01-02T09:00:00Z", "notes": "moved" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_eq!(res.json["start"], "2024-01-02T09:00:00Z");
+    assert_eq!(res.json["end"], "2024-01-02T09:15:00Z");
+    assert_eq!(res.json["notes"], "moved");
+    assert_eq!(res.json["occurrence"]["seriesId"], id.as_str());
+    assert_eq!(res.json["occurrence"]["start"], "2024-01-02T08:00:00Z");
+    let override_id = res.json["id"].as_str().unwrap().to_string();
+    assert_ne!(override_id, id);
+
+    // the stored occurrence replaces the expanded one
+    let res = app.get(&format!("/api/v1/activity?{}", WEEK)).await;
+    assert_eq!(
+        starts(&res.json),
+        vec![
+            "2024-01-01T08:00:00Z",
+            "2024-01-02T09:00:00Z",
+            "2024-01-03T08:00:00Z",
+            "2024-01-04T08:00:00Z",
+            "2024-01-05T08:00:00Z",
+        ]
+    );
+    assert_eq!(res.json[1]["id"], override_id.as_str());
+
+    // it is an activity like any other from here on
+    let res = app.get(&format!("/api/v1/activity/{}", override_id)).await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app
+        .patch(
+            &format!("/api/v1/series/{}/occurrences/2024-01-02T08:00:00Z", id),
+            json!({ "notes": "again" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    assert_eq!(res.json["code"], "occurrence_not_found");
+
+    let res = app
+        .delete(&format!(
+            "/api/v1/series/{}/occurrences/2024-01-03T08:00:00Z",
+            id
+        ))
+        .await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+
+    let res = app.get(&format!("/api/v1/series/{}", id)).await;
+    assert_eq!(
+        res.json["exceptions"],
+        json!(["2024-01-02T08:00:00Z", "2024-01-03T08:00:00Z"])
+    );
+
+    let res = app.get(&format!("/api/v1/activity?{}", WEEK)).await;
+    assert_eq!(res.json.as_array().unwrap().len(), 4);
+
+    // deleting the series keeps what was edited on its own
+    let res = app.delete(&format!("/api/v1/series/{}", id)).await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+
+    let res = app.get(&format!("/api/v1/activity?{}", WEEK)).await;
+    assert_eq!(starts(&res.json), vec!["2024-01-02T09:00:00Z"]);
+}
+
+#[tokio::test]
+async fn edit_following_occurrences() {
+    let mut app = TestApp::new();
+    app.register("series@test.com", "password").await;
+    let id = standup(&mut app).await;
+
+    let res = app
+        .patch(
+            &format!("/api/v1/series/{}/occurrences/2024-01-03T08:00:00Z", id),
+            json!({
+                "scope": "following",
+                "title": "sync",
+                "start": "2024-01-03T08:30:00Z",
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_eq!(res.json["title"], "sync");
+    assert_eq!(res.json["start"], "2024-01-03T08:30:00Z");
+    assert_eq!(res.json["rrule"], "FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR");
+    assert_ne!(res.json["id"], id.as_str());
+
+    let res = app.get(&format!("/api/v1/series/{}", id)).await;
+    assert_eq!(res.json["until"], "2024-01-03T08:00:00Z");
+
+    let res = app.get(&format!("/api/v1/activity?{}", WEEK)).await;
+    assert_eq!(
+        listed(&res.json),
+        vec![
+            ("2024-01-01T08:00:00Z".to_string(), "standup".to_string()),
+            ("2024-01-02T08:00:00Z".to_string(), "standup".to_string()),
+            ("2024-01-03T08:30:00Z".to_string(), "sync".to_string()),
+            ("2024-01-04T08:30:00Z".to_string(), "sync".to_string()),
+            ("2024-01-05T08:30:00Z".to_string(), "sync".to_string()),
+        ]
+    );
+
+    // all occurrences, from any of them
+    let res = app
+        .patch(
+            &format!("/api/v1/series/{}/occurrences/2024-01-02T08:00:00Z", id),
+            json!({ "scope": "all", "group": "team" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["group"], "team");
+    assert_eq!(res.json["start"], "2024-01-01T08:00:00Z");
+
+    let res = app
+        .delete(&format!(
+            "/api/v1/series/{}/occurrences/2024-01-02T08:00:00Z?scope=following",
+            id
+        ))
+        .await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+
+    let res = app.get(&format!("/api/v1/activity?{}", WEEK)).await;
+    assert_eq!(res.json.as_array().unwrap().len(), 4);
+
+    let res = app.get("/api/v1/series").await;
+    assert_eq!(res.json.as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn series_in_stats_and_feed() {
+    let mut app = TestApp::new();
+    app.register("series@test.com", "password").await;
+    standup(&mut app).await;
+
+    let res = app
+        .get(&format!("/api/v1/stats?{}&groupBy=title", WEEK))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["count"], 5);
+    assert_eq!(res.json["buckets"][0]["key"], "standup");
+    assert_eq!(res.json["buckets"][0]["total"], 5 * 900_000);
+
+    let res = app.post("/api/v1/calendar", json!({})).await;
+    let path = res.json["path"].as_str().unwrap().to_string();
+    let res = app.get(&format!("{}?{}", path, WEEK)).await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    // each occurrence is an event of its own
+    let ics = String::from_utf8(res.bytes).unwrap();
+    let mut uids: Vec<&str> = ics.lines().filter(|l| l.starts_with("UID:")).collect();
+    assert_eq!(uids.len(), 5);
+    uids.dedup();
+    assert_eq!(uids.len(), 5);
+    assert!(ics.contains("DTSTART;TZID=UTC+0100:20240102T090000\r\n"));
+}
+
+#[tokio::test]
+async fn series_validation() {
+    let mut app = TestApp::new();
+    app.register("series@test.com", "password").await;
+    let id = standup(&mut app).await;
+
+    let res = app
+        .post(
+            "/api/v1/series",
+            json!({
+                "title": "standup",
+                "variant": "Default",
+                "group": "work",
+                "start": "2024-01-01T08:00:00Z",
+                "duration": 900,
+                "timezone": 0,
+                "rrule": "FREQ=HOURLY",
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "rrule");
+
+    let res = app
+        .post(
+            "/api/v1/series",
+            json!({
+                "title": "standup",
+                "variant": "Default",
+                "group": "work",
+                "start": "2024-01-01T08:00:00Z",
+                "duration": 0,
+                "timezone": 0,
+                "rrule": "FREQ=DAILY",
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "duration");
+
+    let res = app
+        .patch(
+            &format!("/api/v1/series/{}/occurrences/2024-01-02T08:00:00Z", id),
+            json!({ "scope": "all", "duration": 0 }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "duration");
+
+    let res = app
+        .patch(
+            &format!("/api/v1/series/{}/occurrences/2024-01-02T08:00:00Z", id),
+            json!({ "rrule": "FREQ=DAILY" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "rrule");
+
+    // not an occurrence of the series
+    let res = app
+        .patch(
+            &format!("/api/v1/series/{}/occurrences/2024-01-06T08:00:00Z", id),
+            json!({ "title": "weekend" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let res = app
+        .delete(&format!("/api/v1/series/{}/occurrences/tomorrow", id))
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "occurrence");
+
+    let mut other = app.new_session();
+    other.register("other@test.com", "password").await;
+
+    let res = other.get(&format!("/api/v1/series/{}", id)).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    assert_eq!(res.json["code"], "series_not_found");
+
+    let res = other.get(&format!("/api/v1/activity?{}", WEEK)).await;
+    assert_eq!(res.json, json!([]));
+}
+
+#[tokio::test]
+async fn series_limit() {
+    let mut app = TestApp::new();
+    app.register("series@test.com", "password").await;
+    let mut ids = vec![];
+    for _ in 0..MAX_SERIES {
+        ids.push(standup(&mut app).await);
+    }
+
+    let res = app
+        .post(
+            "/api/v1/series",
+            json!({
+                "title": "standup",
+                "variant": "Default",
+                "group": "work",
+                "start": "2024-01-01T08:00:00Z",
+                "duration": 900,
+                "timezone": 0,
+                "rrule": "FREQ=DAILY",
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CONFLICT);
+    assert_eq!(res.json["code"], "series_limit_reached");
+
+    // splitting one makes another series
+    let res = app
+        .patch(
+            &format!("/api/v1/series/{}/occurrences/2024-01-03T08:00:00Z", ids[0]),
+            json!({ "scope": "following", "title": "retro" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CONFLICT);
+    assert_eq!(res.json["code"], "series_limit_reached");
+
+    // deleted series don't count
+    let res = app.delete(&format!("/api/v1/series/{}", ids[1])).await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+    standup(&mut app).await;
+}
@@ -0,0 +1,198 @@
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+
+use super::{activity_body, TestApp};
+
+const HOUR: i64 = 60 * 60 * 1000;
+
+fn activities() -> Vec<Value> {
+    let activities = [
+        // crosses midnight utc
+        (
+            "read",
+            "Default",
+            "hobby",
+            "2024-01-01T22:00:00Z",
+            "2024-01-02T02:00:00Z",
+            0,
+        ),
+        // crosses midnight utc, but not local midnight at utc+1
+        (
+            "gym",
+            "Exercise",
+            "sport",
+            "2024-01-02T23:30:00Z",
+            "2024-01-03T00:30:00Z",
+            -60,
+        ),
+        (
+            "read",
+            "Default",
+            "hobby",
+            "2024-01-08T09:00:00Z",
+            "2024-01-08T10:00:00Z",
+            0,
+        ),
+    ];
+
+    activities
+        .into_iter()
+        .map(|(title, variant, group, start, end, timezone)| {
+            let mut body = activity_body(title, start, end);
+            body["variant"] = json!(variant);
+            body["group"] = json!(group);
+            body["timezone"] = json!(timezone);
+            body
+        })
+        .collect()
+}
+
+async fn stats(app: &mut TestApp, query: &str) -> Value {
+    let res = app
+        .get(&format!(
+            "/api/v1/stats?start=2024-01-01T00:00:00Z&end=2024-02-01T00:00:00Z{}",
+            query
+        ))
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    res.json
+}
+
+fn buckets(stats: &Value) -> Vec<(String, i64, u64)> {
+    stats["buckets"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|b| {
+            (
+                b["key"].as_str().unwrap().to_string(),
+                b["total"].as_i64().unwrap(),
+                b["count"].as_u64().unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn stats_by_time() {
+    let mut app = TestApp::seeded("stats@test.com", activities()).await;
+
+    let res = stats(&mut app, "").await;
+    assert_eq!(res["groupBy"], "day");
+    assert_eq!(res["total"], 6 * HOUR);
+    assert_eq!(res["count"], 3);
+    assert_eq!(res["average"], 2 * HOUR);
+    assert_eq!(
+        buckets(&res),
+        vec![
+            ("2024-01-01".to_string(), 2 * HOUR, 1),
+            ("2024-01-02".to_string(), 2 * HOUR, 1),
+            ("2024-01-03".to_string(), HOUR, 1),
+            ("2024-01-08".to_string(), HOUR, 1),
+        ]
+    );
+    assert_eq!(res["buckets"][1]["average"], 2 * HOUR);
+
+    // one offset for everything splits the gym session at utc midnight
+    let res = stats(&mut app, "&timezone=0").await;
+    assert_eq!(
+        buckets(&res)[1..3],
+        [
+            ("2024-01-02".to_string(), 5 * HOUR / 2, 2),
+            ("2024-01-03".to_string(), HOUR / 2, 1),
+        ]
+    );
+    assert_eq!(res["buckets"][1]["average"], 5 * HOUR / 4);
+
+    let res = stats(&mut app, "&groupBy=week").await;
+    assert_eq!(
+        buckets(&res),
+        vec![
+            ("2024-W01".to_string(), 5 * HOUR, 2),
+            ("2024-W02".to_string(), HOUR, 1),
+        ]
+    );
+
+    let res = stats(&mut app, "&groupBy=month&title=read").await;
+    assert_eq!(res["count"], 2);
+    assert_eq!(buckets(&res), vec![("2024-01".to_string(), 5 * HOUR, 2)]);
+}
+
+#[tokio::test]
+async fn stats_by_field() {
+    let mut app = TestApp::seeded("stats@test.com", activities()).await;
+
+    let res = stats(&mut app, "&groupBy=title").await;
+    assert_eq!(
+        buckets(&res),
+        vec![
+            ("read".to_string(), 5 * HOUR, 2),
+            ("gym".to_string(), HOUR, 1),
+        ]
+    );
+
+    let res = stats(&mut app, "&groupBy=variant").await;
+    assert_eq!(
+        buckets(&res),
+        vec![
+            ("Default".to_string(), 5 * HOUR, 2),
+            ("Exercise".to_string(), HOUR, 1),
+        ]
+    );
+
+    // only the part within the range counts
+    let res = app
+        .get("/api/v1/stats?start=2024-01-02T00:00:00Z&end=2024-01-08T09:30:00Z&groupBy=group")
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["total"], 7 * HOUR / 2);
+    assert_eq!(
+        buckets(&res.json),
+        vec![
+            ("hobby".to_string(), 5 * HOUR / 2, 2),
+            ("sport".to_string(), HOUR, 1),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn stats_validation() {
+    let mut app = TestApp::seeded("stats@test.com", activities()).await;
+
+    let res = app
+        .get("/api/v1/stats?start=2024-02-01T00:00:00Z&end=2024-01-01T00:00:00Z&timezone=900")
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["code"], "validation_failed");
+    let fields: Vec<&str> = res.json["fields"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| f["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, vec!["end", "timezone"]);
+
+    let res = app
+        .get("/api/v1/stats?start=2000-01-01T00:00:00Z&end=2024-01-01T00:00:00Z")
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+
+    let res = app
+        .get("/api/v1/stats?start=2024-01-01T00:00:00Z&end=2024-02-01T00:00:00Z&groupBy=year")
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+
+    // empty ranges have no buckets
+    let res = app
+        .get("/api/v1/stats?start=2023-01-01T00:00:00Z&end=2023-02-01T00:00:00Z")
+        .await;
+    assert_eq!(res.json["count"], 0);
+    assert_eq!(res.json["average"], 0);
+    assert_eq!(res.json["buckets"], json!([]));
+
+    app.post("/api/v1/logout", json!({})).await;
+    let res = app
+        .get("/api/v1/stats?start=2024-01-01T00:00:00Z&end=2024-02-01T00:00:00Z")
+        .await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+}
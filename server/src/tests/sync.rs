@@ -0,0 +1,98 @@
+use axum::http::StatusCode;
+use serde_json::json;
+
+use super::{activity_body, TestApp};
+
+const START: &str = "2000-01-01T09:00:00.000Z";
+const END: &str = "2000-01-01T10:00:00.000Z";
+
+#[tokio::test]
+async fn sync() {
+    let mut app = TestApp::new();
+    app.register("sync@test.com", "password").await;
+
+    let kept = app
+        .post("/api/v1/activity", activity_body("kept", START, END))
+        .await
+        .json;
+    let removed = app
+        .post("/api/v1/activity", activity_body("removed", START, END))
+        .await
+        .json;
+
+    // a first sync returns every live activity
+    let res = app.get("/api/v1/sync").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["changed"].as_array().unwrap().len(), 2);
+    assert_eq!(res.json["deleted"], json!([]));
+    assert!(res.json["changed"][0]["updatedAt"].is_string());
+    let since = res.json["next"].as_str().unwrap().to_string();
+
+    let uri = format!("/api/v1/activity/{}", kept["id"].as_str().unwrap());
+    app.patch(&uri, json!({ "title": "edited" })).await;
+    let uri = format!("/api/v1/activity/{}", removed["id"].as_str().unwrap());
+    app.delete(&uri).await;
+
+    // deletes are kept as tombstones for sync, but gone everywhere else
+    let res = app.get(&uri).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    let res = app.get("/api/v1/activity").await;
+    assert_eq!(res.json.as_array().unwrap().len(), 1);
+
+    let res = app.get(&format!("/api/v1/sync?since={}", since)).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["changed"].as_array().unwrap().len(), 1);
+    assert_eq!(res.json["changed"][0]["title"], "edited");
+    assert_eq!(res.json["changed"][0]["v"], 2);
+    assert_eq!(res.json["deleted"], json!([removed["id"]]));
+
+    // a second delete finds nothing
+    let res = app.delete(&uri).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let res = app.get("/api/v1/sync?since=yesterday").await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "since");
+}
+
+#[tokio::test]
+async fn sync_series() {
+    let mut app = TestApp::new();
+    app.register("sync@test.com", "password").await;
+
+    let series = app
+        .post(
+            "/api/v1/series",
+            json!({
+                "title": "standup",
+                "variant": "Default",
+                "group": "work",
+                "start": "2024-01-01T08:00:00Z",
+                "duration": 900,
+                "timezone": 0,
+                "rrule": "FREQ=DAILY",
+            }),
+        )
+        .await
+        .json;
+
+    // series are synced as rules, not as their occurrences
+    let res = app.get("/api/v1/sync").await;
+    assert_eq!(res.json["changed"], json!([]));
+    assert_eq!(res.json["changedSeries"][0]["id"], series["id"]);
+    assert_eq!(res.json["deletedSeries"], json!([]));
+    let since = res.json["next"].as_str().unwrap().to_string();
+
+    let uri = format!("/api/v1/series/{}", series["id"].as_str().unwrap());
+    let res = app.delete(&uri).await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+    let res = app.get(&uri).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let res = app.get(&format!("/api/v1/sync?since={}", since)).await;
+    assert_eq!(res.json["changedSeries"], json!([]));
+    assert_eq!(res.json["deletedSeries"], json!([series["id"]]));
+
+    let res = app.get("/api/v1/sync").await;
+    assert_eq!(res.json["deletedSeries"], json!([]));
+}
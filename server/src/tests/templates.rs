@@ -0,0 +1,244 @@
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+
+use super::TestApp;
+
+fn leg_day() -> Value {
+    json!({
+        "name": "legs",
+        "group": "sport",
+        "duration": 3600,
+        "exercises": [
+            { "variant": "Strength", "title": "squat", "sets": [
+                { "idx": 0, "reps": 5 },
+                { "idx": 1, "reps": 5 },
+                { "idx": 2, "reps": 5, "weight": 60 },
+            ] },
+            { "variant": "Strength", "title": "lunge", "sets": [{ "idx": 0, "reps": 10 }] },
+            { "variant": "Cardio", "title": "bike", "duration": 600, "distance": 0 },
+        ],
+    })
+}
+
+fn weights(exercise: &Value) -> Vec<Option<u64>> {
+    exercise["sets"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| s["weight"].as_u64())
+        .collect()
+}
+
+#[tokio::test]
+async fn template_crud() {
+    let mut app = TestApp::new();
+    app.register("templates@test.com", "password").await;
+
+    let res = app.post("/api/v1/templates", leg_day()).await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_eq!(res.json["name"], "legs");
+    assert_eq!(res.json["notes"], "");
+    assert_eq!(res.json["exercises"].as_array().unwrap().len(), 3);
+    let id = res.json["id"].as_str().unwrap().to_string();
+
+    let res = app
+        .post(
+            "/api/v1/templates",
+            json!({ "name": "arms", "group": "sport", "exercises": [] }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+
+    // by name
+    let res = app.get("/api/v1/templates").await;
+    assert_eq!(res.status, StatusCode::OK);
+    let names: Vec<&str> = res
+        .json
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["arms", "legs"]);
+
+    let res = app
+        .patch(
+            &format!("/api/v1/templates/{}", id),
+            json!({ "name": "heavy legs", "notes": "go slow" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["name"], "heavy legs");
+    assert_eq!(res.json["notes"], "go slow");
+    assert_eq!(res.json["duration"], 3600);
+
+    let res = app.get(&format!("/api/v1/templates/{}", id)).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["name"], "heavy legs");
+
+    let res = app
+        .patch(&format!("/api/v1/templates/{}", id), json!({ "name": " " }))
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+
+    let res = app.delete(&format!("/api/v1/templates/{}", id)).await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+
+    let res = app.get(&format!("/api/v1/templates/{}", id)).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    assert_eq!(res.json["code"], "template_not_found");
+
+    let res = app.delete(&format!("/api/v1/templates/{}", id)).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn template_validation() {
+    let mut app = TestApp::new();
+    app.register("templates@test.com", "password").await;
+
+    let res = app
+        .post(
+            "/api/v1/templates",
+            json!({
+                "name": "",
+                "group": "sport",
+                "exercises": [{ "variant": "Strength", "title": "", "sets": [] }],
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    let fields: Vec<&str> = res.json["fields"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| f["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, vec!["name", "exercises[0].title"]);
+
+    let res = app.get("/api/v1/templates/nope").await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn instantiate_template() {
+    let mut app = TestApp::new();
+    app.register("templates@test.com", "password").await;
+
+    let res = app.post("/api/v1/templates", leg_day()).await;
+    let id = res.json["id"].as_str().unwrap().to_string();
+    let uri = format!("/api/v1/templates/{}/instantiate", id);
+
+    // nothing to prefill from yet
+    let res = app
+        .post(
+            &uri,
+            json!({ "start": "2024-01-01T09:00:00Z", "timezone": 0 }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_eq!(res.json["title"], "legs");
+    assert_eq!(res.json["variant"], "Exercise");
+    assert_eq!(res.json["group"], "sport");
+    assert_eq!(res.json["end"], "2024-01-01T10:00:00Z");
+    let exercises = &res.json["data"]["exercise"];
+    assert_eq!(weights(&exercises[0]), vec![None, None, Some(60)]);
+    assert_eq!(exercises[2]["variant"], "Cardio");
+
+    // a squat session without weights doesn't count as the last one
+    for (start, weight) in [
+        ("2024-01-02T09:00:00Z", json!(100)),
+        ("2024-01-03T09:00:00Z", json!(null)),
+    ] {
+        let res = app
+            .post(
+                "/api/v1/activity",
+                json!({
+                    "title": "gym",
+                    "variant": "Exercise",
+                    "group": "sport",
+                    "start": start,
+                    "end": start,
+                    "timezone": 0,
+                    "data": { "exercise": [{ "variant": "Strength", "title": "squat", "sets": [
+                        { "idx": 0, "reps": 5, "weight": weight },
+                    ] }] },
+                }),
+            )
+            .await;
+        assert_eq!(res.status, StatusCode::CREATED);
+    }
+
+    let res = app
+        .post(
+            &uri,
+            json!({
+                "start": "2024-01-08T09:00:00Z",
+                "end": "2024-01-08T09:45:00Z",
+                "timezone": -60,
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_eq!(res.json["end"], "2024-01-08T09:45:00Z");
+    assert_eq!(res.json["timezone"], -60);
+    let exercises = &res.json["data"]["exercise"];
+    assert_eq!(weights(&exercises[0]), vec![Some(100), Some(100), Some(60)]);
+    assert_eq!(weights(&exercises[1]), vec![None]);
+    assert!(res.json["newRecords"].is_array());
+
+    let res = app.get("/api/v1/activity").await;
+    assert_eq!(res.json.as_array().unwrap().len(), 4);
+
+    let res = app
+        .post(
+            &uri,
+            json!({
+                "start": "2024-01-08T09:00:00Z",
+                "end": "2024-01-08T08:00:00Z",
+                "timezone": 0,
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn templates_are_per_user() {
+    let mut app = TestApp::new();
+    app.register("owner@test.com", "password").await;
+
+    let res = app.post("/api/v1/templates", leg_day()).await;
+    let id = res.json["id"].as_str().unwrap().to_string();
+
+    let mut other = app.new_session();
+    other.register("other@test.com", "password").await;
+
+    let res = other.get("/api/v1/templates").await;
+    assert_eq!(res.json, json!([]));
+
+    let res = other.get(&format!("/api/v1/templates/{}", id)).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let res = other
+        .patch(
+            &format!("/api/v1/templates/{}", id),
+            json!({ "name": "mine" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let res = other
+        .post(
+            &format!("/api/v1/templates/{}/instantiate", id),
+            json!({ "start": "2024-01-01T09:00:00Z", "timezone": 0 }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let res = other.delete(&format!("/api/v1/templates/{}", id)).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let res = app.get(&format!("/api/v1/templates/{}", id)).await;
+    assert_eq!(res.status, StatusCode::OK);
+}
@@ -0,0 +1,184 @@
+use axum::http::StatusCode;
+use mongodb::bson::DateTime;
+use serde_json::{json, Value};
+
+use super::TestApp;
+
+fn reading(start: Option<&str>) -> Value {
+    let mut body = json!({
+        "title": "reading",
+        "variant": "Default",
+        "group": "leisure",
+        "timezone": 0,
+    });
+    if let Some(start) = start {
+        body["start"] = json!(start);
+    }
+
+    body
+}
+
+fn ids(res: &Value) -> Vec<&str> {
+    res.as_array()
+        .unwrap()
+        .iter()
+        .map(|a| a["id"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn start_and_stop_timer() {
+    let mut app = TestApp::new();
+    app.register("timer@test.com", "password").await;
+
+    let res = app.get("/api/v1/timer").await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    assert_eq!(res.json["code"], "timer_not_found");
+
+    let res = app
+        .post("/api/v1/timer/start", reading(Some("2024-01-01T09:00:00Z")))
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_eq!(res.json["start"], "2024-01-01T09:00:00Z");
+    assert_eq!(res.json["end"], Value::Null);
+    let id = res.json["id"].as_str().unwrap().to_string();
+
+    let res = app.post("/api/v1/timer/start", reading(None)).await;
+    assert_eq!(res.status, StatusCode::CONFLICT);
+    assert_eq!(res.json["code"], "timer_running");
+
+    let res = app.get("/api/v1/timer").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["id"], id.as_str());
+
+    // a running timer lasts until now
+    let res = app
+        .get("/api/v1/activity?start=2024-06-01T00:00:00Z&end=2024-06-02T00:00:00Z")
+        .await;
+    assert_eq!(ids(&res.json), vec![id.as_str()]);
+
+    let res = app
+        .get("/api/v1/activity?start=2999-01-01T00:00:00Z&end=2999-01-02T00:00:00Z")
+        .await;
+    assert_eq!(res.json, json!([]));
+
+    let res = app
+        .post(
+            "/api/v1/timer/stop",
+            json!({ "end": "2024-01-01T08:00:00Z" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "end");
+
+    let res = app
+        .post(
+            "/api/v1/timer/stop",
+            json!({ "end": "2024-01-01T10:00:00Z" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["id"], id.as_str());
+    assert_eq!(res.json["end"], "2024-01-01T10:00:00Z");
+    assert_eq!(res.json["v"], 2);
+    assert!(res.json["newRecords"].is_array());
+
+    let res = app
+        .get("/api/v1/activity?start=2024-06-01T00:00:00Z&end=2024-06-02T00:00:00Z")
+        .await;
+    assert_eq!(res.json, json!([]));
+
+    let res = app.get("/api/v1/timer").await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let res = app.post("/api/v1/timer/stop", json!({})).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    assert_eq!(res.json["code"], "timer_not_found");
+
+    // stopped, another can start
+    let res = app.post("/api/v1/timer/start", reading(None)).await;
+    assert_eq!(res.status, StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn timer_defaults_to_now() {
+    let mut app = TestApp::new();
+    app.register("timer@test.com", "password").await;
+
+    let res = app.post("/api/v1/timer/start", reading(None)).await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    let start = DateTime::parse_rfc3339_str(res.json["start"].as_str().unwrap()).unwrap();
+
+    // compared as dates, the formatted fractions vary in length
+    let res = app.post("/api/v1/timer/stop", json!({})).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let end = DateTime::parse_rfc3339_str(res.json["end"].as_str().unwrap()).unwrap();
+    assert!(end >= start);
+}
+
+#[tokio::test]
+async fn deleted_timer_stops() {
+    let mut app = TestApp::new();
+    app.register("timer@test.com", "password").await;
+
+    let res = app.post("/api/v1/timer/start", reading(None)).await;
+    let id = res.json["id"].as_str().unwrap().to_string();
+
+    let res = app.delete(&format!("/api/v1/activity/{}", id)).await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.get("/api/v1/timer").await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let res = app.post("/api/v1/timer/start", reading(None)).await;
+    assert_eq!(res.status, StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn timer_validation() {
+    let mut app = TestApp::new();
+    app.register("timer@test.com", "password").await;
+
+    let mut body = reading(Some("now"));
+    body["title"] = json!(" ");
+    body["timezone"] = json!(5000);
+    let res = app.post("/api/v1/timer/start", body).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    let fields: Vec<&str> = res.json["fields"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| f["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, vec!["title", "start", "timezone"]);
+
+    // stopping it now would end it before it started
+    let res = app
+        .post("/api/v1/timer/start", reading(Some("2999-01-01T09:00:00Z")))
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "start");
+    assert_eq!(
+        res.json["fields"][0]["message"],
+        "must not be in the future"
+    );
+
+    let res = app
+        .post("/api/v1/timer/stop", json!({ "end": "later" }))
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "end");
+
+    // one running timer per user, not per server
+    let res = app.post("/api/v1/timer/start", reading(None)).await;
+    assert_eq!(res.status, StatusCode::CREATED);
+
+    let mut other = app.new_session();
+    other.register("other@test.com", "password").await;
+
+    let res = other.get("/api/v1/timer").await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let res = other.post("/api/v1/timer/start", reading(None)).await;
+    assert_eq!(res.status, StatusCode::CREATED);
+}
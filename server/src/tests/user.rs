@@ -0,0 +1,151 @@
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+
+use super::{activity_body, TestApp};
+
+#[tokio::test]
+async fn get_and_update_me() {
+    let mut app = TestApp::new();
+    let registered = app.register("me@test.com", "password").await;
+
+    let res = app.get("/api/v1/me").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json, registered.json);
+
+    let res = app
+        .patch(
+            "/api/v1/me",
+            json!({ "givenName": "", "img": "javascript:alert(1)" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    let fields: Vec<_> = res.json["fields"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| f["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, vec!["givenName", "img"]);
+
+    let res = app
+        .patch(
+            "/api/v1/me",
+            json!({ "givenName": "new", "img": "https://example.com/me.png" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["givenName"], "new");
+    assert_eq!(res.json["familyName"], "mctestface");
+    assert_eq!(res.json["img"], "https://example.com/me.png");
+
+    let res = app.patch("/api/v1/me", json!({ "img": "" })).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["img"], "");
+    assert_eq!(res.json["givenName"], "new");
+
+    let mut anon = app.new_session();
+    let res = anon.get("/api/v1/me").await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+}
+
+fn activity() -> serde_json::Value {
+    activity_body(
+        "run",
+        "2000-01-01T09:00:00.000Z",
+        "2000-01-01T09:30:00.000Z",
+    )
+}
+
+#[tokio::test]
+async fn delete_me() {
+    let mut app = TestApp::new();
+    app.register("delete@test.com", "password").await;
+    app.post("/api/v1/activity", activity()).await;
+    app.post("/api/v1/activity", activity()).await;
+    let access = app.cookie("access").unwrap();
+
+    // another user's data is left alone
+    let mut other = app.new_session();
+    other.register("other@test.com", "password").await;
+    other.post("/api/v1/activity", activity()).await;
+
+    let res = app
+        .request(Method::DELETE, "/api/v1/me", Some(json!({})))
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["fields"][0]["field"], "pass");
+
+    let res = app
+        .request(
+            Method::DELETE,
+            "/api/v1/me",
+            Some(json!({ "pass": "wrong" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+
+    let res = app
+        .request(
+            Method::DELETE,
+            "/api/v1/me",
+            Some(json!({ "pass": "password" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json["status"], "deleted");
+    assert_eq!(res.json["erased"]["activities"], 2);
+    assert!(res.json["erased"]["tokens"].as_u64().unwrap() >= 2);
+    assert!(app.cookie("access").is_none());
+    assert!(app.cookie("refresh").is_none());
+
+    // the old access token went with the account
+    app.set_cookie("access", access);
+    let res = app.post("/api/v1/activity", activity()).await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(res.json["code"], "invalid_token");
+    app.remove_cookie("access");
+
+    let res = app
+        .post(
+            "/api/v1/login",
+            json!({ "email": "delete@test.com", "pass": "password" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+
+    let res = other.get("/api/v1/activity").await;
+    assert_eq!(res.json.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn delete_me_with_grace_period() {
+    let mut app = TestApp::with_env(|env| env.account_deletion_grace_days = Some(30));
+    app.register("grace-delete@test.com", "password").await;
+    let access = app.cookie("access").unwrap();
+
+    let res = app
+        .request(
+            Method::DELETE,
+            "/api/v1/me",
+            Some(json!({ "pass": "password" })),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::ACCEPTED);
+    assert_eq!(res.json["status"], "scheduled");
+    assert!(res.json["purgeAt"].is_string());
+    assert!(res.json.get("erased").is_none());
+
+    // the old session is revoked and the account can't be signed in to
+    app.set_cookie("access", access);
+    let res = app.get("/api/v1/me").await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+
+    let res = app
+        .post(
+            "/api/v1/login",
+            json!({ "email": "grace-delete@test.com", "pass": "password" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    assert_eq!(res.json["code"], "account_deleted");
+}
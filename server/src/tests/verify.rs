@@ -0,0 +1,85 @@
+use axum::http::StatusCode;
+use serde_json::json;
+
+use super::{activity_body, TestApp};
+
+fn activity() -> serde_json::Value {
+    activity_body(
+        "run",
+        "2000-01-01T09:00:00.000Z",
+        "2000-01-01T09:30:00.000Z",
+    )
+}
+
+#[tokio::test]
+async fn verify_email() {
+    let mut app = TestApp::new();
+    let res = app.register("verify@test.com", "password").await;
+    assert_eq!(res.json["verified"], false);
+    let token = app.mailed_token("verify@test.com", "verify-email");
+
+    let res = app
+        .post("/api/v1/verify-email", json!({ "token": "nope" }))
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.json["code"], "invalid_verification_token");
+
+    // session tokens are signed with the same key but are not accepted
+    let access = app.cookie("access").unwrap();
+    let res = app
+        .post("/api/v1/verify-email", json!({ "token": access }))
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+
+    let res = app.post("/api/v1/verify-email/resend", json!({})).await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+    let resent = app.mailed_token("verify@test.com", "verify-email");
+
+    let mut anon = app.new_session();
+    let res = anon
+        .post("/api/v1/verify-email", json!({ "token": token }))
+        .await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+
+    // tokens stay valid until they expire
+    let res = anon
+        .post("/api/v1/verify-email", json!({ "token": resent }))
+        .await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+
+    let res = anon
+        .post(
+            "/api/v1/login",
+            json!({ "email": "verify@test.com", "pass": "password" }),
+        )
+        .await;
+    assert_eq!(res.json["verified"], true);
+
+    // nothing more is sent once verified
+    let sent = app.sent_mail().len();
+    let res = app.post("/api/v1/verify-email/resend", json!({})).await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+    assert_eq!(app.sent_mail().len(), sent);
+}
+
+#[tokio::test]
+async fn unverified_grace_period() {
+    let mut app = TestApp::with_env(|env| env.unverified_grace_days = Some(0));
+    app.register("grace@test.com", "password").await;
+
+    let res = app.post("/api/v1/activity", activity()).await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    assert_eq!(res.json["code"], "email_not_verified");
+
+    let res = app.get("/api/v1/activity").await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let token = app.mailed_token("grace@test.com", "verify-email");
+    let res = app
+        .post("/api/v1/verify-email", json!({ "token": token }))
+        .await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+
+    let res = app.post("/api/v1/activity", activity()).await;
+    assert_eq!(res.status, StatusCode::CREATED);
+}
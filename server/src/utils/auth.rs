@@ -6,7 +6,7 @@ use axum_extra::extract::PrivateCookieJar;
 use mongodb::bson::oid::ObjectId;
 use uuid::Uuid;
 
-use crate::error::error::AuthError;
+use crate::error::error::AppError;
 use crate::models::auth_model::{AccessClaims, AccessToken, RefreshToken, TokenDB};
 use crate::AppState;
 
@@ -14,6 +14,28 @@ pub fn generate_jti() -> String {
     Uuid::new_v4().to_string()
 }
 
+/// Rejects writes from users whose unverified grace period has run out.
+/// Only reads the user when a grace period is configured.
+pub async fn require_write_access(app_state: &AppState, sub: &str) -> Result<(), AppError> {
+    let grace_days = match app_state.env.unverified_grace_days {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+
+    let uid = ObjectId::parse_str(sub).map_err(|_| AppError::InvalidToken)?;
+    let user = app_state
+        .db
+        .get_user_by_id(uid)
+        .await?
+        .ok_or(AppError::UserNotFound)?;
+
+    if user.is_read_only(Some(grace_days)) {
+        return Err(AppError::EmailNotVerified);
+    }
+
+    Ok(())
+}
+
 #[async_trait]
 impl<S> FromRequestParts<S> for AccessClaims
 where
@@ -21,40 +43,41 @@ where
     Key: FromRef<S>,
     AppState: FromRef<S>,
 {
-    type Rejection = AuthError;
+    type Rejection = AppError;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        let jar = PrivateCookieJar::<Key>::from_request_parts(parts, &state)
+        let jar = PrivateCookieJar::<Key>::from_request_parts(parts, state)
             .await
-            .map_err(|_| AuthError::InternalError)?;
+            .map_err(|_| AppError::internal("failed to read cookies"))?;
 
         let state = parts
             .extract_with_state::<AppState, _>(state)
             .await
-            .map_err(|_| AuthError::InternalError)?;
+            .map_err(|_| AppError::internal("failed to read app state"))?;
 
         // extract token from cookie and decode the user data
         let access_token = match AccessToken::try_from(&jar) {
             Ok(token) => Ok(Some(token)),
-            Err(err) if err == AuthError::MissingToken => Ok(None),
+            Err(AppError::MissingToken) => Ok(None),
             Err(err) => Err(err),
         }?;
 
-        // check if access token is blacklisted
+        // check if access token is blacklisted. every issued token is stored,
+        // so a missing one was erased with its user
         match access_token {
             Some(token) if !token.expired => {
                 match state.db.get_token(&token.claims.jti).await {
                     Ok(token) => match token {
                         Some(t) => {
                             if t.black {
-                                Err(AuthError::Forbidden)
+                                Err(AppError::Forbidden)
                             } else {
                                 Ok(())
                             }
                         }
-                        None => Ok(()),
+                        None => Err(AppError::InvalidToken),
                     },
-                    _ => Err(AuthError::InternalError),
+                    Err(e) => Err(AppError::from(e)),
                 }?;
 
                 parts.extensions.insert(jar);
@@ -71,14 +94,10 @@ where
                         Some(t) => {
                             // if it has - revoke all associated tokens
                             if t.black {
-                                let _ = state
-                                    .db
-                                    .blacklist_user_tokens(
-                                        ObjectId::parse_str(&refresh_token.claims.sub)
-                                            .expect("failed to parse string to ObjectId"),
-                                    )
-                                    .await;
-                                Err(AuthError::Forbidden)
+                                let uid = ObjectId::parse_str(&refresh_token.claims.sub)
+                                    .map_err(|_| AppError::InvalidToken)?;
+                                let _ = state.db.blacklist_user_tokens(uid).await;
+                                Err(AppError::TokenReused)
                             } else {
                                 // if not - blacklist only the current refresh token
                                 let _ = state
@@ -88,9 +107,9 @@ where
                                 Ok(())
                             }
                         }
-                        None => Err(AuthError::InvalidToken),
+                        None => Err(AppError::InvalidToken),
                     },
-                    _ => Err(AuthError::InternalError),
+                    Err(e) => Err(AppError::from(e)),
                 }?;
 
                 // create new tokens
@@ -0,0 +1,1127 @@
+use std::collections::BTreeSet;
+
+use chrono::{
+    Datelike, Days, Duration, FixedOffset, LocalResult, Months, NaiveDate, NaiveDateTime,
+    NaiveTime, Offset, TimeZone, Utc, Weekday,
+};
+use chrono_tz::Tz;
+use mongodb::bson::DateTime;
+
+use crate::models::activity_model::Activity;
+
+// https://datatracker.ietf.org/doc/html/rfc5545
+const PRODID: &str = "-//chrono//activities//EN";
+const MAX_LINE_OCTETS: usize = 75;
+
+fn to_chrono(date: DateTime) -> chrono::DateTime<Utc> {
+    chrono::DateTime::from_timestamp_millis(date.timestamp_millis()).unwrap_or_default()
+}
+
+/// Escapes a TEXT value, section 3.3.11.
+pub fn escape_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            ';' => out.push_str("\\;"),
+            ',' => out.push_str("\\,"),
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Appends a content line, folded at 75 octets without splitting a character.
+fn push_line(out: &mut String, line: &str) {
+    let mut len = 0;
+
+    for c in line.chars() {
+        if len + c.len_utf8() > MAX_LINE_OCTETS {
+            out.push_str("\r\n ");
+            // the leading space counts towards the next line
+            len = 1;
+        }
+        out.push(c);
+        len += c.len_utf8();
+    }
+
+    out.push_str("\r\n");
+}
+
+/// Timezone offsets are stored as js `getTimezoneOffset` minutes, which has
+/// the opposite sign of the utc offset.
+fn utc_offset(timezone: i16) -> String {
+    let minutes = -i32::from(timezone);
+    let sign = if minutes < 0 { '-' } else { '+' };
+
+    format!("{}{:02}{:02}", sign, minutes.abs() / 60, minutes.abs() % 60)
+}
+
+fn tzid(timezone: i16) -> String {
+    format!("UTC{}", utc_offset(timezone))
+}
+
+fn utc_time(date: DateTime) -> String {
+    to_chrono(date).format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// A DATE-TIME property in the activity's own offset. Utc is written as such
+/// so it needs no VTIMEZONE.
+fn date_time(name: &str, date: DateTime, timezone: i16) -> String {
+    if timezone == 0 {
+        return format!("{}:{}", name, utc_time(date));
+    }
+
+    let local = to_chrono(date) - Duration::minutes(timezone.into());
+    format!(
+        "{};TZID={}:{}",
+        name,
+        tzid(timezone),
+        local.format("%Y%m%dT%H%M%S")
+    )
+}
+
+// a fixed offset has no transitions, so one STANDARD observance covers all time
+fn push_timezone(out: &mut String, timezone: i16) {
+    let offset = utc_offset(timezone);
+
+    push_line(out, "BEGIN:VTIMEZONE");
+    push_line(out, &format!("TZID:{}", tzid(timezone)));
+    push_line(out, "BEGIN:STANDARD");
+    push_line(out, "DTSTART:19700101T000000");
+    push_line(out, &format!("TZOFFSETFROM:{}", offset));
+    push_line(out, &format!("TZOFFSETTO:{}", offset));
+    push_line(out, &format!("TZNAME:{}", tzid(timezone)));
+    push_line(out, "END:STANDARD");
+    push_line(out, "END:VTIMEZONE");
+}
+
+fn push_event(out: &mut String, activity: &Activity, timezone: i16) {
+    push_line(out, "BEGIN:VEVENT");
+    push_line(out, &format!("UID:{}@chrono", activity.id.to_hex()));
+    push_line(out, &format!("DTSTAMP:{}", utc_time(activity.updated_at)));
+    push_line(out, &date_time("DTSTART", activity.start, timezone));
+    // a running timer has no end yet
+    if let Some(end) = activity.end {
+        push_line(out, &date_time("DTEND", end, timezone));
+    }
+    push_line(out, &format!("SUMMARY:{}", escape_text(&activity.title)));
+    if !activity.notes.is_empty() {
+        push_line(
+            out,
+            &format!("DESCRIPTION:{}", escape_text(&activity.notes)),
+        );
+    }
+    if !activity.group.is_empty() {
+        push_line(out, &format!("CATEGORIES:{}", escape_text(&activity.group)));
+    }
+    push_line(out, &format!("CREATED:{}", utc_time(activity.created_at)));
+    push_line(
+        out,
+        &format!("LAST-MODIFIED:{}", utc_time(activity.updated_at)),
+    );
+    push_line(out, &format!("SEQUENCE:{}", activity.v.saturating_sub(1)));
+    push_line(out, "END:VEVENT");
+}
+
+/// Renders activities as a published, read-only VCALENDAR. Each event keeps
+/// the offset it was logged in unless `timezone` overrides it.
+pub fn render_calendar(name: &str, activities: &[Activity], timezone: Option<i16>) -> String {
+    let mut out = String::new();
+
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, &format!("PRODID:{}", PRODID));
+    push_line(&mut out, "CALSCALE:GREGORIAN");
+    push_line(&mut out, "METHOD:PUBLISH");
+    push_line(&mut out, &format!("X-WR-CALNAME:{}", escape_text(name)));
+
+    let offsets: BTreeSet<i16> = activities
+        .iter()
+        .map(|a| timezone.unwrap_or(a.timezone))
+        .filter(|tz| *tz != 0)
+        .collect();
+    for offset in offsets {
+        push_timezone(&mut out, offset);
+    }
+
+    for activity in activities {
+        push_event(&mut out, activity, timezone.unwrap_or(activity.timezone));
+    }
+
+    push_line(&mut out, "END:VCALENDAR");
+
+    out
+}
+
+/// A content line, with its parameters and still escaped value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Property {
+    pub name: String,
+    pub params: Vec<(String, String)>,
+    pub value: String,
+}
+
+impl Property {
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Component {
+    pub name: String,
+    pub properties: Vec<Property>,
+    pub components: Vec<Component>,
+}
+
+impl Component {
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.iter().find(|p| p.name == name)
+    }
+
+    pub fn properties<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Property> {
+        self.properties.iter().filter(move |p| p.name == name)
+    }
+
+    pub fn components<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Component> {
+        self.components.iter().filter(move |c| c.name == name)
+    }
+}
+
+// joins folded lines, keeping the number of the line each one starts on
+fn unfold(text: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = vec![];
+
+    for (idx, line) in text.lines().enumerate() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some((_, last))) => last.push_str(rest),
+            _ => lines.push((idx + 1, line.to_string())),
+        }
+    }
+
+    lines
+}
+
+fn parse_line(line: &str) -> Option<Property> {
+    let name_end = line.find([';', ':'])?;
+    let name = line[..name_end].to_ascii_uppercase();
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut params = vec![];
+    let mut rest = &line[name_end..];
+
+    while let Some(param) = rest.strip_prefix(';') {
+        let eq = param.find('=')?;
+        let param_name = param[..eq].to_ascii_uppercase();
+        let mut value = String::new();
+        rest = &param[eq + 1..];
+
+        // values are separated by commas and may be quoted to contain ; : or ,
+        loop {
+            if let Some(quoted) = rest.strip_prefix('"') {
+                let end = quoted.find('"')?;
+                value.push_str(&quoted[..end]);
+                rest = &quoted[end + 1..];
+            } else {
+                let end = rest.find([',', ';', ':'])?;
+                value.push_str(&rest[..end]);
+                rest = &rest[end..];
+            }
+
+            match rest.strip_prefix(',') {
+                Some(next) => {
+                    value.push(',');
+                    rest = next;
+                }
+                None => break,
+            }
+        }
+
+        params.push((param_name, value));
+    }
+
+    Some(Property {
+        name,
+        params,
+        value: rest.strip_prefix(':')?.to_string(),
+    })
+}
+
+/// Parses a VCALENDAR object. Components and properties are kept whether or
+/// not they are understood, interpreting them is left to the caller.
+pub fn parse_calendar(text: &str) -> Result<Component, String> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut stack: Vec<Component> = vec![];
+
+    for (number, line) in unfold(text) {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let property =
+            parse_line(&line).ok_or_else(|| format!("line {}: not a content line", number))?;
+
+        match property.name.as_str() {
+            "BEGIN" => stack.push(Component {
+                name: property.value.trim().to_ascii_uppercase(),
+                ..Default::default()
+            }),
+            "END" => {
+                let component = stack
+                    .pop()
+                    .filter(|c| c.name.eq_ignore_ascii_case(property.value.trim()))
+                    .ok_or_else(|| format!("line {}: unexpected END:{}", number, property.value))?;
+
+                match stack.last_mut() {
+                    Some(parent) => parent.components.push(component),
+                    None if component.name == "VCALENDAR" => return Ok(component),
+                    None => return Err("expected a VCALENDAR".to_string()),
+                }
+            }
+            _ => stack
+                .last_mut()
+                .ok_or_else(|| format!("line {}: property outside of a component", number))?
+                .properties
+                .push(property),
+        }
+    }
+
+    Err("missing END:VCALENDAR".to_string())
+}
+
+/// Reverses `escape_text`.
+pub fn unescape_text(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n' | 'N') => out.push('\n'),
+                Some(c) => out.push(c),
+                None => out.push('\\'),
+            },
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Splits a list of TEXT values, such as CATEGORIES, on unescaped commas.
+pub fn split_text_list(value: &str) -> Vec<String> {
+    let mut values = vec![];
+    let mut start = 0;
+    let mut escaped = false;
+
+    for (idx, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ',' => {
+                values.push(unescape_text(&value[start..idx]));
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    values.push(unescape_text(&value[start..]));
+
+    values
+}
+
+/// A DATE or DATE-TIME value, section 3.3.4 and 3.3.5.
+#[derive(Clone, Debug, PartialEq)]
+pub enum IcalTime {
+    /// All day.
+    Date(NaiveDate),
+    /// Local time wherever the reader is.
+    Floating(NaiveDateTime),
+    Utc(NaiveDateTime),
+    /// Wall clock time in the named TZID.
+    Zoned(NaiveDateTime, String),
+}
+
+impl IcalTime {
+    fn parse_value(value: &str, tzid: Option<&str>) -> Result<Self, String> {
+        let value = value.trim();
+        let invalid = || format!("invalid date {}", value);
+
+        if value.len() == 8 {
+            return NaiveDate::parse_from_str(value, "%Y%m%d")
+                .map(IcalTime::Date)
+                .map_err(|_| invalid());
+        }
+
+        let (local, utc) = match value.strip_suffix('Z') {
+            Some(v) => (v, true),
+            None => (value, false),
+        };
+        let time = NaiveDateTime::parse_from_str(local, "%Y%m%dT%H%M%S").map_err(|_| invalid())?;
+
+        Ok(match (utc, tzid) {
+            (true, _) => IcalTime::Utc(time),
+            (false, Some(tzid)) => IcalTime::Zoned(time, tzid.to_string()),
+            (false, None) => IcalTime::Floating(time),
+        })
+    }
+
+    pub fn parse(property: &Property) -> Result<Self, String> {
+        Self::parse_value(&property.value, property.param("TZID"))
+    }
+
+    /// Every value of a property that takes a list, such as EXDATE.
+    pub fn parse_list(property: &Property) -> Result<Vec<Self>, String> {
+        property
+            .value
+            .split(',')
+            .map(|v| Self::parse_value(v, property.param("TZID")))
+            .collect()
+    }
+
+    pub fn is_date(&self) -> bool {
+        matches!(self, IcalTime::Date(_))
+    }
+
+    /// The wall clock time, midnight for a date.
+    pub fn naive(&self) -> NaiveDateTime {
+        match self {
+            IcalTime::Date(date) => date.and_time(NaiveTime::MIN),
+            IcalTime::Floating(time) | IcalTime::Utc(time) | IcalTime::Zoned(time, _) => *time,
+        }
+    }
+}
+
+/// Parses a DURATION value, section 3.3.6.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration {}", value);
+    let value = value.trim();
+
+    let (negative, rest) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let rest = rest.strip_prefix('P').ok_or_else(invalid)?;
+
+    let mut total = Duration::zero();
+    let mut in_time = false;
+    let mut digits = String::new();
+
+    for c in rest.chars() {
+        match c {
+            'T' if !in_time && digits.is_empty() => in_time = true,
+            c if c.is_ascii_digit() => digits.push(c),
+            unit => {
+                let n: i64 = digits.parse().map_err(|_| invalid())?;
+                digits.clear();
+
+                total += match (in_time, unit) {
+                    (false, 'W') => Duration::weeks(n),
+                    (false, 'D') => Duration::days(n),
+                    (true, 'H') => Duration::hours(n),
+                    (true, 'M') => Duration::minutes(n),
+                    (true, 'S') => Duration::seconds(n),
+                    _ => return Err(invalid()),
+                };
+            }
+        }
+    }
+
+    if !digits.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(if negative { -total } else { total })
+}
+
+// stops expanding rules that never produce another occurrence, such as the
+// 30th of february
+const MAX_RULE_PERIODS: u32 = 50_000;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// The subset of RRULE, section 3.3.10, that calendars use for events in
+/// practice. Other rule parts are rejected rather than ignored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecurrenceRule {
+    pub freq: Frequency,
+    pub interval: u32,
+    pub count: Option<u32>,
+    pub until: Option<IcalTime>,
+    /// Weekdays, with an optional ordinal within the month.
+    pub by_day: Vec<(Option<i32>, Weekday)>,
+    pub by_month_day: Vec<i32>,
+    pub by_month: Vec<u32>,
+}
+
+fn parse_weekday(s: &str) -> Option<Weekday> {
+    match s {
+        "MO" => Some(Weekday::Mon),
+        "TU" => Some(Weekday::Tue),
+        "WE" => Some(Weekday::Wed),
+        "TH" => Some(Weekday::Thu),
+        "FR" => Some(Weekday::Fri),
+        "SA" => Some(Weekday::Sat),
+        "SU" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+
+    NaiveDate::from_ymd_opt(next_year, next_month, 1)
+        .and_then(|d| d.pred_opt())
+        .map(|d| d.day())
+        .unwrap_or(28)
+}
+
+impl RecurrenceRule {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut freq = None;
+        let mut rule = RecurrenceRule {
+            freq: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: vec![],
+            by_month_day: vec![],
+            by_month: vec![],
+        };
+
+        for part in value.trim().split(';').filter(|p| !p.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("invalid rule part {}", part))?;
+            let invalid = || format!("invalid {} {}", key, value);
+
+            match key.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    freq = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(format!("unsupported FREQ {}", value)),
+                    })
+                }
+                "INTERVAL" => {
+                    rule.interval = value.parse().ok().filter(|i| *i > 0).ok_or_else(invalid)?
+                }
+                "COUNT" => rule.count = Some(value.parse().map_err(|_| invalid())?),
+                "UNTIL" => rule.until = Some(IcalTime::parse_value(value, None)?),
+                "BYDAY" => {
+                    for day in value.split(',') {
+                        let day = day.trim().to_ascii_uppercase();
+                        let split = day.len().checked_sub(2).ok_or_else(invalid)?;
+                        let weekday = parse_weekday(&day[split..]).ok_or_else(invalid)?;
+                        let ordinal = match &day[..split] {
+                            "" => None,
+                            n => Some(
+                                n.trim_start_matches('+')
+                                    .parse::<i32>()
+                                    .ok()
+                                    .filter(|n| *n != 0 && n.abs() <= 5)
+                                    .ok_or_else(invalid)?,
+                            ),
+                        };
+                        rule.by_day.push((ordinal, weekday));
+                    }
+                }
+                "BYMONTHDAY" => {
+                    for day in value.split(',') {
+                        let day: i32 = day.trim().parse().map_err(|_| invalid())?;
+                        if day == 0 || day.abs() > 31 {
+                            return Err(invalid());
+                        }
+                        rule.by_month_day.push(day);
+                    }
+                }
+                "BYMONTH" => {
+                    for month in value.split(',') {
+                        let month: u32 = month.trim().parse().map_err(|_| invalid())?;
+                        if !(1..=12).contains(&month) {
+                            return Err(invalid());
+                        }
+                        rule.by_month.push(month);
+                    }
+                }
+                // only changes weekly rules with an interval, close enough to ignore
+                "WKST" => {}
+                key => return Err(format!("unsupported rule part {}", key)),
+            }
+        }
+
+        rule.freq = freq.ok_or("FREQ is required")?;
+
+        if rule.count.is_some() && rule.until.is_some() {
+            return Err("COUNT and UNTIL must not both be set".to_string());
+        }
+
+        let ordinals = rule.by_day.iter().any(|(n, _)| n.is_some());
+        let ordinals_allowed = match rule.freq {
+            Frequency::Monthly => true,
+            Frequency::Yearly => !rule.by_month.is_empty(),
+            _ => false,
+        };
+        if ordinals && !ordinals_allowed {
+            return Err("unsupported BYDAY ordinal".to_string());
+        }
+
+        Ok(rule)
+    }
+
+    fn matches(&self, date: NaiveDate) -> bool {
+        let last = days_in_month(date.year(), date.month()) as i32;
+
+        (self.by_month.is_empty() || self.by_month.contains(&date.month()))
+            && (self.by_month_day.is_empty()
+                || self.by_month_day.iter().any(|d| {
+                    let day = if *d > 0 { *d } else { last + 1 + d };
+                    day == date.day() as i32
+                }))
+            && (self.by_day.is_empty() || self.by_day.iter().any(|(_, wd)| *wd == date.weekday()))
+    }
+
+    // the days of a month picked by BYMONTHDAY and BYDAY, or `default_day`
+    fn month_dates(&self, year: i32, month: u32, default_day: u32) -> Vec<NaiveDate> {
+        let last = days_in_month(year, month);
+        let all = (1..=last).filter_map(|d| NaiveDate::from_ymd_opt(year, month, d));
+
+        let mut dates: Vec<NaiveDate> = if self.by_month_day.is_empty() && self.by_day.is_empty() {
+            NaiveDate::from_ymd_opt(year, month, default_day)
+                .into_iter()
+                .collect()
+        } else {
+            all.filter(|date| {
+                let day_matches = self.by_month_day.is_empty()
+                    || self.by_month_day.iter().any(|d| {
+                        let day = if *d > 0 { *d } else { last as i32 + 1 + d };
+                        day == date.day() as i32
+                    });
+
+                let weekday_matches = self.by_day.is_empty()
+                    || self.by_day.iter().any(|(ordinal, wd)| {
+                        if *wd != date.weekday() {
+                            return false;
+                        }
+                        match ordinal {
+                            None => true,
+                            Some(n) if *n > 0 => (date.day() - 1) / 7 + 1 == *n as u32,
+                            Some(n) => (last - date.day()) / 7 + 1 == n.unsigned_abs(),
+                        }
+                    });
+
+                day_matches && weekday_matches
+            })
+            .collect()
+        };
+
+        dates.sort();
+        dates
+    }
+
+    // candidate dates of the `n`th period after the one containing `start`
+    fn period_dates(&self, start: NaiveDate, n: u32) -> Option<Vec<NaiveDate>> {
+        let n = n.checked_mul(self.interval)?;
+
+        Some(match self.freq {
+            Frequency::Daily => {
+                let date = start.checked_add_days(Days::new(n.into()))?;
+                vec![date]
+                    .into_iter()
+                    .filter(|d| self.matches(*d))
+                    .collect()
+            }
+            Frequency::Weekly => {
+                let monday = start
+                    .checked_sub_days(Days::new(start.weekday().num_days_from_monday().into()))?
+                    .checked_add_days(Days::new(u64::from(n) * 7))?;
+
+                let mut weekdays: Vec<Weekday> = match self.by_day.is_empty() {
+                    true => vec![start.weekday()],
+                    false => self.by_day.iter().map(|(_, wd)| *wd).collect(),
+                };
+                weekdays.sort_by_key(|wd| wd.num_days_from_monday());
+                weekdays.dedup();
+
+                weekdays
+                    .into_iter()
+                    .filter_map(|wd| {
+                        monday.checked_add_days(Days::new(wd.num_days_from_monday().into()))
+                    })
+                    .filter(|d| self.by_month.is_empty() || self.by_month.contains(&d.month()))
+                    .collect()
+            }
+            Frequency::Monthly => {
+                let first = NaiveDate::from_ymd_opt(start.year(), start.month(), 1)?
+                    .checked_add_months(Months::new(n))?;
+
+                self.month_dates(first.year(), first.month(), start.day())
+                    .into_iter()
+                    .filter(|d| self.by_month.is_empty() || self.by_month.contains(&d.month()))
+                    .collect()
+            }
+            Frequency::Yearly => {
+                let year = start.year().checked_add(n.try_into().ok()?)?;
+                let months: Vec<u32> = if !self.by_month.is_empty() {
+                    let mut months = self.by_month.clone();
+                    months.sort();
+                    months
+                } else if !self.by_day.is_empty() || !self.by_month_day.is_empty() {
+                    (1..=12).collect()
+                } else {
+                    vec![start.month()]
+                };
+
+                months
+                    .into_iter()
+                    .flat_map(|m| self.month_dates(year, m, start.day()))
+                    .collect()
+            }
+        })
+    }
+
+    /// Occurrences that start within `from..=to`, as wall clock times like
+    /// `start`, which is always the first occurrence. `until` is the rule's
+    /// UNTIL in that same wall clock. At most `limit` are returned.
+    pub fn expand(
+        &self,
+        start: NaiveDateTime,
+        until: Option<NaiveDateTime>,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+        limit: usize,
+    ) -> Vec<NaiveDateTime> {
+        let mut res = vec![];
+        let in_window = |at: NaiveDateTime| at >= from && at <= to;
+
+        if in_window(start) {
+            res.push(start);
+        }
+        let mut seen = 1;
+
+        for period in 0..MAX_RULE_PERIODS {
+            let Some(dates) = self.period_dates(start.date(), period) else {
+                break;
+            };
+
+            for date in dates {
+                let at = date.and_time(start.time());
+                if at <= start {
+                    continue;
+                }
+
+                let ended = until.is_some_and(|u| at > u) || self.count.is_some_and(|c| seen >= c);
+                if ended || at > to || res.len() >= limit {
+                    return res;
+                }
+
+                seen += 1;
+                if in_window(at) {
+                    res.push(at);
+                }
+            }
+        }
+
+        res
+    }
+}
+
+/// A STANDARD or DAYLIGHT part of a VTIMEZONE.
+#[derive(Clone, Debug)]
+pub struct Observance {
+    start: NaiveDateTime,
+    offset_from: FixedOffset,
+    offset_to: FixedOffset,
+    rule: Option<RecurrenceRule>,
+}
+
+fn parse_utc_offset(value: &str) -> Result<FixedOffset, String> {
+    let invalid = || format!("invalid utc offset {}", value);
+    let value = value.trim();
+
+    let (sign, digits) = match value.split_at_checked(1) {
+        Some(("+", digits)) => (1, digits),
+        Some(("-", digits)) => (-1, digits),
+        _ => return Err(invalid()),
+    };
+    if !matches!(digits.len(), 4 | 6) || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+
+    let hours: i32 = digits[0..2].parse().map_err(|_| invalid())?;
+    let minutes: i32 = digits[2..4].parse().map_err(|_| invalid())?;
+    let seconds: i32 = digits
+        .get(4..6)
+        .unwrap_or("0")
+        .parse()
+        .map_err(|_| invalid())?;
+
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60 + seconds)).ok_or_else(invalid)
+}
+
+/// Where a wall clock time is, for turning it into an instant.
+#[derive(Clone, Debug)]
+pub enum Zone {
+    Fixed(FixedOffset),
+    Named(Tz),
+    /// Defined by a VTIMEZONE in the calendar itself.
+    Defined(Vec<Observance>),
+}
+
+impl Zone {
+    /// A zone from a js `getTimezoneOffset` value.
+    pub fn from_timezone(timezone: i16) -> Self {
+        Zone::Fixed(
+            FixedOffset::west_opt(i32::from(timezone) * 60)
+                .unwrap_or(FixedOffset::east_opt(0).unwrap()),
+        )
+    }
+
+    pub fn from_vtimezone(component: &Component) -> Result<Self, String> {
+        let mut observances = vec![];
+
+        for part in &component.components {
+            if part.name != "STANDARD" && part.name != "DAYLIGHT" {
+                continue;
+            }
+
+            let property = |name: &str| {
+                part.property(name)
+                    .ok_or_else(|| format!("{} is required", name))
+            };
+
+            observances.push(Observance {
+                start: IcalTime::parse(property("DTSTART")?)?.naive(),
+                offset_from: parse_utc_offset(&property("TZOFFSETFROM")?.value)?,
+                offset_to: parse_utc_offset(&property("TZOFFSETTO")?.value)?,
+                rule: part
+                    .property("RRULE")
+                    .map(|p| RecurrenceRule::parse(&p.value))
+                    .transpose()?,
+            });
+        }
+
+        if observances.is_empty() {
+            return Err("VTIMEZONE has no observances".to_string());
+        }
+
+        Ok(Zone::Defined(observances))
+    }
+
+    // the offset of the observance that most recently started before `local`
+    fn defined_offset(observances: &[Observance], local: NaiveDateTime) -> FixedOffset {
+        let onsets = observances.iter().filter_map(|o| {
+            let onset = match &o.rule {
+                Some(rule) => {
+                    let until = rule.until.as_ref().map(|u| match u {
+                        IcalTime::Utc(t) => *t + o.offset_from,
+                        u => u.naive(),
+                    });
+                    let from = local - Duration::days(400);
+
+                    rule.expand(o.start, until, from, local, usize::MAX)
+                        .last()
+                        .copied()
+                }
+                None => Some(o.start).filter(|s| *s <= local),
+            };
+
+            onset.map(|at| (at, o.offset_to))
+        });
+
+        match onsets.max_by_key(|(at, _)| *at) {
+            Some((_, offset)) => offset,
+            None => observances
+                .iter()
+                .min_by_key(|o| o.start)
+                .map(|o| o.offset_from)
+                .unwrap_or(FixedOffset::east_opt(0).unwrap()),
+        }
+    }
+
+    /// The utc time of a wall clock time, and the offset there. Times skipped
+    /// by a transition move forward, repeated ones resolve to the first.
+    pub fn resolve(&self, local: NaiveDateTime) -> (NaiveDateTime, FixedOffset) {
+        let offset = match self {
+            Zone::Fixed(offset) => *offset,
+            Zone::Named(tz) => {
+                let resolved = match tz.from_local_datetime(&local) {
+                    LocalResult::Single(dt) | LocalResult::Ambiguous(dt, _) => Some(dt),
+                    LocalResult::None => tz
+                        .from_local_datetime(&(local + Duration::hours(1)))
+                        .earliest(),
+                };
+
+                match resolved {
+                    Some(dt) => return (dt.naive_utc(), dt.offset().fix()),
+                    None => tz.offset_from_utc_datetime(&local).fix(),
+                }
+            }
+            Zone::Defined(observances) => Self::defined_offset(observances, local),
+        };
+
+        (local - offset, offset)
+    }
+
+    /// The offset in effect at a utc time.
+    pub fn offset_at(&self, utc: NaiveDateTime) -> FixedOffset {
+        match self {
+            Zone::Fixed(offset) => *offset,
+            Zone::Named(tz) => tz.offset_from_utc_datetime(&utc).fix(),
+            Zone::Defined(_) => {
+                let (_, guess) = self.resolve(utc);
+                self.resolve(utc + guess).1
+            }
+        }
+    }
+}
+
+/// A utc offset as a js `getTimezoneOffset` value.
+pub fn timezone_of(offset: FixedOffset) -> i16 {
+    (-offset.local_minus_utc() / 60) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::activity_model::ActivityVariant;
+    use crate::utils::utils::parse_date;
+    use mongodb::bson::oid::ObjectId;
+
+    #[test]
+    fn escapes_text() {
+        assert_eq!(escape_text("a,b;c\\d\r\ne"), "a\\,b\\;c\\\\d\\ne");
+    }
+
+    #[test]
+    fn folds_long_lines() {
+        let mut out = String::new();
+        push_line(&mut out, &format!("SUMMARY:{}", "é".repeat(50)));
+
+        let lines: Vec<&str> = out.trim_end().split("\r\n").collect();
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|l| l.len() <= MAX_LINE_OCTETS));
+        assert!(lines[1..].iter().all(|l| l.starts_with(' ')));
+
+        let unfolded = out.trim_end().replace("\r\n ", "");
+        assert_eq!(unfolded, format!("SUMMARY:{}", "é".repeat(50)));
+    }
+
+    #[test]
+    fn renders_offsets() {
+        assert_eq!(utc_offset(-120), "+0200");
+        assert_eq!(utc_offset(330), "-0530");
+
+        let activity = Activity::new(
+            ActivityVariant::Default,
+            "read".into(),
+            "hobby, books".into(),
+            "".into(),
+            parse_date("2000-01-01T09:00:00Z").unwrap(),
+            parse_date("2000-01-01T10:30:00Z").ok(),
+            -120,
+            None,
+            ObjectId::new(),
+        );
+
+        let ics = render_calendar("chrono", &[activity], None);
+        assert!(ics.contains("TZID:UTC+0200\r\n"));
+        assert!(ics.contains("DTSTART;TZID=UTC+0200:20000101T110000\r\n"));
+        assert!(ics.contains("DTEND;TZID=UTC+0200:20000101T123000\r\n"));
+        assert!(ics.contains("CATEGORIES:hobby\\, books\r\n"));
+        assert!(!ics.contains("DESCRIPTION"));
+
+        let activity = Activity::new(
+            ActivityVariant::Default,
+            "read".into(),
+            "hobby".into(),
+            "".into(),
+            parse_date("2000-01-01T09:00:00Z").unwrap(),
+            parse_date("2000-01-01T10:30:00Z").ok(),
+            -120,
+            None,
+            ObjectId::new(),
+        );
+
+        let ics = render_calendar("chrono", &[activity], Some(0));
+        assert!(!ics.contains("VTIMEZONE"));
+        assert!(ics.contains("DTSTART:20000101T090000Z\r\n"));
+    }
+
+    #[test]
+    fn parses_calendar() {
+        let text = "BEGIN:VCALENDAR\r\n\
+            BEGIN:VEVENT\r\n\
+            DTSTART;TZID=\"Europe/Berlin\":20240101T090000\r\n\
+            SUMMARY:a\\, b\r\n  and c\r\n\
+            CATEGORIES:x\\,y,z\r\n\
+            END:VEVENT\r\n\
+            END:VCALENDAR\r\n";
+
+        let calendar = parse_calendar(text).unwrap();
+        let event = calendar.components("VEVENT").next().unwrap();
+
+        let start = event.property("DTSTART").unwrap();
+        assert_eq!(start.param("TZID"), Some("Europe/Berlin"));
+        assert_eq!(
+            IcalTime::parse(start).unwrap(),
+            IcalTime::Zoned(
+                NaiveDate::from_ymd_opt(2024, 1, 1)
+                    .unwrap()
+                    .and_hms_opt(9, 0, 0)
+                    .unwrap(),
+                "Europe/Berlin".to_string()
+            )
+        );
+        assert_eq!(
+            unescape_text(&event.property("SUMMARY").unwrap().value),
+            "a, b and c"
+        );
+        assert_eq!(
+            split_text_list(&event.property("CATEGORIES").unwrap().value),
+            vec!["x,y", "z"]
+        );
+
+        assert!(parse_calendar("BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nEND:VCALENDAR\r\n").is_err());
+        assert!(parse_calendar("BEGIN:VEVENT\r\nEND:VEVENT\r\n").is_err());
+        assert!(parse_calendar("hello").is_err());
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("PT1H30M").unwrap(), Duration::minutes(90));
+        assert_eq!(parse_duration("P1W").unwrap(), Duration::days(7));
+        assert_eq!(
+            parse_duration("-P1DT2S").unwrap(),
+            -(Duration::days(1) + Duration::seconds(2))
+        );
+        assert!(parse_duration("PT").is_ok());
+        assert!(parse_duration("P1H").is_err());
+        assert!(parse_duration("1D").is_err());
+    }
+
+    fn at(y: i32, m: u32, d: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(9, 0, 0)
+            .unwrap()
+    }
+
+    fn expand(rule: &str, start: NaiveDateTime, limit: usize) -> Vec<NaiveDateTime> {
+        RecurrenceRule::parse(rule).unwrap().expand(
+            start,
+            None,
+            start,
+            start + Duration::days(3660),
+            limit,
+        )
+    }
+
+    #[test]
+    fn expands_rules() {
+        // 2024-01-01 is a monday
+        assert_eq!(
+            expand("FREQ=WEEKLY;BYDAY=MO,FR;COUNT=4", at(2024, 1, 1), 100),
+            vec![
+                at(2024, 1, 1),
+                at(2024, 1, 5),
+                at(2024, 1, 8),
+                at(2024, 1, 12)
+            ]
+        );
+        assert_eq!(
+            expand("FREQ=DAILY;INTERVAL=10", at(2024, 1, 1), 3),
+            vec![at(2024, 1, 1), at(2024, 1, 11), at(2024, 1, 21)]
+        );
+        // months without a 31st are skipped
+        assert_eq!(
+            expand("FREQ=MONTHLY;COUNT=3", at(2024, 1, 31), 100),
+            vec![at(2024, 1, 31), at(2024, 3, 31), at(2024, 5, 31)]
+        );
+        assert_eq!(
+            expand("FREQ=MONTHLY;BYDAY=-1FR;COUNT=3", at(2024, 1, 26), 100),
+            vec![at(2024, 1, 26), at(2024, 2, 23), at(2024, 3, 29)]
+        );
+        assert_eq!(
+            expand("FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=29", at(2024, 2, 29), 2),
+            vec![at(2024, 2, 29), at(2028, 2, 29)]
+        );
+
+        let rule = RecurrenceRule::parse("FREQ=DAILY").unwrap();
+        let window = rule.expand(
+            at(2024, 1, 1),
+            Some(at(2024, 1, 20)),
+            at(2024, 1, 18),
+            at(2025, 1, 1),
+            100,
+        );
+        assert_eq!(
+            window,
+            vec![at(2024, 1, 18), at(2024, 1, 19), at(2024, 1, 20)]
+        );
+
+        assert!(RecurrenceRule::parse("FREQ=HOURLY").is_err());
+        assert!(RecurrenceRule::parse("FREQ=DAILY;BYSETPOS=1").is_err());
+        assert!(RecurrenceRule::parse("FREQ=WEEKLY;BYDAY=2MO").is_err());
+        assert!(RecurrenceRule::parse("FREQ=DAILY;COUNT=2;UNTIL=20240101").is_err());
+        assert!(RecurrenceRule::parse("INTERVAL=2").is_err());
+    }
+
+    #[test]
+    fn resolves_zones() {
+        let berlin = Zone::Named("Europe/Berlin".parse().unwrap());
+        let (utc, offset) = berlin.resolve(at(2024, 7, 1));
+        assert_eq!(utc, at(2024, 7, 1) - Duration::hours(2));
+        assert_eq!(timezone_of(offset), -120);
+        assert_eq!(timezone_of(berlin.offset_at(at(2024, 1, 1))), -60);
+
+        // 02:30 doesn't exist on the day clocks go forward, it becomes 03:30 +0200
+        let gap = NaiveDate::from_ymd_opt(2024, 3, 31)
+            .unwrap()
+            .and_hms_opt(2, 30, 0)
+            .unwrap();
+        let (utc, _) = berlin.resolve(gap);
+        assert_eq!(utc, gap - Duration::hours(1));
+
+        let vtimezone = parse_calendar(
+            "BEGIN:VCALENDAR\r\nBEGIN:VTIMEZONE\r\nTZID:Eastern\r\n\
+             BEGIN:STANDARD\r\nDTSTART:19701101T020000\r\n\
+             RRULE:FREQ=YEARLY;BYMONTH=11;BYDAY=1SU\r\n\
+             TZOFFSETFROM:-0400\r\nTZOFFSETTO:-0500\r\nEND:STANDARD\r\n\
+             BEGIN:DAYLIGHT\r\nDTSTART:19700308T020000\r\n\
+             RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=2SU\r\n\
+             TZOFFSETFROM:-0500\r\nTZOFFSETTO:-0400\r\nEND:DAYLIGHT\r\n\
+             END:VTIMEZONE\r\nEND:VCALENDAR\r\n",
+        )
+        .unwrap();
+        let eastern = Zone::from_vtimezone(&vtimezone.components[0]).unwrap();
+
+        assert_eq!(timezone_of(eastern.resolve(at(2024, 7, 1)).1), 240);
+        assert_eq!(timezone_of(eastern.resolve(at(2024, 1, 15)).1), 300);
+        assert_eq!(timezone_of(eastern.resolve(at(2024, 3, 11)).1), 240);
+        assert_eq!(timezone_of(eastern.resolve(at(2024, 11, 4)).1), 300);
+        assert_eq!(timezone_of(eastern.offset_at(at(2024, 7, 1))), 240);
+    }
+}
@@ -0,0 +1,172 @@
+use std::{future::Future, time::Duration};
+
+use axum::{
+    async_trait,
+    body::{to_bytes, Body},
+    extract::FromRequestParts,
+    http::{
+        header::{CONTENT_TYPE, ETAG},
+        request::Parts,
+        HeaderName, HeaderValue, StatusCode,
+    },
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::time::{interval_at, Instant};
+
+use crate::{
+    database::storage::Storage,
+    error::error::{AppError, FieldError},
+    models::idempotency_model::{
+        IdempotencyRecord, StoredResponse, IDEMPOTENCY_LEASE_RENEW_SECS, MAX_IDEMPOTENCY_KEY_LEN,
+    },
+    utils::utils::parse_object_id,
+};
+
+pub const IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// Set on responses that were replayed from a stored idempotency key.
+pub const IDEMPOTENT_REPLAYED_HEADER: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+/// The optional `Idempotency-Key` header.
+pub struct IdempotencyKey(pub Option<String>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for IdempotencyKey
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let value = match parts.headers.get(IDEMPOTENCY_KEY_HEADER) {
+            Some(v) => v,
+            None => return Ok(Self(None)),
+        };
+
+        match value.to_str() {
+            Ok(v) if !v.is_empty() && v.len() <= MAX_IDEMPOTENCY_KEY_LEN => {
+                Ok(Self(Some(v.to_string())))
+            }
+            _ => Err(AppError::ValidationFailed(vec![FieldError::new(
+                "Idempotency-Key",
+                format!(
+                    "must be between 1 and {} visible ascii characters",
+                    MAX_IDEMPOTENCY_KEY_LEN
+                ),
+            )])),
+        }
+    }
+}
+
+/// Identifies a request by route and body, so a key can't be reused for a different one.
+pub fn fingerprint(route: &str, body: &impl Serialize) -> Result<String, AppError> {
+    let body = serde_json::to_vec(body)
+        .map_err(|_| AppError::internal("failed to serialize request body"))?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(route.as_bytes());
+    hasher.update([0]);
+    hasher.update(&body);
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// Runs `handler` at most once per user and key. A repeat of the same request
+/// gets the stored response back, a different request under the same key is rejected.
+/// Only successful responses are kept, so a failed request can be retried with its key.
+/// The key is leased for `IDEMPOTENCY_LEASE_SECS` and the lease is renewed for as
+/// long as `handler` runs, so a slow request keeps its key. One dropped before it
+/// finishes stops renewing and frees the key once the lease runs out.
+pub async fn idempotent<F>(
+    db: &dyn Storage,
+    user_id: &str,
+    key: Option<String>,
+    fingerprint: String,
+    handler: F,
+) -> Result<Response, AppError>
+where
+    F: Future<Output = Response>,
+{
+    let key = match key {
+        Some(key) => key,
+        None => return Ok(handler.await),
+    };
+    let uid = parse_object_id(user_id).map_err(|_| AppError::InvalidToken)?;
+
+    let record = IdempotencyRecord::new(uid, key.clone(), fingerprint);
+    if let Some(existing) = db.reserve_idempotency_key(record.clone()).await? {
+        if existing.fingerprint != record.fingerprint {
+            return Err(AppError::IdempotencyKeyReused);
+        }
+
+        return match existing.response {
+            Some(stored) => Ok(replay(stored)),
+            None => Err(AppError::RequestInProgress),
+        };
+    }
+
+    // renewed in this task, so a dropped request stops renewing with it
+    let period = Duration::from_secs(IDEMPOTENCY_LEASE_RENEW_SECS);
+    let mut renew = interval_at(Instant::now() + period, period);
+    tokio::pin!(handler);
+    let res = loop {
+        tokio::select! {
+            res = &mut handler => break res,
+            _ = renew.tick() => {
+                if let Err(e) = db.renew_idempotency_key(uid, &key).await {
+                    tracing::error!("failed to renew idempotency key: {}", e);
+                }
+            }
+        }
+    };
+
+    if !res.status().is_success() {
+        let _ = db.release_idempotency_key(uid, &key).await;
+        return Ok(res);
+    }
+
+    let (parts, body) = res.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(v) => v,
+        Err(_) => {
+            let _ = db.release_idempotency_key(uid, &key).await;
+            return Err(AppError::internal("failed to read response body"));
+        }
+    };
+
+    let stored = StoredResponse {
+        status: parts.status.as_u16(),
+        etag: parts
+            .headers
+            .get(ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string),
+        body: String::from_utf8_lossy(&body).into_owned(),
+    };
+    // the write already happened, so hand back its response even if it can't be stored
+    if let Err(e) = db.complete_idempotency_key(uid, &key, stored).await {
+        tracing::error!("failed to store idempotent response: {}", e);
+    }
+
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+fn replay(stored: StoredResponse) -> Response {
+    let status = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+    let mut res = (status, stored.body).into_response();
+
+    let headers = res.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    headers.insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+    if let Some(etag) = stored.etag.and_then(|v| HeaderValue::from_str(&v).ok()) {
+        headers.insert(ETAG, etag);
+    }
+
+    res
+}
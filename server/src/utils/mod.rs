@@ -1,2 +1,10 @@
 pub mod auth;
+pub mod ical;
+pub mod idempotency;
+pub mod purge;
+pub mod records;
+pub mod request_id;
+pub mod series;
+#[allow(clippy::module_inception)]
 pub mod utils;
+pub mod validation;
@@ -0,0 +1,82 @@
+use std::time::Duration;
+
+use mongodb::{bson::DateTime, error::Error};
+use tokio::task::JoinHandle;
+
+use crate::{database::storage::Storage, AppState};
+
+/// How often deactivated accounts are checked for ones due to be erased.
+pub const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Erases every deactivated account whose grace period has run out and
+/// returns how many were erased. Stops at the first failure; the rest are
+/// picked up on the next run.
+pub async fn purge_deleted_users(db: &dyn Storage) -> Result<usize, Error> {
+    let uids = db.get_users_to_purge(DateTime::now()).await?;
+
+    for uid in &uids {
+        let erased = db.erase_user(*uid).await?;
+        tracing::info!(
+            user = uid.to_hex(),
+            activities = erased.activities,
+            tokens = erased.tokens,
+            "purged deleted account"
+        );
+    }
+
+    Ok(uids.len())
+}
+
+pub fn spawn_purge_task(app_state: AppState) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = purge_deleted_users(&*app_state.db).await {
+                tracing::error!("failed to purge deleted accounts: {}", e);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::memory_database::MemoryDatabase;
+    use crate::models::user_model::RegisterUserPayload;
+
+    #[tokio::test]
+    async fn purges_only_due_accounts() {
+        let db = MemoryDatabase::new();
+
+        let mut uids = vec![];
+        for email in ["due@test.com", "later@test.com"] {
+            let user = db
+                .create_user(RegisterUserPayload {
+                    email: email.to_string(),
+                    pass: "password".to_string(),
+                    given_name: "test".to_string(),
+                    family_name: "testerton".to_string(),
+                    generated_pass: false,
+                })
+                .await
+                .unwrap()
+                .unwrap();
+            uids.push(user.id);
+        }
+
+        let now = DateTime::now().timestamp_millis();
+        db.deactivate_user(uids[0], DateTime::from_millis(now - 1000))
+            .await
+            .unwrap();
+        db.deactivate_user(uids[1], DateTime::from_millis(now + 60 * 60 * 1000))
+            .await
+            .unwrap();
+
+        assert_eq!(purge_deleted_users(&db).await.unwrap(), 1);
+        assert!(db.get_user_by_id(uids[0]).await.unwrap().is_none());
+        assert!(db.get_user_by_id(uids[1]).await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashSet;
+
+use mongodb::{bson::oid::ObjectId, error::Error};
+
+use crate::{
+    database::storage::Storage,
+    models::{
+        activity_model::{Activity, ActivityWriteResult, VersionedWrite},
+        record_model::{compute_records, record_titles, PersonalRecord},
+    },
+    utils::{request_id::current_request_id, utils::parse_object_id},
+};
+
+/// Activities touched by a run of writes, and the exercise titles they now
+/// have in them.
+#[derive(Debug, Default)]
+pub struct WrittenActivities {
+    pub ids: Vec<ObjectId>,
+    pub titles: HashSet<String>,
+}
+
+impl WrittenActivities {
+    pub fn activity(activity: &Activity) -> Self {
+        Self {
+            ids: vec![activity.id],
+            titles: record_titles(activity),
+        }
+    }
+
+    pub fn deleted(id: ObjectId) -> Self {
+        Self {
+            ids: vec![id],
+            titles: HashSet::new(),
+        }
+    }
+
+    pub fn from_results<'a>(
+        results: impl IntoIterator<Item = &'a Result<ActivityWriteResult, Error>>,
+    ) -> Self {
+        let mut written = Self::default();
+
+        for res in results {
+            match res {
+                Ok(ActivityWriteResult::Created(a))
+                | Ok(ActivityWriteResult::Updated(VersionedWrite::Done(a))) => {
+                    let activity = Self::activity(a);
+                    written.ids.extend(activity.ids);
+                    written.titles.extend(activity.titles);
+                }
+                Ok(ActivityWriteResult::Deleted(VersionedWrite::Done(a))) => written.ids.push(a.id),
+                _ => {}
+            }
+        }
+
+        written
+    }
+}
+
+/// Recomputes the user's records for the titles in the written activities and
+/// for the records they held before, so editing or deleting a record holder
+/// passes its records on. Returns the records the written activities hold now
+/// that they didn't before.
+pub async fn refresh_records(
+    db: &dyn Storage,
+    user_id: &str,
+    written: WrittenActivities,
+) -> Result<Vec<PersonalRecord>, Error> {
+    let uid = parse_object_id(user_id)?;
+    let WrittenActivities { ids, mut titles } = written;
+
+    let previous = db.get_records(user_id.to_string()).await?;
+    titles.extend(
+        previous
+            .iter()
+            .filter(|r| ids.contains(&r.activity))
+            .map(|r| r.title.clone()),
+    );
+
+    if titles.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let activities = db
+        .find_exercise_activities(user_id.to_string(), titles.iter().cloned().collect())
+        .await?;
+    let records = compute_records(uid, &titles, &activities);
+
+    db.replace_records(
+        user_id.to_string(),
+        titles.into_iter().collect(),
+        records.clone(),
+    )
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .filter(|r| ids.contains(&r.activity) && !r.is_in(&previous))
+        .collect())
+}
+
+/// Like `refresh_records`, for when the write has already succeeded. Records
+/// can be recomputed later, so failing here only gets logged.
+pub async fn try_refresh_records(
+    db: &dyn Storage,
+    user_id: &str,
+    written: WrittenActivities,
+) -> Vec<PersonalRecord> {
+    match refresh_records(db, user_id, written).await {
+        Ok(records) => records,
+        Err(e) => {
+            tracing::error!(
+                request_id = current_request_id(),
+                "failed to refresh records: {}",
+                e
+            );
+            vec![]
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use mongodb::{bson::DateTime, error::Error};
+
+use crate::{
+    database::storage::Storage,
+    models::{
+        activity_model::{Activity, ActivityCursor, GetActivitiesPayload},
+        series_model::MAX_SERIES_OCCURRENCES,
+    },
+};
+
+/// Occurrences of the user's series that overlap `start..=end`, matching the
+/// query's filters and after its cursor, in the same order as activities.
+pub async fn series_occurrences(
+    db: &dyn Storage,
+    user_id: &str,
+    query: &GetActivitiesPayload,
+    start: DateTime,
+    end: DateTime,
+) -> Result<Vec<Activity>, Error> {
+    let after = query.after.as_deref().and_then(ActivityCursor::decode);
+    let limit = query
+        .limit
+        .map_or(MAX_SERIES_OCCURRENCES, |l| l as usize)
+        .min(MAX_SERIES_OCCURRENCES);
+
+    let mut res = vec![];
+
+    for series in db.get_series(user_id.to_string()).await? {
+        if query.title.as_ref().is_some_and(|t| &series.title != t)
+            || query.group.as_ref().is_some_and(|g| &series.group != g)
+            || query.variant.is_some_and(|v| series.variant != v)
+        {
+            continue;
+        }
+
+        // earlier occurrences are skipped before the limit applies
+        let from = match after {
+            Some(after) => start.max(after.start),
+            None => start,
+        };
+        res.extend(
+            series
+                .occurrence_starts(from, end, limit + 1)
+                .into_iter()
+                .map(|at| series.occurrence(at))
+                .filter(|a| after.is_none_or(|c| (a.start, a.id) > (c.start, c.id)))
+                .take(limit),
+        );
+    }
+
+    Ok(res)
+}
+
+/// Adds occurrences to activities listed by start, keeping the first `limit`.
+pub fn merge_occurrences(
+    activities: &mut Vec<Activity>,
+    occurrences: Vec<Activity>,
+    limit: Option<u32>,
+) {
+    if occurrences.is_empty() {
+        return;
+    }
+
+    activities.extend(occurrences);
+    activities.sort_by(|a, b| a.start.cmp(&b.start).then(a.id.cmp(&b.id)));
+    if let Some(limit) = limit {
+        activities.truncate(limit as usize);
+    }
+}
@@ -1,9 +1,12 @@
 use std::iter;
 
-use mongodb::bson::{to_bson, Document};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{to_bson, DateTime, Document};
+use mongodb::error::Error;
 use rand::distributions::Alphanumeric;
 use rand::prelude::SliceRandom;
 use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
 
 pub fn insert_optional<T: serde::Serialize>(doc: &mut Document, key: &str, value: Option<T>) {
     if let Some(v) = value {
@@ -13,6 +16,16 @@ pub fn insert_optional<T: serde::Serialize>(doc: &mut Document, key: &str, value
     }
 }
 
+// payloads are validated before they reach the database, so these only
+// guard against panics rather than produce user facing messages
+pub fn parse_object_id(id: &str) -> Result<ObjectId, Error> {
+    ObjectId::parse_str(id).map_err(Error::custom)
+}
+
+pub fn parse_date(date: &str) -> Result<DateTime, Error> {
+    DateTime::parse_rfc3339_str(date).map_err(Error::custom)
+}
+
 pub fn generate_password(length: usize) -> String {
     let special_chars = b"!@#$%^&*()_+-=[]{}|;:,.<>?";
     let mut rng = thread_rng();
@@ -30,3 +43,20 @@ pub fn generate_password(length: usize) -> String {
     // Shuffle the password to mix special characters into the string
     password.chars().collect::<Vec<_>>().into_iter().collect()
 }
+
+/// A random url safe secret, for tokens that are mailed or shared as links.
+pub fn generate_token() -> String {
+    thread_rng()
+        .sample_iter(Alphanumeric)
+        .take(43)
+        .map(char::from)
+        .collect()
+}
+
+/// Tokens are stored as a sha256 hex digest so a database leak can't be replayed.
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
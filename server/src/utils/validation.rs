@@ -0,0 +1,810 @@
+use axum::async_trait;
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{FromRequest, FromRequestParts, Request};
+use axum::http::header::IF_MATCH;
+use axum::http::request::Parts;
+use axum::http::HeaderMap;
+use axum::Json;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::de::DeserializeOwned;
+
+use crate::error::error::{AppError, FieldError};
+use crate::models::activity_model::{
+    ActivityCursor, BatchActivityPayload, Exercise, GetActivitiesPayload, PatchActivityBody,
+    PostActivityPayload, SyncPayload, SyncToken, MAX_BATCH_OPERATIONS, MAX_TIMEZONE, MIN_TIMEZONE,
+};
+use crate::models::auth_model::{ForgotPasswordPayload, ResetPasswordPayload, VerifyEmailPayload};
+use crate::models::export_model::ExportPayload;
+use crate::models::import_model::{
+    IcalField, IcalImportPayload, ImportPayload, MAX_ICAL_WINDOW_DAYS,
+};
+use crate::models::progress_model::ExerciseProgressPayload;
+use crate::models::record_model::GetRecordsPayload;
+use crate::models::series_model::{
+    DeleteOccurrencePayload, PatchSeriesBody, PostSeriesPayload, SeriesScope,
+};
+use crate::models::stats_model::{StatsPayload, MAX_STATS_RANGE_DAYS};
+use crate::models::template_model::{
+    InstantiateTemplatePayload, PatchTemplatePayload, PostTemplatePayload, MAX_TEMPLATE_EXERCISES,
+};
+use crate::models::timer_model::{StartTimerPayload, StopTimerPayload};
+use crate::models::user_model::{
+    ChangePasswordPayload, DeleteAccountPayload, PatchUserPayload, MAX_IMG_LEN, MAX_NAME_LEN,
+    MAX_PASSWORD_BYTES, MIN_PASSWORD_LEN,
+};
+use crate::utils::ical::RecurrenceRule;
+
+pub trait Validate {
+    fn validate(&self) -> Result<(), Vec<FieldError>>;
+}
+
+/// Like `Json`, but rejects with field-level errors for malformed bodies and
+/// runs `Validate` before the handler sees the payload.
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state)
+            .await
+            .map_err(json_rejection)?;
+
+        value.validate().map_err(AppError::ValidationFailed)?;
+
+        Ok(Self(value))
+    }
+}
+
+fn json_rejection(rejection: JsonRejection) -> AppError {
+    // `Json` deserializes through serde_path_to_error, so the failing field can
+    // be recovered from the error's source chain
+    if let JsonRejection::JsonDataError(err) = &rejection {
+        let source = std::iter::successors(std::error::Error::source(err), |e| e.source())
+            .find_map(|e| e.downcast_ref::<serde_path_to_error::Error<serde_json::Error>>());
+
+        if let Some(err) = source {
+            let message = err.inner().to_string();
+            let message = message.split(" at line ").next().unwrap_or_default();
+
+            return AppError::ValidationFailed(vec![FieldError::new(
+                field_name(err.path()),
+                message,
+            )]);
+        }
+    }
+
+    match rejection {
+        JsonRejection::MissingJsonContentType(_) => AppError::UnsupportedMediaType,
+        rejection => AppError::InvalidBody(rejection.body_text()),
+    }
+}
+
+/// Like `Query`, but rejects with field-level errors and runs `Validate`.
+/// A missing query string deserializes as if every param were absent.
+pub struct ValidatedQuery<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ValidatedQuery<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let query = parts.uri.query().unwrap_or_default();
+        let deserializer =
+            serde_urlencoded::Deserializer::new(form_urlencoded::parse(query.as_bytes()));
+
+        let value: T = serde_path_to_error::deserialize(deserializer).map_err(|err| {
+            AppError::ValidationFailed(vec![FieldError::new(
+                field_name(err.path()),
+                err.inner().to_string(),
+            )])
+        })?;
+
+        value.validate().map_err(AppError::ValidationFailed)?;
+
+        Ok(Self(value))
+    }
+}
+
+fn field_name(path: &serde_path_to_error::Path) -> String {
+    match path.to_string().as_str() {
+        "." => "body".to_string(),
+        p => p.to_string(),
+    }
+}
+
+pub fn validate_object_id(field: &str, id: &str) -> Result<(), AppError> {
+    match ObjectId::parse_str(id) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(AppError::ValidationFailed(vec![FieldError::new(
+            field,
+            "must be a 24 character hex string",
+        )])),
+    }
+}
+
+/// Reads the expected version from `If-Match`, accepting both `"3"` and `W/"3"`.
+/// `*` or no header means any version. A body `v` must agree with the header.
+pub fn expected_version(headers: &HeaderMap, body: Option<u32>) -> Result<Option<u32>, AppError> {
+    let header = match headers.get(IF_MATCH) {
+        Some(value) => value.to_str().unwrap_or_default().trim(),
+        None => return Ok(body),
+    };
+
+    let header = match header {
+        "*" => None,
+        value => {
+            let value = value.strip_prefix("W/").unwrap_or(value).trim_matches('"');
+            let v = value.parse::<u32>().map_err(|_| {
+                AppError::ValidationFailed(vec![FieldError::new(
+                    "If-Match",
+                    "must be an ETag returned by the api",
+                )])
+            })?;
+            Some(v)
+        }
+    };
+
+    match (header, body) {
+        (Some(h), Some(b)) if h != b => Err(AppError::ValidationFailed(vec![FieldError::new(
+            "v",
+            "must match If-Match",
+        )])),
+        (h, b) => Ok(h.or(b)),
+    }
+}
+
+// checks shared between payloads, each pushes onto the list of errors
+
+fn check_date(errors: &mut Vec<FieldError>, field: &str, value: &str) -> Option<DateTime> {
+    match DateTime::parse_rfc3339_str(value) {
+        Ok(v) => Some(v),
+        Err(_) => {
+            errors.push(FieldError::new(field, "must be an RFC 3339 date"));
+            None
+        }
+    }
+}
+
+fn check_range(errors: &mut Vec<FieldError>, start: Option<DateTime>, end: Option<DateTime>) {
+    if let (Some(start), Some(end)) = (start, end) {
+        if end < start {
+            errors.push(FieldError::new("end", "must not be before start"));
+        }
+    }
+}
+
+fn check_timezone(errors: &mut Vec<FieldError>, timezone: i16) {
+    if !(MIN_TIMEZONE..=MAX_TIMEZONE).contains(&timezone) {
+        errors.push(FieldError::new(
+            "timezone",
+            format!(
+                "must be an offset in minutes between {} and {}",
+                MIN_TIMEZONE, MAX_TIMEZONE
+            ),
+        ));
+    }
+}
+
+fn check_password(errors: &mut Vec<FieldError>, field: &str, pass: &str) {
+    if pass.chars().count() < MIN_PASSWORD_LEN {
+        errors.push(FieldError::new(
+            field,
+            format!("must be at least {} characters", MIN_PASSWORD_LEN),
+        ));
+    }
+
+    if pass.len() > MAX_PASSWORD_BYTES {
+        errors.push(FieldError::new(
+            field,
+            format!("must be at most {} bytes", MAX_PASSWORD_BYTES),
+        ));
+    }
+}
+
+fn check_exercises(errors: &mut Vec<FieldError>, exercises: &[Exercise]) {
+    if exercises.len() > MAX_TEMPLATE_EXERCISES {
+        errors.push(FieldError::new(
+            "exercises",
+            format!("must not contain more than {}", MAX_TEMPLATE_EXERCISES),
+        ));
+    }
+
+    for (i, exercise) in exercises.iter().enumerate() {
+        let title = match exercise {
+            Exercise::Strength(e) => &e.title,
+            Exercise::Mobility(e) => &e.title,
+            Exercise::Cardio(e) => &e.title,
+        };
+        if title.trim().is_empty() {
+            errors.push(FieldError::new(
+                format!("exercises[{}].title", i),
+                "must not be empty",
+            ));
+        }
+    }
+}
+
+fn check_rrule(errors: &mut Vec<FieldError>, rrule: &str) {
+    if let Err(e) = RecurrenceRule::parse(rrule) {
+        errors.push(FieldError::new("rrule", e));
+    }
+}
+
+fn into_result(errors: Vec<FieldError>) -> Result<(), Vec<FieldError>> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+impl Validate for PostActivityPayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+
+        if self.title.trim().is_empty() {
+            errors.push(FieldError::new("title", "must not be empty"));
+        }
+
+        let start = check_date(&mut errors, "start", &self.start);
+        let end = check_date(&mut errors, "end", &self.end);
+        check_range(&mut errors, start, end);
+        check_timezone(&mut errors, self.timezone);
+
+        into_result(errors)
+    }
+}
+
+impl Validate for PatchActivityBody {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+
+        if self.title.as_ref().is_some_and(|t| t.trim().is_empty()) {
+            errors.push(FieldError::new("title", "must not be empty"));
+        }
+
+        let start = self
+            .start
+            .as_ref()
+            .and_then(|v| check_date(&mut errors, "start", v));
+        let end = self
+            .end
+            .as_ref()
+            .and_then(|v| check_date(&mut errors, "end", v));
+        check_range(&mut errors, start, end);
+
+        if let Some(timezone) = self.timezone {
+            check_timezone(&mut errors, timezone);
+        }
+
+        into_result(errors)
+    }
+}
+
+impl Validate for GetActivitiesPayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+
+        let start = self
+            .start
+            .as_ref()
+            .and_then(|v| check_date(&mut errors, "start", v));
+        let end = self
+            .end
+            .as_ref()
+            .and_then(|v| check_date(&mut errors, "end", v));
+        check_range(&mut errors, start, end);
+
+        if let Some(timezone) = self.timezone {
+            check_timezone(&mut errors, timezone);
+        }
+
+        if self.limit == Some(0) {
+            errors.push(FieldError::new("limit", "must be greater than 0"));
+        }
+
+        if let Some(after) = &self.after {
+            if ActivityCursor::decode(after).is_none() {
+                errors.push(FieldError::new(
+                    "after",
+                    "must be a cursor returned as next",
+                ));
+            }
+        }
+
+        into_result(errors)
+    }
+}
+
+// operations are validated one by one in the handler so each can fail on its own
+impl Validate for BatchActivityPayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+
+        if self.operations.is_empty() {
+            errors.push(FieldError::new("operations", "must not be empty"));
+        }
+
+        if self.operations.len() > MAX_BATCH_OPERATIONS {
+            errors.push(FieldError::new(
+                "operations",
+                format!("must not contain more than {}", MAX_BATCH_OPERATIONS),
+            ));
+        }
+
+        into_result(errors)
+    }
+}
+
+impl Validate for SyncPayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+
+        if let Some(since) = &self.since {
+            if SyncToken::decode(since).is_none() {
+                errors.push(FieldError::new("since", "must be a token returned as next"));
+            }
+        }
+
+        into_result(errors)
+    }
+}
+
+impl Validate for ForgotPasswordPayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+
+        if self.email.trim().is_empty() {
+            errors.push(FieldError::new("email", "must not be empty"));
+        }
+
+        into_result(errors)
+    }
+}
+
+impl Validate for ResetPasswordPayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+
+        if self.token.is_empty() {
+            errors.push(FieldError::new("token", "must not be empty"));
+        }
+
+        check_password(&mut errors, "pass", &self.pass);
+
+        into_result(errors)
+    }
+}
+
+impl Validate for PatchUserPayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+
+        for (field, value) in [
+            ("givenName", &self.given_name),
+            ("familyName", &self.family_name),
+        ] {
+            match value {
+                Some(v) if v.trim().is_empty() => {
+                    errors.push(FieldError::new(field, "must not be empty"))
+                }
+                Some(v) if v.chars().count() > MAX_NAME_LEN => errors.push(FieldError::new(
+                    field,
+                    format!("must be at most {} characters", MAX_NAME_LEN),
+                )),
+                _ => {}
+            }
+        }
+
+        // an empty string clears the image
+        if let Some(img) = &self.img {
+            if img.len() > MAX_IMG_LEN {
+                errors.push(FieldError::new(
+                    "img",
+                    format!("must be at most {} characters", MAX_IMG_LEN),
+                ));
+            } else if !img.is_empty() && !img.starts_with("https://") {
+                errors.push(FieldError::new("img", "must be an https url"));
+            }
+        }
+
+        into_result(errors)
+    }
+}
+
+// which of the two is required depends on the user, so that is checked in the handler
+impl Validate for DeleteAccountPayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+
+        if self.pass.is_none() && self.email.is_none() {
+            errors.push(FieldError::new(
+                "pass",
+                "must confirm with your password, or email if you have none",
+            ));
+        }
+
+        into_result(errors)
+    }
+}
+
+// the format is checked when deserializing
+impl Validate for ExportPayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        Ok(())
+    }
+}
+
+// rows are validated one by one in the handler
+impl Validate for ImportPayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        Ok(())
+    }
+}
+
+impl Validate for IcalImportPayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+
+        if let Some(url) = &self.url {
+            let scheme = url.split_once("://").map(|(s, _)| s.to_ascii_lowercase());
+            if !matches!(scheme.as_deref(), Some("http" | "https" | "webcal")) {
+                errors.push(FieldError::new(
+                    "url",
+                    "must be an http, https or webcal url",
+                ));
+            }
+        }
+
+        if self.title == IcalField::None {
+            errors.push(FieldError::new("title", "must be taken from a property"));
+        }
+
+        if let Some(timezone) = self.timezone {
+            check_timezone(&mut errors, timezone);
+        }
+
+        if let Some(from) = &self.from {
+            check_date(&mut errors, "from", from);
+        }
+        if let Some(until) = &self.until {
+            check_date(&mut errors, "until", until);
+        }
+
+        // a bound left out defaults relative to now, so the window is checked
+        // whichever of them are given
+        if let Some((from, until)) = self.window() {
+            let days = (until.timestamp_millis() - from.timestamp_millis()) / (24 * 60 * 60 * 1000);
+            let (field, other) = match self.until {
+                Some(_) => ("until", "from"),
+                None => ("from", "until"),
+            };
+            if until < from {
+                let message = match self.until {
+                    Some(_) => "must not be before from",
+                    None => "must not be after until",
+                };
+                errors.push(FieldError::new(field, message));
+            } else if days > MAX_ICAL_WINDOW_DAYS {
+                errors.push(FieldError::new(
+                    field,
+                    format!("must be within {} days of {}", MAX_ICAL_WINDOW_DAYS, other),
+                ));
+            }
+        }
+
+        into_result(errors)
+    }
+}
+
+impl Validate for ExerciseProgressPayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+
+        if self.title.trim().is_empty() {
+            errors.push(FieldError::new("title", "must not be empty"));
+        }
+
+        let start = self
+            .start
+            .as_ref()
+            .and_then(|v| check_date(&mut errors, "start", v));
+        let end = self
+            .end
+            .as_ref()
+            .and_then(|v| check_date(&mut errors, "end", v));
+        check_range(&mut errors, start, end);
+
+        into_result(errors)
+    }
+}
+
+// any title can be asked for, unknown ones have no records
+impl Validate for GetRecordsPayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        Ok(())
+    }
+}
+
+impl Validate for StatsPayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+
+        let start = check_date(&mut errors, "start", &self.start);
+        let end = check_date(&mut errors, "end", &self.end);
+        if let (Some(start), Some(end)) = (start, end) {
+            let days = (end.timestamp_millis() - start.timestamp_millis()) / (24 * 60 * 60 * 1000);
+            if end <= start {
+                errors.push(FieldError::new("end", "must be after start"));
+            } else if days > MAX_STATS_RANGE_DAYS {
+                errors.push(FieldError::new(
+                    "end",
+                    format!("must be within {} days of start", MAX_STATS_RANGE_DAYS),
+                ));
+            }
+        }
+
+        if let Some(timezone) = self.timezone {
+            check_timezone(&mut errors, timezone);
+        }
+
+        into_result(errors)
+    }
+}
+
+impl Validate for PostTemplatePayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+
+        if self.name.trim().is_empty() {
+            errors.push(FieldError::new("name", "must not be empty"));
+        }
+
+        check_exercises(&mut errors, &self.exercises);
+
+        into_result(errors)
+    }
+}
+
+impl Validate for PatchTemplatePayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+
+        if self.name.as_ref().is_some_and(|n| n.trim().is_empty()) {
+            errors.push(FieldError::new("name", "must not be empty"));
+        }
+
+        if let Some(exercises) = &self.exercises {
+            check_exercises(&mut errors, exercises);
+        }
+
+        into_result(errors)
+    }
+}
+
+impl Validate for InstantiateTemplatePayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+
+        let start = check_date(&mut errors, "start", &self.start);
+        let end = self
+            .end
+            .as_ref()
+            .and_then(|v| check_date(&mut errors, "end", v));
+        check_range(&mut errors, start, end);
+        check_timezone(&mut errors, self.timezone);
+
+        into_result(errors)
+    }
+}
+
+impl Validate for PostSeriesPayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+
+        if self.title.trim().is_empty() {
+            errors.push(FieldError::new("title", "must not be empty"));
+        }
+
+        check_date(&mut errors, "start", &self.start);
+        if self.duration == 0 {
+            errors.push(FieldError::new("duration", "must be greater than 0"));
+        }
+        check_timezone(&mut errors, self.timezone);
+        check_rrule(&mut errors, &self.rrule);
+
+        into_result(errors)
+    }
+}
+
+impl Validate for PatchSeriesBody {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+
+        if self.title.as_ref().is_some_and(|t| t.trim().is_empty()) {
+            errors.push(FieldError::new("title", "must not be empty"));
+        }
+
+        if let Some(start) = &self.start {
+            check_date(&mut errors, "start", start);
+        }
+
+        if self.duration == Some(0) {
+            errors.push(FieldError::new("duration", "must be greater than 0"));
+        }
+
+        if let Some(timezone) = self.timezone {
+            check_timezone(&mut errors, timezone);
+        }
+
+        if let Some(rrule) = &self.rrule {
+            match self.scope {
+                SeriesScope::This => errors.push(FieldError::new(
+                    "rrule",
+                    "can only change for following or all occurrences",
+                )),
+                _ => check_rrule(&mut errors, rrule),
+            }
+        }
+
+        into_result(errors)
+    }
+}
+
+// the scope is checked by serde, there is nothing else to it
+impl Validate for DeleteOccurrencePayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        Ok(())
+    }
+}
+
+impl Validate for StartTimerPayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+
+        if self.title.trim().is_empty() {
+            errors.push(FieldError::new("title", "must not be empty"));
+        }
+
+        // a timer stopped now would otherwise end before it started
+        let start = self
+            .start
+            .as_ref()
+            .and_then(|s| check_date(&mut errors, "start", s));
+        if start.is_some_and(|s| s > DateTime::now()) {
+            errors.push(FieldError::new("start", "must not be in the future"));
+        }
+        check_timezone(&mut errors, self.timezone);
+
+        into_result(errors)
+    }
+}
+
+// the end is checked against the timer's start in storage
+impl Validate for StopTimerPayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+
+        if let Some(end) = &self.end {
+            check_date(&mut errors, "end", end);
+        }
+
+        into_result(errors)
+    }
+}
+
+impl Validate for VerifyEmailPayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+
+        if self.token.is_empty() {
+            errors.push(FieldError::new("token", "must not be empty"));
+        }
+
+        into_result(errors)
+    }
+}
+
+impl Validate for ChangePasswordPayload {
+    fn validate(&self) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+
+        check_password(&mut errors, "pass", &self.pass);
+
+        if self.current.as_ref().is_some_and(|c| *c == self.pass) {
+            errors.push(FieldError::new(
+                "pass",
+                "must differ from the current password",
+            ));
+        }
+
+        into_result(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::activity_model::ActivityVariant;
+
+    fn post_payload() -> PostActivityPayload {
+        PostActivityPayload {
+            title: "title".to_string(),
+            variant: ActivityVariant::Default,
+            group: "group".to_string(),
+            notes: None,
+            start: "2000-01-01T09:00:00.000Z".to_string(),
+            end: "2000-01-01T09:30:00.000Z".to_string(),
+            timezone: -60,
+            data: None,
+            color: None,
+            ical_uid: None,
+            occurrence: None,
+        }
+    }
+
+    fn if_match(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(IF_MATCH, value.parse().unwrap());
+        headers
+    }
+
+    fn fields(res: Result<(), Vec<FieldError>>) -> Vec<String> {
+        res.unwrap_err().into_iter().map(|e| e.field).collect()
+    }
+
+    #[test]
+    fn post_activity_payload() {
+        assert!(post_payload().validate().is_ok());
+
+        let mut payload = post_payload();
+        payload.start = "yesterday".to_string();
+        payload.timezone = 1000;
+        assert_eq!(fields(payload.validate()), vec!["start", "timezone"]);
+
+        let mut payload = post_payload();
+        payload.end = "2000-01-01T08:00:00.000Z".to_string();
+        assert_eq!(fields(payload.validate()), vec!["end"]);
+    }
+
+    #[test]
+    fn get_activities_payload() {
+        let payload = GetActivitiesPayload {
+            timezone: Some(-900),
+            variant: None,
+            title: None,
+            group: None,
+            start: Some("2000-01-02T00:00:00.000Z".to_string()),
+            end: Some("2000-01-01T00:00:00.000Z".to_string()),
+            limit: Some(0),
+            after: Some("nope".to_string()),
+        };
+
+        assert_eq!(
+            fields(payload.validate()),
+            vec!["end", "timezone", "limit", "after"]
+        );
+    }
+
+    #[test]
+    fn expected_version_from_headers() {
+        assert_eq!(expected_version(&HeaderMap::new(), None), Ok(None));
+        assert_eq!(expected_version(&HeaderMap::new(), Some(2)), Ok(Some(2)));
+        assert_eq!(expected_version(&if_match("\"3\""), None), Ok(Some(3)));
+        assert_eq!(expected_version(&if_match("W/\"3\""), Some(3)), Ok(Some(3)));
+        assert_eq!(expected_version(&if_match("*"), Some(4)), Ok(Some(4)));
+        assert!(expected_version(&if_match("\"3\""), Some(4)).is_err());
+        assert!(expected_version(&if_match("abc"), None).is_err());
+    }
+}